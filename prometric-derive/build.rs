@@ -0,0 +1,16 @@
+//! Sets the `prometric_disabled` cfg when the `PROMETRIC_STRIP` environment variable is set to `1`
+//! or `true` at build time. Unlike `RUSTFLAGS="--cfg prometric_disabled"`, this also reaches the
+//! macro when cross-compiling with `--target`, where rustflags don't apply to proc macros.
+
+/// The environment variable stripping all metrics at build time.
+const STRIP_KEY: &str = "PROMETRIC_STRIP";
+
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(prometric_disabled)");
+    println!("cargo::rerun-if-env-changed={STRIP_KEY}");
+
+    let strip = std::env::var(STRIP_KEY).is_ok_and(|value| value == "1" || value == "true");
+    if strip {
+        println!("cargo::rustc-cfg=prometric_disabled");
+    }
+}
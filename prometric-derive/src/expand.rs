@@ -1,16 +1,36 @@
+use std::collections::HashMap;
+
 use darling::{FromField, FromMeta};
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 use syn::{
-    Field, GenericArgument, Ident, ItemStruct, LitFloat, LitStr, PathArguments, Result, Type,
-    TypePath,
+    Field, GenericArgument, Ident, ItemEnum, ItemStruct, LitFloat, LitStr, PathArguments, Result,
+    Type, TypePath,
+};
+
+use crate::utils::{
+    is_screaming_snake, is_valid_label_name, is_valid_metric_name, snake_to_pascal,
+    to_screaming_snake,
 };
 
-use crate::utils::{snake_to_pascal, to_screaming_snake};
+/// Returns the default path of the `prometric` crate in the generated code, `::prometric`.
+fn default_crate_path() -> syn::Path {
+    syn::parse_quote! { ::prometric }
+}
+
+/// Returns the crate path as written in the intra-doc links of the generated docs, e.g.
+/// `::prometric`.
+fn doc_path(krate: &syn::Path) -> String {
+    quote! { #krate }.to_string().replace(' ', "")
+}
 
 /// The name of the metric attribute.
 const METRIC_ATTR_NAME: &str = "metric";
 
+/// Whether the `prometric_disabled` cfg is set, in which case inert zero-sized metrics are
+/// generated and nothing is registered. See `build.rs`.
+const DISABLED: bool = cfg!(prometric_disabled);
+
 /// The default separator to use between the scope and the metric name.
 /// NOTE: Prometheus does not support any other separators.
 const DEFAULT_SEPARATOR: &str = "_";
@@ -20,9 +40,213 @@ const DEFAULT_SEPARATOR: &str = "_";
 pub(super) struct MetricsAttr {
     /// The scope to use for the metrics. Used as a prefix for metric names.
     scope: Option<LitStr>,
-    /// If true, generates a static LazyLock with SCREAMING_SNAKE_CASE name.
+    /// The namespace of the metrics, an alternative to `scope` following the conventions of the
+    /// Prometheus client libraries. Composed with the subsystem into `namespace_subsystem`.
+    namespace: Option<LitStr>,
+    /// The subsystem of the metrics, appended to the scope or namespace.
+    subsystem: Option<LitStr>,
+    /// The environment variable prefixing the scope at build time, if set, e.g. per instance.
+    scope_env: Option<LitStr>,
+    /// The `&str` constant prefixing the names of the metrics, e.g. the scope of a parent crate.
+    /// It's the default prefix of the builder, replaced when nested.
+    parent: Option<syn::Expr>,
+    /// The path of the `prometric` crate in the generated code, e.g. `crate = "facade::prometric"`
+    /// when it's re-exported by a facade crate or renamed in `Cargo.toml`. Defaults to
+    /// `::prometric`.
+    #[darling(rename = "crate")]
+    krate: Option<syn::Path>,
+    /// The label keys prepended to the labels of every metric of the struct.
+    labels: Option<Vec<LitStr>>,
+    /// If set, generates a static LazyLock, with a SCREAMING_SNAKE_CASE name derived from the
+    /// struct name unless given, e.g. `static = "METRICS"`.
     #[darling(default, rename = "static")]
-    _static: bool,
+    _static: StaticAttr,
+    /// The default registry of the builder, instead of the global default registry. Either a
+    /// `Registry` or a reference to one.
+    registry: Option<syn::Expr>,
+    /// If true, generates a companion trait with one method per accessor, implemented by the
+    /// metrics struct and by a generated no-op struct.
+    #[darling(default, rename = "trait")]
+    _trait: bool,
+    /// If true, generates a `catalog()` function returning the serializable catalog of the
+    /// metrics. Requires the `catalog` feature of `prometric`.
+    #[darling(default)]
+    catalog: bool,
+    /// If true, accessors take no label arguments, and the labels are set with builder-style
+    /// setters on the accessor instead.
+    #[darling(default)]
+    named_labels: bool,
+    /// The policy sanitizing the label values passed to the accessors: `"escape"`, `"replace"` or
+    /// `"reject"`, see `prometric::sanitize`.
+    sanitize: Option<LitStr>,
+    /// The maximum length of the label values passed to the accessors, in bytes. Requires
+    /// `sanitize`.
+    max_label_len: Option<syn::Expr>,
+    /// The casing of the metric names derived from the field identifiers, e.g. `"camelCase"`.
+    rename_all: Option<LitStr>,
+    /// If true, appends the `_total` suffix to the names of counters missing it.
+    #[darling(default)]
+    total_suffix: bool,
+    /// If true, every field is a nested metrics struct keeping its own scope, built with the
+    /// registry and static labels of the composite.
+    #[darling(default)]
+    compose: bool,
+    /// If true, the metrics are unregistered from their registry when the struct is dropped.
+    #[darling(default)]
+    scoped: bool,
+    /// If true, derives `Clone` on the metrics struct. The clones share the same metrics.
+    #[darling(default)]
+    clone: bool,
+    /// If true, implements `prometheus::core::Collector` for the metrics struct, collecting the
+    /// metrics of its fields, so it can be registered in another registry after it's built.
+    #[darling(default)]
+    collector: bool,
+    /// If true, generates `Debug` and `Display` implementations printing the current values of
+    /// the metrics.
+    #[darling(default)]
+    debug: bool,
+    /// If true, generates a `mock()` constructor recording the calls of the accessors. Requires
+    /// the `recording` feature of `prometric`.
+    #[darling(default)]
+    test_support: bool,
+    /// If true, generates `#[cfg(test)]` getters reading the current values of the metrics back,
+    /// e.g. `requests_value("GET")` or `latency_count()`.
+    #[darling(default)]
+    test_utils: bool,
+}
+
+impl MetricsAttr {
+    /// Returns the path of the `prometric` crate in the generated code.
+    fn krate(&self) -> syn::Path {
+        self.krate.clone().unwrap_or_else(default_crate_path)
+    }
+
+    /// Returns an error if a scope is set on a `compose` struct, or struct-level labels, which
+    /// only apply to metrics.
+    fn validate_compose(&self) -> Result<()> {
+        let unsupported = [
+            self.scope.as_ref(),
+            self.namespace.as_ref(),
+            self.subsystem.as_ref(),
+            self.scope_env.as_ref(),
+            self.labels.iter().flatten().next(),
+        ];
+        match unsupported.into_iter().flatten().next() {
+            Some(arg) => Err(syn::Error::new_spanned(
+                arg,
+                "Composite metrics have no scope or labels, set them on the nested structs",
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the `prometric::sanitize::LabelSanitizer` expression of the label values passed to
+    /// the accessors, if any.
+    fn sanitizer(&self) -> Result<Option<TokenStream>> {
+        let krate = self.krate();
+        let Some(sanitize) = &self.sanitize else {
+            return match &self.max_label_len {
+                Some(max_len) => {
+                    Err(syn::Error::new_spanned(max_len, "`max_label_len` requires `sanitize`"))
+                }
+                None => Ok(None),
+            };
+        };
+
+        let policy = match sanitize.value().as_str() {
+            "escape" => quote! { #krate::sanitize::SanitizePolicy::Escape },
+            "replace" => quote! { #krate::sanitize::SanitizePolicy::Replace },
+            "reject" => quote! { #krate::sanitize::SanitizePolicy::Reject },
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    sanitize,
+                    "Unsupported sanitize policy, use \"escape\", \"replace\" or \"reject\"",
+                ));
+            }
+        };
+        let sanitizer = quote! { #krate::sanitize::LabelSanitizer::new(#policy) };
+        Ok(Some(match &self.max_label_len {
+            Some(max_len) => quote! { #sanitizer.with_max_len(#max_len) },
+            None => sanitizer,
+        }))
+    }
+
+    /// Returns the compile-time scope of the metrics: the `scope` or `namespace`, followed by the
+    /// `subsystem` if any. Without either, defaults to the name of the crate, with invalid
+    /// characters replaced by underscores. An empty `scope` leaves the names unprefixed.
+    fn scope(&self) -> Result<String> {
+        if let (Some(_), Some(namespace)) = (&self.scope, &self.namespace) {
+            return Err(syn::Error::new_spanned(
+                namespace,
+                "`scope` and `namespace` are mutually exclusive",
+            ));
+        }
+
+        let segments = [self.scope.as_ref().or(self.namespace.as_ref()), self.subsystem.as_ref()];
+        for segment in segments.iter().flatten().filter(|segment| !segment.value().is_empty()) {
+            validate_metric_name(segment)?;
+        }
+        let scope = match segments[0] {
+            Some(scope) => scope.value(),
+            None => crate_scope()?,
+        };
+
+        Ok(std::iter::once(scope)
+            .chain(segments[1].map(LitStr::value))
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>()
+            .join(DEFAULT_SEPARATOR))
+    }
+
+    /// Returns the casing of the metric names derived from the field identifiers.
+    fn rename_rule(&self) -> Result<RenameRule> {
+        let Some(rename_all) = &self.rename_all else { return Ok(RenameRule::Snake) };
+        match rename_all.value().as_str() {
+            "snake_case" => Ok(RenameRule::Snake),
+            "camelCase" => Ok(RenameRule::Camel),
+            "PascalCase" => Ok(RenameRule::Pascal),
+            "SCREAMING_SNAKE_CASE" => Ok(RenameRule::ScreamingSnake),
+            "kebab-case" | "SCREAMING-KEBAB-CASE" => Err(syn::Error::new_spanned(
+                rename_all,
+                "Dashes aren't valid in Prometheus metric names, use \"snake_case\"",
+            )),
+            other => Err(syn::Error::new_spanned(
+                rename_all,
+                format!(
+                    "Unsupported casing '{other}'. Use snake_case, camelCase, PascalCase or \
+                     SCREAMING_SNAKE_CASE"
+                ),
+            )),
+        }
+    }
+}
+
+/// The casing of the metric names derived from the field identifiers, set with `rename_all`.
+#[derive(Clone, Copy)]
+enum RenameRule {
+    Snake,
+    Camel,
+    Pascal,
+    ScreamingSnake,
+}
+
+impl RenameRule {
+    /// Apply the casing to a snake_case field identifier.
+    fn apply(self, ident: &str) -> String {
+        match self {
+            Self::Snake => ident.to_owned(),
+            Self::Camel => {
+                let pascal = snake_to_pascal(ident);
+                let mut chars = pascal.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_ascii_lowercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            }
+            Self::Pascal => snake_to_pascal(ident),
+            Self::ScreamingSnake => to_screaming_snake(ident),
+        }
+    }
 }
 
 /// A wrapper over [`prometric`] metric types, containing their type path and generic
@@ -32,11 +256,11 @@ pub(super) struct MetricsAttr {
 /// # use syn::parse_str;
 ///
 /// let counter_ty =
-///     MetricType::from_path(parse_str("::prometric::Counter<u64>").unwrap()).unwrap();
+///     MetricType::from_path(parse_str("::prometric::Counter<u64>").unwrap(), &krate).unwrap();
 /// assert!(matches!(counter_ty, MetricType::Counter("::prometric::Counter", u64)));
 ///
 /// let guauge_ty =
-///     MetricType::from_path(parse_str("Gauge").unwrap()).unwrap();
+///     MetricType::from_path(parse_str("Gauge").unwrap(), &krate).unwrap();
 /// assert!(matches!(gauge_ty, MetricType::Gauge("Gauge", ::prometric::GaugeDefault)));
 /// ```
 enum MetricType {
@@ -44,6 +268,12 @@ enum MetricType {
     Gauge(TypePath, Type),
     Histogram(TypePath),
     Summary(TypePath),
+    /// A duration histogram and a call counter, see `prometric::timer`.
+    Timer(TypePath),
+    /// A constant gauge whose labels carry metadata, see `prometric::info`.
+    Info(TypePath),
+    /// A counter with an implicit `outcome` label, see `prometric::outcome`.
+    Outcome(TypePath),
 }
 
 impl std::fmt::Display for MetricType {
@@ -53,6 +283,9 @@ impl std::fmt::Display for MetricType {
             Self::Gauge(_, _) => write!(f, "Gauge"),
             Self::Histogram(_) => write!(f, "Histogram"),
             Self::Summary(_) => write!(f, "Summary"),
+            Self::Timer(_) => write!(f, "Timer"),
+            Self::Info(_) => write!(f, "Info"),
+            Self::Outcome(_) => write!(f, "OutcomeCounter"),
         }
     }
 }
@@ -86,8 +319,9 @@ impl MetricType {
         }
     }
 
-    /// Parse the metric type (and generic argument) from a path segment.
-    fn from_path(mut path: TypePath) -> Result<Self> {
+    /// Parse the metric type (and generic argument) from a path segment, defaulting the generic
+    /// argument to the alias in the given crate path.
+    fn from_path(mut path: TypePath, krate: &syn::Path) -> Result<Self> {
         let last_segment = path.path.segments.last_mut().unwrap();
         let ident = last_segment.ident.clone();
 
@@ -110,16 +344,14 @@ impl MetricType {
         // and `::prometric::CounterDefault` for the generic argument
         match ident.to_string().as_str() {
             "Counter" => {
-                let generic =
-                    maybe_generic.unwrap_or(syn::parse_str("::prometric::CounterDefault").unwrap());
+                let generic = maybe_generic.unwrap_or(syn::parse_quote! { #krate::CounterDefault });
                 // Ensure the stored `path` has the generic argument
                 override_generic_arg(generic.clone(), &mut last_segment.arguments);
 
                 Ok(Self::Counter(path, generic))
             }
             "Gauge" => {
-                let generic =
-                    maybe_generic.unwrap_or(syn::parse_str("::prometric::GaugeDefault").unwrap());
+                let generic = maybe_generic.unwrap_or(syn::parse_quote! { #krate::GaugeDefault });
                 // Ensure the stored `path` has the generic argument
                 override_generic_arg(generic.clone(), &mut last_segment.arguments);
 
@@ -127,19 +359,64 @@ impl MetricType {
             }
             "Histogram" => Ok(Self::Histogram(path)),
             "Summary" => Ok(Self::Summary(path)),
+            "Timer" => Ok(Self::Timer(path)),
+            "Info" => Ok(Self::Info(path)),
+            "OutcomeCounter" => Ok(Self::Outcome(path)),
             other => Err(syn::Error::new_spanned(
                 ident,
-                format!("Unsupported metric type '{other}'. Use Counter, Gauge, or Histogram"),
+                format!(
+                    "Unsupported metric type '{other}'. Use Counter, Gauge, Histogram, Summary, \
+                     Timer, Info or OutcomeCounter, a Ratio with `ratio = (numerator, \
+                     denominator)` or a GaugeFn with `eval = || ...`, or set `kind = \"...\"` for \
+                     a type alias or newtype. State sets are generated by `#[metrics]` on a \
+                     fieldless enum"
+                ),
+            )),
+        }
+    }
+
+    /// Resolve the metric type from an explicit `kind`, for type aliases and newtypes of the core
+    /// metric types implementing `prometric::AsMetric`.
+    fn from_kind(kind: &LitStr, ty: &Type, krate: &syn::Path) -> Result<Self> {
+        let metric: TypePath = syn::parse_quote! { <#ty as #krate::AsMetric>::Metric };
+        let number: Type = syn::parse_quote! { <#metric as #krate::MetricNumber>::Number };
+
+        match kind.value().as_str() {
+            "counter" => Ok(Self::Counter(metric, number)),
+            "gauge" => Ok(Self::Gauge(metric, number)),
+            "histogram" => Ok(Self::Histogram(metric)),
+            "summary" => Ok(Self::Summary(metric)),
+            other => Err(syn::Error::new_spanned(
+                kind,
+                format!(
+                    "Unsupported metric kind '{other}'. Use counter, gauge, histogram or summary"
+                ),
             )),
         }
     }
 
+    /// Returns the type of the handles to the children of the metric, see `Counter::handle`.
+    fn handle_type(&self, krate: &syn::Path) -> TokenStream {
+        match self {
+            Self::Counter(_, ty) => quote! { #krate::CounterHandle<#ty> },
+            Self::Gauge(_, ty) => quote! { #krate::GaugeHandle<#ty> },
+            Self::Histogram(_) => quote! { #krate::HistogramHandle },
+            Self::Summary(_) => quote! { #krate::SummaryHandle },
+            Self::Timer(_) => quote! { #krate::TimerHandle },
+            Self::Info(_) => quote! { #krate::InfoHandle },
+            Self::Outcome(_) => quote! { #krate::OutcomeCounterHandle },
+        }
+    }
+
     fn full_type(&self) -> &TypePath {
         match self {
             Self::Counter(path, _) |
             Self::Gauge(path, _) |
             Self::Histogram(path) |
-            Self::Summary(path) => path,
+            Self::Summary(path) |
+            Self::Timer(path) |
+            Self::Info(path) |
+            Self::Outcome(path) => path,
         }
     }
 
@@ -147,17 +424,28 @@ impl MetricType {
         &self,
         maybe_buckets: Option<syn::Expr>,
         maybe_quantiles: Option<syn::Expr>,
+        krate: &syn::Path,
     ) -> Result<Partitions> {
         match self {
-            MetricType::Counter(_, _) | MetricType::Gauge(_, _) => Ok(Partitions::NotApplicable),
-            MetricType::Histogram(_) => {
+            MetricType::Counter(_, _) |
+            MetricType::Gauge(_, _) |
+            MetricType::Info(_) |
+            MetricType::Outcome(_) => Ok(Partitions::NotApplicable),
+            MetricType::Histogram(_) | MetricType::Timer(_) => {
                 if maybe_quantiles.is_some() {
                     Err(syn::Error::new_spanned(
                         maybe_quantiles,
-                        "Invalid configuration for Histogram: `quantiles` is not a valid option, use `buckets` or switch to Summary.",
+                        format!(
+                            "Invalid configuration for {self}: `quantiles` is not a valid option, use `buckets` or switch to Summary."
+                        ),
                     ))
                 } else {
-                    Ok(maybe_buckets.map(Partitions::Buckets).unwrap_or(Partitions::None))
+                    let Some(buckets) = maybe_buckets else {
+                        return Ok(Partitions::None);
+                    };
+                    let value =
+                        bucket_generator(&buckets, krate)?.unwrap_or_else(|| quote! { #buckets });
+                    Ok(Partitions::Buckets(buckets, value))
                 }
             }
             MetricType::Summary(_) => {
@@ -190,8 +478,8 @@ enum Partitions {
     ///
     /// Examples: Gauge, Counter
     NotApplicable,
-    /// Buckets of a histogram
-    Buckets(syn::Expr),
+    /// Buckets of a histogram, as written and as the expression evaluating to them
+    Buckets(syn::Expr, TokenStream),
     /// Quantiles of a summary
     Quantiles(syn::Expr),
 }
@@ -199,7 +487,15 @@ enum Partitions {
 impl Partitions {
     fn buckets(&self) -> Option<&syn::Expr> {
         match self {
-            Self::Buckets(buckets) => Some(buckets),
+            Self::Buckets(buckets, _) => Some(buckets),
+            _ => None,
+        }
+    }
+
+    /// Returns the expression evaluating to the buckets, expanding bucket generators.
+    fn buckets_value(&self) -> Option<&TokenStream> {
+        match self {
+            Self::Buckets(_, value) => Some(value),
             _ => None,
         }
     }
@@ -212,384 +508,3781 @@ impl Partitions {
     }
 }
 
-/// A builder that builds metric definitions, initializers, accessors and accessor implementations
-/// from #[metric] attributes.
-struct MetricBuilder {
-    identifier: Ident,
-    /// The type of the metric.
-    ty: MetricType,
-    /// The label keys to define for the metric.
-    labels: Option<Vec<String>>,
-    /// The full name of the metric.
-    /// = scope + separator + identifier || rename.
-    full_name: String,
-    /// The doc string of the metric.
-    help: String,
-    /// The buckets of a histogram or the quantiles of a summary.
-    partitions: Partitions,
-}
+/// Expand a bucket generator of the `buckets` argument, `linear(start, width, count)` or
+/// `exponential(start, factor, count)`, into a call to the `prometheus` function generating the
+/// buckets. Returns `None` if the expression isn't a generator.
+///
+/// Literal arguments are validated, as the `prometheus` functions would otherwise fail at runtime.
+fn bucket_generator(expr: &syn::Expr, krate: &syn::Path) -> Result<Option<TokenStream>> {
+    let syn::Expr::Call(call) = expr else {
+        return Ok(None);
+    };
+    let syn::Expr::Path(func) = call.func.as_ref() else {
+        return Ok(None);
+    };
+    let Some(name) = func.path.get_ident().map(|ident| ident.to_string()) else {
+        return Ok(None);
+    };
+    if name != "linear" && name != "exponential" {
+        return Ok(None);
+    }
 
-impl MetricBuilder {
-    fn try_from(field: &Field, scope: &str) -> Result<Self> {
-        let metric_field = MetricField::from_field(field)?;
-        if metric_field.buckets.is_some() && metric_field.quantiles.is_some() {
-            return Err(syn::Error::new_spanned(
-                field,
-                "The attributes `buckets` and `quantiles` are mutually exclusive",
-            ));
+    let args = call.args.iter().collect::<Vec<_>>();
+    let [start, step, count] = args[..] else {
+        return Err(syn::Error::new_spanned(
+            call,
+            format!(
+                "Expected `{name}(start, {}, count)`",
+                if name == "linear" { "width" } else { "factor" }
+            ),
+        ));
+    };
+
+    // Returns the value of a numeric literal argument, if it is one.
+    let literal = |arg: &syn::Expr| match arg {
+        syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Float(lit), .. }) => {
+            lit.base10_parse::<f64>().ok()
+        }
+        syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Int(lit), .. }) => {
+            lit.base10_parse::<f64>().ok()
         }
+        _ => None,
+    };
+    let invalid = |arg: &syn::Expr, message: &str| Err(syn::Error::new_spanned(arg, message));
 
-        // prometheus::Opts requires a non-empty help string
-        // Here we retrieve it from the `help` argument of the `metric`,
-        // falling back to the documentation of the field otherwise
-        let help = metric_field.help.or_else(|| {
-            field
-                .attrs
-                .iter()
-                .find(|attr| attr.path().is_ident("doc"))
-                .map(|attr| {
-                    let syn::Meta::NameValue(value) = &attr.meta else {
-                        return Err(syn::Error::new_spanned(attr, "Expected a doc attribute"));
-                    };
+    if literal(count).is_some_and(|count| count < 1.0) {
+        return invalid(count, "`count` must be positive");
+    }
+    if name == "linear" {
+        if literal(step).is_some_and(|width| width <= 0.0) {
+            return invalid(step, "`width` must be positive");
+        }
 
-                    if let syn::Expr::Lit(lit) = &value.value {
-                        if let syn::Lit::Str(lit) = &lit.lit {
-                            Ok(lit.value().trim().to_string())
-                        } else {
-                            Err(syn::Error::new_spanned(attr, "Expected a string literal"))
-                        }
-                    } else {
-                        Err(syn::Error::new_spanned(attr, "Expected a string literal"))
-                    }
-                })
-                .transpose()
-                .ok()
-                .flatten()
-        });
+        Ok(Some(quote! {
+            #krate::prometheus::linear_buckets(#start, #step, #count).expect("Invalid linear buckets")
+        }))
+    } else {
+        if literal(start).is_some_and(|start| start <= 0.0) {
+            return invalid(start, "`start` must be positive");
+        }
+        if literal(step).is_some_and(|factor| factor <= 1.0) {
+            return invalid(step, "`factor` must be greater than 1");
+        }
 
-        let Some(help) = help else {
+        Ok(Some(quote! {
+            #krate::prometheus::exponential_buckets(#start, #step, #count).expect("Invalid exponential buckets")
+        }))
+    }
+}
+
+/// The units supported by `#[metric(unit = "...")]`: the name, the variant of
+/// `prometric::unit::Unit`, and whether it's a time unit.
+const UNITS: &[(&str, &str, bool)] = &[
+    ("seconds", "Seconds", true),
+    ("milliseconds", "Milliseconds", true),
+    ("microseconds", "Microseconds", true),
+    ("nanoseconds", "Nanoseconds", true),
+    ("bytes", "Bytes", false),
+    ("kilobytes", "Kilobytes", false),
+    ("megabytes", "Megabytes", false),
+    ("gigabytes", "Gigabytes", false),
+];
+
+/// The unit-aware helpers generated for each dimension: the suffix of the helper, the type of its
+/// argument, and the variant of `prometric::unit::Unit` it converts from.
+const TIME_HELPERS: &[(&str, &str, &str)] = &[
+    ("secs", "f64", "Seconds"),
+    ("millis", "u64", "Milliseconds"),
+    ("micros", "u64", "Microseconds"),
+    ("nanos", "u64", "Nanoseconds"),
+];
+const DATA_HELPERS: &[(&str, &str, &str)] = &[("bytes", "u64", "Bytes")];
+
+/// The declared unit of a metric.
+struct MetricUnit {
+    name: String,
+    /// The variant of `prometric::unit::Unit`.
+    variant: Ident,
+    is_time: bool,
+}
+
+impl MetricUnit {
+    fn parse(field: &Field, name: String) -> Result<Self> {
+        let Some((_, variant, is_time)) = UNITS.iter().find(|(unit, _, _)| *unit == name) else {
+            let supported = UNITS.iter().map(|(unit, _, _)| *unit).collect::<Vec<_>>().join(", ");
             return Err(syn::Error::new_spanned(
                 field,
-                "Unable to determine `help` label for metric. Provide an explicit `help` argument to `metric` or document the field",
+                format!("Unsupported unit '{name}'. Use one of: {supported}"),
             ));
         };
 
-        let metric_name = metric_field
-            .rename
-            .as_ref()
-            .unwrap_or(&field.ident.as_ref().unwrap().to_string())
-            .to_owned();
-
-        let full_name = format!("{scope}{DEFAULT_SEPARATOR}{metric_name}");
+        Ok(Self { name, variant: format_ident!("{variant}"), is_time: *is_time })
+    }
 
-        let Type::Path(type_path) = metric_field.ty else {
-            return Err(syn::Error::new_spanned(field, "Expected a path type"));
+    /// Append the unit suffix to the metric name, unless it's already there. The suffix goes
+    /// before a `_total` suffix, e.g. `cpu_seconds_total`.
+    fn with_suffix(&self, metric_name: &str) -> String {
+        let (base, total) = match metric_name.strip_suffix("_total") {
+            Some(base) => (base, "_total"),
+            None => (metric_name, ""),
         };
 
-        let ty = MetricType::from_path(type_path)?;
+        let suffix = format!("{DEFAULT_SEPARATOR}{}", self.name);
+        if base.ends_with(&suffix) {
+            metric_name.to_owned()
+        } else {
+            format!("{base}{suffix}{total}")
+        }
+    }
+}
 
-        let partitions = ty.partitions_for(metric_field.buckets, metric_field.quantiles)?;
+/// The `static` argument of the `metrics` attribute: a flag, or the name of the static.
+#[derive(Debug, Default)]
+enum StaticAttr {
+    #[default]
+    Disabled,
+    /// The name of the static is derived from the struct name, e.g. `APP_METRICS`.
+    Derived,
+    /// The name of the static is given, e.g. `static = "METRICS"`.
+    Named(LitStr),
+}
 
-        Ok(Self {
-            identifier: metric_field
-                .ident
-                .ok_or(syn::Error::new_spanned(field, "Expected an identifier"))?,
-            ty,
-            labels: metric_field
-                .labels
-                .map(|labels| labels.iter().map(|label| label.value()).collect()),
-            partitions,
-            full_name,
-            help,
-        })
+impl FromMeta for StaticAttr {
+    fn from_word() -> darling::Result<Self> {
+        Ok(Self::Derived)
     }
 
-    fn labels(&self) -> Vec<String> {
-        self.labels.clone().unwrap_or_default()
+    fn from_value(value: &syn::Lit) -> darling::Result<Self> {
+        match value {
+            syn::Lit::Bool(lit) if lit.value => Ok(Self::Derived),
+            syn::Lit::Bool(_) => Ok(Self::Disabled),
+            syn::Lit::Str(lit) => Ok(Self::Named(lit.clone())),
+            _ => Err(darling::Error::unexpected_lit_type(value)),
+        }
     }
+}
 
-    /// Build the initializer for the metric field.
-    fn build_initializer(&self) -> TokenStream {
-        let ident = &self.identifier;
-        let help = &self.help;
-        let ty = self.ty.full_type();
-        let name = &self.full_name;
-        let labels = self.labels();
-        let partitions = &self.partitions;
+impl StaticAttr {
+    /// Returns whether a static is generated.
+    fn is_enabled(&self) -> bool {
+        !matches!(self, Self::Disabled)
+    }
 
-        match self.ty {
-            MetricType::Counter(_, _) | MetricType::Gauge(_, _) => quote! {
-                #ident: <#ty>::new(self.registry, #name, #help, &[#(#labels),*], self.labels.clone())
-            },
-            MetricType::Histogram(_) => {
-                let buckets = if let Some(buckets_expr) = partitions.buckets() {
-                    quote! { Some(#buckets_expr.into()) }
-                } else {
-                    quote! { None }
-                };
+    /// Returns the identifier of the static of the given metrics struct, if enabled.
+    fn name(&self, ident: &Ident) -> Result<Option<Ident>> {
+        match self {
+            Self::Disabled => Ok(None),
+            Self::Derived => Ok(Some(format_ident!("{}", to_screaming_snake(&ident.to_string())))),
+            Self::Named(name) => name.parse().map(Some).map_err(|_| {
+                syn::Error::new_spanned(
+                    name,
+                    format!("`{}` isn't a valid identifier for the static", name.value()),
+                )
+            }),
+        }
+    }
+}
 
-                quote! {
-                    #ident: <#ty>::new(self.registry, #name, #help, &[#(#labels),*], self.labels.clone(), #buckets)
-                }
-            }
-            MetricType::Summary(_) => {
-                let quantiles = if let Some(quantiles_expr) = partitions.quantiles() {
-                    quote! { Some(#quantiles_expr.into()) }
-                } else {
-                    quote! { None }
-                };
+/// The threshold of an alert, accepting both integer and float literals.
+#[derive(Debug, Clone, Copy)]
+struct Threshold(f64);
 
-                quote! {
-                    #ident: <#ty>::new(self.registry, #name, #help, &[#(#labels),*], self.labels.clone(), #quantiles)
-                }
-            }
+impl FromMeta for Threshold {
+    fn from_value(value: &syn::Lit) -> darling::Result<Self> {
+        match value {
+            syn::Lit::Int(lit) => lit.base10_parse().map(Self).map_err(Into::into),
+            syn::Lit::Float(lit) => lit.base10_parse().map(Self).map_err(Into::into),
+            _ => Err(darling::Error::unexpected_lit_type(value)),
         }
     }
+}
 
-    fn accessor_doc(&self, labels: &[String]) -> String {
-        let help = &self.help;
-        let mut doc_builder = format!(
-            "{help}\n\
-            * Metric type: [`::prometric::{}`]",
-            self.ty,
-        );
+/// The `alert(...)` argument of the `metric` attribute. Exactly one condition must be set.
+#[derive(FromMeta, Debug, Default)]
+struct AlertAttr {
+    /// Fire when the per-second rate of the metric is greater than the threshold.
+    rate_gt: Option<Threshold>,
+    /// Fire when the per-second rate of the metric is less than the threshold.
+    rate_lt: Option<Threshold>,
+    /// Fire when the value of the metric is greater than the threshold.
+    value_gt: Option<Threshold>,
+    /// Fire when the value of the metric is less than the threshold.
+    value_lt: Option<Threshold>,
+    /// How long the condition must hold before the alert fires.
+    #[darling(rename = "for")]
+    for_duration: Option<String>,
+}
 
-        if !labels.is_empty() {
-            doc_builder.push_str(&format!("\n* Labels: {}\n", labels.join(", ")));
-        }
+impl AlertAttr {
+    /// Returns the tokens of the `prometric::rules::AlertCondition` of this alert.
+    fn condition(&self, field: &Field, ty: &MetricType, krate: &syn::Path) -> Result<TokenStream> {
+        let conditions = [
+            self.rate_gt.map(|Threshold(threshold)| (quote! { RateGt }, threshold, true)),
+            self.rate_lt.map(|Threshold(threshold)| (quote! { RateLt }, threshold, true)),
+            self.value_gt.map(|Threshold(threshold)| (quote! { ValueGt }, threshold, false)),
+            self.value_lt.map(|Threshold(threshold)| (quote! { ValueLt }, threshold, false)),
+        ];
 
-        match self.ty {
-            MetricType::Counter(_, _) | MetricType::Gauge(_, _) => {}
-            MetricType::Histogram(_) => {
-                if let Some(buckets_expr) = self.partitions.buckets() {
-                    doc_builder.push_str(&format!("\n* Buckets: {}", quote! { #buckets_expr }));
-                } else {
-                    doc_builder
-                        .push_str("\n* Buckets: [`::prometric::prometheus::DEFAULT_BUCKETS`]");
-                }
-            }
-            MetricType::Summary(_) => {
-                if let Some(quantiles_expr) = self.partitions.quantiles() {
-                    doc_builder.push_str(&format!("\n* Quantiles: {}", quote! { #quantiles_expr }));
-                } else {
-                    doc_builder
-                        .push_str("\n* Buckets: [`::prometric::summary::DEFAULT_QUANTILES`]");
-                }
-            }
+        let mut conditions = conditions.into_iter().flatten();
+        let (Some((variant, threshold, is_rate)), None) = (conditions.next(), conditions.next())
+        else {
+            return Err(syn::Error::new_spanned(
+                field,
+                "`alert` requires exactly one of `rate_gt`, `rate_lt`, `value_gt` or `value_lt`",
+            ));
+        };
+
+        if !is_rate && !matches!(ty, MetricType::Gauge(_, _)) {
+            return Err(syn::Error::new_spanned(
+                field,
+                format!(
+                    "Invalid alert for {ty}: `value_gt` and `value_lt` are only valid for Gauge, use `rate_gt` or `rate_lt`"
+                ),
+            ));
         }
 
-        doc_builder
+        Ok(quote! { #krate::rules::AlertCondition::#variant(#threshold) })
     }
+}
 
-    /// Build the accessor definition and implementation for the metric field.
-    fn build_accessor(&self, vis: &syn::Visibility) -> (TokenStream, TokenStream) {
-        let ident = &self.identifier;
-        let labels = self.labels();
-        let ty = self.ty.full_type();
+/// A label of the `labels` argument: either a string literal, or an identifier bound to a type
+/// implementing `prometric::LabelValue` (`name: Type`).
+struct LabelSpec {
+    /// The name of the label, spanned at its declaration.
+    name: LitStr,
+    ty: Option<Type>,
+}
 
-        let accessor_name = format_ident!("{}Accessor", snake_to_pascal(&ident.to_string()));
+impl syn::parse::Parse for LabelSpec {
+    fn parse(input: syn::parse::ParseStream) -> Result<Self> {
+        if input.peek(LitStr) {
+            let name = input.parse::<LitStr>()?;
+            return Ok(Self { name, ty: None });
+        }
 
-        let label_definitions = labels.iter().map(|label| {
-            let label_ident = format_ident!("{label}");
-            quote! { #label_ident: String }
-        });
+        let ident = input.parse::<Ident>()?;
+        let name = LitStr::new(&ident.to_string(), ident.span());
+        input.parse::<syn::Token![:]>()?;
+        Ok(Self { name, ty: Some(input.parse()?) })
+    }
+}
 
-        let label_arguments = labels.iter().map(|label| {
-            let label_ident = format_ident!("{label}");
-            quote! { #label_ident: impl Into<String> }
-        });
+/// The labels of a metric given with `labels = Type` or `label_names = CONST` instead of an inline
+/// array.
+enum LabelSource {
+    /// A type implementing `prometric::LabelSet`, passed to the accessor, e.g.
+    /// `labels = RequestLabels`.
+    Set(Type),
+    /// A `&[&str]` const of label names, e.g. `label_names = COMMON_LABELS`. The accessor takes an
+    /// array of label values of the same length.
+    Const(syn::Path),
+}
 
-        let def_doc = format!("Accessor for the `{ident}` metric.");
-        let definition = quote! {
-            #[doc = #def_doc]
-            #vis struct #accessor_name<'a> {
-                inner: &'a #ty,
-                #(#label_definitions),*
-            }
-        };
+impl LabelSource {
+    /// Returns the label names expression.
+    fn names(&self, krate: &syn::Path) -> TokenStream {
+        match self {
+            Self::Set(set) => quote! { <#set as #krate::LabelSet>::NAMES },
+            Self::Const(names) => quote! { #names },
+        }
+    }
 
-        let accessor_doc = self.accessor_doc(&labels);
+    /// Returns the type of the labels argument of the accessor.
+    fn argument_ty(&self, krate: &syn::Path) -> TokenStream {
+        match self {
+            Self::Set(set) => quote! { #set },
+            Self::Const(names) => quote! { [impl #krate::ToLabelValue; #names.len()] },
+        }
+    }
 
-        let label_assignments = labels.iter().map(|label| {
-            let label_ident = format_ident!("{label}");
-            quote! { #label_ident: #label_ident.into() }
-        });
+    /// Returns the type of the labels stored in the accessor.
+    fn field_ty(&self) -> TokenStream {
+        match self {
+            Self::Set(set) => quote! { #set },
+            Self::Const(names) => quote! { [String; #names.len()] },
+        }
+    }
 
-        let accessor = quote! {
-            #[doc = #accessor_doc]
-            #[must_use = "This doesn't do anything unless the metric value is changed"]
-            #vis fn #ident(&self, #(#label_arguments),*) -> #accessor_name {
-                #accessor_name {
-                    inner: &self.#ident,
-                    #(#label_assignments),*
-                }
+    /// Returns the assignment of the labels argument to the accessor field.
+    fn assignment(&self, krate: &syn::Path) -> TokenStream {
+        match self {
+            Self::Set(_) => quote! { labels },
+            Self::Const(_) => {
+                quote! { labels: labels.map(#krate::ToLabelValue::to_label_value) }
             }
-        };
-
-        (definition, accessor)
+        }
     }
 
-    fn build_accessor_impl(&self, vis: &syn::Visibility) -> TokenStream {
-        let ident = &self.identifier;
-        let labels = self.labels();
-        let ty = &self.ty;
-
-        let accessor_name = format_ident!("{}Accessor", snake_to_pascal(&ident.to_string()));
-        let label_idents = labels.iter().map(|label| format_ident!("{label}"));
+    /// Returns the expression of the label values of the accessor, as `impl AsRef<[&str]>`.
+    fn values(&self, krate: &syn::Path) -> TokenStream {
+        match self {
+            Self::Set(_) => quote! { #krate::LabelSet::values(&self.labels) },
+            Self::Const(_) => quote! { self.labels.each_ref().map(String::as_str) },
+        }
+    }
 
-        let labels_array = if labels.is_empty() {
-            quote! { let labels = &[]; }
-        } else {
-            quote! { let labels = &[#(self.#label_idents.as_str()),*]; }
+    /// Returns the description of the labels in the accessor documentation.
+    fn doc(&self) -> String {
+        let tokens = match self {
+            Self::Set(set) => quote! { #set },
+            Self::Const(names) => quote! { #names },
         };
+        format!("`{}`", tokens.to_string().replace(' ', ""))
+    }
+}
 
-        let terminal_methods = match ty {
-            MetricType::Counter(_, counter_ty) => quote! {
-                #vis fn inc(&self) {
-                    #labels_array
-                    self.inner.inc(labels);
-                }
+/// The labels of the `labels` and `label_names` arguments of the metric attribute that can't be
+/// parsed by darling.
+#[derive(Default)]
+struct ExtractedLabels {
+    /// The labels bound to a type implementing `prometric::LabelValue`, by name.
+    typed: Vec<(String, Type)>,
+    /// The label set type or the label names const, if given instead of an array.
+    source: Option<LabelSource>,
+}
 
-                #vis fn inc_by<V>(&self, value: V)
-                where
-                    V: ::prometric::IntoAtomic<#counter_ty>,
+/// Extract the typed labels or the label set of the `labels` argument of the metric attribute, or
+/// the label names const of its `label_names` argument.
+///
+/// `name: Type` is not a valid expression, so it can't be parsed by darling. The `labels` array of
+/// the returned field is rewritten to only contain string literals, and a label set type or a
+/// label names const is removed from the attribute.
+fn extract_labels(field: &Field) -> Result<(Field, ExtractedLabels)> {
+    let mut field = field.clone();
+    let mut extracted = ExtractedLabels::default();
+
+    for attr in field.attrs.iter_mut().filter(|attr| attr.path().is_ident(METRIC_ATTR_NAME)) {
+        let syn::Meta::List(list) = &mut attr.meta else {
+            continue;
+        };
+
+        let mut tokens = list.tokens.clone().into_iter().collect::<Vec<_>>();
+        let mut i = 2;
+        while i < tokens.len() {
+            let (proc_macro2::TokenTree::Ident(key), proc_macro2::TokenTree::Punct(eq)) =
+                (&tokens[i - 2], &tokens[i - 1])
+            else {
+                i += 1;
+                continue;
+            };
+            if key != "labels" && key != "label_names" || eq.as_char() != '=' {
+                i += 1;
+                continue;
+            }
+            let is_names = key == "label_names";
+
+            match &tokens[i] {
+                proc_macro2::TokenTree::Group(group)
+                    if !is_names && group.delimiter() == proc_macro2::Delimiter::Bracket =>
                 {
-                    #labels_array
-                    self.inner.inc_by(labels, value.into_atomic());
-                }
+                    let specs = syn::parse::Parser::parse2(
+                        syn::punctuated::Punctuated::<LabelSpec, syn::Token![,]>::parse_terminated,
+                        group.stream(),
+                    )?;
 
-                #vis fn reset(&self) {
-                    #labels_array
-                    self.inner.reset(labels);
-                }
-            },
-            MetricType::Gauge(_, gauge_ty) => quote! {
-                #vis fn inc(&self) {
-                    #labels_array
-                    self.inner.inc(labels);
-                }
+                    let names = specs.iter().map(|spec| &spec.name);
+                    let mut normalized = proc_macro2::Group::new(
+                        proc_macro2::Delimiter::Bracket,
+                        quote! { #(#names),* },
+                    );
+                    normalized.set_span(group.span());
+                    tokens[i] = normalized.into();
 
-                #vis fn dec(&self) {
-                    #labels_array
-                    self.inner.dec(labels);
+                    extracted.typed.extend(
+                        specs.into_iter().filter_map(|spec| Some((spec.name.value(), spec.ty?))),
+                    );
+                    i += 1;
                 }
+                _ => {
+                    // A label set type or a const, up to the next top-level comma. Commas between
+                    // generic arguments are skipped.
+                    let mut end = i;
+                    let mut depth = 0usize;
+                    while end < tokens.len() {
+                        if let proc_macro2::TokenTree::Punct(punct) = &tokens[end] {
+                            match punct.as_char() {
+                                '<' => depth += 1,
+                                '>' => depth = depth.saturating_sub(1),
+                                ',' if depth == 0 => break,
+                                _ => {}
+                            }
+                        }
+                        end += 1;
+                    }
 
-                #vis fn add<V>(&self, value: V)
-                where
-                    V: ::prometric::IntoAtomic<#gauge_ty>,
-                {
-                    #labels_array
-                    self.inner.add(labels, value.into_atomic());
+                    let value = tokens[i..end].iter().cloned().collect::<TokenStream>();
+                    let source = if is_names {
+                        LabelSource::Const(syn::parse2(value)?)
+                    } else {
+                        // A SCREAMING_SNAKE_CASE path is most likely a const of label names, e.g.
+                        // `COMMON_LABELS`, while acronyms like `RPC` may be types.
+                        let const_path =
+                            syn::parse2::<syn::Path>(value.clone()).ok().filter(|path| {
+                                path.segments
+                                    .last()
+                                    .is_some_and(|last| is_screaming_snake(&last.ident.to_string()))
+                            });
+                        if let Some(path) = const_path {
+                            let path = quote! { #path }.to_string().replace(' ', "");
+                            return Err(syn::Error::new_spanned(
+                                value,
+                                format!(
+                                    "`labels` takes a label set type, use `label_names = {path}` \
+                                     for a `&[&str]` const"
+                                ),
+                            ));
+                        }
+                        LabelSource::Set(syn::parse2(value)?)
+                    };
+                    if extracted.source.replace(source).is_some() {
+                        return Err(syn::Error::new_spanned(
+                            &tokens[i - 2],
+                            "A label set and a `label_names` const are mutually exclusive",
+                        ));
+                    }
+
+                    // Remove the argument, and its trailing comma if any.
+                    let end = (end + 1).min(tokens.len());
+                    tokens.drain(i - 2..end);
                 }
+            }
+        }
 
-                #vis fn sub<V>(&self, value: V)
-                where
-                    V: ::prometric::IntoAtomic<#gauge_ty>,
-                {
-                    #labels_array
-                    self.inner.sub(labels, value.into_atomic());
+        list.tokens = tokens.into_iter().collect();
+    }
+
+    Ok((field, extracted))
+}
+
+/// Validate that a `scope` or `rename` value is a valid Prometheus metric name. Invalid names
+/// would otherwise panic at runtime when building the metrics.
+fn validate_metric_name(name: &LitStr) -> Result<()> {
+    if is_valid_metric_name(&name.value()) {
+        return Ok(());
+    }
+
+    Err(syn::Error::new_spanned(
+        name,
+        format!("Invalid metric name `{}`, must match `[a-zA-Z_:][a-zA-Z0-9_:]*`", name.value()),
+    ))
+}
+
+/// Validate that a label name is a valid Prometheus label name, and doesn't use the reserved `__`
+/// prefix. Invalid names would otherwise panic at runtime when building the metrics.
+fn validate_label_name(name: &LitStr) -> Result<()> {
+    let value = name.value();
+    if value.starts_with("__") {
+        return Err(syn::Error::new_spanned(
+            name,
+            format!("Invalid label name `{value}`, the `__` prefix is reserved"),
+        ));
+    }
+    if !is_valid_label_name(&value) {
+        return Err(syn::Error::new_spanned(
+            name,
+            format!("Invalid label name `{value}`, must match `[a-zA-Z_][a-zA-Z0-9_]*`"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Parse the `const_labels` argument of the metric attribute, an array of `(key, value)` tuples of
+/// string literals.
+fn parse_const_labels(array: &syn::ExprArray) -> Result<Vec<(String, String)>> {
+    let lit_str = |expr: &syn::Expr| match expr {
+        syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(lit), .. }) => Ok(lit.clone()),
+        _ => Err(syn::Error::new_spanned(expr, "Expected a string literal")),
+    };
+
+    array
+        .elems
+        .iter()
+        .map(|elem| match elem {
+            syn::Expr::Tuple(tuple) if tuple.elems.len() == 2 => {
+                let key = lit_str(&tuple.elems[0])?;
+                validate_label_name(&key)?;
+                Ok((key.value(), lit_str(&tuple.elems[1])?.value()))
+            }
+            _ => Err(syn::Error::new_spanned(elem, "Expected a `(key, value)` tuple")),
+        })
+        .collect()
+}
+
+/// A builder that builds metric definitions, initializers, accessors and accessor implementations
+/// from #[metric] attributes.
+struct MetricBuilder {
+    identifier: Ident,
+    /// The type of the metric.
+    ty: MetricType,
+    /// The label keys to define for the metric.
+    labels: Option<Vec<String>>,
+    /// The labels bound to a type implementing `prometric::LabelValue`, by name.
+    typed_labels: Vec<(String, Type)>,
+    /// The label set type or the label names const to use instead of positional labels, if any.
+    label_source: Option<LabelSource>,
+    /// The full name of the metric.
+    /// = scope + separator + identifier || rename.
+    full_name: String,
+    /// The name of the metric without the scope, used when the scope is overridden at runtime.
+    /// = identifier || rename.
+    name: String,
+    /// Whether the name of the metric skips the scope, with `#[metric(absolute)]`.
+    absolute: bool,
+    /// The visibility of the accessor, overriding the visibility of the struct.
+    vis: Option<syn::Visibility>,
+    /// The constant labels of the metric, as key-value pairs.
+    const_labels: Vec<(String, String)>,
+    /// The doc string of the metric.
+    help: String,
+    /// The buckets of a histogram or the quantiles of a summary.
+    partitions: Partitions,
+    /// The alert condition and `for` duration of the metric, if any.
+    alert: Option<(TokenStream, Option<String>)>,
+    /// The declared unit of the metric, if any.
+    unit: Option<MetricUnit>,
+    /// The initial value of the metric, if any.
+    init: Option<syn::Expr>,
+    /// Whether updates are buffered per thread.
+    local: bool,
+    /// Whether the accessor records exemplars, see `prometric::exemplar`.
+    exemplars: bool,
+    /// Whether the creation times of the series are tracked, see `prometric::created`.
+    created: bool,
+    /// The `prometric::cardinality::CardinalityLimit` expression of the metric, if any.
+    cardinality_limit: Option<TokenStream>,
+    /// The deprecated alias of the metric, if any, scoped like its name.
+    deprecated_alias: Option<String>,
+    /// The function modifying the options of the metric, if any.
+    opts: Option<syn::Expr>,
+    /// Whether the field wraps the metric, with an explicit `kind`.
+    wrapped: bool,
+    /// Whether the field is an `Option`, only initialized when enabled with the builder.
+    optional: bool,
+    /// The metric type of an `Arc` field with `shared`, which can be supplied with the builder.
+    shared: Option<Type>,
+    /// Whether the labels are set with setters on the accessor instead of accessor arguments.
+    named_labels: bool,
+    /// The `prometric::sanitize::LabelSanitizer` expression of the label values passed to the
+    /// accessor, if any.
+    sanitizer: Option<TokenStream>,
+    /// Whether `#[cfg(test)]` getters reading the current values back are generated.
+    test_utils: bool,
+    /// The path of the `prometric` crate in the generated code.
+    krate: syn::Path,
+}
+
+impl MetricBuilder {
+    fn try_from(
+        field: &Field,
+        scope: &str,
+        struct_labels: &[String],
+        metrics_attr: &MetricsAttr,
+    ) -> Result<Self> {
+        let krate = metrics_attr.krate();
+        let (normalized, extracted_labels) = extract_labels(field)?;
+        let metric_field = MetricField::from_field(&normalized)?;
+        if let Some(source) =
+            extracted_labels.source.as_ref().filter(|_| metric_field.labels.is_some())
+        {
+            let source = match source {
+                LabelSource::Set(_) => "A label set",
+                LabelSource::Const(_) => "A `label_names` const",
+            };
+            return Err(syn::Error::new_spanned(
+                field,
+                format!("{source} and a `labels` array are mutually exclusive"),
+            ));
+        }
+        if metric_field.buckets.is_some() && metric_field.quantiles.is_some() {
+            return Err(syn::Error::new_spanned(
+                field,
+                "The attributes `buckets` and `quantiles` are mutually exclusive",
+            ));
+        }
+
+        // prometheus::Opts requires a non-empty help string
+        // Here we retrieve it from the `help` argument of the `metric`,
+        // falling back to the documentation of the field otherwise
+        let help = metric_field.help.or_else(|| doc_help(&field.attrs));
+
+        let Some(help) = help else {
+            return Err(syn::Error::new_spanned(
+                field,
+                "Unable to determine `help` label for metric. Provide an explicit `help` argument to `metric` or document the field",
+            ));
+        };
+
+        let metric_name = match &metric_field.rename {
+            Some(rename) => {
+                validate_metric_name(rename)?;
+                rename.value()
+            }
+            None => metrics_attr.rename_rule()?.apply(&field.ident.as_ref().unwrap().to_string()),
+        };
+
+        // `Option<Metric>` fields are only initialized when enabled with the builder.
+        let (metric_ty, optional) = match option_inner(&metric_field.ty) {
+            Some(inner) => (inner.clone(), true),
+            None => (metric_field.ty.clone(), false),
+        };
+        // `Arc<Metric>` fields with `shared` can use a metric supplied with the builder.
+        let (metric_ty, shared) = match arc_inner(&metric_ty).filter(|_| metric_field.shared) {
+            Some(inner) => (inner.clone(), Some(inner.clone())),
+            None if metric_field.shared => {
+                return Err(syn::Error::new_spanned(
+                    &metric_field.ty,
+                    "`shared` requires an `Arc<Counter>` or `Arc<Histogram>` field",
+                ));
+            }
+            None => (metric_ty, None),
+        };
+        let ty = match &metric_field.kind {
+            Some(kind) => MetricType::from_kind(kind, &metric_ty, &krate)?,
+            None => {
+                let Type::Path(type_path) = metric_ty else {
+                    return Err(syn::Error::new_spanned(field, "Expected a path type"));
+                };
+                MetricType::from_path(type_path, &krate)?
+            }
+        };
+
+        // The metrics of a timer have fixed names and units, info metrics are constant, and outcome
+        // counters count operations.
+        let fixed_kind = match ty {
+            MetricType::Timer(_) => Some("timers"),
+            MetricType::Info(_) => Some("info metrics"),
+            MetricType::Outcome(_) => Some("outcome counters"),
+            _ => None,
+        };
+        if let Some(kind) = fixed_kind {
+            let unsupported = [
+                ("unit", metric_field.unit.is_some()),
+                ("init", metric_field.init.is_some()),
+                ("alert", metric_field.alert.is_some()),
+                ("local", metric_field.local),
+                ("exemplars", metric_field.exemplars),
+                ("shared", shared.is_some()),
+                ("max_cardinality", metric_field.max_cardinality.is_some()),
+                ("deprecated_alias", metric_field.deprecated_alias.is_some()),
+                ("opts", metric_field.opts.is_some()),
+            ];
+            if let Some((name, _)) = unsupported.iter().find(|(_, set)| *set) {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    format!("`{name}` isn't supported on {kind}"),
+                ));
+            }
+        }
+
+        let partitions = ty.partitions_for(metric_field.buckets, metric_field.quantiles, &krate)?;
+
+        let alert = metric_field
+            .alert
+            .map(|alert| {
+                Ok::<_, syn::Error>((alert.condition(field, &ty, &krate)?, alert.for_duration))
+            })
+            .transpose()?;
+
+        let unit = metric_field.unit.map(|unit| MetricUnit::parse(field, unit)).transpose()?;
+        let help = render_help(help, field, scope, unit.as_ref().map(|unit| unit.name.as_str()))?;
+        let metric_name = match &unit {
+            Some(unit) => unit.with_suffix(&metric_name),
+            None => metric_name,
+        };
+        // The `_total` suffix follows the unit suffix, e.g. `cpu_seconds_total`.
+        let metric_name = match ty {
+            MetricType::Counter(_, _) | MetricType::Outcome(_)
+                if metrics_attr.total_suffix && !metric_name.ends_with("_total") =>
+            {
+                format!("{metric_name}_total")
+            }
+            _ => metric_name,
+        };
+        let full_name = if metric_field.absolute {
+            metric_name.clone()
+        } else {
+            join_name(scope, &metric_name)
+        };
+
+        for label in metric_field.labels.iter().flatten() {
+            validate_label_name(label)?;
+        }
+
+        // Struct-level labels are prepended to the labels of the field.
+        let field_labels = metric_field
+            .labels
+            .map(|labels| labels.iter().map(|label| label.value()).collect::<Vec<_>>());
+        if let Some(label) =
+            field_labels.iter().flatten().find(|label| struct_labels.contains(label))
+        {
+            return Err(syn::Error::new_spanned(
+                field,
+                format!("The label `{label}` is already defined on the struct"),
+            ));
+        }
+        let const_labels = metric_field
+            .const_labels
+            .as_ref()
+            .map(parse_const_labels)
+            .transpose()?
+            .unwrap_or_default();
+        let variable_labels = field_labels.iter().flatten().chain(struct_labels);
+        if let Some(label) = variable_labels
+            .into_iter()
+            .find(|label| const_labels.iter().any(|(key, _)| key == *label))
+        {
+            return Err(syn::Error::new_spanned(
+                field,
+                format!("The label `{label}` can't be both a constant and a variable label"),
+            ));
+        }
+
+        let labels = match field_labels {
+            Some(field_labels) => Some([struct_labels, &field_labels].concat()),
+            None if !struct_labels.is_empty() => Some(struct_labels.to_vec()),
+            None => None,
+        };
+
+        if matches!(ty, MetricType::Outcome(_)) &&
+            labels
+                .iter()
+                .flatten()
+                .chain(const_labels.iter().map(|(key, _)| key))
+                .any(|label| label == "outcome")
+        {
+            return Err(syn::Error::new_spanned(
+                field,
+                "The `outcome` label is implicit on outcome counters",
+            ));
+        }
+
+        if metric_field.local && matches!(ty, MetricType::Gauge(_, _) | MetricType::Summary(_)) {
+            return Err(syn::Error::new_spanned(
+                field,
+                "`local` is only supported on counters and histograms",
+            ));
+        }
+
+        if shared.is_some() && matches!(ty, MetricType::Gauge(_, _) | MetricType::Summary(_)) {
+            return Err(syn::Error::new_spanned(
+                field,
+                "`shared` is only supported on counters and histograms",
+            ));
+        }
+
+        if metric_field.exemplars && matches!(ty, MetricType::Gauge(_, _) | MetricType::Summary(_))
+        {
+            return Err(syn::Error::new_spanned(
+                field,
+                "`exemplars` is only supported on counters and histograms",
+            ));
+        }
+
+        if metric_field.created && !matches!(ty, MetricType::Counter(_, _) | MetricType::Summary(_))
+        {
+            return Err(syn::Error::new_spanned(
+                field,
+                "`created` is only supported on counters and summaries",
+            ));
+        }
+
+        if let Some(max) =
+            metric_field.max_cardinality.as_ref().filter(|_| matches!(ty, MetricType::Summary(_)))
+        {
+            return Err(syn::Error::new_spanned(
+                max,
+                "`max_cardinality` isn't supported on summaries",
+            ));
+        }
+
+        let cardinality_limit = match (&metric_field.max_cardinality, &metric_field.on_overflow) {
+            (Some(max), None) => Some(quote! { #krate::cardinality::CardinalityLimit::new(#max) }),
+            (Some(max), Some(overflow)) => {
+                let overflow = match overflow.value().as_str() {
+                    "other" => quote! { #krate::cardinality::Overflow::Other },
+                    "drop" => quote! { #krate::cardinality::Overflow::Drop },
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            overflow,
+                            "Unsupported overflow, use \"other\" or \"drop\"",
+                        ));
+                    }
+                };
+                Some(quote! {
+                    #krate::cardinality::CardinalityLimit::new(#max).with_overflow(#overflow)
+                })
+            }
+            (None, Some(overflow)) => {
+                return Err(syn::Error::new_spanned(
+                    overflow,
+                    "`on_overflow` requires `max_cardinality`",
+                ));
+            }
+            (None, None) => None,
+        };
+
+        let deprecated_alias = match &metric_field.deprecated_alias {
+            Some(alias) => {
+                validate_metric_name(alias)?;
+                if alias.value() == metric_name {
+                    return Err(syn::Error::new_spanned(
+                        alias,
+                        "`deprecated_alias` must differ from the metric name",
+                    ));
                 }
+                Some(alias.value())
+            }
+            None => None,
+        };
 
-                #vis fn set<V>(&self, value: V)
-                where
-                    V: ::prometric::IntoAtomic<#gauge_ty>,
-                {
-                    #labels_array
-                    self.inner.set(labels, value.into_atomic());
+        if let Some(opts) =
+            metric_field.opts.as_ref().filter(|_| matches!(ty, MetricType::Summary(_)))
+        {
+            return Err(syn::Error::new_spanned(opts, "`opts` isn't supported on summaries"));
+        }
+
+        if let Some(init) = &metric_field.init {
+            if matches!(ty, MetricType::Histogram(_) | MetricType::Summary(_)) {
+                return Err(syn::Error::new_spanned(
+                    init,
+                    "`init` is only supported on counters and gauges",
+                ));
+            }
+            if labels.as_ref().is_some_and(|labels| !labels.is_empty()) ||
+                extracted_labels.source.is_some() ||
+                !extracted_labels.typed.is_empty()
+            {
+                return Err(syn::Error::new_spanned(
+                    init,
+                    "`init` requires a metric without variable labels",
+                ));
+            }
+        }
+
+        Ok(Self {
+            identifier: metric_field
+                .ident
+                .ok_or(syn::Error::new_spanned(field, "Expected an identifier"))?,
+            ty,
+            labels,
+            typed_labels: extracted_labels.typed,
+            label_source: extracted_labels.source,
+            partitions,
+            full_name,
+            name: metric_name,
+            absolute: metric_field.absolute,
+            vis: metric_field.accessor_vis,
+            const_labels,
+            help,
+            alert,
+            unit,
+            init: metric_field.init,
+            local: metric_field.local,
+            exemplars: metric_field.exemplars,
+            created: metric_field.created,
+            cardinality_limit,
+            deprecated_alias,
+            opts: metric_field.opts,
+            wrapped: metric_field.kind.is_some(),
+            optional,
+            shared,
+            named_labels: metrics_attr.named_labels,
+            sanitizer: metrics_attr.sanitizer()?,
+            test_utils: metrics_attr.test_utils,
+            krate,
+        })
+    }
+
+    fn labels(&self) -> Vec<String> {
+        self.labels.clone().unwrap_or_default()
+    }
+
+    /// Returns the condition for the metric to be registered in the builder.
+    fn enabled_condition(&self) -> TokenStream {
+        if self.optional {
+            let key = self.identifier.to_string();
+            quote! { !disabled && self.enabled.contains(#key) }
+        } else {
+            quote! { !disabled }
+        }
+    }
+
+    /// Build the builder method enabling an optional metric.
+    fn build_enable_method(&self, vis: &syn::Visibility) -> Option<TokenStream> {
+        if !self.optional {
+            return None;
+        }
+
+        let ident = &self.identifier;
+        let key = ident.to_string();
+        let method = format_ident!("enable_{ident}");
+        let doc =
+            format!("Enable the optional `{ident}` metric, which isn't registered by default.");
+        Some(quote! {
+            #[doc = #doc]
+            #vis fn #method(mut self, enabled: bool) -> Self {
+                if enabled {
+                    self.enabled.insert(#key);
+                } else {
+                    self.enabled.remove(#key);
                 }
-            },
-            MetricType::Histogram(_) => quote! {
-                #vis fn observe<V>(&self, value: V)
-                where
-                    V: ::prometric::IntoAtomic<f64>,
-                {
-                    #labels_array
-                    self.inner.observe(labels, value.into_atomic());
+                self
+            }
+        })
+    }
+
+    /// Returns the expression of the optional reference to the metric of the field, unwrapping
+    /// the fields with an explicit `kind`.
+    fn field_metric(&self) -> TokenStream {
+        self.field_metric_of(quote! { self })
+    }
+
+    /// Like [`Self::field_metric`], for the field of the given metrics struct expression.
+    fn field_metric_of(&self, metrics: TokenStream) -> TokenStream {
+        let krate = &self.krate;
+        let ident = &self.identifier;
+        // Shared metrics are behind an `Arc`.
+        let metric = if self.shared.is_some() {
+            quote! { #metrics.#ident.as_deref() }
+        } else {
+            quote! { #metrics.#ident.as_ref() }
+        };
+        if self.wrapped {
+            quote! { #metric.map(#krate::AsMetric::as_metric) }
+        } else {
+            metric
+        }
+    }
+
+    /// Build the builder method supplying a shared metric, and the method returning the metric
+    /// to share.
+    fn build_shared_methods(
+        &self,
+        vis: &syn::Visibility,
+        field_vis: &syn::Visibility,
+    ) -> Option<(TokenStream, TokenStream)> {
+        let ty = self.shared.as_ref()?;
+        let ident = &self.identifier;
+        let key = ident.to_string();
+        let with_method = format_ident!("with_shared_{ident}");
+        let with_doc = format!(
+            "Use the given `{ident}` metric instead of registering a new one, to share it with \
+             other instances of the metrics. Get it from an instance with `shared_{ident}()`, \
+             and register a new one if it's `None`."
+        );
+        let method = format_ident!("shared_{ident}");
+        let doc = format!(
+            "Returns the `{ident}` metric, to share it with other instances with \
+             `with_shared_{ident}()`. Returns `None` when the metrics are disabled."
+        );
+        let body = if DISABLED {
+            quote! { None }
+        } else {
+            quote! { self.#ident.clone() }
+        };
+        Some((
+            quote! {
+                #[doc = #with_doc]
+                #vis fn #with_method(
+                    mut self,
+                    metric: impl Into<Option<::std::sync::Arc<#ty>>>,
+                ) -> Self {
+                    if let Some(metric) = metric.into() {
+                        self.shared.insert(#key, Box::new(metric));
+                    }
+                    self
                 }
             },
-            MetricType::Summary(_) => quote! {
-                #vis fn observe<V>(&self, value: V)
-                where
-                    V: ::prometric::IntoAtomic<f64>,
-                {
-                    #labels_array
-                    self.inner.observe(labels, value.into_atomic());
+            quote! {
+                #[doc = #doc]
+                #field_vis fn #method(&self) -> Option<::std::sync::Arc<#ty>> {
+                    #body
                 }
             },
+        ))
+    }
+
+    /// Build the statement clearing the metric in `reset_all`, setting its initial value again.
+    /// Shared metrics are left untouched, as other instances use them.
+    fn build_reset(&self) -> TokenStream {
+        if self.shared.is_some() {
+            return quote! {};
+        }
+        let metric = self.field_metric();
+        let init = match (&self.init, &self.ty) {
+            (Some(init), MetricType::Counter(_, _)) => quote! { metric.inc_by(&[], #init); },
+            (Some(init), _) => quote! { metric.set(&[], #init); },
+            (None, _) => quote! {},
+        };
+        quote! {
+            if let Some(metric) = #metric {
+                metric.clear();
+                #init
+            }
+        }
+    }
+
+    /// Build the statement unregistering the metric in `unregister`.
+    fn build_unregister(&self) -> TokenStream {
+        if self.shared.is_some() {
+            return quote! {};
+        }
+        let metric = self.field_metric();
+        quote! {
+            if let Some(metric) = #metric {
+                metric.unregister(registry);
+            }
+        }
+    }
+
+    /// Build the statement attaching the recorder of the metric in `record_into`.
+    fn build_record_into(&self) -> TokenStream {
+        let metric = self.field_metric();
+        quote! {
+            if let Some(metric) = #metric {
+                metric.record_into(recorder);
+            }
+        }
+    }
+
+    /// Returns whether the metric has variable labels, including struct-level labels.
+    fn has_variable_labels(&self) -> bool {
+        !self.labels().is_empty() || self.label_source.is_some()
+    }
+
+    /// Returns whether the labels of the metric are set with the setters of the accessor.
+    fn has_named_labels(&self) -> bool {
+        self.named_labels && !self.labels().is_empty()
+    }
+
+    /// Returns the type the given label is bound to, if any.
+    fn label_ty(&self, label: &str) -> Option<&Type> {
+        self.typed_labels.iter().find(|(name, _)| name == label).map(|(_, ty)| ty)
+    }
+
+    /// Returns the type of the accessor argument for the given label.
+    fn label_argument_ty(&self, label: &str) -> TokenStream {
+        let krate = &self.krate;
+        match self.label_ty(label) {
+            Some(ty) => quote! { #ty },
+            None => quote! { impl #krate::ToLabelValue },
+        }
+    }
+
+    /// Returns the label names expression passed to the metric constructor.
+    fn label_names(&self) -> TokenStream {
+        let labels = self.labels();
+        match self.label_source.as_ref().map(|source| source.names(&self.krate)) {
+            Some(names) if labels.is_empty() => names,
+            Some(names) => quote! { &[&[#(#labels),*][..], #names].concat() },
+            None => quote! { &[#(#labels),*] },
+        }
+    }
+
+    /// Returns the expression resolving the full name of the metric in the builder, with the scope
+    /// and prefix of the builder. Absolute names skip the scope, but keep the prefix of a parent
+    /// metrics struct.
+    fn runtime_name(&self) -> TokenStream {
+        self.scoped_name(&self.name)
+    }
+
+    /// Returns the runtime name of the metric as registered, with the namespace and subsystem set
+    /// by its `opts`, if any, for the handle of the struct.
+    fn registered_name(&self) -> TokenStream {
+        let krate = &self.krate;
+        let name = self.runtime_name();
+        let (opts_ty, fq_name) = match (&self.opts, &self.ty) {
+            (Some(_), MetricType::Counter(_, _) | MetricType::Gauge(_, _)) => {
+                (quote! { #krate::prometheus::Opts }, quote! { fq_name() })
+            }
+            (Some(_), MetricType::Histogram(_)) => {
+                (quote! { #krate::prometheus::HistogramOpts }, quote! { common_opts.fq_name() })
+            }
+            _ => return name,
+        };
+        let opts = &self.opts;
+        quote! {{
+            fn apply<O>(opts: O, modify: impl FnOnce(O) -> O) -> O {
+                modify(opts)
+            }
+            apply(#opts_ty::new(#name, ""), #opts).#fq_name
+        }}
+    }
+
+    /// Returns the runtime name of the deprecated alias of the metric, if any.
+    fn runtime_alias(&self) -> Option<TokenStream> {
+        self.deprecated_alias.as_ref().map(|alias| self.scoped_name(alias))
+    }
+
+    /// Returns the expression of the given name, scoped like the metric.
+    fn scoped_name(&self, name: &str) -> TokenStream {
+        scoped_name(name, self.absolute, &self.krate)
+    }
+
+    /// Build the initializer for the metric field.
+    fn build_initializer(&self) -> TokenStream {
+        let krate = &self.krate;
+        let ident = &self.identifier;
+        if DISABLED {
+            return quote! { #ident: ::core::marker::PhantomData };
+        }
+
+        let help = &self.help;
+        let ty = self.ty.full_type();
+        let name = self.runtime_name();
+        let labels = self.label_names();
+        let partitions = &self.partitions;
+
+        // The constant labels of the field take precedence over the static labels of the builder.
+        let const_labels = if self.const_labels.is_empty() {
+            quote! { self.labels.clone() }
+        } else {
+            let (keys, values): (Vec<_>, Vec<_>) = self.const_labels.iter().cloned().unzip();
+            quote! {
+                {
+                    let mut labels = self.labels.clone();
+                    #(labels.insert(#keys.to_owned(), #values.to_owned());)*
+                    labels
+                }
+            }
+        };
+
+        let constructor = match self.ty {
+            MetricType::Info(_) | MetricType::Outcome(_) => quote! {
+                <#ty>::new(&self.registry, &#name, #help, #labels, #const_labels)
+            },
+            MetricType::Counter(_, _) | MetricType::Gauge(_, _) => match &self.opts {
+                Some(opts) => quote! {
+                    <#ty>::with_opts(&self.registry, &#name, #help, #labels, #const_labels, #opts)
+                },
+                None => quote! {
+                    <#ty>::new(&self.registry, &#name, #help, #labels, #const_labels)
+                },
+            },
+            MetricType::Histogram(_) | MetricType::Timer(_) => {
+                let buckets = if let Some(buckets_value) = partitions.buckets_value() {
+                    quote! { Some(#buckets_value.into()) }
+                } else {
+                    quote! { None }
+                };
+
+                match &self.opts {
+                    Some(opts) => quote! {
+                        <#ty>::with_opts(
+                            &self.registry, &#name, #help, #labels, #const_labels, #buckets, #opts
+                        )
+                    },
+                    None => quote! {
+                        <#ty>::new(&self.registry, &#name, #help, #labels, #const_labels, #buckets)
+                    },
+                }
+            }
+            MetricType::Summary(_) => {
+                let quantiles = if let Some(quantiles_expr) = partitions.quantiles() {
+                    quote! { Some(#quantiles_expr.into()) }
+                } else {
+                    quote! { None }
+                };
+
+                quote! {
+                    <#ty>::new(&self.registry, &#name, #help, #labels, #const_labels, #quantiles)
+                }
+            }
+        };
+
+        let constructor = match &self.cardinality_limit {
+            Some(limit) => quote! { #constructor.limit_cardinality(&self.registry, #limit) },
+            None => constructor,
+        };
+
+        let constructor = if self.created {
+            quote! { #constructor.with_created() }
+        } else {
+            constructor
+        };
+
+        let constructor = match self.runtime_alias() {
+            Some(alias) => quote! { #constructor.with_alias(&self.registry, &#alias) },
+            None => constructor,
+        };
+
+        // The initial value is set right after registration, so the metric is present before its
+        // first update.
+        let constructor = match (&self.init, &self.ty) {
+            (Some(init), MetricType::Counter(_, _)) => quote! {
+                {
+                    let metric = #constructor;
+                    metric.inc_by(&[], #init);
+                    metric
+                }
+            },
+            (Some(init), _) => quote! {
+                {
+                    let metric = #constructor;
+                    metric.set(&[], #init);
+                    metric
+                }
+            },
+            (None, _) => constructor,
+        };
+
+        let constructor = if self.wrapped {
+            quote! { #krate::AsMetric::from_metric(#constructor) }
+        } else {
+            constructor
+        };
+
+        // A shared metric supplied with the builder is used as is, and a new one registered
+        // otherwise.
+        let constructor = match &self.shared {
+            Some(shared_ty) => {
+                let key = ident.to_string();
+                quote! {
+                    self.shared
+                        .get(#key)
+                        .and_then(|metric| metric.downcast_ref::<::std::sync::Arc<#shared_ty>>())
+                        .cloned()
+                        .unwrap_or_else(|| ::std::sync::Arc::new(#constructor))
+                }
+            }
+            None => constructor,
+        };
+
+        // Nothing is registered when the metrics are disabled at runtime, or when an optional
+        // metric isn't enabled.
+        let enabled = self.enabled_condition();
+        quote! { #ident: (#enabled).then(|| #constructor) }
+    }
+
+    /// Returns the `prometric::descriptor::MetricKind` of the metric. The kind of a timer is the
+    /// kind of its duration histogram.
+    fn kind(&self) -> TokenStream {
+        let krate = &self.krate;
+        match self.ty {
+            MetricType::Counter(_, _) | MetricType::Outcome(_) => {
+                quote! { #krate::descriptor::MetricKind::Counter }
+            }
+            MetricType::Gauge(_, _) | MetricType::Info(_) => {
+                quote! { #krate::descriptor::MetricKind::Gauge }
+            }
+            MetricType::Histogram(_) | MetricType::Timer(_) => {
+                quote! { #krate::descriptor::MetricKind::Histogram }
+            }
+            MetricType::Summary(_) => quote! { #krate::descriptor::MetricKind::Summary },
+        }
+    }
+
+    /// Returns the full name, kind and help of each metric registered for the field: the metric
+    /// itself, or the duration histogram and the call counter of a timer, named like
+    /// `prometric::Timer::new`.
+    fn series(&self) -> Vec<(String, TokenStream, String)> {
+        let krate = &self.krate;
+        let (name, help) = (&self.full_name, &self.help);
+        match self.ty {
+            MetricType::Timer(_) => vec![
+                (
+                    format!("{name}_duration_seconds"),
+                    self.kind(),
+                    format!("{help} The duration of the calls, in seconds."),
+                ),
+                (
+                    format!("{name}_total"),
+                    quote! { #krate::descriptor::MetricKind::Counter },
+                    format!("{help} The total number of calls."),
+                ),
+            ],
+            _ => vec![(name.clone(), self.kind(), help.clone())],
+        }
+    }
+
+    /// Build the `prometric::descriptor::MetricDescriptor` expressions for the metrics of the
+    /// field. The label names only include the variable labels, not the static labels of the
+    /// builder.
+    fn build_descriptors(&self) -> Vec<TokenStream> {
+        let krate = &self.krate;
+        let labels = self.label_names();
+
+        let with_unit = self.unit.as_ref().map(|unit| {
+            let variant = &unit.variant;
+            quote! { .with_unit(#krate::unit::Unit::#variant) }
+        });
+        let with_partitions = match self.ty {
+            MetricType::Counter(_, _) |
+            MetricType::Gauge(_, _) |
+            MetricType::Info(_) |
+            MetricType::Outcome(_) => None,
+            MetricType::Histogram(_) | MetricType::Timer(_) => {
+                Some(match self.partitions.buckets_value() {
+                    Some(buckets) => quote! { .with_buckets(#buckets) },
+                    None => quote! { .with_buckets(#krate::prometheus::DEFAULT_BUCKETS) },
+                })
+            }
+            MetricType::Summary(_) => Some(match self.partitions.quantiles() {
+                Some(quantiles) => quote! { .with_quantiles(#quantiles) },
+                None => quote! { .with_quantiles(#krate::summary::DEFAULT_QUANTILES) },
+            }),
+        };
+
+        // The `outcome` label of outcome counters comes last.
+        let outcome_label = matches!(self.ty, MetricType::Outcome(_))
+            .then(|| quote! { .chain([#krate::OUTCOME_LABEL]) });
+
+        // Only the first metric of a timer, its duration histogram, has buckets.
+        self.series()
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, kind, help))| {
+                let with_partitions = with_partitions.as_ref().filter(|_| i == 0);
+                quote! {
+                    #krate::descriptor::MetricDescriptor::new(#name, #kind, #help, <[&str]>::iter(#labels).copied()#outcome_label)
+                        #with_unit
+                        #with_partitions
+                }
+            })
+            .collect()
+    }
+
+    /// Build the `pub const`s holding the full name and the label names of the metric, e.g.
+    /// `HTTP_REQUESTS_NAME` and `HTTP_REQUESTS_LABELS`.
+    fn build_name_const(&self, vis: &syn::Visibility) -> TokenStream {
+        let krate = &self.krate;
+        let name = &self.full_name;
+        let const_name = format_ident!("{}_NAME", self.identifier.to_string().to_uppercase());
+        let doc = match self.ty {
+            MetricType::Timer(_) => format!(
+                "The base name of the `{}` timer, with the compile-time scope, suffixed with \
+                 `_duration_seconds` and `_total` for its metrics.",
+                self.identifier
+            ),
+            _ => format!(
+                "The full name of the `{}` metric, with the compile-time scope.",
+                self.identifier
+            ),
+        };
+
+        let labels_const_name =
+            format_ident!("{}_LABELS", self.identifier.to_string().to_uppercase());
+        let labels_doc = format!(
+            "The variable label names of the `{}` metric, in accessor order. The static labels of \
+             the builder aren't included.",
+            self.identifier
+        );
+        let labels = self.labels();
+        let count = labels.len();
+        let (labels_ty, labels) = match self.label_source.as_ref().map(|source| source.names(krate))
+        {
+            Some(names) => (
+                quote! { [&'static str; #count + #names.len()] },
+                quote! { #krate::descriptor::concat_labels(&[#(#labels),*], #names) },
+            ),
+            None => (quote! { [&'static str; #count] }, quote! { [#(#labels),*] }),
+        };
+
+        quote! {
+            #[doc = #doc]
+            #vis const #const_name: &'static str = #name;
+
+            #[doc = #labels_doc]
+            #vis const #labels_const_name: #labels_ty = #labels;
+        }
+    }
+
+    /// Build the `prometric::descriptor::MetricMetadata` expressions for the metrics of the field,
+    /// in a const context.
+    fn build_metadata(&self) -> Vec<TokenStream> {
+        let krate = &self.krate;
+        let labels = self.metadata_labels();
+        self.series()
+            .into_iter()
+            .map(|(name, kind, _)| {
+                quote! {
+                    #krate::descriptor::MetricMetadata { name: #name, kind: #kind, labels: #labels }
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the label names of the metric as a `&'static [&'static str]` expression, in a const
+    /// context.
+    fn metadata_labels(&self) -> TokenStream {
+        let krate = &self.krate;
+        let labels = self.labels();
+        match self.label_source.as_ref().map(|source| source.names(krate)) {
+            Some(names) if labels.is_empty() => names,
+            Some(names) => {
+                let count = labels.len();
+                quote! {
+                    {
+                        const LABELS: [&str; #count + #names.len()] =
+                            #krate::descriptor::concat_labels(&[#(#labels),*], #names);
+                        &LABELS
+                    }
+                }
+            }
+            None => quote! { &[#(#labels),*] },
+        }
+    }
+
+    /// Build the `prometric::rules::AlertRule` expression for the metric, if it has an alert.
+    fn build_alert_rule(&self) -> Option<TokenStream> {
+        let krate = &self.krate;
+        let (condition, for_duration) = self.alert.as_ref()?;
+        let name = &self.full_name;
+        let kind = self.kind();
+
+        let with_for = for_duration.as_ref().map(|duration| quote! { .with_for(#duration) });
+
+        Some(quote! {
+            #krate::rules::AlertRule::new(#name, #kind, #condition)#with_for
+        })
+    }
+
+    fn accessor_doc(&self, labels: &[String]) -> String {
+        let help = &self.help;
+        let doc_krate = doc_path(&self.krate);
+        let mut doc_builder = format!(
+            "{help}\n\
+            * Metric type: [`{doc_krate}::{}`]",
+            self.ty,
+        );
+
+        let mut label_docs = labels.to_vec();
+        if let Some(source) = &self.label_source {
+            label_docs.push(source.doc());
+        }
+        if !label_docs.is_empty() {
+            doc_builder.push_str(&format!("\n* Labels: {}\n", label_docs.join(", ")));
+        }
+
+        if !self.const_labels.is_empty() {
+            let const_labels =
+                self.const_labels.iter().map(|(key, value)| format!("{key}={value}"));
+            doc_builder.push_str(&format!(
+                "\n* Const labels: {}\n",
+                const_labels.collect::<Vec<_>>().join(", ")
+            ));
+        }
+
+        if let Some(unit) = &self.unit {
+            doc_builder.push_str(&format!("\n* Unit: {}", unit.name));
+        }
+
+        match self.ty {
+            MetricType::Counter(_, _) | MetricType::Gauge(_, _) | MetricType::Info(_) => {}
+            MetricType::Outcome(_) => {
+                doc_builder.push_str("\n* Outcome label: `outcome`, `success` or `failure`");
+            }
+            MetricType::Histogram(_) | MetricType::Timer(_) => {
+                if let Some(buckets_expr) = self.partitions.buckets() {
+                    doc_builder.push_str(&format!("\n* Buckets: {}", quote! { #buckets_expr }));
+                } else {
+                    doc_builder.push_str(&format!(
+                        "\n* Buckets: [`{doc_krate}::prometheus::DEFAULT_BUCKETS`]"
+                    ));
+                }
+            }
+            MetricType::Summary(_) => {
+                if let Some(quantiles_expr) = self.partitions.quantiles() {
+                    doc_builder.push_str(&format!("\n* Quantiles: {}", quote! { #quantiles_expr }));
+                } else {
+                    doc_builder.push_str(&format!(
+                        "\n* Buckets: [`{doc_krate}::summary::DEFAULT_QUANTILES`]"
+                    ));
+                }
+            }
+        }
+
+        doc_builder
+    }
+
+    /// Build the accessor definition and implementation for the metric field.
+    fn build_accessor(&self, vis: &syn::Visibility) -> (TokenStream, TokenStream) {
+        let ident = &self.identifier;
+        let labels = self.labels();
+        let ty = self.ty.full_type();
+
+        let accessor_name = format_ident!("{}Accessor", snake_to_pascal(&ident.to_string()));
+
+        if DISABLED {
+            return self.build_disabled_accessor(vis, &accessor_name, &labels);
+        }
+
+        // Typed labels are stored as their static label value, avoiding an allocation. Named labels
+        // are unset until their setter is called, and the metric is only pointed to once they are
+        // all set.
+        let label_definitions = labels
+            .iter()
+            .map(|label| {
+                let label_ident = format_ident!("{label}");
+                match (self.label_ty(label), self.named_labels) {
+                    (Some(_), false) => quote! { #label_ident: &'static str },
+                    (None, false) => quote! { #label_ident: String },
+                    (Some(_), true) => quote! { #label_ident: Option<&'static str> },
+                    (None, true) => quote! { #label_ident: Option<String> },
+                }
+            })
+            .chain(self.has_named_labels().then(|| quote! { metric: Option<&'a #ty> }))
+            .chain(self.label_source.as_ref().map(|source| {
+                let field_ty = source.field_ty();
+                quote! { labels: #field_ty }
+            }));
+
+        let (label_idents, label_argument_tys) = self.accessor_arguments();
+
+        let def_doc = format!("Accessor for the `{ident}` metric.");
+        let definition = quote! {
+            #[doc = #def_doc]
+            #vis struct #accessor_name<'a> {
+                inner: Option<&'a #ty>,
+                #(#label_definitions),*
+            }
+        };
+
+        let accessor_doc = self.accessor_doc(&labels);
+
+        let accessor_value = self.build_accessor_value(&accessor_name, self.field_metric());
+        let accessor = quote! {
+            #[doc = #accessor_doc]
+            #[must_use = "This doesn't do anything unless the metric value is changed"]
+            #vis fn #ident(&self, #(#label_idents: #label_argument_tys),*) -> #accessor_name {
+                #accessor_value
+            }
+        };
+
+        (definition, accessor)
+    }
+
+    /// Build the raw accessor definition and implementation for the metric field, taking the values
+    /// of all the labels of the metric as a slice, e.g. when they are assembled dynamically.
+    fn build_raw_accessor(&self, vis: &syn::Visibility) -> (TokenStream, TokenStream) {
+        let ident = &self.identifier;
+        let ty = self.ty.full_type();
+        let raw_accessor_name = format_ident!("{}RawAccessor", snake_to_pascal(&ident.to_string()));
+        let raw_ident = format_ident!("{ident}_with");
+
+        let (def_doc, fields) = if DISABLED {
+            (
+                format!("Raw accessor for the `{ident}` metric. Inert, metrics are disabled."),
+                quote! { _marker: ::core::marker::PhantomData<&'a ()>, },
+            )
+        } else {
+            (
+                format!("Raw accessor for the `{ident}` metric, with borrowed label values."),
+                quote! {
+                    inner: Option<&'a #ty>,
+                    labels: &'a [&'a str],
+                },
+            )
+        };
+        let definition = quote! {
+            #[doc = #def_doc]
+            #vis struct #raw_accessor_name<'a> {
+                #fields
+            }
+        };
+
+        let raw_accessor_value =
+            self.build_raw_accessor_value(&raw_accessor_name, self.field_metric());
+        let accessor_doc = Self::raw_accessor_doc(ident);
+        let accessor = quote! {
+            #[doc = #accessor_doc]
+            #[must_use = "This doesn't do anything unless the metric value is changed"]
+            #vis fn #raw_ident<'a>(&'a self, labels: &'a [&'a str]) -> #raw_accessor_name<'a> {
+                #raw_accessor_value
+            }
+        };
+
+        (definition, accessor)
+    }
+
+    /// Build the handle accessor of the metric field, resolving the labels once and returning a
+    /// handle to the child metric, see `Counter::handle`.
+    fn build_handle_accessor(&self, vis: &syn::Visibility) -> TokenStream {
+        let ident = &self.identifier;
+        let handle_ident = format_ident!("{ident}_handle");
+        let handle_ty = self.ty.handle_type(&self.krate);
+        let (label_idents, label_argument_tys) = self.accessor_arguments();
+        let doc = Self::handle_accessor_doc(ident);
+
+        quote! {
+            #[doc = #doc]
+            #vis fn #handle_ident(&self, #(#label_idents: #label_argument_tys),*) -> #handle_ty {
+                Self::#ident(self, #(#label_idents),*).handle()
+            }
+        }
+    }
+
+    /// Returns the methods of the accessor reading the current value of the series back: the
+    /// suffix of the `test_utils` getter of the struct, the name of the method, shared with the
+    /// core metric, its return type, and its documentation.
+    fn read_methods(&self) -> Vec<(&'static str, Ident, TokenStream, &'static str)> {
+        let krate = &self.krate;
+        let value_doc = "Returns the current value of the series, creating it if it doesn't exist.";
+        let count = (
+            "count",
+            format_ident!("get_sample_count"),
+            quote! { u64 },
+            "Returns the number of observations of the series, creating it if it doesn't exist.",
+        );
+        let sum = (
+            "sum",
+            format_ident!("get_sample_sum"),
+            quote! { f64 },
+            "Returns the sum of the observations of the series, creating it if it doesn't exist.",
+        );
+        match &self.ty {
+            MetricType::Counter(_, counter_ty) => vec![(
+                "value",
+                format_ident!("get"),
+                quote! {
+                    <<#counter_ty as #krate::CounterNumber>::Atomic
+                        as #krate::prometheus::core::Atomic>::T
+                },
+                value_doc,
+            )],
+            MetricType::Gauge(_, gauge_ty) => vec![(
+                "value",
+                format_ident!("get"),
+                quote! {
+                    <<#gauge_ty as #krate::GaugeNumber>::Atomic
+                        as #krate::prometheus::core::Atomic>::T
+                },
+                value_doc,
+            )],
+            MetricType::Histogram(_) | MetricType::Summary(_) => vec![count, sum],
+            MetricType::Timer(_) => {
+                vec![(
+                    "count",
+                    format_ident!("count"),
+                    quote! { u64 },
+                    "Returns the number of calls.",
+                )]
+            }
+            MetricType::Info(_) | MetricType::Outcome(_) => Vec::new(),
+        }
+    }
+
+    /// Build the `#[cfg(test)]` getters of the metric field with `test_utils`, reading the current
+    /// values of the series with the given labels back through the accessor, e.g.
+    /// `requests_value("GET")`. Accessors with named labels only have their read methods.
+    fn build_test_getters(&self, vis: &syn::Visibility) -> TokenStream {
+        if !self.test_utils || self.named_labels {
+            return quote! {};
+        }
+
+        let ident = &self.identifier;
+        let (label_idents, label_argument_tys) = self.accessor_arguments();
+        let getters = self.read_methods().into_iter().map(|(name, method, ret, _)| {
+            let getter = format_ident!("{ident}_{name}");
+            let doc = format!(
+                "Returns the current {name} of [`Self::{ident}`] with the given labels. Generated \
+                 with `#[metrics(test_utils)]`, in tests only."
+            );
+            quote! {
+                #[cfg(test)]
+                #[doc = #doc]
+                #vis fn #getter(&self, #(#label_idents: #label_argument_tys),*) -> #ret {
+                    Self::#ident(self, #(#label_idents),*).#method()
+                }
+            }
+        });
+
+        quote! { #(#getters)* }
+    }
+
+    /// Returns the documentation of the handle accessor method.
+    fn handle_accessor_doc(ident: &Ident) -> String {
+        format!(
+            "Like [`Self::{ident}`], resolving the labels once and returning a handle updating the \
+             metric without the label lookup and allocations, e.g. for hot loops."
+        )
+    }
+
+    /// Build the raw accessor value pointing to the `inner` metric.
+    fn build_raw_accessor_value(
+        &self,
+        raw_accessor_name: &Ident,
+        inner: TokenStream,
+    ) -> TokenStream {
+        if DISABLED {
+            quote! {
+                let _ = labels;
+                #raw_accessor_name { _marker: ::core::marker::PhantomData }
+            }
+        } else {
+            quote! { #raw_accessor_name { inner: #inner, labels } }
+        }
+    }
+
+    /// Returns the documentation of the raw accessor method.
+    fn raw_accessor_doc(ident: &Ident) -> String {
+        format!(
+            "Like [`Self::{ident}`], with the values of all the labels of the metric in order, \
+             including the labels of the struct, as a raw slice. This is an escape hatch for label \
+             values assembled dynamically.\n\n\
+             # Panics\n\
+             Updating the metric panics if the number of values doesn't match the labels."
+        )
+    }
+
+    /// Returns the argument names and types of the accessor method: the labels, followed by the
+    /// label set if any.
+    fn accessor_arguments(&self) -> (Vec<Ident>, Vec<TokenStream>) {
+        // Named labels are set with the setters of the accessor instead.
+        let labels = if self.named_labels { Vec::new() } else { self.labels() };
+        let label_idents = labels
+            .iter()
+            .map(|label| format_ident!("{label}"))
+            .chain(self.label_source.as_ref().map(|_| format_ident!("labels")))
+            .collect();
+        let label_argument_tys = labels
+            .iter()
+            .map(|label| self.label_argument_ty(label))
+            .chain(self.label_source.as_ref().map(|source| source.argument_ty(&self.krate)))
+            .collect();
+
+        (label_idents, label_argument_tys)
+    }
+
+    /// Build the accessor value pointing to the `inner` metric, converting the label arguments.
+    fn build_accessor_value(&self, accessor_name: &Ident, inner: TokenStream) -> TokenStream {
+        let krate = &self.krate;
+        if DISABLED {
+            let (label_idents, _) = self.accessor_arguments();
+            return quote! {
+                let _ = (#(#label_idents),*);
+                #accessor_name { _marker: ::core::marker::PhantomData }
+            };
+        }
+
+        // Untyped label values are sanitized before the accessor is built, and the accessor points
+        // to no metric if one of them is rejected.
+        let mut sanitized = Vec::new();
+        let mut rejections = Vec::new();
+        let mut label_assignments = Vec::new();
+        for label in self.labels() {
+            let label_ident = format_ident!("{label}");
+            let assignment = match (self.label_ty(&label), &self.sanitizer) {
+                _ if self.named_labels => quote! { #label_ident: None },
+                (Some(ty), _) => quote! {
+                    #label_ident: <#ty as #krate::LabelValue>::label_value(&#label_ident)
+                },
+                (None, Some(sanitizer)) => {
+                    sanitized.push(quote! {
+                        let #label_ident = #sanitizer
+                            .sanitize(#krate::ToLabelValue::to_label_value(#label_ident));
+                    });
+                    rejections.push(quote! { #label_ident.is_none() });
+                    quote! { #label_ident: #label_ident.unwrap_or_default() }
+                }
+                (None, None) => quote! {
+                    #label_ident: #krate::ToLabelValue::to_label_value(#label_ident)
+                },
+            };
+            label_assignments.push(assignment);
+        }
+        let label_source_assignment = match (&self.label_source, &self.sanitizer) {
+            (Some(LabelSource::Const(_)), Some(sanitizer)) => {
+                sanitized.push(quote! {
+                    let labels = labels.map(|value| {
+                        #sanitizer.sanitize(#krate::ToLabelValue::to_label_value(value))
+                    });
+                });
+                rejections.push(quote! { labels.iter().any(Option::is_none) });
+                Some(quote! { labels: labels.map(Option::unwrap_or_default) })
+            }
+            (source, _) => source.as_ref().map(|source| source.assignment(krate)),
+        };
+        let inner = if rejections.is_empty() {
+            inner
+        } else {
+            quote! { if #(#rejections)||* { None } else { #inner } }
+        };
+        // The accessor points to no metric until the named labels are set.
+        let (inner, metric) = if self.has_named_labels() {
+            (quote! { None }, Some(quote! { metric: #inner, }))
+        } else {
+            (inner, None)
+        };
+
+        quote! {
+            #(#sanitized)*
+            #accessor_name {
+                inner: #inner,
+                #metric
+                #(#label_assignments,)*
+                #label_source_assignment
+            }
+        }
+    }
+
+    /// Build the methods of the companion trait generated with `#[metrics(trait)]` for the accessor
+    /// and the raw accessor, and their implementations for the metrics struct and for the no-op
+    /// struct, whose accessors point to no metric.
+    fn build_trait_methods(&self) -> Vec<(TokenStream, TokenStream, TokenStream)> {
+        let ident = &self.identifier;
+        let accessor_name = format_ident!("{}Accessor", snake_to_pascal(&ident.to_string()));
+        let (label_idents, label_argument_tys) = self.accessor_arguments();
+        let accessor_doc = self.accessor_doc(&self.labels());
+
+        let signature = quote! {
+            fn #ident(&self, #(#label_idents: #label_argument_tys),*) -> #accessor_name<'_>
+        };
+
+        let trait_method = quote! {
+            #[doc = #accessor_doc]
+            #[must_use = "This doesn't do anything unless the metric value is changed"]
+            #signature;
+        };
+
+        let metrics_method = quote! {
+            #signature {
+                Self::#ident(self, #(#label_idents),*)
+            }
+        };
+
+        let noop_value = self.build_accessor_value(&accessor_name, quote! { None });
+        let noop_method = quote! {
+            #signature {
+                #noop_value
+            }
+        };
+
+        let raw_accessor_name = format_ident!("{}RawAccessor", snake_to_pascal(&ident.to_string()));
+        let raw_ident = format_ident!("{ident}_with");
+        let raw_accessor_doc = Self::raw_accessor_doc(ident);
+        let raw_signature = quote! {
+            fn #raw_ident<'a>(&'a self, labels: &'a [&'a str]) -> #raw_accessor_name<'a>
+        };
+        let raw_noop_value = self.build_raw_accessor_value(&raw_accessor_name, quote! { None });
+
+        let handle_ident = format_ident!("{ident}_handle");
+        let handle_ty = self.ty.handle_type(&self.krate);
+        let handle_accessor_doc = Self::handle_accessor_doc(ident);
+        let handle_signature = quote! {
+            fn #handle_ident(&self, #(#label_idents: #label_argument_tys),*) -> #handle_ty
+        };
+
+        vec![
+            (trait_method, metrics_method, noop_method),
+            (
+                quote! {
+                    #[doc = #handle_accessor_doc]
+                    #handle_signature;
+                },
+                quote! {
+                    #handle_signature {
+                        Self::#handle_ident(self, #(#label_idents),*)
+                    }
+                },
+                quote! {
+                    #handle_signature {
+                        let _ = (#(#label_idents),*);
+                        <#handle_ty>::noop()
+                    }
+                },
+            ),
+            (
+                quote! {
+                    #[doc = #raw_accessor_doc]
+                    #[must_use = "This doesn't do anything unless the metric value is changed"]
+                    #raw_signature;
+                },
+                quote! {
+                    #raw_signature {
+                        Self::#raw_ident(self, labels)
+                    }
+                },
+                quote! {
+                    #raw_signature {
+                        #raw_noop_value
+                    }
+                },
+            ),
+        ]
+    }
+
+    /// Build the inert accessor definition and implementation for the metric field, used when the
+    /// `prometric_disabled` cfg is set. The accessor is zero-sized and the labels are discarded
+    /// without being converted.
+    fn build_disabled_accessor(
+        &self,
+        vis: &syn::Visibility,
+        accessor_name: &Ident,
+        labels: &[String],
+    ) -> (TokenStream, TokenStream) {
+        let ident = &self.identifier;
+        let (label_idents, label_argument_tys) = self.accessor_arguments();
+
+        let def_doc = format!("Accessor for the `{ident}` metric. Inert, metrics are disabled.");
+        let definition = quote! {
+            #[doc = #def_doc]
+            #vis struct #accessor_name<'a> {
+                _marker: ::core::marker::PhantomData<&'a ()>,
+            }
+        };
+
+        let accessor_doc = self.accessor_doc(labels);
+        let accessor_value = self.build_accessor_value(accessor_name, quote! {});
+        let accessor = quote! {
+            #[doc = #accessor_doc]
+            #[must_use = "This doesn't do anything unless the metric value is changed"]
+            #[inline(always)]
+            #vis fn #ident(&self, #(#label_idents: #label_argument_tys),*) -> #accessor_name {
+                #accessor_value
+            }
+        };
+
+        (definition, accessor)
+    }
+
+    /// Build the label setters of the accessor with `#[metrics(named_labels)]`, one per label.
+    /// Empty otherwise.
+    fn build_label_setters(&self, vis: &syn::Visibility) -> TokenStream {
+        let krate = &self.krate;
+        if !self.named_labels {
+            return quote! {};
+        }
+
+        let label_idents =
+            self.labels().iter().map(|label| format_ident!("{label}")).collect::<Vec<_>>();
+        let setters = self.labels().into_iter().map(|label| {
+            let label_ident = format_ident!("{label}");
+            let doc = format!("Set the value of the `{label}` label.");
+            let argument_ty = self.label_argument_ty(&label);
+            let assignment = if DISABLED {
+                quote! { let _ = value; }
+            } else {
+                let assignment = match (self.label_ty(&label), &self.sanitizer) {
+                    (Some(ty), _) => quote! {
+                        self.#label_ident = Some(<#ty as #krate::LabelValue>::label_value(&value));
+                    },
+                    // A rejected value leaves the accessor pointing to no metric.
+                    (None, Some(sanitizer)) => quote! {
+                        let value = #krate::ToLabelValue::to_label_value(value);
+                        match #sanitizer.sanitize(value) {
+                            Some(value) => self.#label_ident = Some(value),
+                            None => self.metric = None,
+                        }
+                    },
+                    (None, None) => quote! {
+                        self.#label_ident = Some(#krate::ToLabelValue::to_label_value(value));
+                    },
+                };
+                // The accessor points to the metric once every label is set.
+                quote! {
+                    #assignment
+                    self.inner = self.metric.filter(|_| #(self.#label_idents.is_some())&&*);
+                }
+            };
+            quote! {
+                #[doc = #doc]
+                #[must_use = "This doesn't do anything unless the metric value is changed"]
+                #vis fn #label_ident(mut self, value: #argument_ty) -> Self {
+                    #assignment
+                    self
+                }
+            }
+        });
+
+        quote! { #(#setters)* }
+    }
+
+    /// Build the inert terminal methods of the accessor, used when the `prometric_disabled` cfg is
+    /// set.
+    fn build_disabled_accessor_impl(&self, vis: &syn::Visibility) -> TokenStream {
+        let krate = &self.krate;
+        let value_method = |name: &str, value_ty: &Type| {
+            let name = format_ident!("{name}");
+            quote! {
+                #[inline(always)]
+                #vis fn #name<V>(&self, value: V)
+                where
+                    V: #krate::IntoAtomic<#value_ty>,
+                {
+                    let _ = value;
+                }
+            }
+        };
+        let unit_method = |name: &str| {
+            let name = format_ident!("{name}");
+            quote! {
+                #[inline(always)]
+                #vis fn #name(&self) {}
+            }
+        };
+
+        let f64_ty: Type = syn::parse_quote!(f64);
+        let handle_ty = self.ty.handle_type(&self.krate);
+        let handle = quote! {
+            #[inline(always)]
+            #vis fn handle(&self) -> #handle_ty {
+                <#handle_ty>::noop()
+            }
+        };
+        let methods = match &self.ty {
+            MetricType::Counter(_, counter_ty) => {
+                let (inc, inc_by, reset) =
+                    (unit_method("inc"), value_method("inc_by", counter_ty), unit_method("reset"));
+                quote! { #inc #inc_by #reset }
+            }
+            MetricType::Gauge(_, gauge_ty) => {
+                let (inc, dec, set_to_current_time) =
+                    (unit_method("inc"), unit_method("dec"), unit_method("set_to_current_time"));
+                let (add, sub, set) = (
+                    value_method("add", gauge_ty),
+                    value_method("sub", gauge_ty),
+                    value_method("set", gauge_ty),
+                );
+                quote! { #inc #dec #add #sub #set #set_to_current_time }
+            }
+            MetricType::Histogram(_) | MetricType::Summary(_) => value_method("observe", &f64_ty),
+            MetricType::Timer(_) => quote! {
+                #[inline(always)]
+                #vis fn observe(&self, duration: ::std::time::Duration) {
+                    let _ = duration;
+                }
+
+                #[inline(always)]
+                #vis fn count(&self) -> u64 {
+                    0
+                }
+            },
+            MetricType::Info(_) => unit_method("set"),
+            MetricType::Outcome(_) => quote! {
+                #[inline(always)]
+                #vis fn inc(&self, outcome: #krate::Outcome) {
+                    let _ = outcome;
+                }
+
+                #[inline(always)]
+                #vis fn success(&self) {}
+
+                #[inline(always)]
+                #vis fn failure(&self) {}
+
+                #[inline(always)]
+                #vis fn record<T, E>(&self, result: ::core::result::Result<T, E>) -> ::core::result::Result<T, E> {
+                    result
+                }
+            },
+        };
+        let exemplar_methods = match &self.ty {
+            _ if !self.exemplars => quote! {},
+            MetricType::Counter(_, counter_ty) => quote! {
+                #[inline(always)]
+                #vis fn inc_with_exemplar(&self, trace_id: &str) {
+                    let _ = trace_id;
+                }
+
+                #[inline(always)]
+                #vis fn inc_by_with_exemplar<V>(&self, value: V, exemplar: &[(&str, &str)])
+                where
+                    V: #krate::IntoAtomic<#counter_ty>,
+                {
+                    let _ = (value, exemplar);
+                }
+            },
+            MetricType::Histogram(_) => quote! {
+                #[inline(always)]
+                #vis fn observe_with_exemplar<V>(&self, value: V, exemplar: &[(&str, &str)])
+                where
+                    V: #krate::IntoAtomic<f64>,
+                {
+                    let _ = (value, exemplar);
+                }
+            },
+            MetricType::Gauge(_, _) |
+            MetricType::Summary(_) |
+            MetricType::Timer(_) |
+            MetricType::Info(_) |
+            MetricType::Outcome(_) => quote! {},
+        };
+        let remove = self.has_variable_labels().then(|| {
+            quote! {
+                #[inline(always)]
+                #vis fn remove(&self) -> bool {
+                    false
+                }
+            }
+        });
+
+        // Timers already count their calls.
+        let read_methods = self
+            .read_methods()
+            .into_iter()
+            .filter(|_| !matches!(self.ty, MetricType::Timer(_)))
+            .map(|(_, method, ret, _)| {
+                quote! {
+                    #[inline(always)]
+                    #vis fn #method(&self) -> #ret {
+                        Default::default()
+                    }
+                }
+            });
+
+        quote! { #methods #exemplar_methods #(#read_methods)* #handle #remove }
+    }
+
+    /// Build the unit-aware helpers of the accessor, converting from another unit of the same
+    /// dimension into the declared unit of the metric. Empty if no unit is declared.
+    fn build_unit_helpers(&self, vis: &syn::Visibility) -> TokenStream {
+        let krate = &self.krate;
+        let Some(unit) = &self.unit else {
+            return quote! {};
+        };
+        let to = &unit.variant;
+
+        let helpers = if unit.is_time { TIME_HELPERS } else { DATA_HELPERS };
+        let helpers = helpers.iter().map(|(suffix, arg_ty, from)| {
+            let cast = (*arg_ty != "f64").then(|| quote! { as f64 });
+            let arg_ty = format_ident!("{arg_ty}");
+            let from = format_ident!("{from}");
+            let (method, store) = match &self.ty {
+                MetricType::Counter(_, ty) => ("inc_by", Some(ty)),
+                MetricType::Gauge(_, ty) => ("set", Some(ty)),
+                MetricType::Histogram(_) | MetricType::Summary(_) | MetricType::Timer(_) => {
+                    ("observe", None)
+                }
+                MetricType::Info(_) | MetricType::Outcome(_) => {
+                    unreachable!("units aren't supported on info metrics and outcome counters")
+                }
+            };
+
+            let name = format_ident!("{method}_{suffix}");
+            let method = format_ident!("{method}");
+            let doc = format!(
+                "Like [`Self::{method}`], with a value in {} converted to {}.",
+                from.to_string().to_lowercase(),
+                unit.name
+            );
+            let value = match store {
+                Some(ty) => quote! { <#ty as #krate::unit::FromUnitValue>::from_unit_value(value) },
+                None => quote! { value },
+            };
+
+            quote! {
+                #[doc = #doc]
+                #[inline]
+                #vis fn #name(&self, value: #arg_ty) {
+                    let value = #krate::unit::Unit::#from.convert(value #cast, #krate::unit::Unit::#to);
+                    self.#method(#value);
+                }
+            }
+        });
+
+        quote! { #(#helpers)* }
+    }
+
+    /// Build the timing methods of a histogram, summary or timer accessor: `start_timer()`
+    /// (histograms only) or `start()` (timers only), returning a guard, `observe_duration()`
+    /// (histograms and summaries only) and `observe_since()`, and `observe_future()` /
+    /// `observe_async()`, timing a future. The elapsed time is observed in the declared time unit
+    /// of the metric, or in seconds. Empty for other metric types, and for metrics with a data
+    /// unit.
+    fn build_timer(&self, vis: &syn::Visibility) -> TokenStream {
+        let krate = &self.krate;
+        if !matches!(
+            self.ty,
+            MetricType::Histogram(_) | MetricType::Summary(_) | MetricType::Timer(_)
+        ) {
+            return quote! {};
+        }
+
+        let observe = match &self.unit {
+            None if matches!(self.ty, MetricType::Timer(_)) => {
+                quote! { self.observe(::std::time::Duration::from_secs_f64(seconds)) }
+            }
+            None => quote! { self.observe(seconds) },
+            Some(unit) if unit.is_time => quote! { self.observe_secs(seconds) },
+            Some(_) => return quote! {},
+        };
+
+        let start_timer = match self.ty {
+            MetricType::Histogram(_) => Some(quote! {
+                /// Start a timer observing the elapsed time when dropped.
+                #vis fn start_timer(self) -> #krate::HistogramTimer<impl FnOnce(f64) + 'a> {
+                    #krate::HistogramTimer::new(move |seconds| #observe)
+                }
+            }),
+            MetricType::Timer(_) => Some(quote! {
+                /// Start timing a call, observed and counted when the returned guard is dropped.
+                #vis fn start(self) -> #krate::HistogramTimer<impl FnOnce(f64) + 'a> {
+                    #krate::HistogramTimer::new(move |seconds| #observe)
+                }
+            }),
+            _ => None,
+        };
+        // Timers already observe durations.
+        let observe_duration = (!matches!(self.ty, MetricType::Timer(_))).then(|| {
+            quote! {
+                /// Observe the duration, converted to seconds or to the declared time unit.
+                #vis fn observe_duration(&self, duration: ::std::time::Duration) {
+                    let seconds = duration.as_secs_f64();
+                    #observe;
+                }
+            }
+        });
+
+        quote! {
+            #start_timer
+            #observe_duration
+
+            /// Observe the time elapsed since the given instant, like `observe_duration`.
+            #vis fn observe_since(&self, start: ::std::time::Instant) {
+                let seconds = start.elapsed().as_secs_f64();
+                #observe;
+            }
+
+            /// Time the future from its first poll to its completion, observing the elapsed time.
+            /// Nothing is observed if the future is dropped before completion.
+            #vis fn observe_future<F>(
+                self,
+                future: F,
+            ) -> impl ::core::future::Future<Output = F::Output> + 'a
+            where
+                F: ::core::future::Future + 'a,
+            {
+                async move {
+                    let start = ::std::time::Instant::now();
+                    let output = future.await;
+                    let seconds = start.elapsed().as_secs_f64();
+                    #observe;
+                    output
+                }
+            }
+
+            /// Time the future returned by the closure, like `observe_future`.
+            #vis fn observe_async<F, Fut>(
+                self,
+                f: F,
+            ) -> impl ::core::future::Future<Output = Fut::Output> + 'a
+            where
+                F: FnOnce() -> Fut + 'a,
+                Fut: ::core::future::Future + 'a,
+            {
+                self.observe_future(async move { f().await })
+            }
+        }
+    }
+
+    /// Build the `track()` method of a gauge accessor, incrementing the gauge and returning a guard
+    /// decrementing it when dropped. Empty for other metric types.
+    fn build_tracker(&self, vis: &syn::Visibility) -> TokenStream {
+        let krate = &self.krate;
+        if !matches!(self.ty, MetricType::Gauge(_, _)) {
+            return quote! {};
+        }
+
+        quote! {
+            /// Increment the gauge, and decrement it when the returned guard is dropped, including
+            /// when unwinding from a panic.
+            #vis fn track(self) -> #krate::GaugeGuard<impl FnOnce() + 'a> {
+                self.inc();
+                #krate::GaugeGuard::new(move || self.dec())
+            }
+        }
+    }
+
+    fn build_accessor_impl(&self, vis: &syn::Visibility) -> (TokenStream, TokenStream) {
+        let krate = &self.krate;
+        let ident = &self.identifier;
+        let labels = self.labels();
+        let ty = &self.ty;
+
+        let accessor_name = format_ident!("{}Accessor", snake_to_pascal(&ident.to_string()));
+        let raw_accessor_name = format_ident!("{}RawAccessor", snake_to_pascal(&ident.to_string()));
+
+        let unit_helpers = self.build_unit_helpers(vis);
+        let timer = self.build_timer(vis);
+        let tracker = self.build_tracker(vis);
+        let label_setters = self.build_label_setters(vis);
+
+        if DISABLED {
+            let terminal_methods = self.build_disabled_accessor_impl(vis);
+            return (
+                quote! {
+                    impl<'a> #accessor_name<'a> {
+                        #label_setters
+                        #terminal_methods
+                        #unit_helpers
+                        #timer
+                        #tracker
+                    }
+                },
+                quote! {
+                    impl<'a> #raw_accessor_name<'a> {
+                        #terminal_methods
+                        #unit_helpers
+                        #timer
+                        #tracker
+                    }
+                },
+            );
+        }
+        // Named labels are all set when the accessor points to a metric.
+        let label_values = labels.iter().map(|label| {
+            let label_ident = format_ident!("{label}");
+            match (self.label_ty(label), self.named_labels) {
+                (Some(_), false) => quote! { self.#label_ident },
+                (None, false) => quote! { self.#label_ident.as_str() },
+                (Some(_), true) => quote! { self.#label_ident.unwrap_or_default() },
+                (None, true) => quote! { self.#label_ident.as_deref().unwrap_or_default() },
+            }
+        });
+
+        let source_values = self.label_source.as_ref().map(|source| source.values(krate));
+        let labels_array = if source_values.is_some() && labels.is_empty() {
+            quote! {
+                let values = #source_values;
+                let labels = values.as_ref();
+            }
+        } else if source_values.is_some() {
+            quote! {
+                let values = #source_values;
+                let labels = &[&[#(#label_values),*][..], values.as_ref()].concat();
+            }
+        } else if labels.is_empty() {
+            quote! { let labels = &[]; }
+        } else {
+            quote! { let labels = &[#(#label_values),*]; }
+        };
+
+        // The accessor points to no metric when the metrics are disabled at runtime, and in the
+        // no-op implementation of the companion trait.
+        let inner_binding = quote! { let Some(inner) = self.__metric() else { return }; };
+
+        // Updates of local metrics are buffered per thread.
+        let (inc, inc_by, observe) = if self.local {
+            (quote! { local_inc }, quote! { local_inc_by }, quote! { local_observe })
+        } else {
+            (quote! { inc }, quote! { inc_by }, quote! { observe })
+        };
+
+        // Exemplars are recorded on the shared metric, bypassing thread-local buffering.
+        let exemplar_methods = |labels_array: &TokenStream| match ty {
+            _ if !self.exemplars => quote! {},
+            MetricType::Counter(_, counter_ty) => quote! {
+                /// Increment the counter, recording the trace ID of the increment as its exemplar.
+                #vis fn inc_with_exemplar(&self, trace_id: &str) {
+                    #inner_binding
+                    #labels_array
+                    inner.inc_with_exemplar(labels, &[("trace_id", trace_id)]);
+                }
+
+                /// Increment the counter by the given value, recording the label pairs of its
+                /// exemplar.
+                #vis fn inc_by_with_exemplar<V>(&self, value: V, exemplar: &[(&str, &str)])
+                where
+                    V: #krate::IntoAtomic<#counter_ty>,
+                {
+                    #inner_binding
+                    #labels_array
+                    inner.inc_by_with_exemplar(labels, value.into_atomic(), exemplar);
+                }
+            },
+            MetricType::Histogram(_) => quote! {
+                /// Observe the value, recording the label pairs of its exemplar, e.g. the trace ID.
+                #vis fn observe_with_exemplar<V>(&self, value: V, exemplar: &[(&str, &str)])
+                where
+                    V: #krate::IntoAtomic<f64>,
+                {
+                    #inner_binding
+                    #labels_array
+                    inner.observe_with_exemplar(labels, value.into_atomic(), exemplar);
+                }
+            },
+            MetricType::Gauge(_, _) |
+            MetricType::Summary(_) |
+            MetricType::Timer(_) |
+            MetricType::Info(_) |
+            MetricType::Outcome(_) => quote! {},
+        };
+
+        let terminal_methods = |labels_array: &TokenStream| match ty {
+            MetricType::Counter(_, counter_ty) => quote! {
+                #vis fn inc(&self) {
+                    #inner_binding
+                    #labels_array
+                    inner.#inc(labels);
+                }
+
+                #vis fn inc_by<V>(&self, value: V)
+                where
+                    V: #krate::IntoAtomic<#counter_ty>,
+                {
+                    #inner_binding
+                    #labels_array
+                    inner.#inc_by(labels, value.into_atomic());
+                }
+
+                #vis fn reset(&self) {
+                    #inner_binding
+                    #labels_array
+                    inner.reset(labels);
+                }
+            },
+            MetricType::Gauge(_, gauge_ty) => quote! {
+                #vis fn inc(&self) {
+                    #inner_binding
+                    #labels_array
+                    inner.inc(labels);
+                }
+
+                #vis fn dec(&self) {
+                    #inner_binding
+                    #labels_array
+                    inner.dec(labels);
+                }
+
+                #vis fn add<V>(&self, value: V)
+                where
+                    V: #krate::IntoAtomic<#gauge_ty>,
+                {
+                    #inner_binding
+                    #labels_array
+                    inner.add(labels, value.into_atomic());
+                }
+
+                #vis fn sub<V>(&self, value: V)
+                where
+                    V: #krate::IntoAtomic<#gauge_ty>,
+                {
+                    #inner_binding
+                    #labels_array
+                    inner.sub(labels, value.into_atomic());
+                }
+
+                #vis fn set<V>(&self, value: V)
+                where
+                    V: #krate::IntoAtomic<#gauge_ty>,
+                {
+                    #inner_binding
+                    #labels_array
+                    inner.set(labels, value.into_atomic());
+                }
+
+                /// Set the gauge to the current UNIX timestamp, in seconds.
+                #vis fn set_to_current_time(&self) {
+                    #inner_binding
+                    #labels_array
+                    inner.set_to_current_time(labels);
+                }
+            },
+            MetricType::Histogram(_) => quote! {
+                #vis fn observe<V>(&self, value: V)
+                where
+                    V: #krate::IntoAtomic<f64>,
+                {
+                    #inner_binding
+                    #labels_array
+                    inner.#observe(labels, value.into_atomic());
+                }
+            },
+            MetricType::Summary(_) => quote! {
+                #vis fn observe<V>(&self, value: V)
+                where
+                    V: #krate::IntoAtomic<f64>,
+                {
+                    #inner_binding
+                    #labels_array
+                    inner.observe(labels, value.into_atomic());
+                }
+            },
+            MetricType::Timer(_) => quote! {
+                /// Observe the duration of a call, and count it.
+                #vis fn observe(&self, duration: ::std::time::Duration) {
+                    #inner_binding
+                    #labels_array
+                    inner.observe(labels, duration);
+                }
+
+                /// Returns the number of calls.
+                #vis fn count(&self) -> u64 {
+                    let Some(inner) = self.__metric() else { return 0 };
+                    #labels_array
+                    inner.count(labels)
+                }
+            },
+            MetricType::Info(_) => quote! {
+                /// Set the label values of the info metric, replacing the previously set ones.
+                #vis fn set(&self) {
+                    #inner_binding
+                    #labels_array
+                    inner.set(labels);
+                }
+            },
+            MetricType::Outcome(_) => quote! {
+                /// Count an operation with the given outcome.
+                #vis fn inc(&self, outcome: #krate::Outcome) {
+                    #inner_binding
+                    #labels_array
+                    inner.inc(labels, outcome);
+                }
+
+                /// Count a successful operation.
+                #vis fn success(&self) {
+                    self.inc(#krate::Outcome::Success);
+                }
+
+                /// Count a failed operation.
+                #vis fn failure(&self) {
+                    self.inc(#krate::Outcome::Failure);
+                }
+
+                /// Count an operation with the outcome of the given result, returning it.
+                #vis fn record<T, E>(&self, result: ::core::result::Result<T, E>) -> ::core::result::Result<T, E> {
+                    self.inc(#krate::Outcome::of(&result));
+                    result
+                }
+            },
+        };
+
+        // Timers already count their calls. Buffered updates of local metrics aren't read back
+        // until flushed.
+        let read_methods = |labels_array: &TokenStream| {
+            let methods = self
+                .read_methods()
+                .into_iter()
+                .filter(|_| !matches!(ty, MetricType::Timer(_)))
+                .map(|(_, method, ret, doc)| {
+                    quote! {
+                        #[doc = #doc]
+                        #vis fn #method(&self) -> #ret {
+                            let Some(inner) = self.__metric() else { return Default::default() };
+                            #labels_array
+                            inner.#method(labels)
+                        }
+                    }
+                });
+            quote! { #(#methods)* }
+        };
+
+        let handle_ty = ty.handle_type(krate);
+        let with_handle = |labels_array: &TokenStream| {
+            let methods = terminal_methods(labels_array);
+            let exemplar_methods = exemplar_methods(labels_array);
+            let read_methods = read_methods(labels_array);
+            let remove = self.has_variable_labels().then(|| {
+                quote! {
+                    /// Remove the series of the labels, e.g. to drop the stale series of a
+                    /// disconnected peer. Returns `false` if the series doesn't exist.
+                    #vis fn remove(&self) -> bool {
+                        let Some(inner) = self.__metric() else { return false };
+                        #labels_array
+                        inner.remove(labels)
+                    }
+                }
+            });
+            quote! {
+                #methods
+                #exemplar_methods
+                #read_methods
+
+                /// Resolve the labels of the metric once, returning a handle updating it without
+                /// the label lookup, e.g. for hot loops.
+                #vis fn handle(&self) -> #handle_ty {
+                    let Some(inner) = self.__metric() else { return <#handle_ty>::noop() };
+                    #labels_array
+                    inner.handle(labels)
+                }
+
+                #remove
+            }
+        };
+
+        // The raw accessor borrows the label values.
+        let raw_terminal_methods = with_handle(&quote! { let labels = self.labels; });
+        let terminal_methods = with_handle(&labels_array);
+
+        // An update with an unset named label is dropped, which is asserted against in debug
+        // builds. The accessor points to no metric either when a label value is rejected,
+        // or when the metrics are disabled at runtime.
+        let full_ty = self.ty.full_type();
+        let unset_check = self.has_named_labels().then(|| {
+            let label_idents = labels.iter().map(|label| format_ident!("{label}"));
+            let message = format!(
+                "The update of `{}` is dropped: every label must be set with its setter first",
+                self.identifier
+            );
+            quote! {
+                debug_assert!(self.metric.is_none() || #(self.#label_idents.is_some())&&*, #message);
+            }
+        });
+
+        (
+            quote! {
+                impl<'a> #accessor_name<'a> {
+                    #label_setters
+
+                    /// Returns the metric to update, if any.
+                    #[inline]
+                    fn __metric(&self) -> Option<&'a #full_ty> {
+                        #unset_check
+                        self.inner
+                    }
+
+                    #terminal_methods
+                    #unit_helpers
+                    #timer
+                    #tracker
+                }
+            },
+            quote! {
+                impl<'a> #raw_accessor_name<'a> {
+                    /// Returns the metric to update, if any.
+                    #[inline]
+                    fn __metric(&self) -> Option<&'a #full_ty> {
+                        self.inner
+                    }
+
+                    #raw_terminal_methods
+                    #unit_helpers
+                    #timer
+                    #tracker
+                }
+            },
+        )
+    }
+}
+
+#[derive(FromField)]
+#[darling(attributes(metric))]
+#[allow(dead_code)]
+struct MetricField {
+    /// The identifier of the field.
+    ident: Option<Ident>,
+    /// The type of the field.
+    ty: Type,
+    /// The name override to use for the metric.
+    rename: Option<LitStr>,
+    /// The label keys to define for the metric.
+    labels: Option<Vec<LitStr>>,
+    /// The help string to use for the metric. Takes precedence over the doc attribute.
+    help: Option<String>,
+    /// The constant labels of the metric, in addition to the static labels of the builder.
+    const_labels: Option<syn::ExprArray>,
+    /// The sample rate to use for the histogram.
+    /// TODO: Implement this.
+    sample: Option<LitFloat>,
+    /// The buckets to use for the histogram.
+    ///
+    /// Mutually exclusive with `quantiles`
+    buckets: Option<syn::Expr>,
+    /// The quantiles to use for the summary.
+    ///
+    /// Mutually exclusive with `buckets`
+    quantiles: Option<syn::Expr>,
+    /// The alert to generate a rule stub for.
+    alert: Option<AlertAttr>,
+    /// The unit of the metric, generating unit-aware helpers on the accessor.
+    unit: Option<String>,
+    /// Whether the field is a nested metrics struct, flattened into the registry and scope of the
+    /// parent.
+    #[darling(default)]
+    flatten: bool,
+    /// Whether the name of the metric skips the scope of the struct.
+    #[darling(default)]
+    absolute: bool,
+    /// The visibility of the accessor, e.g. `vis = "pub(crate)"`. Defaults to the visibility of
+    /// the struct.
+    #[darling(rename = "vis")]
+    accessor_vis: Option<syn::Visibility>,
+    /// The initial value of a counter or gauge without variable labels, set at build time.
+    init: Option<syn::Expr>,
+    /// Whether updates of a counter or histogram are buffered per thread, see `prometric::local`.
+    #[darling(default)]
+    local: bool,
+    /// Whether the accessor of a counter or histogram records exemplars, see
+    /// `prometric::exemplar`.
+    #[darling(default)]
+    exemplars: bool,
+    /// Whether the creation times of the series of a counter or summary are tracked, exported as
+    /// `_created` samples in the OpenMetrics text format, see `prometric::created`.
+    #[darling(default)]
+    created: bool,
+    /// The maximum number of distinct label value combinations of the metric, see
+    /// `prometric::cardinality`.
+    max_cardinality: Option<syn::Expr>,
+    /// What happens to new combinations beyond `max_cardinality`: `"other"` (default) or
+    /// `"drop"`.
+    on_overflow: Option<LitStr>,
+    /// A previous name of the metric, still exported during a migration window, see
+    /// `prometric::alias`.
+    deprecated_alias: Option<LitStr>,
+    /// A function modifying the `prometheus::Opts` or `prometheus::HistogramOpts` of the metric
+    /// before registration.
+    opts: Option<syn::Expr>,
+    /// The kind of metric of a field typed as an alias or newtype of a metric type, e.g.
+    /// `kind = "counter"`.
+    kind: Option<LitStr>,
+    /// Whether an `Arc` metric can be shared between instances of the struct, supplied with the
+    /// builder.
+    #[darling(default)]
+    shared: bool,
+    /// The numerator and denominator counter fields of a `Ratio` field, e.g.
+    /// `ratio = (hits, lookups)`.
+    ratio: Option<syn::Expr>,
+    /// The callback of a `GaugeFn` field evaluated when gathered, e.g. `eval = || queue.len()`.
+    eval: Option<syn::Expr>,
+}
+
+/// Returns the doc comment of the given attributes as a help string, with its lines trimmed and
+/// joined with spaces. Blank lines and doc attributes that aren't string literals are skipped.
+fn doc_help(attrs: &[syn::Attribute]) -> Option<String> {
+    let lines = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(lit), .. }),
+                ..
+            }) => Some(lit.value()),
+            _ => None,
+        })
+        .map(|line| line.trim().to_owned())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+
+    (!lines.is_empty()).then(|| lines.join(" "))
+}
+
+/// Substitute the `{scope}` and `{unit}` placeholders of a help string with the compile-time scope
+/// and the unit of the metric, e.g. for metrics structs generated by declarative macros.
+fn render_help(
+    help: String,
+    spanned: impl quote::ToTokens,
+    scope: &str,
+    unit: Option<&str>,
+) -> Result<String> {
+    if help.contains("{unit}") && unit.is_none() {
+        return Err(syn::Error::new_spanned(
+            spanned,
+            "The `{unit}` placeholder of the help requires a `unit` argument",
+        ));
+    }
+    Ok(help.replace("{scope}", scope).replace("{unit}", unit.unwrap_or_default()))
+}
+
+/// Returns the inner type of an `Option` type.
+fn option_inner(ty: &Type) -> Option<&Type> {
+    generic_inner(ty, "Option")
+}
+
+/// Returns the inner type of an `Arc` type.
+fn arc_inner(ty: &Type) -> Option<&Type> {
+    generic_inner(ty, "Arc")
+}
+
+/// Returns the type argument of a path type with the given name and a single type argument.
+fn generic_inner<'a>(ty: &'a Type, name: &str) -> Option<&'a Type> {
+    let Type::Path(syn::TypePath { qself: None, path }) = ty else { return None };
+    let segment = path.segments.last().filter(|segment| segment.ident == name)?;
+    let PathArguments::AngleBracketed(args) = &segment.arguments else { return None };
+    match args.args.first() {
+        Some(GenericArgument::Type(inner)) if args.args.len() == 1 => Some(inner),
+        _ => None,
+    }
+}
+
+/// Returns the default scope of the metrics, the name of the crate being compiled with invalid
+/// characters, e.g. dashes, replaced by underscores.
+fn crate_scope() -> Result<String> {
+    let Ok(name) = std::env::var("CARGO_PKG_NAME") else {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "Missing `scope` argument, and `CARGO_PKG_NAME` isn't set to default to",
+        ));
+    };
+
+    let scope = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect::<String>();
+    Ok(if scope.starts_with(|c: char| c.is_ascii_digit()) { format!("_{scope}") } else { scope })
+}
+
+/// Join the compile-time scope and the name of a metric, like `prometric::join_name`. Empty scopes,
+/// e.g. with `scope = ""`, are skipped.
+fn join_name(scope: &str, name: &str) -> String {
+    if scope.is_empty() { name.to_owned() } else { format!("{scope}{DEFAULT_SEPARATOR}{name}") }
+}
+
+/// Returns the expression resolving the full name of a metric in the builder, with the scope and
+/// prefix of the builder. Absolute names skip the scope, but keep the prefix of a parent metrics
+/// struct.
+fn scoped_name(name: &str, absolute: bool, krate: &syn::Path) -> TokenStream {
+    if absolute {
+        quote! {
+            match &self.prefix {
+                Some(prefix) => #krate::join_name(prefix, #name),
+                None => #name.to_owned(),
+            }
+        }
+    } else {
+        quote! { self.metric_name(#name) }
+    }
+}
+
+/// Returns whether the `metric` attribute of the field has the given argument, e.g. `flatten`.
+/// Typed labels can't be parsed by darling, so the argument is looked up in the tokens first.
+fn has_metric_arg(field: &Field, arg: &str) -> bool {
+    field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident(METRIC_ATTR_NAME))
+        .filter_map(|attr| attr.meta.require_list().ok())
+        .flat_map(|list| list.tokens.clone())
+        .any(|token| matches!(token, proc_macro2::TokenTree::Ident(ident) if ident == arg))
+}
+
+/// Returns whether the field is a nested metrics struct, with `#[metric(flatten)]`.
+fn is_flattened(field: &Field) -> Result<bool> {
+    if !has_metric_arg(field, "flatten") {
+        return Ok(false);
+    }
+
+    let metric_field = MetricField::from_field(field)?;
+    if metric_field.labels.is_some() ||
+        metric_field.buckets.is_some() ||
+        metric_field.quantiles.is_some() ||
+        metric_field.alert.is_some() ||
+        metric_field.unit.is_some() ||
+        metric_field.rename.is_some() ||
+        metric_field.absolute ||
+        metric_field.accessor_vis.is_some() ||
+        metric_field.init.is_some() ||
+        metric_field.local ||
+        metric_field.exemplars ||
+        metric_field.created ||
+        metric_field.max_cardinality.is_some() ||
+        metric_field.on_overflow.is_some() ||
+        metric_field.opts.is_some() ||
+        metric_field.kind.is_some()
+    {
+        return Err(syn::Error::new_spanned(
+            field,
+            "`flatten` can't be combined with other arguments of `metric`",
+        ));
+    }
+
+    Ok(true)
+}
+
+/// How a computed gauge gets its value when gathered.
+enum Computed {
+    /// `ratio = (numerator, denominator)`, the ratio of two counter fields of the struct, see
+    /// `prometric::ratio`.
+    Ratio {
+        /// The `ratio` argument, spanning errors on the counters.
+        ratio: syn::Expr,
+        numerator: Ident,
+        denominator: Ident,
+    },
+    /// `eval = || ...`, a callback returning a number, see `prometric::gauge_fn`.
+    Eval(syn::Expr),
+}
+
+/// A gauge field computed when gathered rather than updated: a `Ratio` field with
+/// `#[metric(ratio = (numerator, denominator))]`, or a `GaugeFn` field with
+/// `#[metric(eval = || ...)]`.
+struct ComputedBuilder {
+    identifier: Ident,
+    /// The declared type of the field.
+    ty: Type,
+    computed: Computed,
+    help: String,
+    /// The name of the gauge, without the scope.
+    name: String,
+    full_name: String,
+    absolute: bool,
+    /// The path of the `prometric` crate in the generated code.
+    krate: syn::Path,
+}
+
+/// The counter field of a metrics struct, as referenced by a ratio.
+struct RatioCounter {
+    labels: Vec<String>,
+    /// The label names expression of the descriptor of the counter.
+    label_names: TokenStream,
+    /// The label names expression of the metadata of the counter.
+    metadata_labels: TokenStream,
+    /// The expression of the optional reference to the counter, in the built `metrics`.
+    metric: TokenStream,
+}
+
+impl ComputedBuilder {
+    /// Parse the computed gauge of the field, or return `None` if it has neither a `ratio` nor an
+    /// `eval` argument.
+    fn try_from(
+        field: &Field,
+        scope: &str,
+        struct_labels: &[String],
+        metrics_attr: &MetricsAttr,
+    ) -> Result<Option<Self>> {
+        if !has_metric_arg(field, "ratio") && !has_metric_arg(field, "eval") {
+            return Ok(None);
+        }
+
+        let metric_field = MetricField::from_field(field)?;
+        let arg = match (&metric_field.ratio, &metric_field.eval) {
+            (Some(_), Some(_)) => {
+                return Err(syn::Error::new_spanned(field, "`ratio` and `eval` can't be combined"));
+            }
+            (Some(_), None) => "ratio",
+            (None, Some(_)) => "eval",
+            (None, None) => return Ok(None),
+        };
+        if metric_field.labels.is_some() ||
+            metric_field.buckets.is_some() ||
+            metric_field.quantiles.is_some() ||
+            metric_field.alert.is_some() ||
+            metric_field.unit.is_some() ||
+            metric_field.flatten ||
+            metric_field.init.is_some() ||
+            metric_field.local ||
+            metric_field.exemplars ||
+            metric_field.created ||
+            metric_field.max_cardinality.is_some() ||
+            metric_field.on_overflow.is_some() ||
+            metric_field.deprecated_alias.is_some() ||
+            metric_field.opts.is_some() ||
+            metric_field.kind.is_some() ||
+            metric_field.shared
+        {
+            return Err(syn::Error::new_spanned(
+                field,
+                format!("`{arg}` can only be combined with `rename`, `absolute`, `help` and `vis`"),
+            ));
+        }
+
+        let computed = match (metric_field.ratio, metric_field.eval) {
+            (Some(ratio), _) => {
+                let counters = match &ratio {
+                    syn::Expr::Tuple(tuple) if tuple.elems.len() == 2 => tuple
+                        .elems
+                        .iter()
+                        .map(|elem| match elem {
+                            syn::Expr::Path(path) => path.path.get_ident().cloned(),
+                            _ => None,
+                        })
+                        .collect::<Option<Vec<_>>>(),
+                    _ => None,
+                };
+                let Some([numerator, denominator]) =
+                    counters.and_then(|idents| <[Ident; 2]>::try_from(idents).ok())
+                else {
+                    return Err(syn::Error::new_spanned(
+                        ratio,
+                        "Expected `ratio = (numerator, denominator)`, with two counter fields",
+                    ));
+                };
+                Computed::Ratio { ratio, numerator, denominator }
+            }
+            (None, Some(eval)) => {
+                // The callback returns a single value, which can't be labeled per series.
+                if !struct_labels.is_empty() {
+                    return Err(syn::Error::new_spanned(
+                        field,
+                        "`eval` gauges have a single series, and can't have the `labels` of the \
+                         struct",
+                    ));
+                }
+                Computed::Eval(eval)
+            }
+            (None, None) => unreachable!("checked above"),
+        };
+
+        let Some(help) = metric_field.help.or_else(|| doc_help(&field.attrs)) else {
+            return Err(syn::Error::new_spanned(
+                field,
+                "Unable to determine `help` label for metric. Provide an explicit `help` argument to `metric` or document the field",
+            ));
+        };
+        let help = render_help(help, field, scope, None)?;
+
+        let name = match &metric_field.rename {
+            Some(rename) => {
+                validate_metric_name(rename)?;
+                rename.value()
+            }
+            None => metrics_attr.rename_rule()?.apply(&field.ident.as_ref().unwrap().to_string()),
+        };
+        let full_name = if metric_field.absolute { name.clone() } else { join_name(scope, &name) };
+
+        Ok(Some(Self {
+            identifier: metric_field
+                .ident
+                .ok_or(syn::Error::new_spanned(field, "Expected an identifier"))?,
+            ty: metric_field.ty,
+            computed,
+            help,
+            name,
+            full_name,
+            absolute: metric_field.absolute,
+            krate: metrics_attr.krate(),
+        }))
+    }
+
+    /// Returns the identifier of the local variable holding the runtime name of a ratio in
+    /// `build()`, resolved before the builder is consumed.
+    fn name_var(&self) -> Ident {
+        format_ident!("__{}_name", self.identifier)
+    }
+
+    /// Returns the counters of a ratio, checking that they are counters of the struct with the
+    /// same labels, or `None` for an `eval` gauge.
+    fn counters<'a>(
+        &self,
+        counters: &'a HashMap<String, RatioCounter>,
+    ) -> Result<Option<(&'a RatioCounter, &'a RatioCounter)>> {
+        let Computed::Ratio { ratio, numerator, denominator } = &self.computed else {
+            return Ok(None);
+        };
+        let counter = |ident: &Ident| {
+            counters.get(&ident.to_string()).ok_or_else(|| {
+                syn::Error::new_spanned(
+                    ident,
+                    format!("`{ident}` isn't a counter field of this struct"),
+                )
+            })
+        };
+        let (numerator, denominator) = (counter(numerator)?, counter(denominator)?);
+        if numerator.labels != denominator.labels {
+            return Err(syn::Error::new_spanned(
+                ratio,
+                "The counters of a ratio must have the same labels",
+            ));
+        }
+        Ok(Some((numerator, denominator)))
+    }
+
+    /// Build the initializer of the field. A ratio is registered once both counters are built,
+    /// see [`Self::build_ratio_initializer`], and an `eval` gauge right away with the static
+    /// labels of the builder.
+    fn build_initializer(&self) -> TokenStream {
+        let krate = &self.krate;
+        let ident = &self.identifier;
+        if DISABLED {
+            return quote! { #ident: ::core::marker::PhantomData };
+        }
+
+        match &self.computed {
+            Computed::Ratio { .. } => quote! { #ident: None },
+            Computed::Eval(eval) => {
+                let name = scoped_name(&self.name, self.absolute, &self.krate);
+                let help = &self.help;
+                quote! {
+                    #ident: (!disabled).then(|| {
+                        let eval = #eval;
+                        #krate::GaugeFn::new(
+                            &self.registry,
+                            &#name,
+                            #help,
+                            self.labels.clone(),
+                            move || eval() as f64,
+                        )
+                    })
+                }
+            }
+        }
+    }
+
+    /// Build the statement registering a ratio in `build()`, once both counters are built.
+    fn build_ratio_initializer(
+        &self,
+        numerator: &RatioCounter,
+        denominator: &RatioCounter,
+    ) -> TokenStream {
+        let krate = &self.krate;
+        let ident = &self.identifier;
+        let name_var = self.name_var();
+        let help = &self.help;
+        let (numerator, denominator) = (&numerator.metric, &denominator.metric);
+        quote! {
+            metrics.#ident = match (#numerator, #denominator) {
+                (Some(numerator), Some(denominator)) => Some(#krate::Ratio::new(
+                    metrics.__metrics_handle.registry(),
+                    &#name_var,
+                    #help,
+                    numerator,
+                    denominator,
+                )),
+                _ => None,
+            };
+        }
+    }
+
+    /// Build the accessor of the gauge, returning `None` when it isn't registered.
+    fn build_accessor(&self, vis: &syn::Visibility) -> TokenStream {
+        let ident = &self.identifier;
+        let ty = &self.ty;
+        let doc_krate = doc_path(&self.krate);
+        let doc = match &self.computed {
+            Computed::Ratio { numerator, denominator, .. } => format!(
+                "{}\n* Metric type: [`{doc_krate}::Ratio`] of `{numerator}` over `{denominator}`",
+                self.help
+            ),
+            Computed::Eval(_) => {
+                format!("{}\n* Metric type: [`{doc_krate}::GaugeFn`]", self.help)
+            }
+        };
+        let body = if DISABLED {
+            quote! { None }
+        } else {
+            quote! { self.#ident.as_ref() }
+        };
+        quote! {
+            #[doc = #doc]
+            #vis fn #ident(&self) -> Option<&#ty> {
+                #body
+            }
+        }
+    }
+
+    /// Build the `prometric::descriptor::MetricDescriptor` expression of the gauge, with the given
+    /// label names expression.
+    fn build_descriptor(&self, label_names: &TokenStream) -> TokenStream {
+        let krate = &self.krate;
+        let (name, help) = (&self.full_name, &self.help);
+        quote! {
+            #krate::descriptor::MetricDescriptor::new(
+                #name,
+                #krate::descriptor::MetricKind::Gauge,
+                #help,
+                <[&str]>::iter(#label_names).copied(),
+            )
+        }
+    }
+
+    /// Build the `prometric::descriptor::MetricMetadata` expression of the gauge, with the given
+    /// label names expression.
+    fn build_metadata(&self, metadata_labels: &TokenStream) -> TokenStream {
+        let krate = &self.krate;
+        let name = &self.full_name;
+        quote! {
+            #krate::descriptor::MetricMetadata {
+                name: #name,
+                kind: #krate::descriptor::MetricKind::Gauge,
+                labels: #metadata_labels,
+            }
+        }
+    }
+}
+
+/// Build the initializer, accessor, alert rules and descriptors of a nested metrics struct. Its
+/// metrics are registered with the registry and static labels of the parent, prefixed with the
+/// parent scope.
+fn build_flattened(
+    field: &Field,
+    scope: Option<&str>,
+    vis: &syn::Visibility,
+    krate: &syn::Path,
+) -> (TokenStream, TokenStream, TokenStream, TokenStream) {
+    let ident = field.ident.as_ref().unwrap();
+    let ty = &field.ty;
+
+    // The nested metrics of a composite keep their own scope, only prefixed by the builder.
+    let prefix = match scope {
+        Some(_) => quote! { builder = builder.with_prefix(self.scope()); },
+        None => quote! {
+            if let Some(prefix) = &self.prefix {
+                builder = builder.with_prefix(prefix.clone());
+            }
+        },
+    };
+    let initializer = if DISABLED {
+        quote! { #ident: <#ty>::builder().build() }
+    } else {
+        quote! {
+            #ident: {
+                let mut builder =
+                    <#ty>::builder().with_registry(&self.registry).disabled(disabled);
+                if let Some(prefix) = &self.registry_prefix {
+                    builder = builder.with_registry_prefix(prefix.clone());
+                }
+                #prefix
+                for (key, value) in &self.labels {
+                    builder = builder.with_label(key.clone(), value.clone());
+                }
+                builder.build()
+            }
+        }
+    };
+
+    let doc = match scope {
+        Some(scope) => {
+            format!("The nested metrics of `{ident}`, prefixed with the `{scope}` scope.")
+        }
+        None => format!("The nested metrics of `{ident}`."),
+    };
+    let accessor = quote! {
+        #[doc = #doc]
+        #vis fn #ident(&self) -> &#ty {
+            &self.#ident
+        }
+    };
+
+    let (alert_rules, descriptors) = match scope {
+        Some(scope) => (
+            quote! {
+                <#ty>::alert_rules().into_iter().map(|mut rule| {
+                    rule.metric = #krate::join_name(#scope, &rule.metric);
+                    rule
+                })
+            },
+            quote! {
+                <#ty>::describe().into_iter().map(|mut descriptor| {
+                    descriptor.name = #krate::join_name(#scope, &descriptor.name);
+                    descriptor
+                })
+            },
+        ),
+        None => (quote! { <#ty>::alert_rules() }, quote! { <#ty>::describe() }),
+    };
+
+    (initializer, accessor, alert_rules, descriptors)
+}
+
+pub fn expand(metrics_attr: MetricsAttr, input: &mut ItemStruct) -> Result<TokenStream> {
+    let krate = metrics_attr.krate();
+    let mut initializers = Vec::with_capacity(input.fields.len());
+    let mut definitions = Vec::with_capacity(input.fields.len());
+    let mut accessors = Vec::with_capacity(input.fields.len());
+    let mut accessor_impls = Vec::with_capacity(input.fields.len());
+    let mut alert_rules = Vec::new();
+    let mut late_alert_rules = Vec::new();
+    let mut descriptors = Vec::new();
+    let mut late_descriptors = Vec::new();
+    let mut metadata_table = Vec::new();
+    let mut builder_methods = Vec::new();
+    let mut handle_names = Vec::new();
+    let mut trait_methods = Vec::new();
+    let mut trait_metrics_methods = Vec::new();
+    let mut trait_noop_methods = Vec::new();
+    let mut resets = Vec::new();
+    let mut unregisters = Vec::new();
+    let mut record_intos = Vec::new();
+    // The optional collectors of the fields, for the `Collector` implementation.
+    let mut collectors = Vec::new();
+    // Computed gauges, and ratios registered once their counters are built, resolving their names
+    // beforehand.
+    let mut computed_gauges = Vec::new();
+    let mut ratio_counters = HashMap::new();
+    let mut ratio_names = Vec::new();
+
+    // The visibility of the metrics struct
+    let vis = &input.vis;
+    // The identifier of the metrics struct
+    let ident = &input.ident;
+
+    // Accessors are named after the fields.
+    if let syn::Fields::Unnamed(fields) = &input.fields {
+        return Err(syn::Error::new_spanned(
+            fields,
+            "Tuple structs aren't supported, as accessors are named after the fields. Use named \
+             fields instead",
+        ));
+    }
+
+    if metrics_attr.scoped && metrics_attr._static.is_enabled() {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`scoped` metrics can't be `static`, as statics are never dropped",
+        ));
+    }
+    if metrics_attr.scoped && metrics_attr.clone {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`scoped` metrics can't be `clone`, as dropping a clone would unregister the metrics \
+             of the others",
+        ));
+    }
+    // The metrics are cheaply cloneable, sharing their collectors.
+    if metrics_attr.clone {
+        input.attrs.push(syn::parse_quote! { #[derive(Clone)] });
+    }
+    let compose = metrics_attr.compose;
+    let scope = if compose {
+        metrics_attr.validate_compose()?;
+        String::new()
+    } else {
+        metrics_attr.scope()?
+    };
+    for label in metrics_attr.labels.iter().flatten() {
+        validate_label_name(label)?;
+    }
+    let struct_labels =
+        metrics_attr.labels.iter().flatten().map(|label| label.value()).collect::<Vec<_>>();
+
+    for field in input.fields.iter_mut() {
+        // The generated items of a field are guarded by the same `cfg` attributes.
+        let cfgs =
+            field.attrs.iter().filter(|attr| attr.path().is_ident("cfg")).collect::<Vec<_>>();
+        let cfgs = quote! { #(#cfgs)* };
+
+        if let Some(attr) =
+            field.attrs.iter().find(|attr| compose && attr.path().is_ident(METRIC_ATTR_NAME))
+        {
+            return Err(syn::Error::new_spanned(
+                attr,
+                "Fields of composite metrics are nested metrics structs, without `#[metric]`",
+            ));
+        }
+        if compose || is_flattened(field)? {
+            if metrics_attr._trait {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "Nested metrics aren't supported with `trait`",
+                ));
+            }
+            let (initializer, accessor, rules, nested_descriptors) =
+                build_flattened(field, (!compose).then_some(scope.as_str()), vis, &krate);
+            initializers.push(quote! { #cfgs #initializer });
+            accessors.push(quote! { #cfgs #accessor });
+            let field_ident = &field.ident;
+            resets.push(quote! { #cfgs self.#field_ident.reset_all(); });
+            unregisters.push(quote! { #cfgs self.#field_ident.unregister(); });
+            record_intos.push(quote! { #cfgs self.#field_ident.record_into(recorder); });
+            collectors.push(quote! {
+                #cfgs
+                Some(&self.#field_ident as &dyn #krate::prometheus::core::Collector)
+            });
+            late_alert_rules.push(quote! { #cfgs rules.extend(#rules); });
+            late_descriptors.push(quote! { #cfgs descriptors.extend(#nested_descriptors); });
+            let field_ty = &field.ty;
+            // Without a prefix, the nested metrics of a composite are prefixed with their parent.
+            let nested_name = if compose {
+                quote! {
+                    match self.prefix.as_deref().or(<#field_ty>::PARENT_SCOPE) {
+                        Some(prefix) => #krate::join_name(prefix, &descriptor.name),
+                        None => descriptor.name,
+                    }
+                }
+            } else {
+                quote! { self.metric_name(&descriptor.name) }
+            };
+            handle_names.push(quote! {
+                #cfgs
+                names.extend(<#field_ty>::describe().into_iter().map(|descriptor| #nested_name));
+            });
+
+            field.attrs.retain(|attr| !attr.path().is_ident(METRIC_ATTR_NAME));
+            continue;
+        }
+
+        if let Some(computed) =
+            ComputedBuilder::try_from(field, &scope, &struct_labels, &metrics_attr)?
+        {
+            let ident = &computed.identifier;
+            let initializer = computed.build_initializer();
+            initializers.push(quote! { #cfgs #initializer });
+            let field_vis = MetricField::from_field(field)?.accessor_vis;
+            let accessor = computed.build_accessor(field_vis.as_ref().unwrap_or(vis));
+            accessors.push(quote! { #cfgs #accessor });
+            unregisters.push(quote! {
+                #cfgs
+                if let Some(gauge) = &self.#ident {
+                    gauge.unregister(registry);
+                }
+            });
+            collectors.push(quote! {
+                #cfgs
+                self.#ident
+                    .as_ref()
+                    .map(|gauge| gauge as &dyn #krate::prometheus::core::Collector)
+            });
+            let runtime_name = scoped_name(&computed.name, computed.absolute, &krate);
+            if matches!(computed.computed, Computed::Ratio { .. }) {
+                let name_var = computed.name_var();
+                ratio_names.push(quote! { #cfgs let #name_var = #runtime_name; });
+            }
+            handle_names.push(quote! { #cfgs names.push(#runtime_name); });
+
+            field.attrs.retain(|attr| !attr.path().is_ident(METRIC_ATTR_NAME));
+            let ty = &field.ty;
+            field.ty = if DISABLED {
+                syn::parse_quote! { ::core::marker::PhantomData<#ty> }
+            } else {
+                syn::parse_quote! { Option<#ty> }
+            };
+            computed_gauges.push((computed, cfgs));
+            continue;
+        }
+
+        let builder = MetricBuilder::try_from(field, &scope, &struct_labels, &metrics_attr)?;
+        // The accessor can be more or less visible than the struct.
+        let field_vis = builder.vis.as_ref().unwrap_or(vis);
+        if matches!(builder.ty, MetricType::Counter(_, _)) {
+            ratio_counters.insert(
+                builder.identifier.to_string(),
+                RatioCounter {
+                    labels: builder.labels(),
+                    label_names: builder.label_names(),
+                    metadata_labels: builder.metadata_labels(),
+                    metric: builder.field_metric_of(quote! { metrics }),
+                },
+            );
+        }
+
+        let initializer = builder.build_initializer();
+        initializers.push(quote! { #cfgs #initializer });
+        let reset = builder.build_reset();
+        resets.push(quote! { #cfgs #reset });
+        let unregister = builder.build_unregister();
+        unregisters.push(quote! { #cfgs #unregister });
+        let record_into = builder.build_record_into();
+        record_intos.push(quote! { #cfgs #record_into });
+        let metric = builder.field_metric();
+        collectors.push(quote! {
+            #cfgs
+            #metric.map(|metric| metric as &dyn #krate::prometheus::core::Collector)
+        });
+        let (definition, accessor) = builder.build_accessor(field_vis);
+        definitions.push(quote! { #cfgs #definition });
+        accessors.push(quote! { #cfgs #accessor });
+        let (raw_definition, raw_accessor) = builder.build_raw_accessor(field_vis);
+        definitions.push(quote! { #cfgs #raw_definition });
+        accessors.push(quote! { #cfgs #raw_accessor });
+        let handle_accessor = builder.build_handle_accessor(field_vis);
+        accessors.push(quote! { #cfgs #handle_accessor });
+        let test_getters = builder.build_test_getters(field_vis);
+        accessors.push(quote! { #cfgs #test_getters });
+        let (accessor_impl, raw_accessor_impl) = builder.build_accessor_impl(field_vis);
+        accessor_impls.push(quote! { #cfgs #accessor_impl });
+        accessor_impls.push(quote! { #cfgs #raw_accessor_impl });
+        if metrics_attr._trait {
+            if let Some(field_vis) = &builder.vis {
+                return Err(syn::Error::new_spanned(
+                    field_vis,
+                    "Accessor visibility can't be overridden with `trait`",
+                ));
+            }
+            for (trait_method, metrics_method, noop_method) in builder.build_trait_methods() {
+                trait_methods.push(quote! { #cfgs #trait_method });
+                trait_metrics_methods.push(quote! { #cfgs #metrics_method });
+                trait_noop_methods.push(quote! { #cfgs #noop_method });
+            }
+        }
+        let name_const = builder.build_name_const(field_vis);
+        accessors.push(quote! { #cfgs #name_const });
+        for metadata in builder.build_metadata() {
+            metadata_table.push(quote! { #cfgs #metadata });
+        }
+        let runtime_name = builder.registered_name();
+        // The overflow counter of a cardinality limit is gathered along with the metric, and the
+        // call counter of a timer along with its duration histogram.
+        let push_names = if matches!(builder.ty, MetricType::Timer(_)) {
+            quote! {
+                let name = #runtime_name;
+                names.push(format!("{name}_duration_seconds"));
+                names.push(format!("{name}_total"));
+            }
+        } else if builder.cardinality_limit.is_some() {
+            quote! {
+                let name = #runtime_name;
+                names.push(format!("{name}_cardinality_overflow"));
+                names.push(name);
+            }
+        } else {
+            quote! { names.push(#runtime_name); }
+        };
+        // So is the deprecated alias.
+        let push_names = match builder.runtime_alias() {
+            Some(alias) => quote! { #push_names names.push(#alias); },
+            None => push_names,
+        };
+        if builder.optional {
+            let key = builder.identifier.to_string();
+            handle_names.push(quote! {
+                #cfgs
+                if self.enabled.contains(#key) {
+                    #push_names
+                }
+            });
+        } else {
+            handle_names.push(quote! { #cfgs { #push_names } });
+        }
+        if let Some(enable_method) = builder.build_enable_method(vis) {
+            builder_methods.push(quote! { #cfgs #enable_method });
+        }
+        if let Some((with_method, method)) = builder.build_shared_methods(vis, field_vis) {
+            builder_methods.push(quote! { #cfgs #with_method });
+            accessors.push(quote! { #cfgs #method });
+        }
+        for descriptor in builder.build_descriptors() {
+            if cfgs.is_empty() {
+                descriptors.push(descriptor);
+            } else {
+                late_descriptors.push(quote! { #cfgs descriptors.push(#descriptor); });
+            }
+        }
+        if let Some(rule) = builder.build_alert_rule() {
+            if cfgs.is_empty() {
+                alert_rules.push(rule);
+            } else {
+                late_alert_rules.push(quote! { #cfgs rules.push(#rule); });
+            }
+        }
+
+        // Remove the metric attribute from the field.
+        field.attrs.retain(|attr| !attr.path().is_ident(METRIC_ATTR_NAME));
+
+        // Make the metric inert and zero-sized, or optional when it can be disabled at runtime.
+        let ty = option_inner(&field.ty).unwrap_or(&field.ty).clone();
+        field.ty = if DISABLED {
+            syn::parse_quote! { ::core::marker::PhantomData<#ty> }
+        } else {
+            syn::parse_quote! { Option<#ty> }
+        };
+    }
+
+    // Ratios reference the counters of the struct, wherever they are declared.
+    let mut ratio_initializers = Vec::new();
+    for (computed, cfgs) in &computed_gauges {
+        let (label_names, metadata_labels) = match computed.counters(&ratio_counters)? {
+            Some((numerator, denominator)) => {
+                if !DISABLED {
+                    let initializer = computed.build_ratio_initializer(numerator, denominator);
+                    ratio_initializers.push(quote! { #cfgs #initializer });
+                }
+                (numerator.label_names.clone(), numerator.metadata_labels.clone())
+            }
+            None => (quote! { &[] }, quote! { &[] }),
+        };
+        let descriptor = computed.build_descriptor(&label_names);
+        late_descriptors.push(quote! { #cfgs descriptors.push(#descriptor); });
+        let metadata = computed.build_metadata(&metadata_labels);
+        metadata_table.push(quote! { #cfgs #metadata });
+    }
+
+    // Alert rules and descriptors of nested metrics and of `cfg`-guarded fields are added after
+    // the others.
+    let alert_rules_body = if late_alert_rules.is_empty() {
+        quote! { vec![#(#alert_rules),*] }
+    } else {
+        quote! {
+            #[allow(unused_mut, clippy::vec_init_then_push)]
+            let mut rules = vec![#(#alert_rules),*];
+            #(#late_alert_rules)*
+            rules
+        }
+    };
+    let describe_body = if late_descriptors.is_empty() {
+        quote! { vec![#(#descriptors),*] }
+    } else {
+        quote! {
+            #[allow(unused_mut, clippy::vec_init_then_push)]
+            let mut descriptors = vec![#(#descriptors),*];
+            #(#late_descriptors)*
+            descriptors
+        }
+    };
+
+    // The struct holds a handle to its registry and metric names, to gather only its metrics. The
+    // names are resolved with the scope and prefix of the builder, and the names of nested metrics
+    // from their descriptors.
+    let (handle_names, handle_initializer, gather_body, render_body, registry_body) = if DISABLED {
+        (
+            None,
+            None,
+            quote! { Vec::new() },
+            quote! { String::new() },
+            quote! { #krate::prometheus::default_registry() },
+        )
+    } else {
+        let syn::Fields::Named(fields) = &mut input.fields else {
+            return Err(syn::Error::new_spanned(&input.fields, "Expected named fields"));
         };
+        fields.named.push(syn::parse::Parser::parse2(
+            syn::Field::parse_named,
+            quote! { __metrics_handle: #krate::MetricsHandle },
+        )?);
+
+        (
+            Some(quote! {
+                #[allow(unused_mut, clippy::vec_init_then_push)]
+                let mut names = Vec::new();
+                if !disabled {
+                    #(#handle_names)*
+                }
+            }),
+            Some(quote! {
+                __metrics_handle: #krate::MetricsHandle::new(
+                    self.registry.into_owned(),
+                    self.registry_prefix,
+                    names,
+                )
+            }),
+            quote! { self.__metrics_handle.gather() },
+            quote! { self.__metrics_handle.render() },
+            quote! { self.__metrics_handle.registry() },
+        )
+    };
+
+    // The metrics are inert when disabled, and nothing is registered.
+    let reset_body = (!DISABLED).then(|| quote! { #(#resets)* });
+    let unregister_body = (!DISABLED).then(|| {
+        quote! {
+            let registry = self.__metrics_handle.registry();
+            #(#unregisters)*
+        }
+    });
 
+    let builder_name = format_ident!("{ident}Builder");
+
+    // Ratios are registered once the counters they reference are built.
+    let build_body = if ratio_initializers.is_empty() {
         quote! {
-            impl<'a> #accessor_name<'a> {
-                #terminal_methods
+            #ident {
+                #(#initializers,)*
+                #handle_initializer
             }
         }
-    }
-}
+    } else {
+        quote! {
+            #(#ratio_names)*
+            let mut metrics = #ident {
+                #(#initializers,)*
+                #handle_initializer
+            };
+            #(#ratio_initializers)*
+            metrics
+        }
+    };
 
-#[derive(FromField)]
-#[darling(attributes(metric))]
-#[allow(dead_code)]
-struct MetricField {
-    /// The identifier of the field.
-    ident: Option<Ident>,
-    /// The type of the field.
-    ty: Type,
-    /// The name override to use for the metric.
-    rename: Option<String>,
-    /// The label keys to define for the metric.
-    labels: Option<Vec<LitStr>>,
-    /// The help string to use for the metric. Takes precedence over the doc attribute.
-    help: Option<String>,
-    /// The sample rate to use for the histogram.
-    /// TODO: Implement this.
-    sample: Option<LitFloat>,
-    /// The buckets to use for the histogram.
-    ///
-    /// Mutually exclusive with `quantiles`
-    buckets: Option<syn::Expr>,
-    /// The quantiles to use for the summary.
-    ///
-    /// Mutually exclusive with `buckets`
-    quantiles: Option<syn::Expr>,
-}
+    // Nothing is registered when disabled, and composite metrics have no scope.
+    let discard_builder = if DISABLED {
+        Some(quote! {
+            let _ = (
+                self.registry, self.registry_prefix, self.labels, self.scope, self.subsystem,
+                self.prefix, self.disabled, self.enabled, self.shared,
+            );
+        })
+    } else {
+        compose.then(|| quote! { let _ = (&self.scope, &self.subsystem); })
+    };
 
-pub fn expand(metrics_attr: MetricsAttr, input: &mut ItemStruct) -> Result<TokenStream> {
-    let mut initializers = Vec::with_capacity(input.fields.len());
-    let mut definitions = Vec::with_capacity(input.fields.len());
-    let mut accessors = Vec::with_capacity(input.fields.len());
-    let mut accessor_impls = Vec::with_capacity(input.fields.len());
+    // Metrics can be disabled at runtime with the builder or the environment.
+    let resolve_disabled = (!DISABLED).then(|| {
+        quote! { let disabled = self.disabled.unwrap_or_else(#krate::disabled_by_env); }
+    });
 
-    // The visibility of the metrics struct
-    let vis = &input.vis;
-    // The identifier of the metrics struct
-    let ident = &input.ident;
+    // Prefixes the scope with the value of the `scope_env` environment variable, if set.
+    let scope_env_prefix = match &metrics_attr.scope_env {
+        Some(var) => quote! {
+            let scope = match ::std::env::var(#var) {
+                Ok(prefix) if !prefix.is_empty() => #krate::join_name(&prefix, scope),
+                _ => scope.to_owned(),
+            };
+        },
+        None => quote! { let scope = scope.to_owned(); },
+    };
 
-    for field in input.fields.iter_mut() {
-        let builder =
-            MetricBuilder::try_from(field, &metrics_attr.scope.as_ref().unwrap().value())?;
+    // Resolves the scope and full metric names, with the scope override, environment prefix,
+    // subsystem and prefix of the builder if any.
+    let name_resolvers = (!DISABLED).then(|| {
+        // Composite metrics have no scope, only the prefix of the builder.
+        if compose {
+            return quote! {
+                fn metric_name(&self, name: &str) -> String {
+                    match &self.prefix {
+                        Some(prefix) => #krate::join_name(prefix, name),
+                        None => name.to_owned(),
+                    }
+                }
+            };
+        }
+        quote! {
+            fn scope(&self) -> String {
+                let scope = self.scope.as_deref().unwrap_or(#scope);
+                #scope_env_prefix
+                let scope = match &self.subsystem {
+                    Some(subsystem) => #krate::join_name(&scope, subsystem),
+                    None => scope,
+                };
+                match &self.prefix {
+                    Some(prefix) => #krate::join_name(prefix, &scope),
+                    None => scope,
+                }
+            }
 
-        initializers.push(builder.build_initializer());
-        let (definition, accessor) = builder.build_accessor(vis);
-        definitions.push(definition);
-        accessors.push(accessor);
-        accessor_impls.push(builder.build_accessor_impl(vis));
+            fn metric_name(&self, name: &str) -> String {
+                #krate::join_name(&self.scope(), name)
+            }
+        }
+    });
 
-        // Remove the metric attribute from the field.
-        field.attrs.retain(|attr| !attr.path().is_ident(METRIC_ATTR_NAME));
-    }
+    // Composite metrics have no scope to override.
+    let scope_methods = (!compose).then(|| {
+        quote! {
+            /// Override the compile-time scope of the metrics, e.g. to deploy the same binary as
+            /// several services. Alert rules keep the compile-time scope.
+            #vis fn with_scope(mut self, scope: impl Into<String>) -> Self {
+                self.scope = Some(scope.into());
+                self
+            }
 
-    let builder_name = format_ident!("{ident}Builder");
+            /// Insert a subsystem segment between the scope and the names of the metrics, e.g.
+            /// `app_db_queries` with the `db` subsystem, to build the same struct once per
+            /// subsystem. Absolute names and alert rules are unaffected.
+            #vis fn with_subsystem(mut self, subsystem: impl Into<String>) -> Self {
+                self.subsystem = Some(subsystem.into());
+                self
+            }
+        }
+    });
+
+    let catalog = metrics_attr.catalog.then(|| {
+        quote! {
+            /// Returns the serializable catalog of the metrics of this struct, including nested
+            /// metrics. Generated with `#[metrics(catalog)]`.
+            #vis fn catalog() -> #krate::catalog::Catalog {
+                #krate::catalog::Catalog::new(Self::describe())
+            }
+        }
+    });
+
+    // The docs link to the items of the crate path.
+    let doc_krate = doc_path(&krate);
+    let registry_prefix_doc = format!(
+        "Set the prefix of the registry, when it was created with \
+         [`{doc_krate}::prefixed_registry`], for `gather()` and `render()` to match the prefixed \
+         names of the metrics. Nested metrics structs inherit it."
+    );
+    let disabled_doc = format!(
+        "Disable the metrics at runtime: nothing is registered and the accessors are inert. \
+         Defaults to the value of the `PROMETRIC_DISABLED` environment variable, see \
+         [`{doc_krate}::disabled_by_env`]."
+    );
 
     let mut output = quote! {
         #vis struct #builder_name<'a> {
-            registry: &'a ::prometric::prometheus::Registry,
+            registry: ::std::borrow::Cow<'a, #krate::prometheus::Registry>,
+            registry_prefix: Option<String>,
             labels: ::std::collections::HashMap<String, String>,
+            scope: Option<String>,
+            subsystem: Option<String>,
+            prefix: Option<String>,
+            disabled: Option<bool>,
+            enabled: ::std::collections::HashSet<&'static str>,
+            shared: ::std::collections::HashMap<
+                &'static str,
+                Box<dyn ::std::any::Any + Send + Sync>,
+            >,
         }
 
         impl<'a> #builder_name<'a> {
             /// Set the registry to use for the metrics.
-            #vis fn with_registry(mut self, registry: &'a ::prometric::prometheus::Registry) -> Self {
-                self.registry = registry;
+            #vis fn with_registry(mut self, registry: &'a #krate::prometheus::Registry) -> Self {
+                self.registry = ::std::borrow::Cow::Borrowed(registry);
+                self
+            }
+
+            #[doc = #registry_prefix_doc]
+            #vis fn with_registry_prefix(mut self, prefix: impl Into<String>) -> Self {
+                self.registry_prefix = Some(prefix.into());
                 self
             }
 
@@ -599,37 +4292,93 @@ pub fn expand(metrics_attr: MetricsAttr, input: &mut ItemStruct) -> Result<Token
                 self
             }
 
+            /// Add static labels to the metrics struct, e.g. from a configuration map.
+            #vis fn with_labels<K, V>(mut self, labels: impl IntoIterator<Item = (K, V)>) -> Self
+            where
+                K: Into<String>,
+                V: Into<String>,
+            {
+                self.labels.extend(labels.into_iter().map(|(key, value)| (key.into(), value.into())));
+                self
+            }
+
+            /// Add a static label for each of the given environment variables that is set and
+            /// non-empty, named after the lowercase variable name, e.g. `hostname` for `HOSTNAME`.
+            #vis fn with_labels_from_env(self, vars: &[&str]) -> Self {
+                self.with_labels(#krate::labels_from_env(vars))
+            }
+
+            /// Add the `pod`, `namespace` and `node` static labels of a Kubernetes pod, from the
+            /// `POD_NAME`, `POD_NAMESPACE` and `NODE_NAME` environment variables, skipping the
+            /// unset ones.
+            #vis fn with_kubernetes_labels(self) -> Self {
+                self.with_labels(#krate::kubernetes_labels())
+            }
+
+            #scope_methods
+
+            /// Prefix the names of the metrics, e.g. with the scope of a parent metrics struct
+            /// flattening this one with `#[metric(flatten)]`. Replaces the `parent` scope.
+            #vis fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+                self.prefix = Some(prefix.into());
+                self
+            }
+
+            #[doc = #disabled_doc]
+            #vis fn disabled(mut self, disabled: bool) -> Self {
+                self.disabled = Some(disabled);
+                self
+            }
+
+            #(#builder_methods)*
+
+            #name_resolvers
+
             /// Build and register the metrics with the registry.
             #vis fn build(self) -> #ident {
-                #ident {
-                    #(#initializers),*
-                }
+                #discard_builder
+                #resolve_disabled
+                #handle_names
+                #build_body
             }
         }
 
         #input
     };
 
-    let static_decl = if metrics_attr._static {
-        let static_name = format_ident!("{}", to_screaming_snake(&ident.to_string()));
-        Some(quote! {
+    // The parent of the attribute is the default prefix of the builder.
+    let (parent_scope, default_prefix) = match &metrics_attr.parent {
+        Some(parent) => {
+            (quote! { Some(#parent) }, quote! { Some(::std::string::ToString::to_string(#parent)) })
+        }
+        None => (quote! { None }, quote! { None }),
+    };
+
+    // The registry of the attribute is the default of the builder, and of the static instance.
+    let default_registry = match &metrics_attr.registry {
+        Some(registry) => quote! { ::std::borrow::Cow::Owned((#registry).clone()) },
+        None => quote! {
+            ::std::borrow::Cow::Borrowed(#krate::prometheus::default_registry())
+        },
+    };
+
+    let static_decl = metrics_attr._static.name(ident)?.map(|static_name| {
+        quote! {
             /// A static instance of the metrics, initialized with default values.
             /// This static is generated when `static` is enabled on the `#[metrics]` attribute.
             #vis static #static_name: ::std::sync::LazyLock<#ident> = ::std::sync::LazyLock::new(|| #ident::builder().build());
-        })
-    } else {
-        None
-    };
+        }
+    });
 
     // When static is true, make builder() private so users must use the static LazyLock
-    let builder_vis = if metrics_attr._static {
+    let builder_vis = if metrics_attr._static.is_enabled() {
         quote! {}
     } else {
         quote! { #vis }
     };
 
     // When static is true, don't implement Default
-    let default_impl = if metrics_attr._static {
+    let default_impl = if metrics_attr._static.is_enabled() {
         quote! {}
     } else {
         quote! {
@@ -641,6 +4390,11 @@ pub fn expand(metrics_attr: MetricsAttr, input: &mut ItemStruct) -> Result<Token
         }
     };
 
+    let alert_rules_doc = format!(
+        "Returns the alert rules defined with `#[metric(alert(...))]` on the metrics of this \
+         struct. Render them with [`{doc_krate}::rules::RuleFileBuilder`]."
+    );
+
     output = quote! {
         #output
 
@@ -651,19 +4405,184 @@ pub fn expand(metrics_attr: MetricsAttr, input: &mut ItemStruct) -> Result<Token
         #(#accessor_impls)*
 
         impl #ident {
+            /// The parent scope prefixing the names of the metrics when they aren't nested, set
+            /// with `#[metrics(parent = ...)]`.
+            #vis const PARENT_SCOPE: Option<&'static str> = #parent_scope;
+
             /// Create a new builder for the metrics struct.
             /// It will be initialized with the default registry and no labels.
             #builder_vis fn builder<'a>() -> #builder_name<'a> {
                 #builder_name {
-                    registry: ::prometric::prometheus::default_registry(),
+                    registry: #default_registry,
+                    registry_prefix: None,
                     labels: ::std::collections::HashMap::new(),
+                    scope: None,
+                    subsystem: None,
+                    prefix: #default_prefix,
+                    disabled: None,
+                    enabled: ::std::collections::HashSet::new(),
+                    shared: ::std::collections::HashMap::new(),
                 }
             }
 
+            #[doc = #alert_rules_doc]
+            #vis fn alert_rules() -> Vec<#krate::rules::AlertRule> {
+                #alert_rules_body
+            }
+
+            /// Returns the registry the metrics of this struct are registered with, e.g. to pass it
+            /// to the exporter when using a custom registry. Returns the default registry when
+            /// metrics are disabled.
+            #vis fn registry(&self) -> &#krate::prometheus::Registry {
+                #registry_body
+            }
+
+            /// Reset the metrics of this struct, including nested metrics, to their state after
+            /// `build()`: all the children of the metrics are removed, and the initial values set
+            /// again. This returns to a clean state, e.g. between tests sharing the default
+            /// registry.
+            #vis fn reset_all(&self) {
+                #reset_body
+            }
+
+            /// Unregister the metrics of this struct, including nested metrics, from their
+            /// registry. Updates keep applying to the metrics, without exporting them. Called on
+            /// drop with `#[metrics(scoped)]`.
+            #vis fn unregister(&self) {
+                #unregister_body
+            }
+
+            /// Gather the metric families of this struct from its registry, excluding the other
+            /// metrics of the registry.
+            #vis fn gather(&self) -> Vec<#krate::prometheus::proto::MetricFamily> {
+                #gather_body
+            }
+
+            /// Gather the metric families of this struct from its registry, and encode them in the
+            /// text format. This lets library crates expose their metrics without owning the
+            /// exporter.
+            #vis fn render(&self) -> String {
+                #render_body
+            }
+
+            /// The name, type and label names of the metrics of this struct, with the compile-time
+            /// scope. Nested metrics aren't included, see the table of their own struct.
+            #vis const METRICS: &'static [#krate::descriptor::MetricMetadata] =
+                &[#(#metadata_table),*];
+
+            /// Returns the descriptors of the metrics of this struct: name, type, help, label names,
+            /// unit, and buckets or quantiles. The label names don't include the static labels of
+            /// the builder, and the names use the compile-time scope.
+            #vis fn describe() -> Vec<#krate::descriptor::MetricDescriptor> {
+                #describe_body
+            }
+
+            #catalog
+
             #(#accessors)*
         }
     };
 
+    if metrics_attr.scoped {
+        output = quote! {
+            #output
+
+            impl Drop for #ident {
+                fn drop(&mut self) {
+                    self.unregister();
+                }
+            }
+        };
+    }
+
+    if metrics_attr.test_support {
+        let record_into_body = if DISABLED {
+            quote! { let _ = recorder; }
+        } else {
+            quote! { #(#record_intos)* }
+        };
+        output = quote! {
+            #output
+
+            impl #ident {
+                /// Record the operations on the metrics of this struct, including nested metrics,
+                /// into the given recorder, whether it is started or not. Nested metrics must also
+                /// enable `test_support`. Generated with `#[metrics(test_support)]`.
+                #vis fn record_into(&self, recorder: &#krate::recording::Recorder) {
+                    #record_into_body
+                }
+
+                /// Build the metrics with a private registry, recording the calls of their
+                /// accessors (metric, label values and value) into the returned recorder, e.g. to
+                /// assert that a code path incremented a metric. Generated with
+                /// `#[metrics(test_support)]`.
+                #vis fn mock() -> (Self, #krate::recording::Recorder) {
+                    let registry = #krate::prometheus::Registry::new();
+                    let metrics = Self::builder().with_registry(&registry).disabled(false).build();
+                    let recorder = #krate::recording::Recorder::new();
+                    metrics.record_into(&recorder);
+                    (metrics, recorder)
+                }
+            }
+        };
+    }
+
+    if metrics_attr.debug {
+        let values = if DISABLED {
+            quote! { Vec::<(String, f64)>::new() }
+        } else {
+            quote! { self.__metrics_handle.values() }
+        };
+        let name = ident.to_string();
+        output = quote! {
+            #output
+
+            impl ::std::fmt::Debug for #ident {
+                fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                    write!(f, "{} ", #name)?;
+                    let mut map = f.debug_map();
+                    for (series, value) in #values {
+                        map.entry(&::std::format_args!("{series}"), &value);
+                    }
+                    map.finish()
+                }
+            }
+
+            impl ::std::fmt::Display for #ident {
+                fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                    for (series, value) in #values {
+                        writeln!(f, "{series}={value}")?;
+                    }
+                    Ok(())
+                }
+            }
+        };
+    }
+
+    // The metrics of the fields are collected in declaration order, nothing when disabled.
+    if metrics_attr.collector {
+        let collectors = if DISABLED { Vec::new() } else { collectors };
+        output = quote! {
+            #output
+
+            impl #krate::prometheus::core::Collector for #ident {
+                fn desc(&self) -> Vec<&#krate::prometheus::core::Desc> {
+                    #[allow(unused_mut)]
+                    let mut desc = Vec::new();
+                    #(desc.extend((#collectors).into_iter().flat_map(|collector| collector.desc()));)*
+                    desc
+                }
+
+                fn collect(&self) -> Vec<#krate::prometheus::proto::MetricFamily> {
+                    #[allow(unused_mut)]
+                    let mut families = Vec::new();
+                    #(families.extend((#collectors).into_iter().flat_map(|collector| collector.collect()));)*
+                    families
+                }
+            }
+        };
+    }
+
     if let Some(static_decl) = static_decl {
         output = quote! {
             #output
@@ -672,5 +4591,221 @@ pub fn expand(metrics_attr: MetricsAttr, input: &mut ItemStruct) -> Result<Token
         };
     }
 
+    if metrics_attr._trait {
+        let trait_name = format_ident!("{ident}Api");
+        let noop_name = format_ident!("Noop{ident}");
+        let trait_doc = format!(
+            "The accessors of [`{ident}`], implemented by [`{noop_name}`] which records nothing. \
+             Generated with `#[metrics(trait)]`."
+        );
+        let noop_doc = format!(
+            "A no-op implementation of [`{trait_name}`], recording nothing and registering no \
+             metrics, e.g. for unit tests."
+        );
+
+        output = quote! {
+            #output
+
+            #[doc = #trait_doc]
+            #vis trait #trait_name {
+                #(#trait_methods)*
+            }
+
+            impl #trait_name for #ident {
+                #(#trait_metrics_methods)*
+            }
+
+            #[doc = #noop_doc]
+            #[derive(Debug, Default, Clone, Copy)]
+            #vis struct #noop_name;
+
+            impl #trait_name for #noop_name {
+                #(#trait_noop_methods)*
+            }
+        };
+    }
+
+    Ok(output)
+}
+
+/// Expand the `metrics` attribute on a fieldless enum into a state set metric: a
+/// `prometric::StateSet` with one series per variant, labeled with the name of the metric, where
+/// the current state is `1` and the others `0`.
+pub fn expand_state_set(metrics_attr: MetricsAttr, input: &ItemEnum) -> Result<TokenStream> {
+    let krate = metrics_attr.krate();
+    let unsupported = [
+        ("labels", metrics_attr.labels.is_some()),
+        ("static", metrics_attr._static.is_enabled()),
+        ("registry", metrics_attr.registry.is_some()),
+        ("trait", metrics_attr._trait),
+        ("catalog", metrics_attr.catalog),
+        ("compose", metrics_attr.compose),
+        ("scoped", metrics_attr.scoped),
+        ("clone", metrics_attr.clone),
+        ("collector", metrics_attr.collector),
+        ("debug", metrics_attr.debug),
+        ("test_support", metrics_attr.test_support),
+        ("test_utils", metrics_attr.test_utils),
+        ("parent", metrics_attr.parent.is_some()),
+        ("sanitize", metrics_attr.sanitize.is_some()),
+        ("max_label_len", metrics_attr.max_label_len.is_some()),
+    ];
+    if let Some((name, _)) = unsupported.iter().find(|(_, set)| *set) {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            format!("`{name}` isn't supported on enums"),
+        ));
+    }
+
+    let scope = metrics_attr.scope()?;
+
+    let Some(help) = doc_help(&input.attrs) else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "Unable to determine `help` label for metric. Document the enum",
+        ));
+    };
+    let help = render_help(help, &input.ident, &scope, None)?;
+    if input.variants.is_empty() {
+        return Err(syn::Error::new_spanned(input, "Expected at least one variant"));
+    }
+    if let Some(variant) =
+        input.variants.iter().find(|variant| !matches!(variant.fields, syn::Fields::Unit))
+    {
+        return Err(syn::Error::new_spanned(variant, "State set variants can't have fields"));
+    }
+
+    let vis = &input.vis;
+    let ident = &input.ident;
+    let metrics_name = format_ident!("{ident}Metrics");
+    let name = join_name(&scope, &to_screaming_snake(&ident.to_string()).to_lowercase());
+
+    let variants = input.variants.iter().map(|variant| &variant.ident).collect::<Vec<_>>();
+    let values = variants
+        .iter()
+        .map(|variant| to_screaming_snake(&variant.to_string()).to_lowercase())
+        .collect::<Vec<_>>();
+
+    let (field_ty, initializer, set_body) = if DISABLED {
+        (
+            quote! { ::core::marker::PhantomData<#krate::StateSet<#ident>> },
+            quote! {
+                let _ = registry;
+                ::core::marker::PhantomData
+            },
+            quote! { let _ = state; },
+        )
+    } else {
+        (
+            quote! { Option<#krate::StateSet<#ident>> },
+            quote! {
+                (!#krate::disabled_by_env()).then(|| {
+                    let state_set = #krate::StateSet::new(
+                        registry,
+                        #name,
+                        #help,
+                        &[],
+                        ::std::collections::HashMap::new(),
+                    );
+                    state_set.reset(&[]);
+                    state_set
+                })
+            },
+            quote! {
+                let Some(state_set) = &self.state_set else { return };
+                state_set.set(&[], state);
+            },
+        )
+    };
+
+    let doc = format!(
+        "The state set metric of [`{ident}`], where the series of the current state is `1` and \
+         the others `0`."
+    );
+
+    let output = quote! {
+        #input
+
+        impl #krate::LabelValue for #ident {
+            fn label_value(&self) -> &'static str {
+                match self {
+                    #(Self::#variants => #values,)*
+                }
+            }
+        }
+
+        impl #krate::State for #ident {
+            const STATES: &'static [Self] = &[#(Self::#variants),*];
+        }
+
+        #[doc = #doc]
+        #vis struct #metrics_name {
+            state_set: #field_ty,
+        }
+
+        impl #metrics_name {
+            /// The full name of the metric, also used as the name of its label.
+            #vis const NAME: &'static str = #name;
+
+            /// Register the metric with the registry, with every state set to `0`. Nothing is
+            /// registered if metrics are disabled with the `PROMETRIC_DISABLED` environment
+            /// variable.
+            #vis fn new(registry: &#krate::prometheus::Registry) -> Self {
+                Self { state_set: { #initializer } }
+            }
+
+            /// Set the current state, and the other states to `0`, atomically.
+            #vis fn set_state(&self, state: #ident) {
+                #set_body
+            }
+        }
+
+        impl Default for #metrics_name {
+            fn default() -> Self {
+                Self::new(#krate::prometheus::default_registry())
+            }
+        }
+    };
+    Ok(output)
+}
+
+/// Expand the `Labels` derive macro into an implementation of `prometric::LabelSet`.
+pub fn expand_label_set(input: &syn::DeriveInput) -> Result<TokenStream> {
+    let syn::Data::Struct(syn::DataStruct { fields: syn::Fields::Named(fields), .. }) = &input.data
+    else {
+        return Err(syn::Error::new_spanned(
+            input,
+            "Labels can only be derived for structs with named fields",
+        ));
+    };
+
+    // The path of the crate is set serde-style, e.g. `#[labels(crate = "facade::prometric")]`.
+    let mut krate = default_crate_path();
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("labels")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                krate = meta.value()?.parse::<LitStr>()?.parse::<syn::Path>()?;
+                Ok(())
+            } else {
+                Err(meta.error("Unknown `labels` argument, expected `crate`"))
+            }
+        })?;
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let field_idents =
+        fields.named.iter().filter_map(|field| field.ident.as_ref()).collect::<Vec<_>>();
+    let names = field_idents.iter().map(|ident| ident.to_string());
+
+    let output = quote! {
+        impl #impl_generics #krate::LabelSet for #ident #ty_generics #where_clause {
+            const NAMES: &'static [&'static str] = &[#(#names),*];
+
+            fn values(&self) -> impl AsRef<[&str]> {
+                [#(#krate::AsLabelValue::as_label_value(&self.#field_idents)),*]
+            }
+        }
+    };
     Ok(output)
 }
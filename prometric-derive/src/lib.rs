@@ -1,7 +1,7 @@
 //! This crate contains the attribute macro for generating Prometheus metrics.
 //! Refer to the [metrics] attribute documentation for more information.
 use proc_macro::TokenStream;
-use syn::{ItemStruct, parse_macro_input};
+use syn::{DeriveInput, Item, parse_macro_input};
 
 use crate::expand::MetricsAttr;
 
@@ -14,8 +14,57 @@ mod utils;
 ///
 /// # Attributes
 ///
-/// - `scope`: Sets the prefix for metric names (required)
-/// - `static`: If enabled, generates a static `LazyLock` with a SCREAMING_SNAKE_CASE name.
+/// - `scope`: Sets the prefix for metric names. Defaults to the name of the crate, with dashes
+///   replaced by underscores, and `scope = ""` leaves the names unprefixed. It can be overridden at
+///   runtime with the `with_scope` method of the builder, and extended with a subsystem segment
+///   with `with_subsystem` (e.g. `app_db_queries`).
+/// - `namespace` and `subsystem`: An alternative to `scope` following the conventions of the
+///   Prometheus client libraries, composed into the scope `namespace_subsystem` (e.g. `namespace =
+///   "myapp", subsystem = "p2p"` for `myapp_p2p_*`). `namespace` and `scope` are mutually
+///   exclusive, and `subsystem` can also extend a `scope`.
+/// - `scope_env`: An environment variable whose value, if set when the metrics are built, prefixes
+///   the scope, e.g. `scope_env = "METRICS_SCOPE"` for `tenant1_app_*` with
+///   `METRICS_SCOPE=tenant1`. Alert rules keep the compile-time scope.
+/// - `parent`: A `&str` constant prefixing the names of the metrics, e.g. `parent =
+///   crate::metrics::ROOT_SCOPE` for `root_p2p_*` with `scope = "p2p"`. See [Parent
+///   Scope](#parent-scope).
+/// - `labels`: Label keys prepended to the labels of every metric, e.g. `labels = ["chain"]`. Every
+///   accessor takes them as leading arguments.
+/// - `static`: If enabled, generates a static `LazyLock` with a SCREAMING_SNAKE_CASE name, or the
+///   name given with `static = "NAME"`.
+/// - `registry`: The default registry of the builder (and of the `static` metrics), instead of the
+///   global default registry, e.g. `registry = MY_REGISTRY.clone()`. Either a `Registry` or a
+///   reference to one. It can still be overridden with `with_registry`.
+/// - `trait`: If enabled, generates a companion trait with one method per accessor, and a no-op
+///   implementation. See [Companion Trait](#companion-trait).
+/// - `named_labels`: If enabled, accessors take no label arguments, and the labels are set with
+///   builder-style setters instead. See [Named Labels](#named-labels).
+/// - `sanitize`: The policy sanitizing untrusted label values passed to the accessors, one of
+///   `"escape"`, `"replace"` or `"reject"`, with an optional `max_label_len` in bytes. See [Label
+///   Sanitization](#label-sanitization).
+/// - `rename_all`: The casing of the metric names derived from field identifiers, one of
+///   `"snake_case"` (default), `"camelCase"`, `"PascalCase"` or `"SCREAMING_SNAKE_CASE"`. See
+///   [Metric Name Casing](#metric-name-casing).
+/// - `total_suffix`: If enabled, appends `_total` to counter names that don't already end with it.
+/// - `compose`: If enabled, every field is a nested metrics struct keeping its own scope, built
+///   with the registry and static labels of the composite. See [Composite
+///   Metrics](#composite-metrics).
+/// - `scoped`: If enabled, the metrics are unregistered from their registry when the struct is
+///   dropped. See [Scoped Registration](#scoped-registration).
+/// - `clone`: If enabled, derives `Clone` on the metrics struct, with clones sharing the same
+///   metrics. See [Cloning](#cloning).
+/// - `collector`: If enabled, implements `prometheus::core::Collector` for the metrics struct,
+///   collecting the metrics of its fields. See [Collector](#collector).
+/// - `debug`: If enabled, generates `Debug` and `Display` implementations printing the current
+///   values of the metrics. See [Rendering](#rendering).
+/// - `test_support`: If enabled, generates a `mock()` constructor recording the calls of the
+///   accessors. Requires the `recording` feature of `prometric`. See [Mocking](#mocking).
+/// - `test_utils`: If enabled, generates `#[cfg(test)]` getters reading the current values of the
+///   metrics back. See [Test Getters](#test-getters).
+/// - `catalog`: If enabled, generates a `catalog()` function returning the serializable catalog of
+///   the metrics. Requires the `catalog` feature of `prometric`. See [Catalog](#catalog).
+/// - `crate`: The path of the `prometric` crate in the generated code, e.g. `crate =
+///   "facade::prometric"`, when it's re-exported or renamed. See [Crate Path](#crate-path).
 ///
 /// # Example
 /// ```rust
@@ -38,7 +87,9 @@ mod utils;
 ///     #[metric(rename = "current_active_users", labels = ["service"], help = "The current number of active users.")]
 ///     current_users: Gauge,
 ///
-///     /// The balance of the account, in dollars. Uses a floating point number.
+///     // Multi-line doc comments are joined into a single help string.
+///     /// The balance of the account, in dollars.
+///     /// Uses a floating point number.
 ///     #[metric(rename = "account_balance", labels = ["account_id"])]
 ///     account_balance: Gauge<f64>,
 ///
@@ -101,30 +152,1390 @@ mod utils;
 /// SCREAMING_SNAKE_CASE name. The builder methods and `Default` implementation are made private,
 /// ensuring the only way to access the metrics is through the static instance.
 ///
-/// If `static` is enabled, `prometheus::default_registry()` is used.
+/// If `static` is enabled, the `registry` of the attribute is used, or
+/// `prometheus::default_registry()` by default. The static is named after the struct, unless a
+/// name is given with `static = "NAME"`, e.g. to shorten long struct names:
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "server", static = "METRICS")]
+/// struct ApplicationServerMetrics {
+///     /// The total number of requests.
+///     #[metric]
+///     requests: Counter,
+/// }
+///
+/// METRICS.requests().inc();
+/// ```
 ///
 /// ```rust
 /// use prometric::{Counter, Gauge};
 /// use prometric_derive::metrics;
 ///
-/// #[metrics(scope = "app", static)]
+/// #[metrics(scope = "app", static)]
+/// struct AppMetrics {
+///     /// The total number of requests.
+///     #[metric(labels = ["method"])]
+///     requests: Counter,
+///
+///     /// The current number of active connections.
+///     #[metric]
+///     active_connections: Gauge,
+/// }
+///
+/// // Use the static directly anywhere
+/// APP_METRICS.requests("GET").inc();
+/// APP_METRICS.active_connections().set(10);
+///
+/// // The following would not compile:
+/// // let metrics = AppMetrics::builder();  // Error: builder() is private
+/// // let metrics = AppMetrics::default();   // Error: Default is not implemented
+/// ```
+///
+/// # Timers
+/// Histogram accessors have a `start_timer()` method, returning a [`prometric::HistogramTimer`]
+/// guard observing the elapsed time when dropped. The time is observed in the declared time unit
+/// of the histogram, or in seconds.
+///
+/// ```rust
+/// use prometric::Histogram;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The duration of HTTP requests.
+///     #[metric(labels = ["method"])]
+///     http_requests_duration: Histogram,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// {
+///     let _timer = metrics.http_requests_duration("GET").start_timer();
+///     // Handle the request.
+/// }
+///
+/// // Stop a timer early, or discard it.
+/// let timer = metrics.http_requests_duration("POST").start_timer();
+/// let elapsed = timer.observe();
+///
+/// // Observe a measured duration, or the time elapsed since an instant.
+/// metrics.http_requests_duration("GET").observe_duration(std::time::Duration::from_millis(20));
+/// let start = std::time::Instant::now();
+/// metrics.http_requests_duration("PUT").observe_since(start);
+/// ```
+///
+/// Histogram and summary accessors also have `observe_duration(duration)` and
+/// `observe_since(instant)`, converting to the declared time unit, or to seconds, so durations
+/// can't be observed in the wrong unit. Timer accessors have `observe_since(instant)`.
+///
+/// Histogram and summary accessors also have `observe_future(future)` and `observe_async(|| ...)`
+/// methods, timing a future from its first poll to its completion. Nothing is observed if the
+/// future is dropped before completion.
+///
+/// ```rust
+/// # use prometric::Histogram;
+/// # use prometric_derive::metrics;
+/// #
+/// # #[metrics(scope = "app")]
+/// # struct AppMetrics {
+/// #     /// The duration of HTTP requests.
+/// #     #[metric(labels = ["method"])]
+/// #     http_requests_duration: Histogram,
+/// # }
+/// #
+/// async fn handle(metrics: &AppMetrics) -> u16 {
+///     metrics.http_requests_duration("GET").observe_future(async { 200 }).await
+/// }
+/// ```
+///
+/// # Timer Fields
+/// Fields typed as [`prometric::Timer`] pair a duration histogram with a call counter sharing its
+/// labels, e.g. `app_rpc_calls_duration_seconds` and `app_rpc_calls_total` for `rpc_calls`. The
+/// accessor has `start()`, returning a guard observing and counting the call when dropped,
+/// `observe(duration)` and `count()`. The `buckets` argument applies to the histogram.
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use prometric::Timer;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// RPC calls.
+///     #[metric(labels = ["method"])]
+///     rpc_calls: Timer,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// {
+///     let _timer = metrics.rpc_calls("eth_call").start();
+///     // Handle the call.
+/// }
+/// metrics.rpc_calls("eth_getLogs").observe(Duration::from_millis(120));
+/// let calls = metrics.rpc_calls("eth_call").count();
+/// ```
+///
+/// # Ratios
+/// Fields typed as [`prometric::Ratio`] with `#[metric(ratio = (numerator, denominator))]` export a
+/// gauge computed at gather time from two counter fields with the same labels, one series per label
+/// set of the numerator. Series whose denominator is missing or zero are skipped. The accessor
+/// returns the [`prometric::Ratio`], or `None` when metrics are disabled.
+///
+/// ```rust
+/// use prometric::{Counter, Ratio};
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The cache hit ratio.
+///     #[metric(ratio = (cache_hits, cache_lookups))]
+///     cache_hit_ratio: Ratio,
+///     /// The total number of cache hits.
+///     #[metric(labels = ["cache"])]
+///     cache_hits: Counter,
+///     /// The total number of cache lookups.
+///     #[metric(labels = ["cache"])]
+///     cache_lookups: Counter,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.cache_hits("blocks").inc();
+/// metrics.cache_lookups("blocks").inc_by(2u64);
+/// let ratio = metrics.cache_hit_ratio().and_then(|ratio| ratio.value(&[("cache", "blocks")]));
+/// ```
+///
+/// # Callback Gauges
+/// Fields typed as [`prometric::GaugeFn`] with `#[metric(eval = ...)]` export a gauge evaluated
+/// when the registry is gathered, rather than set whenever the value changes. The argument is a
+/// closure or function returning a number, and the gauge has the static labels of the builder, but
+/// no labels of its own. The accessor returns the [`prometric::GaugeFn`], or `None` when metrics
+/// are disabled.
+///
+/// ```rust
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// use prometric::GaugeFn;
+/// use prometric_derive::metrics;
+///
+/// static QUEUE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The number of queued jobs.
+///     #[metric(eval = || QUEUE_DEPTH.load(Ordering::Relaxed))]
+///     queue_depth: GaugeFn,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// QUEUE_DEPTH.store(3, Ordering::Relaxed);
+/// let depth = metrics.queue_depth().map(GaugeFn::get);
+/// ```
+///
+/// # Info Fields
+/// Fields typed as [`prometric::Info`] export a constant `1`-valued gauge whose labels carry
+/// metadata, such as the version and git SHA of the build. The accessor has `set()`, replacing the
+/// previously set label values, so the metric keeps a single series.
+///
+/// ```rust
+/// use prometric::Info;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The build of the application.
+///     #[metric(labels = ["version", "git_sha"])]
+///     build_info: Info,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.build_info(env!("CARGO_PKG_VERSION"), "abc123").set();
+/// ```
+///
+/// # Outcome Counters
+/// Fields typed as [`prometric::OutcomeCounter`] count operations by an implicit `outcome` label,
+/// appended to their labels, with the `success` or `failure` value. The accessor has `success()`,
+/// `failure()`, `inc(outcome)`, and `record(result)`, counting the outcome of a `Result` and
+/// returning it.
+///
+/// ```rust
+/// use prometric::OutcomeCounter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of RPC calls.
+///     #[metric(labels = ["method"])]
+///     rpc_calls: OutcomeCounter,
+/// }
+///
+/// fn call(metrics: &AppMetrics) -> Result<u64, String> {
+///     let block = metrics.rpc_calls("eth_blockNumber").record(Ok::<_, String>(1))?;
+///     Ok(block)
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.rpc_calls("eth_call").failure();
+/// assert_eq!(call(&metrics), Ok(1));
+/// ```
+///
+/// # In-flight Tracking
+/// Gauge accessors have a `track()` method, incrementing the gauge and returning a
+/// [`prometric::GaugeGuard`] decrementing it when dropped, including when unwinding from a panic.
+///
+/// ```rust
+/// use prometric::Gauge;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The number of in-flight HTTP requests.
+///     #[metric(labels = ["method"])]
+///     http_requests_in_flight: Gauge,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// {
+///     let _guard = metrics.http_requests_in_flight("GET").track();
+///     // Handle the request.
+/// }
+/// ```
+///
+/// # Timestamp Gauges
+/// Gauge accessors have a `set_to_current_time()` method, setting the gauge to the current UNIX
+/// timestamp in seconds, e.g. for `*_last_success_timestamp_seconds` metrics. Integer gauges are
+/// truncated to whole seconds.
+///
+/// ```rust
+/// use prometric::Gauge;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The time of the last successful backup, in seconds since the UNIX epoch.
+///     last_backup_timestamp_seconds: Gauge<f64>,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.last_backup_timestamp_seconds().set_to_current_time();
+/// ```
+///
+/// # Bucket Generators
+/// Instead of an array, `buckets` can be generated with `linear(start, width, count)` or
+/// `exponential(start, factor, count)`, expanding to [`prometric::prometheus::linear_buckets`] and
+/// [`prometric::prometheus::exponential_buckets`]:
+///
+/// ```rust
+/// use prometric::Histogram;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The duration of HTTP requests, from 1ms to ~16s.
+///     #[metric(buckets = exponential(0.001, 2.0, 15))]
+///     http_requests_duration: Histogram,
+/// }
+/// ```
+///
+/// # Constant Labels
+/// The `const_labels` argument of `metric` adds constant labels to a single metric, in addition to
+/// the static labels of the builder:
+///
+/// ```rust
+/// use prometric::Gauge;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The number of queued jobs.
+///     #[metric(labels = ["priority"], const_labels = [("queue", "ingest")])]
+///     queued_jobs: Gauge,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.queued_jobs("high").set(3);
+/// ```
+///
+/// # Label Values
+/// Accessors take the values of untyped labels as [`prometric::ToLabelValue`], implemented for all
+/// types implementing `Display`, so integers or addresses are passed without `format!()`.
+///
+/// ```rust
+/// use std::net::SocketAddr;
+///
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of messages received from each peer, per protocol version.
+///     #[metric(labels = ["peer", "version"])]
+///     messages: Counter,
+/// }
+///
+/// let peer: SocketAddr = "10.0.0.1:30303".parse().unwrap();
+/// let metrics = AppMetrics::default();
+/// metrics.messages(peer, 68).inc();
+/// ```
+///
+/// # Label Sanitization
+/// With `#[metrics(sanitize = "...")]`, the values of untyped labels passed to the accessors are
+/// sanitized, e.g. peer-supplied strings. Values containing control characters such as newlines,
+/// or the U+FFFD character left by the lossy decoding of invalid UTF-8, or longer than
+/// `max_label_len` bytes, are escaped (`"escape"`), have their invalid characters replaced with `_`
+/// (`"replace"`), or are rejected (`"reject"`), in which case the update is dropped. Over-long
+/// values are truncated by the first two policies. Typed labels, label sets and raw accessors are
+/// not sanitized. See [`prometric::sanitize`].
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "p2p", sanitize = "escape", max_label_len = 64)]
+/// struct PeerMetrics {
+///     /// The total number of messages received from each client.
+///     #[metric(labels = ["client"])]
+///     messages: Counter,
+/// }
+///
+/// let metrics = PeerMetrics::default();
+/// // Exported as `client="geth\\n"`.
+/// metrics.messages("geth\n").inc();
+/// ```
+///
+/// # Named Labels
+/// With `#[metrics(named_labels)]`, accessors take no label arguments. Labels are set by name with
+/// setters on the accessor, in any order, so label values can't be swapped silently.
+///
+/// NOTE: Every label must be set before the update, which isn't checked at compile time. An update
+/// (or read) with an unset label panics in debug builds, and is silently dropped in release builds,
+/// like one with a rejected label value.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app", named_labels)]
+/// struct AppMetrics {
+///     /// The total number of HTTP requests.
+///     #[metric(labels = ["method", "path", "status", "region"])]
+///     http_requests: Counter,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.http_requests().method("GET").path("/").status(200).region("eu").inc();
+/// ```
+///
+/// # Typed Labels
+/// A label can be bound to a type implementing [`prometric::LabelValue`] with `name: Type` in
+/// `labels`, e.g. an enum. The accessor then takes that type instead of any `Display` value,
+/// preventing typos and unbounded cardinality.
+///
+/// ```rust
+/// use prometric::{Counter, LabelValue};
+/// use prometric_derive::metrics;
+///
+/// enum HttpMethod {
+///     Get,
+///     Post,
+/// }
+///
+/// impl LabelValue for HttpMethod {
+///     fn label_value(&self) -> &'static str {
+///         match self {
+///             Self::Get => "GET",
+///             Self::Post => "POST",
+///         }
+///     }
+/// }
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of HTTP requests.
+///     #[metric(labels = [method: HttpMethod, "path"])]
+///     http_requests: Counter,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.http_requests(HttpMethod::Get, "/").inc();
+/// ```
+///
+/// # Raw Accessors
+/// Every metric also gets a raw accessor, suffixed with `_with`, taking the values of all its
+/// labels in order as a slice, including the labels of the struct. This is an escape hatch for
+/// label values assembled dynamically. Updating the metric panics if the number of values doesn't
+/// match its labels.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of HTTP requests.
+///     #[metric(labels = ["method", "status"])]
+///     http_requests: Counter,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// let status = 200.to_string();
+/// metrics.http_requests_with(&["GET", &status]).inc();
+/// ```
+///
+/// # Removing Series
+/// Accessors of metrics with labels have a `remove()` method, deleting the series of their label
+/// values, e.g. to drop the series of a disconnected peer in long-running processes. It returns
+/// `false` if the series doesn't exist.
+///
+/// ```rust
+/// use prometric::Gauge;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "p2p")]
+/// struct PeerMetrics {
+///     /// The latency of each peer, in milliseconds.
+///     #[metric(labels = ["peer"])]
+///     peer_latency: Gauge,
+/// }
+///
+/// let metrics = PeerMetrics::default();
+/// metrics.peer_latency("peer-1").set(20);
+/// // On disconnect
+/// metrics.peer_latency("peer-1").remove();
+/// ```
+///
+/// The generated `reset_all()` method removes the series of every metric of the struct, including
+/// nested metrics, and sets the initial values of `#[metric(init = ...)]` again. Integration tests
+/// sharing a registry can return to a clean state between cases with a single call.
+///
+/// ```rust
+/// # use prometric::Gauge;
+/// # use prometric_derive::metrics;
+/// #
+/// # #[metrics(scope = "p2p")]
+/// # struct PeerMetrics {
+/// #     /// The latency of each peer, in milliseconds.
+/// #     #[metric(labels = ["peer"])]
+/// #     peer_latency: Gauge,
+/// # }
+/// #
+/// let metrics = PeerMetrics::default();
+/// metrics.peer_latency("peer-1").set(20);
+/// metrics.reset_all();
+/// ```
+///
+/// # Scoped Registration
+/// Metrics stay registered after their struct is dropped. With `#[metrics(scoped)]`, the struct
+/// unregisters its metrics on drop, including nested metrics, so short-lived components (e.g. per
+/// connection or per job) don't leak their collectors. Other structs can call the generated
+/// `unregister()` method explicitly.
+///
+/// Registrations are identified by metric name and static labels: give each instance distinct
+/// static labels, or dropping one instance unregisters the metrics of another.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "conn", scoped)]
+/// struct ConnectionMetrics {
+///     /// The total number of bytes received.
+///     #[metric]
+///     received_bytes: Counter,
+/// }
+///
+/// let registry = prometheus::Registry::new();
+/// let metrics =
+///     ConnectionMetrics::builder().with_registry(&registry).with_label("peer", "peer-1").build();
+/// metrics.received_bytes().inc_by(1024);
+/// drop(metrics);
+/// assert!(registry.gather().is_empty());
+/// ```
+///
+/// # Cloning
+/// Metrics structs can derive `Clone`, with `#[metrics(clone)]` or `#[derive(Clone)]` on the
+/// struct. Cloning is cheap: the clones share the same metrics, so updates through any clone are
+/// exported once, and unregistering one unregisters them all. `scoped` structs can't be cloned, as
+/// dropping a clone would unregister the metrics of the others.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app", clone)]
+/// struct AppMetrics {
+///     /// The total number of processed jobs.
+///     #[metric]
+///     jobs: Counter,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// let worker_metrics = metrics.clone();
+/// std::thread::spawn(move || worker_metrics.jobs().inc()).join().unwrap();
+/// ```
+///
+/// # Collector
+/// With `#[metrics(collector)]`, the metrics struct implements `prometheus::core::Collector`,
+/// collecting the metrics of its fields in declaration order. This allows registering it in a
+/// registry only known after it's built: build it with a private registry, then register a clone
+/// of it. Flattened and nested structs must also have the `collector` option. Deprecated aliases
+/// and the overflow counters of cardinality limits are only registered in the registry of the
+/// builder. When metrics are disabled, nothing is collected.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app", clone, collector)]
+/// struct AppMetrics {
+///     /// The total number of processed jobs.
+///     #[metric]
+///     jobs: Counter,
+/// }
+///
+/// let metrics = AppMetrics::builder().with_registry(&prometheus::Registry::new()).build();
+/// metrics.jobs().inc();
+///
+/// let registry = prometheus::Registry::new();
+/// registry.register(Box::new(metrics.clone())).unwrap();
+/// let _output = prometric::gather_to_string(&registry).unwrap();
+/// ```
+///
+/// # Cached Handles
+/// Every metric also gets a handle method, suffixed with `_handle`, taking the same arguments as
+/// its accessor. It resolves the labels once and returns a handle to the child metric, which can
+/// be stored in hot paths to skip the label lookup on every update. Accessors also have a
+/// `handle()` method.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of processed packets.
+///     #[metric(labels = ["direction"])]
+///     packets: Counter,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// let inbound = metrics.packets_handle("inbound");
+/// for _ in 0..10 {
+///     inbound.inc();
+/// }
+/// ```
+///
+/// # Thread-local Metrics
+/// Counters and histograms updated from many threads at once can buffer their updates per thread
+/// with `#[metric(local)]`, avoiding the contention on their shared atomics. The buffered updates
+/// of all threads are flushed every [`prometric::local::FLUSH_INTERVAL`] and on every scrape, and
+/// those of a thread on [`prometric::local::flush`] and when it exits. Handles aren't buffered.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of processed events.
+///     #[metric(labels = ["kind"], local)]
+///     events: Counter,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.events("block").inc();
+/// prometric::local::flush();
+/// ```
+///
+/// # Exemplars
+/// Counters and histograms with `#[metric(exemplars)]` have `inc_with_exemplar(trace_id)`,
+/// `inc_by_with_exemplar(value, labels)` and `observe_with_exemplar(value, labels)` accessor
+/// methods, recording the latest exemplar of each series or bucket, e.g. to jump from a latency
+/// panel to a trace. Exemplars are only exposed in the OpenMetrics format, which the exporter
+/// serves to scrapers accepting it. See [`prometric::exemplar`].
+///
+/// ```rust
+/// use prometric::{Counter, Histogram};
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of requests.
+///     #[metric(labels = ["method"], exemplars)]
+///     requests: Counter,
+///     /// The duration of the requests, in seconds.
+///     #[metric(exemplars)]
+///     duration: Histogram,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.requests("GET").inc_with_exemplar("4bf92f3577b34da6");
+/// metrics.duration().observe_with_exemplar(0.25, &[("trace_id", "4bf92f3577b34da6")]);
+/// ```
+///
+/// # Created Timestamps
+/// Counters and summaries with `#[metric(created)]` track the creation time of each series,
+/// exported as its `_created` sample in the OpenMetrics format, e.g. for scrapers detecting counter
+/// resets. The creation time is recorded on the first update of a series, and renewed when it's
+/// reset. Every update locks a process-wide store, so it's best kept off hot paths. See
+/// [`prometric::created`].
+///
+/// ```rust
+/// use prometric::{Counter, Summary};
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of requests.
+///     #[metric(labels = ["method"], created)]
+///     requests: Counter,
+///     /// The size of the responses, in bytes.
+///     #[metric(created)]
+///     response_size: Summary,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.requests("GET").inc();
+/// metrics.response_size().observe(512.0);
+/// ```
+///
+/// # Cardinality Limits
+/// Counters, gauges and histograms with `#[metric(max_cardinality = ...)]` track their distinct
+/// label value combinations. Beyond the limit, new combinations are routed to a single series
+/// whose label values are all `other`, or dropped with `on_overflow = "drop"`, and counted by the
+/// `<name>_cardinality_overflow` counter. See [`prometric::cardinality`].
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of messages received from each peer.
+///     #[metric(labels = ["peer"], max_cardinality = 1000)]
+///     received: Counter,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.received("10.0.0.1:30303").inc();
+/// ```
+///
+/// # Label Sets
+/// A struct deriving [`Labels`] can be used as `labels = Type`, to share a set of labels between
+/// several metrics. The accessor then takes the label set instead of positional labels.
+///
+/// ```rust
+/// use prometric::{Counter, Histogram};
+/// use prometric_derive::{Labels, metrics};
+///
+/// #[derive(Labels)]
+/// struct RequestLabels {
+///     method: &'static str,
+///     path: String,
+/// }
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of HTTP requests.
+///     #[metric(labels = RequestLabels)]
+///     http_requests: Counter,
+///     /// The duration of HTTP requests.
+///     #[metric(labels = RequestLabels)]
+///     http_requests_duration: Histogram,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.http_requests(RequestLabels { method: "GET", path: "/".to_owned() }).inc();
+/// ```
+///
+/// Label names can also come from a `&[&str]` const, with `label_names = CONST_NAME`, while
+/// `labels = Type` always names a label set type. The accessor then takes an array of label values
+/// of the same length.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// const COMMON_LABELS: &[&str] = &["chain", "network"];
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of RPC requests.
+///     #[metric(label_names = COMMON_LABELS)]
+///     rpc_requests: Counter,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.rpc_requests(["ethereum", "mainnet"]).inc();
+/// ```
+///
+/// # Mocking
+/// With `#[metrics(test_support)]`, the generated `mock()` constructor builds the metrics with a
+/// private registry, and returns them with a [`prometric::recording::Recorder`] logging every
+/// call of their accessors: metric, label values and value. Tests can assert that a code path
+/// updated a metric without scraping the text output. Nested metrics must enable
+/// `test_support` too. Requires the `recording` feature of `prometric`, usually as a
+/// dev-dependency.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app", test_support)]
+/// struct AppMetrics {
+///     /// The total number of errors.
+///     #[metric(labels = ["kind"])]
+///     errors: Counter,
+/// }
+///
+/// let (metrics, recorder) = AppMetrics::mock();
+/// metrics.errors("timeout").inc();
+/// # #[cfg(not(prometric_disabled))]
+/// assert_eq!(recorder.log(), [r#"app_errors{kind="timeout"} inc"#]);
+/// ```
+///
+/// # Reading Values
+/// The accessors read the current value of their series back with `get()` on counters and gauges,
+/// `get_sample_count()` and `get_sample_sum()` on histograms and summaries, and `count()` on
+/// timers, e.g. to log or branch on a gauge. Reading a series creates it, without taking a slot of
+/// its cardinality limit: series beyond the limit read as zero. The buffered updates of `local`
+/// metrics aren't included until flushed. Disabled metrics read as zero.
+///
+/// ```rust
+/// use prometric::{Gauge, Histogram};
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The number of queued jobs.
+///     queued_jobs: Gauge,
+///     /// The duration of HTTP requests.
+///     #[metric(labels = ["method"])]
+///     http_requests_duration: Histogram,
+/// }
+///
+/// let metrics = AppMetrics::builder().with_registry(&prometheus::Registry::new()).build();
+/// metrics.queued_jobs().set(3);
+/// metrics.http_requests_duration("GET").observe(0.5);
+/// # #[cfg(not(prometric_disabled))]
+/// # {
+/// assert_eq!(metrics.queued_jobs().get(), 3);
+/// assert_eq!(metrics.http_requests_duration("GET").get_sample_count(), 1);
+/// assert_eq!(metrics.http_requests_duration("GET").get_sample_sum(), 0.5);
+/// # }
+/// ```
+///
+/// # Test Getters
+/// With `#[metrics(test_utils)]`, every metric also gets `#[cfg(test)]` getters reading the
+/// current value of its series back, taking the same arguments as its accessor:
+/// `<field>_value` for counters and gauges, `<field>_count` and `<field>_sum` for histograms and
+/// summaries, and `<field>_count` for timers. The getters call the read methods of the accessors
+/// (see [Reading Values](#reading-values)), which structs with `named_labels` use instead. The
+/// getters are only compiled in the unit tests of the crate defining the struct.
+///
+/// ```rust
+/// use prometric::{Counter, Histogram};
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app", test_utils)]
+/// struct AppMetrics {
+///     /// The total number of HTTP requests.
+///     #[metric(labels = ["method", "path"])]
+///     http_requests: Counter,
+///     /// The duration of HTTP requests.
+///     #[metric(labels = ["method"])]
+///     http_requests_duration: Histogram,
+/// }
+///
+/// // In a `#[test]` of the crate:
+/// #[cfg(test)]
+/// fn counts_requests() {
+///     let metrics = AppMetrics::builder().with_registry(&prometheus::Registry::new()).build();
+///     metrics.http_requests("GET", "/").inc();
+///     assert_eq!(metrics.http_requests_value("GET", "/"), 1);
+///     assert_eq!(metrics.http_requests_duration_count("GET"), 0);
+/// }
+/// ```
+///
+/// # Descriptors
+/// The generated `describe()` function returns a [`prometric::descriptor::MetricDescriptor`] for
+/// every metric of the struct, with its name, type, help, label names, unit, and buckets or
+/// quantiles. This is useful for admin endpoints and documentation generators.
+///
+/// ```rust
+/// use prometric::Histogram;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The duration of HTTP requests.
+///     #[metric(labels = ["method"], buckets = [0.1, 1.0])]
+///     http_requests_duration: Histogram,
+/// }
+///
+/// let descriptors = AppMetrics::describe();
+/// assert_eq!(descriptors[0].name, "app_http_requests_duration");
+/// assert_eq!(descriptors[0].buckets, Some(vec![0.1, 1.0]));
+/// ```
+///
+/// # Name Constants
+/// The full name of every metric is also available as a constant, suffixed with `_NAME`, and its
+/// variable label names as an array constant, suffixed with `_LABELS`. The generated `METRICS`
+/// table holds the name, type and label names of every metric as a
+/// [`prometric::descriptor::MetricMetadata`], e.g. to reference metric names in alert tests. They
+/// use the compile-time scope.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of HTTP requests.
+///     #[metric(rename = "http_requests_total", labels = ["method"])]
+///     http_requests: Counter,
+/// }
+///
+/// assert_eq!(AppMetrics::HTTP_REQUESTS_NAME, "app_http_requests_total");
+/// assert_eq!(AppMetrics::HTTP_REQUESTS_LABELS, ["method"]);
+/// assert_eq!(AppMetrics::METRICS[0].labels, ["method"]);
+/// ```
+///
+/// # Catalog
+/// With `#[metrics(catalog)]` and the `catalog` feature of `prometric`, the generated `catalog()`
+/// function returns the descriptors of the metrics as a [`prometric::catalog::Catalog`], which
+/// serializes to JSON for external tooling, e.g. to generate dashboards.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app", catalog)]
+/// struct AppMetrics {
+///     /// The total number of HTTP requests.
+///     #[metric(labels = ["method"])]
+///     http_requests: Counter,
+/// }
+///
+/// let json = AppMetrics::catalog().to_json();
+/// assert!(json.contains(r#""name": "app_http_requests""#));
+/// ```
+///
+/// # Rendering
+/// The generated `gather()` and `render()` methods gather only the metrics of the struct from its
+/// registry, as metric families or encoded in the text format. Library crates can use them to
+/// expose their metrics without owning the exporter. Metrics built against a registry created
+/// with `prometric::prefixed_registry` are gathered by their prefixed names when the builder is
+/// given the same prefix with `with_registry_prefix`. The registry itself is returned by the
+/// generated `registry()` method, e.g. to pass a custom registry to the exporter.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "lib")]
+/// struct LibMetrics {
+///     /// The total number of calls.
+///     #[metric]
+///     calls: Counter,
+/// }
+///
+/// let metrics = LibMetrics::default();
+/// metrics.calls().inc();
+///
+/// // Only the `lib_*` metrics of the default registry.
+/// let text = metrics.render();
+/// ```
+///
+/// With `#[metrics(debug)]`, the struct implements `Debug` and `Display`, printing the current
+/// value of each series, e.g. with `dbg!(&metrics)` on test failures. Histograms and summaries
+/// print their `_count` and `_sum` series.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "job", debug)]
+/// struct JobMetrics {
+///     /// The total number of processed items.
+///     #[metric]
+///     items: Counter,
+/// }
+///
+/// let metrics = JobMetrics::default();
+/// metrics.items().inc_by(3);
+/// // Prints `JobMetrics {job_items: 3.0}`.
+/// dbg!(&metrics);
+/// ```
+///
+/// # Conditional Metrics
+/// Fields guarded by `#[cfg(...)]` attributes have their initializers, accessors and alert rules
+/// guarded by the same attributes, so metrics can be feature-gated.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of cache hits.
+///     #[cfg(feature = "cache")]
+///     #[metric]
+///     cache_hits: Counter,
+/// }
+/// ```
+///
+/// # Help Placeholders
+/// The `{scope}` and `{unit}` placeholders of a help string, from the `help` argument or the doc
+/// comment, are replaced with the compile-time scope of the struct and the `unit` of the metric.
+/// This keeps the help accurate for metrics structs generated by a declarative macro. Scopes set
+/// at runtime, like prefixes or `scope_env`, aren't reflected.
+///
+/// ```rust
+/// use prometric::Histogram;
+/// use prometric_derive::metrics;
+///
+/// macro_rules! service_metrics {
+///     ($name:ident, $scope:literal) => {
+///         #[metrics(scope = $scope)]
+///         struct $name {
+///             /// The latency of {scope} requests, in {unit}.
+///             #[metric(unit = "seconds")]
+///             latency: Histogram,
+///         }
+///     };
+/// }
+///
+/// service_metrics!(RpcMetrics, "rpc");
+///
+/// assert_eq!(RpcMetrics::describe()[0].help, "The latency of rpc requests, in seconds.");
+/// ```
+///
+/// # Absolute Names
+/// Metrics with an externally mandated name (e.g. `up` or `build_info`) can skip the scope of the
+/// struct with `#[metric(absolute)]`, including a scope set at runtime. Combine it with `rename`
+/// to set the exact name.
+///
+/// ```rust
+/// use prometric::Gauge;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// Whether the application is up.
+///     #[metric(absolute)]
+///     up: Gauge,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.up().set(1);
+/// ```
+///
+/// # Labels from the Environment
+/// The builder can read static labels from environment variables, skipping the unset or empty
+/// ones: `with_labels_from_env` names each label after the lowercase variable, and
+/// `with_kubernetes_labels` adds the `pod`, `namespace` and `node` labels from the `POD_NAME`,
+/// `POD_NAMESPACE` and `NODE_NAME` variables, usually set with the Downward API.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of HTTP requests.
+///     #[metric]
+///     http_requests: Counter,
+/// }
+///
+/// let metrics = AppMetrics::builder()
+///     .with_labels_from_env(&["HOSTNAME", "REGION"])
+///     .with_kubernetes_labels()
+///     .build();
+/// metrics.http_requests().inc();
+/// ```
+///
+/// # Metric Name Casing
+/// Metric names derived from field identifiers are snake_case by default. `rename_all` changes
+/// the casing, e.g. to match an existing dashboard, and `total_suffix` enforces the `_total`
+/// suffix of counters, after any unit suffix. Names set with `rename` are kept as they are.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app", rename_all = "camelCase", total_suffix)]
+/// struct AppMetrics {
+///     /// The total number of HTTP requests.
+///     #[metric]
+///     http_requests: Counter,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// // Recorded as `app_httpRequests_total`.
+/// metrics.http_requests().inc();
+/// ```
+///
+/// # Deprecated Aliases
+/// Renaming a metric breaks the dashboards and alerts using its old name. With
+/// `#[metric(deprecated_alias = "...")]`, the metric is also exported under its old name, scoped
+/// like the new one, until they migrate. Both names share the same series, and the help of the
+/// alias points to the new name. See [`prometric::alias`].
+///
+/// ```rust
+/// use prometric::Gauge;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The number of items in the queue.
+///     #[metric(rename = "queue_depth", deprecated_alias = "queued_items")]
+///     depth: Gauge,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// // Exported as both `app_queue_depth` and `app_queued_items`.
+/// metrics.depth().set(3);
+/// ```
+///
+/// # Initial Values
+/// Counters and gauges without variable labels can be initialized at build time with
+/// `#[metric(init = ...)]`, taking a literal or an expression, so they are present before their
+/// first update.
+///
+/// ```rust
+/// use prometric::Gauge;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// Whether the application is up.
+///     #[metric(absolute, init = 1)]
+///     up: Gauge,
+/// }
+///
+/// // Exported as `up 1` without any update.
+/// let metrics = AppMetrics::default();
+/// ```
+///
+/// # Options Passthrough
+/// Options of the underlying `prometheus` metric that aren't modeled by the macro can be set with
+/// `#[metric(opts = ...)]`, taking a closure or function modifying the `prometheus::Opts` of a
+/// counter or gauge, or the `prometheus::HistogramOpts` of a histogram, before registration.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of requests.
+///     #[metric(opts = |opts| opts.const_label("region", "eu"))]
+///     requests: Counter,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.requests().inc();
+/// ```
+///
+/// # Explicit Kinds
+/// The metric type is inferred from the name of the field type (`Counter`, `Gauge`, `Histogram`
+/// or `Summary`). Fields typed as an alias or a newtype of a metric type declare it with
+/// `#[metric(kind = "...")]` instead. Newtypes implement [`prometric::AsMetric`].
+///
+/// ```rust
+/// use prometric_derive::metrics;
+///
+/// type RequestCounter = prometric::Counter<f64>;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of requests.
+///     #[metric(kind = "counter", labels = ["method"])]
+///     requests: RequestCounter,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.requests("GET").inc_by(1.5);
+/// ```
+///
+/// # Optional Metrics
+/// Fields typed as `Option<Metric>` are only registered when enabled at build time with the
+/// generated `enable_<field>` builder method, e.g. for expensive, high-cardinality debug metrics.
+/// Their accessors return no-op metrics when they aren't enabled.
+///
+/// ```rust
+/// use prometric::{Counter, Histogram};
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of requests.
+///     #[metric]
+///     requests: Counter,
+///     /// The latency of each peer.
+///     #[metric(labels = ["peer"])]
+///     peer_latency: Option<Histogram>,
+/// }
+///
+/// let debug = std::env::var("DEBUG_METRICS").is_ok();
+/// let metrics = AppMetrics::builder().enable_peer_latency(debug).build();
+/// metrics.peer_latency("peer-1").observe(0.25);
+/// ```
+///
+/// # Shared Metrics
+/// Counters and histograms typed as `Arc<Metric>` with `#[metric(shared)]` can be shared between
+/// instances of the struct, e.g. one per worker. The metric of an instance is returned by the
+/// generated `shared_<field>` method, and passed to the builder of the next instances with
+/// `with_shared_<field>`, which use it instead of registering a new metric that would replace it.
+/// A new metric is registered when `None` is passed, e.g. from disabled metrics.
+/// `reset_all` and `unregister` leave shared metrics untouched.
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "worker")]
+/// struct WorkerMetrics {
+///     /// The total number of jobs processed by all the workers.
+///     #[metric(shared)]
+///     jobs: Arc<Counter>,
+/// }
+///
+/// let first = WorkerMetrics::builder().build();
+/// let workers = (0..4)
+///     .map(|_| WorkerMetrics::builder().with_shared_jobs(first.shared_jobs()).build())
+///     .collect::<Vec<_>>();
+/// ```
+///
+/// # Accessor Visibility
+/// Accessors have the visibility of the struct by default. Override it per metric with
+/// `#[metric(vis = "...")]`, e.g. to keep internal metrics of a public struct out of the API of
+/// the crate. An empty string makes the accessor private. Not supported with `trait`.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// pub struct AppMetrics {
+///     /// The total number of requests.
+///     #[metric]
+///     requests: Counter,
+///     /// The total number of internal retries.
+///     #[metric(vis = "pub(crate)")]
+///     retries: Counter,
+/// }
+/// ```
+///
+/// # Companion Trait
+/// With `#[metrics(trait)]`, a trait named after the struct with an `Api` suffix is generated, with
+/// one method per accessor. It is implemented by the struct, and by a generated `Noop` struct
+/// which registers no metrics and records nothing. Components generic over the trait can then be
+/// built without a registry in unit tests. Nested metrics aren't supported.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app", trait)]
 /// struct AppMetrics {
 ///     /// The total number of requests.
 ///     #[metric(labels = ["method"])]
 ///     requests: Counter,
+/// }
 ///
-///     /// The current number of active connections.
+/// fn handle(metrics: &impl AppMetricsApi) {
+///     metrics.requests("GET").inc();
+/// }
+///
+/// handle(&AppMetrics::default());
+/// handle(&NoopAppMetrics);
+/// ```
+///
+/// # State Sets
+/// On a fieldless enum, the attribute generates a state set metric (the OpenMetrics `StateSet`
+/// pattern): a gauge named after the scope and the enum, with one series per variant labeled with
+/// the name of the metric, where the current state is `1` and the others `0`. The help string is
+/// taken from the documentation of the enum, and the variants are labeled in snake_case.
+///
+/// The generated `{Enum}Metrics` struct registers the metric with `new(registry)` (or `default()`),
+/// and updates it with `set_state`, switching states atomically. The enum also implements
+/// [`prometric::LabelValue`] and [`prometric::State`], so it can be used with a labeled
+/// [`prometric::StateSet`] too. Only the `scope` argument is supported on enums.
+///
+/// ```rust
+/// use prometric_derive::metrics;
+///
+/// /// The sync state of the node.
+/// #[metrics(scope = "node")]
+/// enum SyncState {
+///     Idle,
+///     Syncing,
+///     Synced,
+/// }
+///
+/// let metrics = SyncStateMetrics::default();
+/// metrics.set_state(SyncState::Syncing);
+/// // node_sync_state{node_sync_state="idle"} 0
+/// // node_sync_state{node_sync_state="synced"} 0
+/// // node_sync_state{node_sync_state="syncing"} 1
+/// ```
+///
+/// # Nested Metrics
+/// A field annotated with `#[metric(flatten)]` can be another `metrics` struct. It is built with
+/// the registry and static labels of the parent, and its metric names are prefixed with the parent
+/// scope (e.g. `app_db_queries`), including its absolute names. Its alert rules are included in the
+/// parent's.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "db")]
+/// struct DbMetrics {
+///     /// The total number of queries.
+///     #[metric(labels = ["table"])]
+///     queries: Counter,
+/// }
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of HTTP requests.
 ///     #[metric]
-///     active_connections: Gauge,
+///     http_requests: Counter,
+///     #[metric(flatten)]
+///     db: DbMetrics,
 /// }
 ///
-/// // Use the static directly anywhere
-/// APP_METRICS.requests("GET").inc();
-/// APP_METRICS.active_connections().set(10);
+/// let metrics = AppMetrics::default();
+/// metrics.db().queries("users").inc();
+/// ```
 ///
-/// // The following would not compile:
-/// // let metrics = AppMetrics::builder();  // Error: builder() is private
-/// // let metrics = AppMetrics::default();   // Error: Default is not implemented
+/// # Composite Metrics
+/// With `#[metrics(compose)]`, every field is a nested `metrics` struct keeping its own scope, and
+/// the composite is built in one `build()` sharing its registry and static labels. This replaces
+/// threading many metrics structs through constructors. The composite has no scope or
+/// struct-level labels of its own, and its fields take no `#[metric]` attribute.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "p2p")]
+/// struct P2pMetrics {
+///     /// The total number of dials.
+///     #[metric]
+///     dials: Counter,
+/// }
+///
+/// #[metrics(scope = "rpc")]
+/// struct RpcMetrics {
+///     /// The total number of calls.
+///     #[metric(labels = ["method"])]
+///     calls: Counter,
+/// }
+///
+/// #[metrics(compose)]
+/// struct NodeMetrics {
+///     p2p: P2pMetrics,
+///     rpc: RpcMetrics,
+/// }
+///
+/// let metrics = NodeMetrics::builder().with_label("chain", "mainnet").build();
+/// metrics.p2p().dials().inc();
+/// metrics.rpc().calls("eth_call").inc();
+/// ```
+///
+/// # Parent Scope
+/// The scope of a parent, e.g. of the root crate of a workspace, is shared with
+/// `#[metrics(parent = ...)]` instead of copied into every scope. It takes a `&str` constant and
+/// prefixes the names of the metrics, as the default prefix of the builder: a struct nested with
+/// `#[metric(flatten)]` is prefixed with the scope of its parent struct instead, and the nested
+/// structs of a composite keep their parent. Like the prefix of the builder, the parent isn't part
+/// of the descriptors, name constants and alert rules.
+///
+/// ```rust
+/// use prometric::Gauge;
+/// use prometric_derive::metrics;
+///
+/// pub const ROOT_SCOPE: &str = "root";
+///
+/// #[metrics(scope = "p2p", parent = ROOT_SCOPE)]
+/// struct P2pMetrics {
+///     /// The number of connected peers.
+///     #[metric]
+///     peers: Gauge, // `root_p2p_peers`
+/// }
+///
+/// let metrics = P2pMetrics::builder().build();
+/// metrics.peers().set(8);
+/// assert_eq!(P2pMetrics::PARENT_SCOPE, Some("root"));
+/// ```
+///
+/// # Units
+/// The `unit` argument of `metric` declares the unit of the metric, and generates helpers on its
+/// accessor converting from other units of the same dimension:
+/// - Time units (`seconds`, `milliseconds`, `microseconds`, `nanoseconds`): `_secs(f64)`,
+///   `_millis(u64)`, `_micros(u64)` and `_nanos(u64)`.
+/// - Data units (`bytes`, `kilobytes`, `megabytes`, `gigabytes`): `_bytes(u64)`.
+///
+/// The helpers are prefixed with `inc_by` for counters, `set` for gauges, and `observe` for
+/// histograms and summaries.
+///
+/// The unit is appended to the metric name as a suffix (e.g. `app_request_duration_seconds`),
+/// unless the name already ends with it. The suffix goes before a `_total` suffix. The unit can be
+/// recovered from the name with [`prometric::unit::Unit::from_metric_name`], and is recorded in
+/// the metric descriptors.
+///
+/// ```rust
+/// use prometric::{Gauge, Histogram};
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The duration of requests.
+///     #[metric(unit = "seconds")]
+///     request_duration: Histogram,
+///
+///     /// The size of the cache.
+///     #[metric(unit = "megabytes")]
+///     cache_size: Gauge<f64>,
+/// }
+///
+/// let metrics = AppMetrics::default();
+///
+/// // Observes 0.25 seconds.
+/// metrics.request_duration().observe_millis(250);
+/// // Sets 1.5 megabytes.
+/// metrics.cache_size().set_bytes(1_500_000);
+/// ```
+///
+/// # Crate Path
+/// The generated code refers to `::prometric`. When `prometric` is re-exported by another crate,
+/// e.g. an internal facade crate, or renamed in `Cargo.toml`, its path is set serde-style with
+/// `#[metrics(crate = "...")]`, and `#[labels(crate = "...")]` for [`Labels`](derive@Labels).
+/// The expressions of the attributes, e.g. `buckets` or `init`, are kept as written.
+///
+/// ```rust
+/// # mod facade {
+/// #     pub use prometric;
+/// # }
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app", crate = "facade::prometric")]
+/// struct AppMetrics {
+///     /// The total number of requests.
+///     #[metric]
+///     requests: facade::prometric::Counter,
+/// }
+///
+/// let metrics = AppMetrics::default();
+/// metrics.requests().inc();
 /// ```
 ///
 /// # Exporting Metrics
@@ -147,6 +1558,36 @@ mod utils;
 ///     .expect("Failed to install exporter");
 /// ```
 ///
+/// # Disabling Metrics
+/// When built with the `PROMETRIC_STRIP=1` environment variable (or with
+/// `RUSTFLAGS="--cfg prometric_disabled"`), the fields of the struct are replaced with zero-sized
+/// [`PhantomData`](core::marker::PhantomData) markers, nothing is registered, and all accessor
+/// methods are no-ops. The accessor API is unchanged, so no call sites need to be `cfg`-ed. This
+/// isn't a cargo feature, so that `--all-features` builds keep their metrics. Rustflags don't reach
+/// proc macros when cross-compiling with `--target`, so prefer the environment variable there.
+///
+/// Metrics can also be disabled at runtime, without code changes, by setting the
+/// `PROMETRIC_DISABLED` environment variable to `1` or `true`, or with the `disabled` method of the
+/// builder which takes precedence. The metrics are then not registered, and the accessors do
+/// nothing.
+///
+/// ```rust
+/// use prometric::Counter;
+/// use prometric_derive::metrics;
+///
+/// #[metrics(scope = "app")]
+/// struct AppMetrics {
+///     /// The total number of requests.
+///     #[metric]
+///     requests: Counter,
+/// }
+///
+/// let registry = prometheus::Registry::new();
+/// let metrics = AppMetrics::builder().with_registry(&registry).disabled(true).build();
+/// metrics.requests().inc();
+/// assert!(registry.gather().is_empty());
+/// ```
+///
 /// # Process Metrics Example
 ///
 /// When the `process` feature is enabled, the `ProcessCollector` is used to collect metrics about
@@ -202,6 +1643,9 @@ mod utils;
 /// # HELP system_cpu_usage System-wide CPU usage percentage.
 /// # TYPE system_cpu_usage gauge
 /// system_cpu_usage 6.7168498039245605
+/// # HELP system_info Information about the host, exposed as labels with a constant value of 1.
+/// # TYPE system_info gauge
+/// system_info{arch="x86_64",hostname="devbox",kernel_version="6.8.0-48-generic",os_name="Ubuntu",os_version="24.04"} 1
 /// # HELP system_max_cpu_frequency The maximum CPU frequency of all cores in MHz.
 /// # TYPE system_max_cpu_frequency gauge
 /// system_max_cpu_frequency 5339
@@ -216,7 +1660,7 @@ mod utils;
 pub fn metrics(attr: TokenStream, item: TokenStream) -> TokenStream {
     // NOTE: We use `proc_macro_attribute` here because we're actually rewriting the struct. Derive
     // macros are additive.
-    let mut input = parse_macro_input!(item as ItemStruct);
+    let input = parse_macro_input!(item as Item);
 
     let attributes: MetricsAttr = match syn::parse(attr) {
         Ok(v) => v,
@@ -225,5 +1669,28 @@ pub fn metrics(attr: TokenStream, item: TokenStream) -> TokenStream {
         }
     };
 
-    expand::expand(attributes, &mut input).unwrap_or_else(|err| err.into_compile_error()).into()
+    let expanded = match input {
+        Item::Struct(mut input) => expand::expand(attributes, &mut input),
+        Item::Enum(input) => expand::expand_state_set(attributes, &input),
+        input => Err(syn::Error::new_spanned(input, "Expected a struct or a fieldless enum")),
+    };
+
+    expanded.unwrap_or_else(|err| err.into_compile_error()).into()
+}
+
+/// Derive [`prometric::LabelSet`] for a struct with named fields, to use it as
+/// `#[metric(labels = Type)]`. The label names are the field names, in declaration order.
+///
+/// Fields must implement [`prometric::AsLabelValue`]: strings (`String`, `&str`, `Cow<str>`), or
+/// types implementing [`prometric::LabelValue`].
+///
+/// The path of the `prometric` crate can be set with `#[labels(crate = "...")]`, see [Crate
+/// Path](macro@metrics#crate-path).
+///
+/// See [`macro@metrics`] for an example.
+#[proc_macro_derive(Labels, attributes(labels))]
+pub fn labels(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+
+    expand::expand_label_set(&input).unwrap_or_else(|err| err.into_compile_error()).into()
 }
@@ -41,3 +41,27 @@ pub(crate) fn to_screaming_snake(s: &str) -> String {
 
     result
 }
+
+/// Returns whether the identifier is in SCREAMING_SNAKE_CASE with several words, like the name of
+/// a const. Single words are ambiguous with acronym type names, e.g. `RPC`, and aren't.
+pub(crate) fn is_screaming_snake(ident: &str) -> bool {
+    ident.contains('_') &&
+        ident.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_') &&
+        ident.chars().any(|c| c.is_ascii_uppercase())
+}
+
+/// Returns `true` if the string is a valid Prometheus metric name, matching
+/// `[a-zA-Z_:][a-zA-Z0-9_:]*`.
+pub(crate) fn is_valid_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':') &&
+        chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Returns `true` if the string is a valid Prometheus label name, matching
+/// `[a-zA-Z_][a-zA-Z0-9_]*`.
+pub(crate) fn is_valid_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') &&
+        chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,354 @@
+#![cfg(prometric_disabled)]
+
+#[derive(prometric_derive::Labels)]
+struct RequestLabels {
+    method: &'static str,
+}
+
+const COMMON_LABELS: &[&str] = &["chain"];
+
+#[prometric_derive::metrics(scope = "traited", trait)]
+struct TraitedMetrics {
+    /// The total number of jobs.
+    #[metric(labels = ["kind"])]
+    jobs: prometric::Counter,
+
+    /// The total number of blocks.
+    #[metric(label_names = COMMON_LABELS)]
+    blocks: prometric::Counter,
+}
+
+#[prometric_derive::metrics(scope = "nested")]
+struct NestedMetrics {
+    /// The total number of queries.
+    #[metric]
+    queries: prometric::Counter,
+}
+
+#[prometric_derive::metrics(scope = "disabled")]
+struct DisabledMetrics {
+    /// The total number of HTTP requests.
+    #[metric(labels = ["method", "path"])]
+    http_requests: prometric::Counter,
+
+    /// The current number of active users.
+    #[metric(labels = ["service", active: bool])]
+    current_users: prometric::Gauge<f64>,
+
+    /// The duration of HTTP requests.
+    #[metric(buckets = [0.1, 1.0], unit = "seconds")]
+    http_requests_duration: prometric::Histogram,
+
+    /// The size of HTTP responses.
+    #[metric(labels = RequestLabels)]
+    response_size: prometric::Summary,
+
+    #[metric(flatten)]
+    nested: NestedMetrics,
+}
+
+#[test]
+fn disabled_metrics_are_inert() {
+    assert_eq!(std::mem::size_of::<DisabledMetrics>(), 0);
+
+    let registry = prometheus::Registry::new();
+    let metrics = DisabledMetrics::builder().with_registry(&registry).build();
+
+    metrics.http_requests("GET", "/").inc();
+    metrics.http_requests("GET", "/").inc_by(2);
+    metrics.http_requests_with(&["GET", "/"]).inc();
+    metrics.http_requests_handle("GET", "/").inc();
+    assert!(!metrics.http_requests("GET", "/").remove());
+    metrics.current_users("service-1", true).set(1.5);
+    drop(metrics.current_users("service-1", false).track());
+    metrics.http_requests_duration().observe(0.5);
+    metrics.http_requests_duration().observe_millis(500);
+    metrics.http_requests_duration().start_timer().observe();
+    metrics.nested().queries().inc();
+    metrics.response_size(RequestLabels { method: "GET" }).observe(1024);
+    metrics.reset_all();
+    metrics.unregister();
+
+    assert!(registry.gather().is_empty());
+    assert!(metrics.gather().is_empty());
+    assert!(metrics.render().is_empty());
+}
+
+#[test]
+fn disabled_companion_trait_is_inert() {
+    fn run(metrics: &impl TraitedMetricsApi) {
+        metrics.jobs("sync").inc();
+        metrics.blocks(["ethereum"]).inc();
+    }
+
+    let registry = prometheus::Registry::new();
+    run(&TraitedMetrics::builder().with_registry(&registry).build());
+    run(&NoopTraitedMetrics);
+
+    assert!(registry.gather().is_empty());
+}
+
+/// The sync state of the node.
+#[prometric_derive::metrics(scope = "node")]
+enum SyncState {
+    Idle,
+    Syncing,
+}
+
+#[test]
+fn disabled_state_set_is_inert() {
+    let registry = prometheus::Registry::new();
+    let metrics = SyncStateMetrics::new(&registry);
+    metrics.set_state(SyncState::Syncing);
+    metrics.set_state(SyncState::Idle);
+
+    assert_eq!(std::mem::size_of::<SyncStateMetrics>(), 0);
+    assert!(registry.gather().is_empty());
+}
+
+#[prometric_derive::metrics(scope = "named", named_labels)]
+struct NamedMetrics {
+    /// The total number of HTTP requests.
+    #[metric(labels = ["method", "path"])]
+    named_requests: prometric::Counter,
+}
+
+#[test]
+fn disabled_named_labels_are_inert() {
+    let registry = prometheus::Registry::new();
+    let metrics = NamedMetrics::builder().with_registry(&registry).build();
+    metrics.named_requests().method("GET").path("/").inc();
+
+    assert!(registry.gather().is_empty());
+}
+
+#[prometric_derive::metrics(scope = "debug", debug)]
+struct DebugMetrics {
+    /// The total number of HTTP requests.
+    #[metric]
+    debug_requests: prometric::Counter,
+}
+
+#[test]
+fn disabled_debug_is_empty() {
+    let metrics = DebugMetrics::default();
+    metrics.debug_requests().inc();
+
+    assert_eq!(metrics.to_string(), "");
+    assert_eq!(format!("{metrics:?}"), "DebugMetrics {}");
+}
+
+#[prometric_derive::metrics(scope = "shared")]
+struct SharedMetrics {
+    /// The total number of jobs.
+    #[metric(shared)]
+    shared_jobs: std::sync::Arc<prometric::Counter>,
+}
+
+#[test]
+fn disabled_shared_metrics_are_none() {
+    let metrics = SharedMetrics::default();
+    metrics.shared_jobs().inc();
+
+    assert!(metrics.shared_shared_jobs().is_none());
+}
+
+#[prometric_derive::metrics(scope = "timer")]
+struct TimerMetrics {
+    /// The RPC calls.
+    #[metric(labels = ["method"])]
+    timer_calls: prometric::Timer,
+}
+
+#[test]
+fn disabled_timers_are_inert() {
+    let metrics = TimerMetrics::default();
+    metrics.timer_calls("eth_call").observe(std::time::Duration::from_secs(1));
+    drop(metrics.timer_calls("eth_call").start());
+
+    assert_eq!(metrics.timer_calls("eth_call").count(), 0);
+}
+
+#[prometric_derive::metrics(scope = "ratio")]
+struct RatioMetrics {
+    /// The cache hit ratio.
+    #[metric(ratio = (ratio_hits, ratio_lookups))]
+    ratio_hit_ratio: prometric::Ratio,
+    /// The cache hits.
+    #[metric]
+    ratio_hits: prometric::Counter,
+    /// The cache lookups.
+    #[metric]
+    ratio_lookups: prometric::Counter,
+}
+
+#[test]
+fn disabled_ratios_are_inert() {
+    let metrics = RatioMetrics::default();
+    metrics.ratio_hits().inc();
+    metrics.ratio_lookups().inc();
+
+    assert!(metrics.ratio_hit_ratio().is_none());
+}
+
+#[prometric_derive::metrics(scope = "gauge_fn")]
+struct GaugeFnMetrics {
+    /// The number of queued jobs.
+    #[metric(eval = || 1u64)]
+    gauge_fn_queue_depth: prometric::GaugeFn,
+}
+
+#[test]
+fn disabled_gauge_fns_are_inert() {
+    let metrics = GaugeFnMetrics::default();
+
+    assert!(metrics.gauge_fn_queue_depth().is_none());
+}
+
+#[prometric_derive::metrics(scope = "info")]
+struct InfoMetrics {
+    /// The build of the node.
+    #[metric(labels = ["version"])]
+    info_build: prometric::Info,
+}
+
+#[test]
+fn disabled_info_is_inert() {
+    let metrics = InfoMetrics::default();
+    metrics.info_build("1.0.0").set();
+    metrics.info_build("1.0.0").handle().set();
+}
+
+#[prometric_derive::metrics(scope = "clone", clone)]
+struct CloneMetrics {
+    /// The total number of requests.
+    #[metric]
+    clone_requests: prometric::Counter,
+}
+
+#[test]
+fn disabled_metrics_can_be_cloned() {
+    let metrics = CloneMetrics::default();
+    metrics.clone().clone_requests().inc();
+}
+
+#[prometric_derive::metrics(scope = "collected", collector)]
+struct CollectedMetrics {
+    /// The total number of requests.
+    #[metric]
+    collected_requests: prometric::Counter,
+}
+
+#[test]
+fn disabled_collector_collects_nothing() {
+    use prometheus::core::Collector;
+
+    let metrics = CollectedMetrics::default();
+    metrics.collected_requests().inc();
+    assert!(metrics.desc().is_empty());
+    assert!(metrics.collect().is_empty());
+}
+
+#[prometric_derive::metrics(scope = "created")]
+struct CreatedMetrics {
+    /// The total number of requests.
+    #[metric(created)]
+    created_requests: prometric::Counter,
+}
+
+#[test]
+fn disabled_created_counters_are_inert() {
+    let metrics = CreatedMetrics::default();
+    metrics.created_requests().inc();
+}
+
+#[prometric_derive::metrics(scope = "sanitized", sanitize = "reject")]
+struct SanitizedMetrics {
+    /// The total number of messages.
+    #[metric(labels = ["peer"])]
+    sanitized_messages: prometric::Counter,
+}
+
+#[test]
+fn disabled_sanitized_labels_are_inert() {
+    let metrics = SanitizedMetrics::default();
+    metrics.sanitized_messages("a\nb").inc();
+}
+
+#[prometric_derive::metrics(scope = "getters", test_utils)]
+struct GetterMetrics {
+    /// The total number of requests.
+    #[metric(labels = ["method"])]
+    getter_requests: prometric::Counter,
+    /// The duration of requests.
+    getter_duration: prometric::Histogram,
+}
+
+#[test]
+fn disabled_test_getters_return_defaults() {
+    let metrics = GetterMetrics::default();
+    metrics.getter_requests("GET").inc();
+    metrics.getter_duration().observe(1.0);
+    assert_eq!(metrics.getter_requests_value("GET"), 0);
+    assert_eq!(metrics.getter_duration_count(), 0);
+    assert_eq!(metrics.getter_duration_sum(), 0.0);
+}
+
+#[prometric_derive::metrics(scope = "outcome")]
+struct OutcomeMetrics {
+    /// The total number of RPC calls.
+    #[metric(labels = ["method"])]
+    outcome_calls: prometric::OutcomeCounter,
+}
+
+#[test]
+fn disabled_outcome_counters_pass_results_through() {
+    let metrics = OutcomeMetrics::default();
+    metrics.outcome_calls("eth_call").success();
+    assert_eq!(metrics.outcome_calls("eth_call").record(Err::<(), _>("timeout")), Err("timeout"));
+}
+
+#[prometric_derive::metrics(scope = "read")]
+struct ReadMetrics {
+    /// The number of queued jobs.
+    read_queued_jobs: prometric::Gauge,
+    /// The duration of requests.
+    read_duration: prometric::Histogram,
+}
+
+#[test]
+fn disabled_read_methods_return_zero() {
+    let metrics = ReadMetrics::default();
+    metrics.read_queued_jobs().set(3);
+    metrics.read_duration().observe(1.0);
+    assert_eq!(metrics.read_queued_jobs().get(), 0);
+    assert_eq!(metrics.read_duration().get_sample_count(), 0);
+    assert_eq!(metrics.read_duration().get_sample_sum(), 0.0);
+}
+
+#[prometric_derive::metrics(scope = "timestamps")]
+struct TimestampMetrics {
+    /// The time of the last successful sync, in seconds since the UNIX epoch.
+    last_sync_timestamp_seconds: prometric::Gauge<f64>,
+}
+
+#[test]
+fn disabled_timestamp_gauges_are_inert() {
+    let metrics = TimestampMetrics::default();
+    metrics.last_sync_timestamp_seconds().set_to_current_time();
+    assert_eq!(metrics.last_sync_timestamp_seconds().get(), 0.0);
+}
+
+#[prometric_derive::metrics(scope = "durations")]
+struct DurationMetrics {
+    /// The duration of queue waits.
+    queue_wait_duration: prometric::Histogram,
+}
+
+#[test]
+fn disabled_duration_observations_are_inert() {
+    let metrics = DurationMetrics::default();
+    metrics.queue_wait_duration().observe_duration(std::time::Duration::from_millis(20));
+    metrics.queue_wait_duration().observe_since(std::time::Instant::now());
+    assert_eq!(metrics.queue_wait_duration().get_sample_count(), 0);
+}
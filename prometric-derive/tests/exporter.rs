@@ -1,4 +1,11 @@
-use prometric::{Counter, exporter::ExporterBuilder};
+// These tests assert on the exported metrics, which are inert with the `prometric_disabled` cfg.
+#![cfg(not(prometric_disabled))]
+
+use prometric::{
+    Counter,
+    exporter::{ExporterBuilder, config::ExporterConfig},
+    scrape::{ScrapeClient, ScrapedCollector},
+};
 use prometric_derive::metrics;
 
 use http_body_util::{BodyExt, Empty};
@@ -83,3 +90,143 @@ async fn test_exporter_async() {
     // Verify the counter value is 2 (incremented twice)
     assert!(body.contains("app_test_counter 2"));
 }
+
+#[metrics(scope = "libp2p")]
+struct LibraryMetrics {
+    /// Library metric.
+    #[metric]
+    dials: Counter,
+}
+
+#[tokio::test]
+async fn test_exporter_prefixed_registry() {
+    let registry = prometheus::Registry::new();
+    let app_metrics = TestMetrics::builder().with_registry(&registry).build();
+    app_metrics.counter().inc();
+
+    // The library scope stacks with the application-level prefix
+    let library_registry = prometric::prefixed_registry("myapp");
+    let library_metrics = LibraryMetrics::builder().with_registry(&library_registry).build();
+    library_metrics.dials().inc();
+
+    ExporterBuilder::new()
+        .with_address("127.0.0.1:9092")
+        .with_registry(registry)
+        .with_additional_registry(library_registry)
+        .install()
+        .unwrap();
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+    let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+
+    let uri = "http://127.0.0.1:9092/metrics".parse().unwrap();
+    let response = client.get(uri).await.expect("Failed to make request");
+
+    assert_eq!(response.status(), 200);
+
+    let body_bytes =
+        response.into_body().collect().await.expect("Failed to read response body").to_bytes();
+    let body = String::from_utf8(body_bytes.to_vec()).expect("Invalid UTF-8");
+
+    assert!(body.contains("test_counter 1"));
+    assert!(body.contains("myapp_libp2p_dials 1"));
+}
+
+#[tokio::test]
+async fn test_exporter_config() {
+    let registry = prometheus::Registry::new();
+    let app_metrics = TestMetrics::builder().with_registry(&registry).build();
+    app_metrics.counter().inc();
+    let library_metrics = LibraryMetrics::builder().with_registry(&registry).build();
+    library_metrics.dials().inc();
+
+    let config = ExporterConfig::parse(
+        "PROMETRIC_DISABLED_METRICS=libp2p_dials\nPROMETRIC_AUTH_TOKEN=secret",
+    )
+    .unwrap();
+
+    ExporterBuilder::new()
+        .with_address("127.0.0.1:9093")
+        .with_registry(registry)
+        .with_config(config)
+        .install()
+        .unwrap();
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+    let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+
+    let uri: hyper::Uri = "http://127.0.0.1:9093/metrics".parse().unwrap();
+    let response = client.get(uri.clone()).await.expect("Failed to make request");
+    assert_eq!(response.status(), 401);
+
+    let request = hyper::Request::get(uri)
+        .header(hyper::header::AUTHORIZATION, "Bearer secret")
+        .body(Empty::new())
+        .unwrap();
+    let response = client.request(request).await.expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+
+    let body_bytes =
+        response.into_body().collect().await.expect("Failed to read response body").to_bytes();
+    let body = String::from_utf8(body_bytes.to_vec()).expect("Invalid UTF-8");
+
+    assert!(body.contains("test_counter 1"));
+    assert!(!body.contains("libp2p_dials"));
+}
+
+#[tokio::test]
+async fn test_scrape_exporter() {
+    let registry = prometheus::Registry::new();
+    let metrics = TestMetrics::builder().with_registry(&registry).build();
+    metrics.counter().inc_by(3);
+
+    ExporterBuilder::new()
+        .with_address("127.0.0.1:9094")
+        .with_registry(registry)
+        .install()
+        .unwrap();
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+    // Merge the scraped metrics into the registry of an aggregator.
+    let client = ScrapeClient::new("http://127.0.0.1:9094/metrics").unwrap();
+    let collector = ScrapedCollector::new(client.uri());
+    collector.update(client.scrape().await.unwrap());
+
+    let aggregator = prometheus::Registry::new();
+    aggregator.register(Box::new(collector)).unwrap();
+
+    let body = prometric::gather_to_string(&aggregator).unwrap();
+    assert!(body.contains("# TYPE test_counter counter"));
+    assert!(body.contains("test_counter 3"));
+}
+
+#[tokio::test]
+async fn test_exporter_global_labels() {
+    let registry = prometheus::Registry::new();
+    let metrics = TestMetrics::builder().with_registry(&registry).build();
+    metrics.counter().inc();
+
+    ExporterBuilder::new()
+        .with_address("127.0.0.1:9095")
+        .with_registry(registry)
+        .with_global_label("region", "eu-west-1")
+        .with_global_label("instance", "node-1")
+        .install()
+        .unwrap();
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+    let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+
+    let uri = "http://127.0.0.1:9095/metrics".parse().unwrap();
+    let response = client.get(uri).await.expect("Failed to make request");
+
+    let body_bytes =
+        response.into_body().collect().await.expect("Failed to read response body").to_bytes();
+    let body = String::from_utf8(body_bytes.to_vec()).expect("Invalid UTF-8");
+
+    assert!(body.contains("test_counter{instance=\"node-1\",region=\"eu-west-1\"} 1"));
+}
@@ -1,6 +1,10 @@
+// These tests assert on the exported metrics, which are inert with the `prometric_disabled` cfg.
+#![cfg(not(prometric_disabled))]
+
 use std::time::Duration;
 
 use prometheus::Encoder as _;
+use prometric::testing::Harness;
 
 /// This is a struct that contains the metrics for the application.
 ///
@@ -163,6 +167,20 @@ fn test_static() {
     TEST_METRICS.test_gauge().inc();
 }
 
+#[prometric_derive::metrics(scope = "named_static", static = "NAMED_METRICS")]
+struct VeryLongNamedStaticMetricsStruct {
+    /// Test counter metric.
+    #[metric]
+    named_counter: prometric::Counter,
+}
+
+#[test]
+fn static_name_override_works() {
+    NAMED_METRICS.named_counter().inc();
+
+    assert!(NAMED_METRICS.render().contains("named_static_named_counter 1"));
+}
+
 #[test]
 fn bucket_expressions_work() {
     const BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
@@ -177,18 +195,14 @@ fn bucket_expressions_work() {
         hist: prometric::Histogram,
     }
 
-    let registry = prometheus::default_registry();
-    let app_metrics = BucketMetrics::builder().with_registry(registry).build();
+    let harness = Harness::new();
+    let app_metrics =
+        harness.build(|registry| BucketMetrics::builder().with_registry(registry).build());
 
     let duration = Duration::from_secs(1);
     app_metrics.hist().observe(duration.as_secs_f64());
 
-    let encoder = prometheus::TextEncoder::new();
-    let metric_families = registry.gather(); // Wait, need to expose registry
-
-    let mut buffer = vec![];
-    encoder.encode(&metric_families, &mut buffer).unwrap();
-    let output = String::from_utf8(buffer).unwrap();
+    let output = harness.gather();
 
     assert!(output.contains("test_hist"));
 }
@@ -202,18 +216,14 @@ fn bucket_defaults_work() {
         hist: prometric::Histogram,
     }
 
-    let registry = prometheus::default_registry();
-    let app_metrics = BucketMetrics::builder().with_registry(registry).build();
+    let harness = Harness::new();
+    let app_metrics =
+        harness.build(|registry| BucketMetrics::builder().with_registry(registry).build());
 
     let duration = Duration::from_secs(1);
     app_metrics.hist().observe(duration.as_secs_f64());
 
-    let encoder = prometheus::TextEncoder::new();
-    let metric_families = registry.gather(); // Wait, need to expose registry
-
-    let mut buffer = vec![];
-    encoder.encode(&metric_families, &mut buffer).unwrap();
-    let output = String::from_utf8(buffer).unwrap();
+    let output = harness.gather();
 
     assert!(output.contains("test_hist"));
 }
@@ -227,18 +237,14 @@ fn quantiles_defaults_work() {
         summary: prometric::Summary,
     }
 
-    let registry = prometheus::default_registry();
-    let app_metrics = QuantileMetrics::builder().with_registry(registry).build();
+    let harness = Harness::new();
+    let app_metrics =
+        harness.build(|registry| QuantileMetrics::builder().with_registry(registry).build());
 
     let duration = Duration::from_secs(1);
     app_metrics.summary().observe(duration.as_secs_f64());
 
-    let encoder = prometheus::TextEncoder::new();
-    let metric_families = registry.gather(); // Wait, need to expose registry
-
-    let mut buffer = vec![];
-    encoder.encode(&metric_families, &mut buffer).unwrap();
-    let output = String::from_utf8(buffer).unwrap();
+    let output = harness.gather();
 
     assert!(output.contains("test_summary"));
 }
@@ -252,8 +258,9 @@ fn quantiles_with_batching_work() {
         summary: prometric::Summary,
     }
 
-    let registry = prometheus::default_registry();
-    let app_metrics = QuantileMetrics::builder().with_registry(registry).build();
+    let harness = Harness::new();
+    let app_metrics =
+        harness.build(|registry| QuantileMetrics::builder().with_registry(registry).build());
 
     let duration = Duration::from_secs(1);
     for i in 0..10000 {
@@ -265,14 +272,2690 @@ fn quantiles_with_batching_work() {
         }
     }
 
-    let encoder = prometheus::TextEncoder::new();
-    let metric_families = registry.gather(); // Wait, need to expose registry
-
-    let mut buffer = vec![];
-    encoder.encode(&metric_families, &mut buffer).unwrap();
-    let output = String::from_utf8(buffer).unwrap();
+    let output = harness.gather();
 
     println!("{output}");
 
     assert!(output.contains("test_summary"));
 }
+
+#[test]
+fn alert_rules_work() {
+    #[prometric_derive::metrics(scope = "alerts")]
+    struct AlertMetrics {
+        /// The total number of errors.
+        #[metric(labels = ["kind"], alert(rate_gt = 5))]
+        errors: prometric::Counter,
+
+        /// The number of queued jobs.
+        #[metric(alert(value_gt = 100.5, for = "10m"))]
+        queued: prometric::Gauge,
+
+        /// The number of active users.
+        #[metric]
+        users: prometric::Gauge,
+    }
+
+    let registry = prometheus::Registry::new();
+    let metrics = AlertMetrics::builder().with_registry(&registry).build();
+    metrics.errors("timeout").inc();
+    metrics.queued().set(1);
+    metrics.users().set(1);
+
+    let alerts = AlertMetrics::alert_rules();
+    assert_eq!(alerts.len(), 2);
+
+    let rules = prometric::rules::RuleFileBuilder::new("alerts").with_alerts(alerts).render();
+    println!("{rules}");
+
+    assert!(rules.contains("- alert: AlertsErrorsRateHigh\n"));
+    assert!(rules.contains("expr: rate(alerts_errors[5m]) > 5\n"));
+    assert!(rules.contains("- alert: AlertsQueuedHigh\n"));
+    assert!(rules.contains("expr: alerts_queued > 100.5\n        for: 10m\n"));
+    assert!(!rules.contains("alerts_users"));
+}
+
+#[test]
+fn unit_helpers_work() {
+    #[prometric_derive::metrics(scope = "units")]
+    struct UnitMetrics {
+        /// The duration of requests.
+        #[metric(unit = "seconds")]
+        duration: prometric::Histogram,
+
+        /// The latency of the last request.
+        #[metric(labels = ["peer"], unit = "microseconds")]
+        latency: prometric::Gauge,
+
+        /// The total received data.
+        #[metric(unit = "kilobytes")]
+        received: prometric::Counter<f64>,
+
+        /// The total CPU time.
+        #[metric(rename = "cpu_total", unit = "seconds")]
+        cpu: prometric::Counter<f64>,
+
+        /// The size of the last response.
+        #[metric(rename = "response_size_bytes", unit = "bytes")]
+        response_size: prometric::Gauge,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| UnitMetrics::builder().with_registry(registry).build());
+
+    metrics.duration().observe_millis(250);
+    metrics.duration().observe_nanos(1_500_000_000);
+    metrics.latency("a").set_millis(3);
+    metrics.received().inc_by_bytes(2_500);
+    metrics.cpu().inc_by_millis(500);
+    metrics.response_size().set(10);
+
+    // The unit suffix is appended to the metric names, before `_total` and only once.
+    let output = harness.gather();
+    assert!(output.contains("units_duration_seconds_sum 1.75"));
+    assert!(output.contains("units_duration_seconds_bucket{le=\"0.25\"} 1"));
+    assert_eq!(harness.value("units_latency_microseconds", &[("peer", "a")]), Some(3000.0));
+    assert_eq!(harness.value("units_received_kilobytes", &[]), Some(2.5));
+    assert_eq!(harness.value("units_cpu_seconds_total", &[]), Some(0.5));
+    assert_eq!(harness.value("units_response_size_bytes", &[]), Some(10.0));
+
+    let descriptors = prometric::descriptor::MetricDescriptor::from_registry(harness.registry());
+    let unit = |name: &str| descriptors.iter().find(|d| d.name == name).and_then(|d| d.unit);
+    assert_eq!(unit("units_cpu_seconds_total"), Some(prometric::unit::Unit::Seconds));
+}
+
+#[test]
+fn typed_labels_work() {
+    enum HttpMethod {
+        Get,
+        Post,
+    }
+
+    impl prometric::LabelValue for HttpMethod {
+        fn label_value(&self) -> &'static str {
+            match self {
+                Self::Get => "GET",
+                Self::Post => "POST",
+            }
+        }
+    }
+
+    #[prometric_derive::metrics(scope = "typed")]
+    struct TypedMetrics {
+        /// The total number of HTTP requests.
+        #[metric(labels = [method: HttpMethod, "path", cached: bool])]
+        requests: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| TypedMetrics::builder().with_registry(registry).build());
+
+    metrics.requests(HttpMethod::Get, "/", true).inc();
+    metrics.requests(HttpMethod::Post, "/", false).inc_by(2);
+
+    let labels = |method, cached| [("method", method), ("path", "/"), ("cached", cached)];
+    assert_eq!(harness.value("typed_requests", &labels("GET", "true")), Some(1.0));
+    assert_eq!(harness.value("typed_requests", &labels("POST", "false")), Some(2.0));
+}
+
+#[test]
+fn label_sets_work() {
+    #[derive(prometric_derive::Labels)]
+    struct RequestLabels {
+        method: &'static str,
+        path: String,
+        cached: bool,
+    }
+
+    #[prometric_derive::metrics(scope = "label_set")]
+    struct LabelSetMetrics {
+        /// The total number of HTTP requests.
+        #[metric(labels = RequestLabels, help = "The total number of HTTP requests.")]
+        requests: prometric::Counter,
+        /// The duration of HTTP requests.
+        #[metric(labels = RequestLabels)]
+        duration: prometric::Histogram,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| LabelSetMetrics::builder().with_registry(registry).build());
+
+    let labels = || RequestLabels { method: "GET", path: "/".to_owned(), cached: true };
+    metrics.requests(labels()).inc_by(3);
+    metrics.duration(labels()).observe(0.5);
+
+    let expected = [("method", "GET"), ("path", "/"), ("cached", "true")];
+    assert_eq!(harness.value("label_set_requests", &expected), Some(3.0));
+    assert!(
+        harness
+            .gather()
+            .contains(r#"label_set_duration_count{cached="true",method="GET",path="/"} 1"#)
+    );
+}
+
+#[test]
+fn label_set_paths_work() {
+    mod labels {
+        #[derive(prometric_derive::Labels)]
+        pub struct Peer {
+            pub peer: &'static str,
+        }
+    }
+
+    // An acronym, which isn't a `label_names` const.
+    #[derive(prometric_derive::Labels)]
+    #[allow(clippy::upper_case_acronyms)]
+    struct RPC {
+        method: &'static str,
+    }
+
+    #[prometric_derive::metrics(scope = "label_set_paths")]
+    struct LabelSetPathMetrics {
+        /// The total number of messages received from each peer.
+        #[metric(labels = labels::Peer)]
+        messages: prometric::Counter,
+        /// The total number of RPC requests.
+        #[metric(labels = RPC)]
+        rpc_requests: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| LabelSetPathMetrics::builder().with_registry(registry).build());
+    metrics.messages(labels::Peer { peer: "a" }).inc();
+    metrics.rpc_requests(RPC { method: "eth_call" }).inc();
+
+    assert_eq!(harness.value("label_set_paths_messages", &[("peer", "a")]), Some(1.0));
+    assert_eq!(harness.value("label_set_paths_rpc_requests", &[("method", "eth_call")]), Some(1.0));
+}
+
+mod consts {
+    pub const PEER_LABELS: &[&str] = &["peer", "direction"];
+}
+
+#[test]
+fn const_labels_work() {
+    const COMMON_LABELS: &[&str] = &["chain", "network"];
+
+    #[prometric_derive::metrics(scope = "const_labels", labels = ["service"])]
+    struct ConstLabelMetrics {
+        /// The total number of RPC requests.
+        #[metric(label_names = COMMON_LABELS)]
+        rpc_requests: prometric::Counter,
+        /// The number of connected peers.
+        #[metric(label_names = consts::PEER_LABELS)]
+        peers: prometric::Gauge,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| ConstLabelMetrics::builder().with_registry(registry).build());
+
+    let chain = String::from("ethereum");
+    metrics.rpc_requests("node", [chain, "mainnet".to_owned()]).inc();
+    metrics.peers("node", ["peer-1", "inbound"]).set(3);
+
+    assert_eq!(
+        harness.value(
+            "const_labels_rpc_requests",
+            &[("service", "node"), ("chain", "ethereum"), ("network", "mainnet")]
+        ),
+        Some(1.0)
+    );
+    assert_eq!(
+        harness.value(
+            "const_labels_peers",
+            &[("service", "node"), ("peer", "peer-1"), ("direction", "inbound")]
+        ),
+        Some(3.0)
+    );
+}
+
+#[test]
+fn raw_accessors_work() {
+    #[prometric_derive::metrics(scope = "raw", labels = ["chain"])]
+    struct RawMetrics {
+        /// The total number of RPC requests.
+        #[metric(labels = ["method", "status"])]
+        rpc_requests: prometric::Counter,
+        /// The duration of RPC requests.
+        #[metric(unit = "seconds")]
+        rpc_duration: prometric::Histogram,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| RawMetrics::builder().with_registry(registry).build());
+
+    let status = 200.to_string();
+    metrics.rpc_requests_with(&["ethereum", "eth_call", &status]).inc_by(2);
+    metrics.rpc_duration_with(&["ethereum"]).observe_millis(250);
+
+    assert_eq!(
+        harness.value(
+            "raw_rpc_requests",
+            &[("chain", "ethereum"), ("method", "eth_call"), ("status", "200")]
+        ),
+        Some(2.0)
+    );
+    assert!(harness.gather().contains(r#"raw_rpc_duration_seconds_sum{chain="ethereum"} 0.25"#));
+
+    // The number of label values must match the labels of the metric.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        metrics.rpc_requests_with(&["ethereum"]).inc();
+    }));
+    assert!(result.is_err());
+}
+
+#[test]
+fn handles_work() {
+    #[prometric_derive::metrics(scope = "handles", labels = ["chain"])]
+    struct HandleMetrics {
+        /// The total number of processed packets.
+        #[metric(labels = ["direction"])]
+        packets: prometric::Counter,
+        /// The size of the queue.
+        #[metric]
+        queue_size: prometric::Gauge<i64>,
+        /// The size of the packets.
+        #[metric(buckets = [64.0, 1500.0])]
+        packet_size: prometric::Histogram,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| HandleMetrics::builder().with_registry(registry).build());
+
+    let packets = metrics.packets_handle("ethereum", "inbound");
+    let queue_size = metrics.queue_size("ethereum").handle();
+    let packet_size = metrics.packet_size_with(&["ethereum"]).handle();
+    for _ in 0..3 {
+        packets.inc();
+        queue_size.dec();
+        packet_size.observe(1000.0);
+    }
+    packets.inc_by(2);
+
+    let labels = [("chain", "ethereum"), ("direction", "inbound")];
+    assert_eq!(harness.value("handles_packets", &labels), Some(5.0));
+    assert_eq!(harness.value("handles_queue_size", &[("chain", "ethereum")]), Some(-3.0));
+    assert!(
+        harness.gather().contains(r#"handles_packet_size_bucket{chain="ethereum",le="1500"} 3"#)
+    );
+
+    // Handles of disabled metrics update nothing.
+    let disabled = harness
+        .build(|registry| HandleMetrics::builder().with_registry(registry).disabled(true).build());
+    disabled.packets_handle("ethereum", "outbound").inc();
+    assert_eq!(
+        harness.value("handles_packets", &[("chain", "ethereum"), ("direction", "outbound")]),
+        None
+    );
+}
+
+#[test]
+fn metric_metadata_works() {
+    use prometric::descriptor::{MetricKind, MetricMetadata};
+
+    const PEER_LABELS: &[&str] = &["peer", "direction"];
+
+    #[prometric_derive::metrics(scope = "metadata", labels = ["chain"])]
+    struct MetadataMetrics {
+        /// The total number of requests.
+        #[metric(rename = "requests_total", labels = ["method"])]
+        requests: prometric::Counter,
+        /// The number of connected peers.
+        #[metric(label_names = PEER_LABELS)]
+        peers: prometric::Gauge,
+        /// The duration of the uptime.
+        #[metric(absolute)]
+        uptime: prometric::Gauge,
+    }
+
+    const NAME: &str = MetadataMetrics::REQUESTS_NAME;
+    assert_eq!(NAME, "metadata_requests_total");
+    assert_eq!(MetadataMetrics::UPTIME_NAME, "uptime");
+    const LABELS: [&str; 2] = MetadataMetrics::REQUESTS_LABELS;
+    assert_eq!(LABELS, ["chain", "method"]);
+    assert_eq!(MetadataMetrics::PEERS_LABELS, ["chain", "peer", "direction"]);
+    assert_eq!(MetadataMetrics::UPTIME_LABELS, ["chain"]);
+    assert_eq!(
+        MetadataMetrics::METRICS,
+        [
+            MetricMetadata {
+                name: "metadata_requests_total",
+                kind: MetricKind::Counter,
+                labels: &["chain", "method"],
+            },
+            MetricMetadata {
+                name: "metadata_peers",
+                kind: MetricKind::Gauge,
+                labels: &["chain", "peer", "direction"],
+            },
+            MetricMetadata { name: "uptime", kind: MetricKind::Gauge, labels: &["chain"] },
+        ]
+    );
+}
+
+#[test]
+fn catalog_works() {
+    #[prometric_derive::metrics(scope = "catalog", catalog)]
+    struct CatalogMetrics {
+        /// The total number of requests.
+        #[metric(labels = ["method"])]
+        requests: prometric::Counter,
+        /// The duration of requests.
+        #[metric(unit = "seconds", buckets = [0.1, 1.0])]
+        duration: prometric::Histogram,
+    }
+
+    let catalog = CatalogMetrics::catalog();
+    assert_eq!(catalog.metrics, CatalogMetrics::describe());
+
+    let json = catalog.to_json();
+    assert_eq!(
+        json,
+        r#"{
+  "metrics": [
+    {
+      "name": "catalog_requests",
+      "kind": "counter",
+      "help": "The total number of requests.",
+      "labels": [
+        "method"
+      ]
+    },
+    {
+      "name": "catalog_duration_seconds",
+      "kind": "histogram",
+      "help": "The duration of requests.",
+      "labels": [],
+      "unit": "seconds",
+      "buckets": [
+        0.1,
+        1.0
+      ]
+    }
+  ]
+}"#
+    );
+}
+
+#[test]
+fn state_sets_work() {
+    /// The sync state of the node.
+    #[prometric_derive::metrics(scope = "node")]
+    #[derive(Debug, Clone, Copy)]
+    enum SyncState {
+        Idle,
+        Syncing,
+        FullySynced,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(SyncStateMetrics::new);
+    assert_eq!(SyncStateMetrics::NAME, "node_sync_state");
+
+    // Every state is exposed before the first transition.
+    for state in ["idle", "syncing", "fully_synced"] {
+        assert_eq!(harness.value("node_sync_state", &[("node_sync_state", state)]), Some(0.0));
+    }
+
+    metrics.set_state(SyncState::Syncing);
+    metrics.set_state(SyncState::FullySynced);
+    assert_eq!(harness.value("node_sync_state", &[("node_sync_state", "idle")]), Some(0.0));
+    assert_eq!(harness.value("node_sync_state", &[("node_sync_state", "syncing")]), Some(0.0));
+    assert_eq!(harness.value("node_sync_state", &[("node_sync_state", "fully_synced")]), Some(1.0));
+    assert_eq!(prometric::LabelValue::label_value(&SyncState::Idle), "idle");
+    assert_eq!(<SyncState as prometric::State>::STATES.len(), 3);
+}
+
+#[test]
+fn initial_values_work() {
+    const MAX_PEERS: u64 = 50;
+
+    #[prometric_derive::metrics(scope = "init")]
+    struct InitMetrics {
+        /// Whether the application is up.
+        #[metric(init = 1)]
+        up: prometric::Gauge,
+        /// The configured maximum number of peers.
+        #[metric(init = MAX_PEERS * 2)]
+        max_peers: prometric::Gauge,
+        /// The total number of restarts.
+        #[metric(init = 3)]
+        restarts: prometric::Counter,
+        /// The ratio of the cache to use.
+        #[metric(init = 0.5, const_labels = [("cache", "blocks")])]
+        cache_ratio: prometric::Gauge<f64>,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| InitMetrics::builder().with_registry(registry).build());
+
+    assert_eq!(harness.value("init_up", &[]), Some(1.0));
+    assert_eq!(harness.value("init_max_peers", &[]), Some(100.0));
+    assert_eq!(harness.value("init_restarts", &[]), Some(3.0));
+    assert_eq!(harness.value("init_cache_ratio", &[("cache", "blocks")]), Some(0.5));
+
+    metrics.restarts().inc();
+    assert_eq!(harness.value("init_restarts", &[]), Some(4.0));
+}
+
+#[test]
+fn remove_works() {
+    #[prometric_derive::metrics(scope = "remove", labels = ["chain"])]
+    struct RemoveMetrics {
+        /// The number of messages received from each peer.
+        #[metric(labels = ["peer"])]
+        messages: prometric::Counter,
+        /// The latency of each peer.
+        #[metric(labels = ["peer"])]
+        latency: prometric::Histogram,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| RemoveMetrics::builder().with_registry(registry).build());
+
+    metrics.messages("ethereum", "peer-1").inc();
+    metrics.messages("ethereum", "peer-2").inc();
+    metrics.latency("ethereum", "peer-1").observe(0.1);
+
+    assert!(metrics.messages("ethereum", "peer-1").remove());
+    assert!(metrics.latency_with(&["ethereum", "peer-1"]).remove());
+    assert!(!metrics.messages("ethereum", "peer-3").remove());
+
+    assert_eq!(
+        harness.value("remove_messages", &[("chain", "ethereum"), ("peer", "peer-1")]),
+        None
+    );
+    assert_eq!(
+        harness.value("remove_messages", &[("chain", "ethereum"), ("peer", "peer-2")]),
+        Some(1.0)
+    );
+    assert!(!harness.gather().contains("remove_latency"));
+
+    // The series is exported again on the next update.
+    metrics.messages("ethereum", "peer-1").inc();
+    assert_eq!(
+        harness.value("remove_messages", &[("chain", "ethereum"), ("peer", "peer-1")]),
+        Some(1.0)
+    );
+}
+
+#[test]
+fn local_metrics_work() {
+    #[prometric_derive::metrics(scope = "local")]
+    struct LocalMetrics {
+        /// The total number of processed events.
+        #[metric(labels = ["kind"], local)]
+        events: prometric::Counter,
+        /// The size of the processed events.
+        #[metric(buckets = [64.0, 1024.0], local)]
+        event_size: prometric::Histogram,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| LocalMetrics::builder().with_registry(registry).build());
+
+    // Flushed on scrape.
+    metrics.events("block").inc_by(2);
+    metrics.event_size().observe(100.0);
+    assert_eq!(harness.value("local_events", &[("kind", "block")]), Some(2.0));
+    assert!(harness.gather().contains("local_event_size_count 1"));
+
+    // Flushed explicitly, or on thread exit.
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..100 {
+                    metrics.events("tx").inc();
+                }
+                prometric::local::flush();
+            });
+        }
+    });
+    let metrics = std::sync::Arc::new(metrics);
+    let handle = std::thread::spawn({
+        let metrics = metrics.clone();
+        move || metrics.events("tx").inc()
+    });
+    handle.join().unwrap();
+    assert_eq!(harness.value("local_events", &[("kind", "tx")]), Some(401.0));
+}
+
+#[test]
+fn opts_work() {
+    fn with_region(opts: prometheus::Opts) -> prometheus::Opts {
+        opts.const_label("region", "eu")
+    }
+
+    #[prometric_derive::metrics(scope = "opts")]
+    struct OptsMetrics {
+        /// The total number of requests.
+        #[metric(opts = with_region)]
+        requests: prometric::Counter,
+        /// The number of connections.
+        #[metric(opts = |opts| opts.namespace("edge"))]
+        connections: prometric::Gauge,
+        /// The duration of requests.
+        #[metric(opts = |opts| opts.buckets(vec![0.5]))]
+        duration: prometric::Histogram,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| OptsMetrics::builder().with_registry(registry).build());
+    metrics.requests().inc();
+    metrics.connections().set(3);
+    metrics.duration().observe(0.1);
+
+    assert_eq!(harness.value("opts_requests", &[("region", "eu")]), Some(1.0));
+    assert_eq!(harness.value("edge_opts_connections", &[]), Some(3.0));
+    let output = metrics.render();
+    assert!(output.contains(r#"opts_duration_bucket{le="0.5"} 1"#));
+    assert!(!output.contains(r#"opts_duration_bucket{le="0.005"}"#));
+    assert!(output.contains("edge_opts_connections 3"));
+}
+
+#[test]
+fn struct_registry_works() {
+    static LIB_REGISTRY: std::sync::LazyLock<prometheus::Registry> =
+        std::sync::LazyLock::new(prometheus::Registry::new);
+
+    #[prometric_derive::metrics(scope = "lib", registry = LIB_REGISTRY.clone())]
+    struct LibMetrics {
+        /// The total number of calls.
+        #[metric]
+        calls: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "lib_static", static, registry = &*LIB_REGISTRY)]
+    struct LibStaticMetrics {
+        /// The total number of invocations.
+        #[metric]
+        invocations: prometric::Counter,
+    }
+
+    let metrics = LibMetrics::default();
+    metrics.calls().inc();
+    LIB_STATIC_METRICS.invocations().inc();
+
+    let output = prometric::gather_to_string(&LIB_REGISTRY).unwrap();
+    assert!(output.contains("lib_calls 1"));
+    assert!(output.contains("lib_static_invocations 1"));
+    assert!(!prometric::gather_to_string(prometheus::default_registry()).unwrap().contains("lib_"));
+
+    // The builder can still override the registry.
+    let registry = prometheus::Registry::new();
+    let metrics = LibMetrics::builder().with_registry(&registry).build();
+    metrics.calls().inc_by(2);
+    assert!(prometric::gather_to_string(&registry).unwrap().contains("lib_calls 2"));
+}
+
+#[test]
+fn namespace_and_subsystem_work() {
+    #[prometric_derive::metrics(namespace = "myapp", subsystem = "p2p")]
+    struct P2pMetrics {
+        /// The number of connected peers.
+        #[metric]
+        peers: prometric::Gauge,
+    }
+
+    #[prometric_derive::metrics(scope = "myapp", subsystem = "db")]
+    struct DbMetrics {
+        /// The total number of queries.
+        #[metric]
+        queries: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let p2p = harness.build(|registry| P2pMetrics::builder().with_registry(registry).build());
+    let db = harness.build(|registry| DbMetrics::builder().with_registry(registry).build());
+    p2p.peers().set(3);
+    db.queries().inc();
+
+    assert_eq!(P2pMetrics::PEERS_NAME, "myapp_p2p_peers");
+    assert_eq!(harness.value("myapp_p2p_peers", &[]), Some(3.0));
+    assert_eq!(harness.value("myapp_db_queries", &[]), Some(1.0));
+}
+
+#[test]
+fn explicit_kind_works() {
+    type RequestCounter = prometric::Counter<f64>;
+
+    /// A histogram of latencies, in seconds.
+    struct Latency(prometric::Histogram);
+
+    impl prometric::AsMetric for Latency {
+        type Metric = prometric::Histogram;
+
+        fn from_metric(metric: prometric::Histogram) -> Self {
+            Self(metric)
+        }
+
+        fn as_metric(&self) -> &prometric::Histogram {
+            &self.0
+        }
+    }
+
+    #[prometric_derive::metrics(scope = "kind")]
+    struct KindMetrics {
+        /// The total number of requests.
+        #[metric(kind = "counter", labels = ["method"])]
+        requests: RequestCounter,
+        /// The latency of requests.
+        #[metric(kind = "histogram", buckets = [0.1, 1.0])]
+        latency: Latency,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| KindMetrics::builder().with_registry(registry).build());
+    metrics.requests("GET").inc_by(1.5);
+    metrics.latency().observe(0.5);
+
+    assert_eq!(harness.value("kind_requests", &[("method", "GET")]), Some(1.5));
+    assert!(harness.gather().contains(r#"kind_latency_bucket{le="1"} 1"#));
+}
+
+#[test]
+fn optional_metrics_work() {
+    #[prometric_derive::metrics(scope = "optional")]
+    struct OptionalMetrics {
+        /// The latency of each peer, only recorded when debugging.
+        #[metric(labels = ["peer"])]
+        peer_latency: Option<prometric::Histogram>,
+        /// The total number of dropped messages, only recorded when debugging.
+        #[metric]
+        dropped: Option<prometric::Counter>,
+        /// The total number of messages.
+        #[metric]
+        messages: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| {
+        OptionalMetrics::builder().with_registry(registry).enable_peer_latency(true).build()
+    });
+
+    metrics.peer_latency("a").observe(0.5);
+    metrics.dropped().inc();
+    metrics.messages().inc();
+    assert_eq!(harness.value("optional_peer_latency", &[("peer", "a")]), Some(1.0));
+    assert_eq!(harness.value("optional_dropped", &[]), None);
+    assert_eq!(harness.value("optional_messages", &[]), Some(1.0));
+
+    // Disabled again by the last call.
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| {
+        OptionalMetrics::builder()
+            .with_registry(registry)
+            .enable_peer_latency(true)
+            .enable_peer_latency(false)
+            .build()
+    });
+    metrics.peer_latency("a").observe(0.5);
+    assert_eq!(harness.value("optional_peer_latency", &[("peer", "a")]), None);
+}
+
+#[test]
+fn exemplars_work() {
+    #[prometric_derive::metrics(scope = "exemplar")]
+    struct ExemplarMetrics {
+        /// The total number of requests.
+        #[metric(labels = ["method"], exemplars)]
+        requests: prometric::Counter,
+        /// The latency of the requests.
+        #[metric(buckets = [0.1, 1.0], exemplars)]
+        latency: prometric::Histogram,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| ExemplarMetrics::builder().with_registry(registry).build());
+
+    metrics.requests("GET").inc_with_exemplar("abc");
+    metrics.requests("GET").inc_by_with_exemplar(2, &[("trace_id", "def")]);
+    metrics.latency().observe_with_exemplar(0.5, &[("trace_id", "ghi")]);
+    assert_eq!(harness.value("exemplar_requests", &[("method", "GET")]), Some(3.0));
+
+    let output = prometric::exemplar::encode_to_string(&harness.registry().gather());
+    assert!(output.contains(r#"exemplar_requests_total{method="GET"} 3 # {trace_id="def"} 2 "#));
+    assert!(output.contains(r#"exemplar_latency_bucket{le="0.1"} 0"#));
+    assert!(output.contains(r#"exemplar_latency_bucket{le="1"} 1 # {trace_id="ghi"} 0.5 "#));
+    assert!(output.ends_with("# EOF\n"));
+}
+
+#[tokio::test]
+async fn observe_future_works() {
+    #[prometric_derive::metrics(scope = "future")]
+    struct FutureMetrics {
+        /// The duration of the queries.
+        #[metric(labels = ["table"])]
+        query_duration: prometric::Histogram,
+        /// The duration of the requests.
+        #[metric(unit = "milliseconds")]
+        request_duration: prometric::Summary,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| FutureMetrics::builder().with_registry(registry).build());
+
+    let rows = metrics
+        .query_duration("blocks")
+        .observe_future(async {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            42
+        })
+        .await;
+    assert_eq!(rows, 42);
+    assert_eq!(harness.value("future_query_duration", &[("table", "blocks")]), Some(1.0));
+
+    // The future isn't polled, so nothing is timed or observed until it's awaited.
+    let future = metrics.request_duration().observe_async(|| async { "ok" });
+    assert_eq!(harness.value("future_request_duration_milliseconds", &[]), None);
+    assert_eq!(future.await, "ok");
+    assert_eq!(harness.value("future_request_duration_milliseconds", &[]), Some(1.0));
+}
+
+#[test]
+fn scope_env_works() {
+    #[prometric_derive::metrics(scope = "app", scope_env = "PROMETRIC_TEST_SCOPE_PREFIX")]
+    struct ScopeEnvMetrics {
+        /// The total number of tenant requests.
+        #[metric]
+        tenant_requests: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| ScopeEnvMetrics::builder().with_registry(registry).build());
+    metrics.tenant_requests().inc();
+    assert_eq!(harness.value("app_tenant_requests", &[]), Some(1.0));
+
+    // SAFETY: The variable is only read by this test.
+    unsafe { std::env::set_var("PROMETRIC_TEST_SCOPE_PREFIX", "tenant1") };
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| ScopeEnvMetrics::builder().with_registry(registry).build());
+    metrics.tenant_requests().inc();
+    assert_eq!(harness.value("tenant1_app_tenant_requests", &[]), Some(1.0));
+    unsafe { std::env::remove_var("PROMETRIC_TEST_SCOPE_PREFIX") };
+}
+
+#[test]
+fn display_label_values_work() {
+    #[prometric_derive::metrics(scope = "display")]
+    struct DisplayMetrics {
+        /// The total number of messages received from each peer, per protocol version.
+        #[metric(labels = ["peer", "version"])]
+        peer_messages: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| DisplayMetrics::builder().with_registry(registry).build());
+
+    let peer: std::net::SocketAddr = "10.0.0.1:30303".parse().unwrap();
+    metrics.peer_messages(peer, 68).inc();
+    metrics.peer_messages(String::from("10.0.0.1:30303"), "68").inc();
+    assert_eq!(
+        harness.value("display_peer_messages", &[("peer", "10.0.0.1:30303"), ("version", "68")]),
+        Some(2.0)
+    );
+}
+
+#[test]
+fn max_cardinality_works() {
+    #[prometric_derive::metrics(scope = "cardinality")]
+    struct CardinalityMetrics {
+        /// The total number of messages received from each peer.
+        #[metric(labels = ["peer"], max_cardinality = 2)]
+        received: prometric::Counter,
+        /// The latency of each peer.
+        #[metric(labels = ["peer"], max_cardinality = 1, on_overflow = "drop")]
+        peer_rtt: prometric::Histogram,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| CardinalityMetrics::builder().with_registry(registry).build());
+
+    for peer in ["a", "b", "c", "d", "a"] {
+        metrics.received(peer).inc();
+        metrics.peer_rtt(peer).observe(0.1);
+    }
+
+    assert_eq!(harness.value("cardinality_received", &[("peer", "a")]), Some(2.0));
+    assert_eq!(harness.value("cardinality_received", &[("peer", "b")]), Some(1.0));
+    assert_eq!(harness.value("cardinality_received", &[("peer", "c")]), None);
+    assert_eq!(harness.value("cardinality_received", &[("peer", "other")]), Some(2.0));
+    assert_eq!(harness.value("cardinality_received_cardinality_overflow", &[]), Some(2.0));
+
+    assert_eq!(harness.value("cardinality_peer_rtt", &[("peer", "a")]), Some(2.0));
+    assert_eq!(harness.value("cardinality_peer_rtt", &[("peer", "other")]), None);
+    assert_eq!(harness.value("cardinality_peer_rtt_cardinality_overflow", &[]), Some(3.0));
+
+    // Removing a series frees its slot.
+    assert!(metrics.received("b").remove());
+    metrics.received("e").inc();
+    assert_eq!(harness.value("cardinality_received", &[("peer", "e")]), Some(1.0));
+
+    assert!(metrics.render().contains("cardinality_received_cardinality_overflow 2"));
+}
+
+#[test]
+fn named_labels_work() {
+    enum Protocol {
+        Tcp,
+    }
+
+    impl prometric::LabelValue for Protocol {
+        fn label_value(&self) -> &'static str {
+            match self {
+                Self::Tcp => "tcp",
+            }
+        }
+    }
+
+    #[prometric_derive::metrics(scope = "named", labels = ["chain"], named_labels)]
+    struct NamedMetrics {
+        /// The total number of received bytes.
+        #[metric(labels = ["peer", protocol: Protocol, "direction"])]
+        bytes: prometric::Counter,
+        /// The latency of the requests.
+        #[metric(labels = ["method"])]
+        request_latency: prometric::Histogram,
+        /// The number of tasks.
+        #[metric]
+        tasks: prometric::Gauge,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| NamedMetrics::builder().with_registry(registry).build());
+
+    metrics.bytes().chain(1).direction("in").protocol(Protocol::Tcp).peer("a").inc_by(10);
+    metrics.request_latency().method("GET").chain(1).observe(0.5);
+    metrics.tasks().chain("mainnet").set(3);
+
+    assert_eq!(
+        harness.value(
+            "named_bytes",
+            &[("chain", "1"), ("peer", "a"), ("protocol", "tcp"), ("direction", "in")]
+        ),
+        Some(10.0)
+    );
+    assert_eq!(
+        harness.value("named_request_latency", &[("chain", "1"), ("method", "GET")]),
+        Some(1.0)
+    );
+    assert_eq!(harness.value("named_tasks", &[("chain", "mainnet")]), Some(3.0));
+
+    // An update with an unset label panics in debug builds, and isn't recorded otherwise.
+    let unset =
+        |update: &dyn Fn()| std::panic::catch_unwind(std::panic::AssertUnwindSafe(update)).is_err();
+    assert_eq!(unset(&|| metrics.request_latency().chain(2).observe(0.5)), cfg!(debug_assertions));
+    assert_eq!(unset(&|| metrics.tasks().set(5)), cfg!(debug_assertions));
+    assert_eq!(
+        unset(&|| assert_eq!(metrics.request_latency().chain(1).get_sample_count(), 0)),
+        cfg!(debug_assertions)
+    );
+    assert_eq!(harness.value("named_request_latency", &[("chain", "2"), ("method", "")]), None);
+    assert_eq!(harness.value("named_tasks", &[("chain", "")]), None);
+    assert!(!harness.gather().contains(r#"chain="2""#));
+}
+
+#[test]
+fn rename_all_works() {
+    #[prometric_derive::metrics(scope = "casing", rename_all = "camelCase", total_suffix)]
+    struct CasingMetrics {
+        /// The total number of HTTP requests.
+        #[metric]
+        http_requests: prometric::Counter,
+        /// The total number of errors, already suffixed.
+        #[metric(rename = "errors_total")]
+        errors: prometric::Counter,
+        /// The total CPU time.
+        #[metric(unit = "seconds")]
+        cpu: prometric::Counter<f64>,
+        /// The number of open connections.
+        #[metric]
+        open_connections: prometric::Gauge,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| CasingMetrics::builder().with_registry(registry).build());
+    metrics.http_requests().inc();
+    metrics.errors().inc();
+    metrics.cpu().inc_by(1.5);
+    metrics.open_connections().set(2);
+
+    assert_eq!(harness.value("casing_httpRequests_total", &[]), Some(1.0));
+    assert_eq!(harness.value("casing_errors_total", &[]), Some(1.0));
+    assert_eq!(harness.value("casing_cpu_seconds_total", &[]), Some(1.5));
+    assert_eq!(harness.value("casing_openConnections", &[]), Some(2.0));
+}
+
+#[test]
+fn deprecated_alias_works() {
+    #[prometric_derive::metrics(scope = "alias")]
+    struct AliasMetrics {
+        /// The depth of the queue.
+        #[metric(rename = "queue_depth", deprecated_alias = "queued_items", labels = ["queue"])]
+        depth: prometric::Gauge,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| AliasMetrics::builder().with_registry(registry).build());
+    metrics.depth("jobs").set(3);
+
+    assert_eq!(harness.value("alias_queue_depth", &[("queue", "jobs")]), Some(3.0));
+    assert_eq!(harness.value("alias_queued_items", &[("queue", "jobs")]), Some(3.0));
+}
+
+#[test]
+fn labels_from_env_work() {
+    #[prometric_derive::metrics(scope = "env_labels")]
+    struct EnvLabelsMetrics {
+        /// The total number of env label requests.
+        #[metric]
+        env_requests: prometric::Counter,
+    }
+
+    // SAFETY: The variables are only read by this test.
+    unsafe { std::env::set_var("PROMETRIC_TEST_REGION", "eu-west-1") };
+    unsafe { std::env::set_var("PROMETRIC_TEST_EMPTY", "") };
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| {
+        EnvLabelsMetrics::builder()
+            .with_registry(registry)
+            .with_labels_from_env(&[
+                "PROMETRIC_TEST_REGION",
+                "PROMETRIC_TEST_EMPTY",
+                "PROMETRIC_TEST_UNSET",
+            ])
+            .build()
+    });
+    metrics.env_requests().inc();
+
+    assert_eq!(
+        harness.value("env_labels_env_requests", &[("prometric_test_region", "eu-west-1")]),
+        Some(1.0)
+    );
+    let output = harness.gather();
+    assert!(!output.contains("prometric_test_empty"));
+    assert!(!output.contains("prometric_test_unset"));
+}
+
+#[test]
+fn composite_metrics_work() {
+    #[prometric_derive::metrics(scope = "p2p")]
+    struct P2pMetrics {
+        /// The total number of dials.
+        #[metric]
+        dials: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "rpc")]
+    struct RpcMetrics {
+        /// The total number of calls.
+        #[metric(labels = ["method"], alert(rate_gt = 100))]
+        calls: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(compose)]
+    struct NodeMetrics {
+        p2p: P2pMetrics,
+        rpc: RpcMetrics,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| {
+        NodeMetrics::builder().with_registry(registry).with_label("chain", "mainnet").build()
+    });
+    metrics.p2p().dials().inc();
+    metrics.rpc().calls("eth_call").inc_by(2);
+
+    assert_eq!(harness.value("p2p_dials", &[("chain", "mainnet")]), Some(1.0));
+    assert_eq!(
+        harness.value("rpc_calls", &[("chain", "mainnet"), ("method", "eth_call")]),
+        Some(2.0)
+    );
+    assert_eq!(metrics.gather().len(), 2);
+    assert_eq!(NodeMetrics::alert_rules()[0].metric, "rpc_calls");
+    assert_eq!(
+        NodeMetrics::describe().iter().map(|descriptor| &descriptor.name).collect::<Vec<_>>(),
+        ["p2p_dials", "rpc_calls"]
+    );
+
+    // The prefix of the builder still applies to the nested metrics.
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| {
+        NodeMetrics::builder().with_registry(registry).with_prefix("node").build()
+    });
+    metrics.p2p().dials().inc();
+    assert_eq!(harness.value("node_p2p_dials", &[]), Some(1.0));
+}
+
+#[test]
+fn reset_all_works() {
+    #[prometric_derive::metrics(scope = "reset_db")]
+    struct ResetDbMetrics {
+        /// The total number of reset queries.
+        #[metric(labels = ["table"])]
+        reset_queries: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "reset")]
+    struct ResetMetrics {
+        /// The total number of reset requests.
+        #[metric(labels = ["method"])]
+        reset_requests: prometric::Counter,
+        /// The number of reset workers.
+        #[metric(init = 4)]
+        reset_workers: prometric::Gauge,
+        /// The duration of reset requests.
+        #[metric]
+        reset_duration: prometric::Histogram,
+        #[metric(flatten)]
+        db: ResetDbMetrics,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| ResetMetrics::builder().with_registry(registry).build());
+    metrics.reset_requests("GET").inc();
+    metrics.reset_workers().set(8);
+    metrics.reset_duration().observe(1.0);
+    metrics.db().reset_queries("users").inc();
+
+    metrics.reset_all();
+    assert_eq!(harness.value("reset_reset_requests", &[("method", "GET")]), None);
+    assert_eq!(harness.value("reset_reset_workers", &[]), Some(4.0));
+    assert_eq!(harness.value("reset_reset_duration", &[]), None);
+    assert_eq!(harness.value("reset_db_reset_queries", &[("table", "users")]), None);
+
+    metrics.reset_requests("GET").inc();
+    assert_eq!(harness.value("reset_reset_requests", &[("method", "GET")]), Some(1.0));
+}
+
+#[test]
+fn scoped_metrics_work() {
+    #[prometric_derive::metrics(scope = "job")]
+    struct JobStepMetrics {
+        /// The total number of job steps.
+        #[metric(max_cardinality = 10, labels = ["step"])]
+        job_steps: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "job", scoped)]
+    struct JobMetrics {
+        /// The total number of processed items.
+        #[metric(deprecated_alias = "items")]
+        processed_items: prometric::Counter,
+        /// The duration of the job.
+        #[metric]
+        job_duration: prometric::Histogram,
+        #[metric(flatten)]
+        steps: JobStepMetrics,
+    }
+
+    let harness = Harness::new();
+    for job in ["a", "b"] {
+        let metrics = harness.build(|registry| {
+            JobMetrics::builder().with_registry(registry).with_label("job", job).build()
+        });
+        metrics.processed_items().inc();
+        metrics.job_duration().observe(1.0);
+        metrics.steps().job_steps("fetch").inc();
+        assert_eq!(harness.value("job_processed_items", &[("job", job)]), Some(1.0));
+        assert_eq!(harness.value("job_items", &[("job", job)]), Some(1.0));
+    }
+
+    assert!(harness.registry().gather().is_empty());
+}
+
+#[test]
+fn debug_values_work() {
+    #[prometric_derive::metrics(scope = "dbg", debug)]
+    struct DebugMetrics {
+        /// The total number of debug requests.
+        #[metric(labels = ["method"])]
+        debug_requests: prometric::Counter,
+        /// The duration of debug requests.
+        #[metric]
+        debug_duration: prometric::Histogram,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| DebugMetrics::builder().with_registry(registry).build());
+    metrics.debug_requests("GET").inc_by(2);
+    metrics.debug_duration().observe(0.5);
+
+    assert_eq!(
+        metrics.to_string(),
+        "dbg_debug_duration_count=1\ndbg_debug_duration_sum=0.5\n\
+         dbg_debug_requests{method=\"GET\"}=2\n"
+    );
+    assert_eq!(
+        format!("{metrics:?}"),
+        r#"DebugMetrics {dbg_debug_duration_count: 1.0, dbg_debug_duration_sum: 0.5, dbg_debug_requests{method="GET"}: 2.0}"#
+    );
+}
+
+#[test]
+fn mock_metrics_work() {
+    #[prometric_derive::metrics(scope = "mock_db", test_support)]
+    struct MockDbMetrics {
+        /// The duration of mock queries.
+        #[metric]
+        mock_query_duration: prometric::Histogram,
+    }
+
+    #[prometric_derive::metrics(scope = "mock", test_support)]
+    struct MockMetrics {
+        /// The total number of mock errors.
+        #[metric(labels = ["kind"])]
+        mock_errors: prometric::Counter,
+        /// The number of mock connections.
+        #[metric]
+        mock_connections: prometric::Gauge,
+        #[metric(flatten)]
+        db: MockDbMetrics,
+    }
+
+    let (metrics, recorder) = MockMetrics::mock();
+    metrics.mock_errors("timeout").inc();
+    metrics.mock_connections().set(3);
+    metrics.db().mock_query_duration().observe(0.5);
+
+    assert_eq!(
+        recorder.log(),
+        [
+            r#"mock_mock_errors{kind="timeout"} inc"#,
+            "mock_mock_connections set 3",
+            "mock_mock_db_mock_query_duration observe 0.5",
+        ]
+    );
+    // Nothing is registered in the default registry.
+    assert!(
+        prometric::prometheus::default_registry()
+            .gather()
+            .iter()
+            .all(|family| !family.name().starts_with("mock_"))
+    );
+}
+
+#[test]
+fn shared_metrics_work() {
+    #[prometric_derive::metrics(scope = "worker")]
+    struct WorkerMetrics {
+        /// The total number of jobs processed by the workers.
+        #[metric(shared)]
+        worker_jobs: std::sync::Arc<prometric::Counter>,
+        /// The duration of the jobs of the workers.
+        #[metric(shared, labels = ["queue"])]
+        worker_job_duration: std::sync::Arc<prometric::Histogram>,
+    }
+
+    let harness = Harness::new();
+    let first = harness.build(|registry| WorkerMetrics::builder().with_registry(registry).build());
+    let second = harness.build(|registry| {
+        WorkerMetrics::builder()
+            .with_registry(registry)
+            .with_shared_worker_jobs(first.shared_worker_jobs())
+            .with_shared_worker_job_duration(first.shared_worker_job_duration())
+            .build()
+    });
+
+    // Building the second instance doesn't disconnect the metrics of the first.
+    first.worker_jobs().inc();
+    second.worker_jobs().inc();
+    first.worker_job_duration("default").observe(1.0);
+    second.worker_job_duration("default").observe(2.0);
+    assert_eq!(harness.value("worker_worker_jobs", &[]), Some(2.0));
+    assert!(harness.gather().contains(r#"worker_worker_job_duration_count{queue="default"} 2"#));
+
+    // Shared metrics are left untouched by the other instances.
+    second.reset_all();
+    second.unregister();
+    assert_eq!(harness.value("worker_worker_jobs", &[]), Some(2.0));
+}
+
+#[test]
+fn parent_scope_works() {
+    const ROOT_SCOPE: &str = "root";
+
+    #[prometric_derive::metrics(scope = "p2p", parent = ROOT_SCOPE)]
+    struct ParentP2pMetrics {
+        /// The number of connected peers.
+        #[metric]
+        peers: prometric::Gauge,
+    }
+
+    #[prometric_derive::metrics(scope = "root")]
+    struct ParentRootMetrics {
+        #[metric(flatten)]
+        p2p: ParentP2pMetrics,
+    }
+
+    #[prometric_derive::metrics(compose)]
+    struct ParentNodeMetrics {
+        p2p: ParentP2pMetrics,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| ParentP2pMetrics::builder().with_registry(registry).build());
+    metrics.peers().set(1);
+    assert_eq!(ParentP2pMetrics::PARENT_SCOPE, Some("root"));
+    assert_eq!(harness.value("root_p2p_peers", &[]), Some(1.0));
+
+    // The scope of the flattening struct replaces the parent.
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| ParentRootMetrics::builder().with_registry(registry).build());
+    metrics.p2p().peers().set(2);
+    assert_eq!(harness.value("root_p2p_peers", &[]), Some(2.0));
+
+    // The nested metrics of a composite keep their parent.
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| ParentNodeMetrics::builder().with_registry(registry).build());
+    metrics.p2p().peers().set(3);
+    assert!(metrics.render().contains("root_p2p_peers 3"));
+}
+
+#[test]
+fn timer_fields_work() {
+    #[prometric_derive::metrics(scope = "rpc")]
+    struct RpcTimerMetrics {
+        /// RPC calls.
+        #[metric(labels = ["method"], buckets = [0.1, 1.0])]
+        rpc_calls: prometric::Timer,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| RpcTimerMetrics::builder().with_registry(registry).build());
+    metrics.rpc_calls("eth_call").observe(Duration::from_millis(50));
+    drop(metrics.rpc_calls("eth_call").start());
+    metrics.rpc_calls_handle("eth_getLogs").observe(Duration::from_secs(2));
+
+    assert_eq!(metrics.rpc_calls("eth_call").count(), 2);
+    assert_eq!(harness.value("rpc_rpc_calls_total", &[("method", "eth_getLogs")]), Some(1.0));
+    let output = metrics.render();
+    assert!(
+        output.contains(r#"rpc_rpc_calls_duration_seconds_bucket{method="eth_call",le="0.1"} 2"#)
+    );
+    assert!(output.contains("RPC calls. The total number of calls."));
+
+    let names = RpcTimerMetrics::describe().into_iter().map(|d| d.name).collect::<Vec<_>>();
+    assert_eq!(names, ["rpc_rpc_calls_duration_seconds", "rpc_rpc_calls_total"]);
+    assert_eq!(RpcTimerMetrics::METRICS.len(), 2);
+}
+
+#[test]
+fn ratio_fields_work() {
+    #[prometric_derive::metrics(scope = "cache")]
+    struct CacheRatioMetrics {
+        /// The cache hit ratio.
+        #[metric(ratio = (cache_hits, cache_lookups))]
+        hit_ratio: prometric::Ratio,
+        /// The total number of cache hits.
+        #[metric(labels = ["cache"])]
+        cache_hits: prometric::Counter,
+        /// The total number of cache lookups.
+        #[metric(labels = ["cache"])]
+        cache_lookups: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| CacheRatioMetrics::builder().with_registry(registry).build());
+    metrics.cache_hits("blocks").inc_by(3u64);
+    metrics.cache_lookups("blocks").inc_by(4u64);
+    metrics.cache_lookups("headers").inc();
+
+    assert_eq!(harness.value("cache_hit_ratio", &[("cache", "blocks")]), Some(0.75));
+    // No hits were recorded for headers, so the ratio has no series for it.
+    assert_eq!(harness.value("cache_hit_ratio", &[("cache", "headers")]), None);
+    assert_eq!(metrics.hit_ratio().unwrap().value(&[("cache", "blocks")]), Some(0.75));
+    assert!(metrics.render().contains("# TYPE cache_hit_ratio gauge"));
+
+    let ratio = CacheRatioMetrics::describe().into_iter().find(|d| d.name == "cache_hit_ratio");
+    assert_eq!(ratio.unwrap().labels, ["cache"]);
+
+    metrics.unregister();
+    assert!(harness.registry().gather().is_empty());
+}
+
+#[test]
+fn gauge_fn_fields_work() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static QUEUE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+    #[prometric_derive::metrics(scope = "gauge_fn")]
+    struct GaugeFnMetrics {
+        /// The number of queued jobs.
+        #[metric(eval = || QUEUE_DEPTH.load(Ordering::Relaxed))]
+        queue_depth: prometric::GaugeFn,
+        /// The load of the queue.
+        #[metric(rename = "load", eval = || 0.5)]
+        queue_load: prometric::GaugeFn,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| {
+        GaugeFnMetrics::builder().with_registry(registry).with_label("node", "a").build()
+    });
+
+    // The gauges are evaluated when gathered.
+    QUEUE_DEPTH.store(3, Ordering::Relaxed);
+    let node = [("node", "a")];
+    assert_eq!(harness.value("gauge_fn_queue_depth", &node), Some(3.0));
+    QUEUE_DEPTH.store(7, Ordering::Relaxed);
+    assert_eq!(harness.value("gauge_fn_queue_depth", &node), Some(7.0));
+    assert_eq!(metrics.queue_depth().unwrap().get(), 7.0);
+    assert_eq!(harness.value("gauge_fn_load", &node), Some(0.5));
+    assert!(metrics.render().contains("# TYPE gauge_fn_queue_depth gauge"));
+
+    let descriptors = GaugeFnMetrics::describe();
+    let depth = descriptors.iter().find(|d| d.name == "gauge_fn_queue_depth").unwrap();
+    assert!(depth.labels.is_empty());
+
+    metrics.unregister();
+    assert!(harness.registry().gather().is_empty());
+}
+
+#[test]
+fn info_fields_work() {
+    #[prometric_derive::metrics(scope = "info")]
+    struct InfoMetrics {
+        /// The build of the node.
+        #[metric(labels = ["version", "git_sha"])]
+        build_info: prometric::Info,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| InfoMetrics::builder().with_registry(registry).build());
+    metrics.build_info("1.0.0", "abc123").set();
+    // Setting the info again replaces the previous label values.
+    metrics.build_info("1.1.0", "def456").set();
+
+    assert_eq!(
+        harness.value("info_build_info", &[("version", "1.1.0"), ("git_sha", "def456")]),
+        Some(1.0)
+    );
+    assert_eq!(
+        harness.value("info_build_info", &[("version", "1.0.0"), ("git_sha", "abc123")]),
+        None
+    );
+    assert!(metrics.render().contains("# TYPE info_build_info gauge"));
+    assert_eq!(InfoMetrics::BUILD_INFO_LABELS, ["version", "git_sha"]);
+
+    let descriptor = &InfoMetrics::describe()[0];
+    assert_eq!(descriptor.kind, prometric::descriptor::MetricKind::Gauge);
+}
+
+#[test]
+fn cloned_metrics_share_collectors() {
+    #[prometric_derive::metrics(scope = "clone", clone)]
+    struct CloneMetrics {
+        /// The total number of requests.
+        #[metric(labels = ["method"])]
+        clone_requests: prometric::Counter,
+        /// The request durations.
+        #[metric]
+        clone_durations: prometric::Histogram,
+        /// The RPC calls.
+        #[metric(labels = ["method"])]
+        clone_calls: prometric::Timer,
+        /// The cache hit ratio.
+        #[metric(ratio = (clone_requests, clone_lookups))]
+        clone_ratio: prometric::Ratio,
+        /// The total number of lookups.
+        #[metric(labels = ["method"])]
+        clone_lookups: prometric::Counter,
+    }
+
+    // A derive on the struct works the same.
+    #[prometric_derive::metrics(scope = "derived_clone")]
+    #[derive(Clone)]
+    struct DerivedCloneMetrics {
+        /// The current number of peers.
+        #[metric]
+        derived_peers: prometric::Gauge,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| CloneMetrics::builder().with_registry(registry).build());
+    let clone = metrics.clone();
+    metrics.clone_requests("GET").inc();
+    clone.clone_requests("GET").inc();
+    clone.clone_durations().observe(1.0);
+    clone.clone_calls("eth_call").observe(std::time::Duration::from_secs(1));
+
+    assert_eq!(harness.value("clone_clone_requests", &[("method", "GET")]), Some(2.0));
+    assert_eq!(metrics.clone_calls("eth_call").count(), 1);
+    assert_eq!(metrics.render(), clone.render());
+
+    let derived =
+        harness.build(|registry| DerivedCloneMetrics::builder().with_registry(registry).build());
+    derived.clone().derived_peers().set(3);
+    assert_eq!(harness.value("derived_clone_derived_peers", &[]), Some(3.0));
+
+    // Unregistering a clone unregisters the shared metrics.
+    clone.unregister();
+    assert!(metrics.gather().is_empty());
+}
+
+#[test]
+fn help_placeholders_work() {
+    // Accessor types are named after the fields, so each struct gets its own module.
+    macro_rules! service_metrics {
+        ($module:ident, $scope:literal) => {
+            // The unused accessors of structs expanded from a local macro are linted.
+            #[allow(dead_code)]
+            mod $module {
+                #[prometric_derive::metrics(scope = $scope)]
+                pub(crate) struct ServiceMetrics {
+                    /// The total number of {scope} requests.
+                    #[metric]
+                    requests: prometric::Counter,
+                    /// The latency of {scope} requests, in {unit}.
+                    #[metric(unit = "seconds")]
+                    latency: prometric::Histogram,
+                }
+            }
+        };
+    }
+    service_metrics!(rpc, "rpc");
+    service_metrics!(p2p, "p2p");
+
+    let help = |descriptors: Vec<prometric::descriptor::MetricDescriptor>| {
+        descriptors.into_iter().map(|descriptor| descriptor.help).collect::<Vec<_>>()
+    };
+    assert_eq!(
+        help(rpc::ServiceMetrics::describe()),
+        ["The total number of rpc requests.", "The latency of rpc requests, in seconds."]
+    );
+    assert_eq!(
+        help(p2p::ServiceMetrics::describe()),
+        ["The total number of p2p requests.", "The latency of p2p requests, in seconds."]
+    );
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| rpc::ServiceMetrics::builder().with_registry(registry).build());
+    metrics.requests().inc();
+    assert!(metrics.render().contains("# HELP rpc_requests The total number of rpc requests."));
+}
+
+#[test]
+fn collector_impl_works() {
+    #[prometric_derive::metrics(scope = "pool", collector, clone)]
+    struct PoolMetrics {
+        /// The total number of queries.
+        #[metric(labels = ["table"])]
+        pool_queries: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "collected", collector, clone)]
+    struct CollectedMetrics {
+        /// The total number of requests.
+        #[metric(labels = ["method"])]
+        collected_requests: prometric::Counter,
+        /// The request durations.
+        #[metric]
+        collected_durations: prometric::Histogram,
+        /// The current number of connections.
+        #[metric(eval = || 4)]
+        collected_connections: prometric::GaugeFn,
+        #[metric(flatten)]
+        pool: PoolMetrics,
+    }
+
+    // Built with a private registry, then registered in another one.
+    let metrics = CollectedMetrics::builder().with_registry(&prometheus::Registry::new()).build();
+    let registry = prometheus::Registry::new();
+    registry.register(Box::new(metrics.clone())).unwrap();
+
+    metrics.collected_requests("GET").inc();
+    metrics.collected_durations().observe(1.0);
+    metrics.pool().pool_queries("users").inc_by(2);
+
+    let output = prometric::gather_to_string(&registry).unwrap();
+    assert!(output.contains(r#"collected_collected_requests{method="GET"} 1"#));
+    assert!(output.contains("collected_collected_durations_count 1"));
+    assert!(output.contains("collected_collected_connections 4"));
+    assert!(output.contains(r#"collected_pool_pool_queries{table="users"} 2"#));
+
+    registry.unregister(Box::new(metrics)).unwrap();
+    assert!(registry.gather().is_empty());
+}
+
+#[test]
+fn created_series_work() {
+    #[prometric_derive::metrics(scope = "created")]
+    struct CreatedMetrics {
+        /// The total number of requests.
+        #[metric(labels = ["method"], created)]
+        created_requests: prometric::Counter,
+        /// The size of the responses.
+        #[metric(quantiles = [0.5], created)]
+        created_sizes: prometric::Summary,
+        /// The total number of errors.
+        #[metric]
+        created_errors: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| CreatedMetrics::builder().with_registry(registry).build());
+
+    metrics.created_requests("GET").inc();
+    metrics.created_sizes().observe(10.0);
+    metrics.created_errors().inc();
+
+    let output = prometric::exemplar::encode_to_string(&harness.registry().gather());
+    assert!(output.contains(
+        "created_created_requests_total{method=\"GET\"} 1\ncreated_created_requests_created{method=\"GET\"} "
+    ));
+    assert!(output.contains("created_created_sizes_sum 10\ncreated_created_sizes_created "));
+    assert!(!output.contains("created_created_errors_created"));
+
+    // The Prometheus text format has no `_created` samples.
+    let output = prometric::gather_to_string(harness.registry()).unwrap();
+    assert!(!output.contains("created_created_requests_created"));
+}
+
+#[test]
+fn crate_path_works() {
+    // E.g. an internal crate wrapping prometric.
+    mod facade {
+        pub use prometric;
+    }
+
+    #[derive(prometric_derive::Labels)]
+    #[labels(crate = "facade::prometric")]
+    struct FacadeLabels {
+        method: &'static str,
+    }
+
+    /// The state of the facade.
+    #[prometric_derive::metrics(scope = "facade", crate = "facade::prometric")]
+    #[derive(Debug, Clone, Copy)]
+    enum FacadeState {
+        Starting,
+        Running,
+    }
+
+    #[prometric_derive::metrics(scope = "facade", crate = "facade::prometric", collector)]
+    struct FacadeMetrics {
+        /// The total number of requests.
+        #[metric(labels = FacadeLabels)]
+        facade_requests: facade::prometric::Counter,
+        /// The request durations.
+        #[metric(buckets = [0.1, 1.0])]
+        facade_durations: facade::prometric::Histogram,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| FacadeMetrics::builder().with_registry(registry).build());
+    metrics.facade_requests(FacadeLabels { method: "GET" }).inc();
+    metrics.facade_durations().observe(0.5);
+    assert_eq!(harness.value("facade_facade_requests", &[("method", "GET")]), Some(1.0));
+
+    let state = harness.build(FacadeStateMetrics::new);
+    state.set_state(FacadeState::Starting);
+    state.set_state(FacadeState::Running);
+    assert_eq!(
+        harness.value("facade_facade_state", &[("facade_facade_state", "running")]),
+        Some(1.0)
+    );
+}
+
+#[test]
+fn crate_path_keeps_user_paths() {
+    // A facade shadowing some items of prometric.
+    mod facade {
+        pub mod prometric {
+            pub use ::prometric::*;
+
+            pub mod prometheus {
+                pub use ::prometric::prometheus::*;
+
+                // Only used if the path in `buckets` is rewritten to the facade.
+                #[allow(dead_code)]
+                pub const DEFAULT_BUCKETS: &[f64; 2] = &[1.0, 2.0];
+            }
+        }
+    }
+
+    #[prometric_derive::metrics(scope = "literal", crate = "facade::prometric")]
+    struct LiteralMetrics {
+        /// The request durations.
+        #[metric(buckets = ::prometric::prometheus::DEFAULT_BUCKETS)]
+        literal_durations: facade::prometric::Histogram,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| LiteralMetrics::builder().with_registry(registry).build());
+    metrics.literal_durations().observe(0.5);
+
+    // The user-written path isn't rewritten to the facade.
+    let output = metrics.render();
+    assert!(output.contains(r#"literal_literal_durations_bucket{le="10"} 1"#));
+    assert!(!output.contains(r#"literal_literal_durations_bucket{le="2"}"#));
+}
+
+#[test]
+fn sanitized_labels_work() {
+    const SANITIZED_LABELS: &[&str] = &["client"];
+
+    #[prometric_derive::metrics(scope = "escaped", sanitize = "escape", max_label_len = 8)]
+    struct EscapedMetrics {
+        /// The total number of messages.
+        #[metric(labels = ["peer"])]
+        escaped_messages: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "rejected", sanitize = "reject", named_labels)]
+    struct RejectedMetrics {
+        /// The total number of messages.
+        #[metric(labels = ["peer"])]
+        rejected_messages: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "replaced", sanitize = "replace")]
+    struct ReplacedMetrics {
+        /// The total number of requests.
+        #[metric(label_names = SANITIZED_LABELS)]
+        replaced_requests: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let escaped =
+        harness.build(|registry| EscapedMetrics::builder().with_registry(registry).build());
+    escaped.escaped_messages("a\nb").inc();
+    escaped.escaped_messages("peer-123456").inc();
+    assert_eq!(harness.value("escaped_escaped_messages", &[("peer", r"a\nb")]), Some(1.0));
+    assert_eq!(harness.value("escaped_escaped_messages", &[("peer", "peer-123")]), Some(1.0));
+
+    let rejected =
+        harness.build(|registry| RejectedMetrics::builder().with_registry(registry).build());
+    rejected.rejected_messages().peer("a\nb").inc();
+    rejected.rejected_messages().peer("a").inc();
+    assert_eq!(harness.value("rejected_rejected_messages", &[("peer", "a")]), Some(1.0));
+    assert_eq!(harness.value("rejected_rejected_messages", &[("peer", "a\nb")]), None);
+
+    let replaced =
+        harness.build(|registry| ReplacedMetrics::builder().with_registry(registry).build());
+    replaced.replaced_requests(["curl\r\n"]).inc();
+    assert_eq!(harness.value("replaced_replaced_requests", &[("client", "curl__")]), Some(1.0));
+}
+
+#[test]
+fn test_utils_getters_work() {
+    #[prometric_derive::metrics(scope = "getters", test_utils)]
+    struct GetterMetrics {
+        /// The total number of requests.
+        #[metric(labels = ["method", "path"])]
+        http_requests: prometric::Counter,
+        /// The number of open connections.
+        connections: prometric::Gauge<i64>,
+        /// The duration of requests.
+        #[metric(labels = ["method"])]
+        http_requests_duration: prometric::Histogram,
+        /// The size of responses.
+        response_size: prometric::Summary,
+        /// The duration of flushes.
+        flush: prometric::Timer,
+    }
+
+    #[prometric_derive::metrics(scope = "named_getters", test_utils, named_labels)]
+    struct NamedGetterMetrics {
+        /// The total number of messages.
+        #[metric(labels = ["peer"])]
+        messages: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| GetterMetrics::builder().with_registry(registry).build());
+    assert_eq!(metrics.http_requests_value("GET", "/"), 0);
+    metrics.http_requests("GET", "/").inc_by(2u64);
+    metrics.connections().set(-3);
+    metrics.http_requests_duration("GET").observe(0.5);
+    metrics.http_requests_duration("GET").observe(1.5);
+    metrics.response_size().observe(10.0);
+    metrics.flush().observe(std::time::Duration::from_millis(5));
+
+    assert_eq!(metrics.http_requests_value("GET", "/"), 2);
+    assert_eq!(metrics.http_requests("GET", "/").get(), 2);
+    assert_eq!(metrics.http_requests_value("POST", "/"), 0);
+    assert_eq!(metrics.connections_value(), -3);
+    assert_eq!(metrics.http_requests_duration_count("GET"), 2);
+    assert_eq!(metrics.http_requests_duration_sum("GET"), 2.0);
+    assert_eq!(metrics.response_size_count(), 1);
+    assert_eq!(metrics.response_size_sum(), 10.0);
+    assert_eq!(metrics.flush_count(), 1);
+
+    let named =
+        harness.build(|registry| NamedGetterMetrics::builder().with_registry(registry).build());
+    named.messages().peer("a").inc();
+    assert_eq!(named.messages().peer("a").get(), 1);
+}
+
+#[test]
+fn outcome_counters_work() {
+    #[prometric_derive::metrics(scope = "outcome", total_suffix)]
+    struct OutcomeMetrics {
+        /// The total number of RPC calls.
+        #[metric(labels = ["method"])]
+        rpc_calls: prometric::OutcomeCounter,
+        /// The total number of flushes.
+        flushes: prometric::OutcomeCounter,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| OutcomeMetrics::builder().with_registry(registry).build());
+    metrics.rpc_calls("eth_call").success();
+    metrics.rpc_calls("eth_call").failure();
+    let result: Result<u64, &str> = metrics.rpc_calls("eth_call").record(Ok(1));
+    assert_eq!(result, Ok(1));
+    assert_eq!(metrics.rpc_calls("eth_call").record(Err::<u64, _>("timeout")), Err("timeout"));
+    metrics.rpc_calls_handle("eth_getLogs").record(&Err::<(), _>("timeout"));
+    metrics.flushes().inc(prometric::Outcome::Success);
+
+    let calls = |method, outcome| {
+        harness.value("outcome_rpc_calls_total", &[("method", method), ("outcome", outcome)])
+    };
+    assert_eq!(calls("eth_call", "success"), Some(2.0));
+    assert_eq!(calls("eth_call", "failure"), Some(2.0));
+    assert_eq!(calls("eth_getLogs", "failure"), Some(1.0));
+    assert_eq!(calls("eth_getLogs", "success"), Some(0.0));
+    assert_eq!(harness.value("outcome_flushes_total", &[("outcome", "success")]), Some(1.0));
+
+    let descriptors = OutcomeMetrics::describe();
+    assert_eq!(descriptors[0].labels, ["method", "outcome"]);
+    assert_eq!(descriptors[0].kind, prometric::descriptor::MetricKind::Counter);
+}
+
+#[test]
+fn default_and_empty_scopes_work() {
+    #[prometric_derive::metrics]
+    struct CrateMetrics {
+        /// The total number of default scoped requests.
+        default_scoped_requests: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "")]
+    struct UnscopedMetrics {
+        /// The total number of unscoped requests.
+        unscoped_requests: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "", subsystem = "db")]
+    struct SubsystemMetrics {
+        /// The total number of queries.
+        unscoped_queries: prometric::Counter,
+    }
+
+    assert_eq!(
+        CrateMetrics::DEFAULT_SCOPED_REQUESTS_NAME,
+        "prometric_derive_default_scoped_requests"
+    );
+    assert_eq!(UnscopedMetrics::UNSCOPED_REQUESTS_NAME, "unscoped_requests");
+
+    let harness = Harness::new();
+    let default = harness.build(|registry| CrateMetrics::builder().with_registry(registry).build());
+    let unscoped =
+        harness.build(|registry| UnscopedMetrics::builder().with_registry(registry).build());
+    let subsystem =
+        harness.build(|registry| SubsystemMetrics::builder().with_registry(registry).build());
+    default.default_scoped_requests().inc();
+    unscoped.unscoped_requests().inc();
+    subsystem.unscoped_queries().inc();
+    assert_eq!(harness.value("prometric_derive_default_scoped_requests", &[]), Some(1.0));
+    assert_eq!(harness.value("unscoped_requests", &[]), Some(1.0));
+    assert_eq!(harness.value("db_unscoped_queries", &[]), Some(1.0));
+
+    // The scope can still be set at runtime.
+    let registry = prometheus::Registry::new();
+    let scoped = UnscopedMetrics::builder().with_registry(&registry).with_scope("app").build();
+    scoped.unscoped_requests().inc();
+    assert!(prometric::gather_to_string(&registry).unwrap().contains("app_unscoped_requests 1"));
+}
+
+#[test]
+fn read_methods_work() {
+    #[prometric_derive::metrics(scope = "read")]
+    struct ReadMetrics {
+        /// The total number of requests.
+        #[metric(labels = ["method"])]
+        read_requests: prometric::Counter<f64>,
+        /// The number of queued jobs.
+        read_queued_jobs: prometric::Gauge<i64>,
+        /// The duration of requests.
+        read_duration: prometric::Histogram,
+        /// The size of responses.
+        read_size: prometric::Summary,
+        /// The score of each peer.
+        #[metric(labels = ["peer"], max_cardinality = 1)]
+        read_peer_score: prometric::Gauge,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| ReadMetrics::builder().with_registry(registry).build());
+    metrics.read_requests("GET").inc_by(1.5);
+    metrics.read_queued_jobs().set(-2);
+    metrics.read_duration().observe(0.25);
+    metrics.read_duration().observe(0.75);
+    metrics.read_size().observe(10.0);
+
+    assert_eq!(metrics.read_requests("GET").get(), 1.5);
+    assert_eq!(metrics.read_requests("POST").get(), 0.0);
+    assert_eq!(metrics.read_queued_jobs().get(), -2);
+    assert_eq!(metrics.read_duration().get_sample_count(), 2);
+    assert_eq!(metrics.read_duration().get_sample_sum(), 1.0);
+    assert_eq!(metrics.read_size().get_sample_count(), 1);
+    assert_eq!(metrics.read_size().get_sample_sum(), 10.0);
+
+    // Reading a series beyond the cardinality limit neither creates it nor takes a slot.
+    assert_eq!(metrics.read_peer_score("a").get(), 0);
+    metrics.read_peer_score("b").set(5);
+    assert_eq!(harness.value("read_read_peer_score", &[("peer", "a")]), None);
+    assert_eq!(harness.value("read_read_peer_score", &[("peer", "b")]), Some(5.0));
+    assert_eq!(harness.value("read_read_peer_score", &[("peer", "other")]), None);
+
+    // Disabled metrics read as zero.
+    let disabled = ReadMetrics::builder().disabled(true).build();
+    disabled.read_queued_jobs().set(3);
+    assert_eq!(disabled.read_queued_jobs().get(), 0);
+}
+
+#[test]
+fn set_to_current_time_works() {
+    #[prometric_derive::metrics(scope = "timestamps")]
+    struct TimestampMetrics {
+        /// The time of the last successful sync, in seconds since the UNIX epoch.
+        last_sync_timestamp_seconds: prometric::Gauge<f64>,
+        /// The time of the last block of each chain, in seconds since the UNIX epoch.
+        #[metric(labels = ["chain"])]
+        last_block_timestamp_seconds: prometric::Gauge<u64>,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| TimestampMetrics::builder().with_registry(registry).build());
+    let before =
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs_f64();
+    metrics.last_sync_timestamp_seconds().set_to_current_time();
+    metrics.last_block_timestamp_seconds("ethereum").set_to_current_time();
+    metrics.last_block_timestamp_seconds_handle("base").set_to_current_time();
+
+    let synced = metrics.last_sync_timestamp_seconds().get();
+    assert!(synced >= before && synced < before + 60.0);
+    let block = metrics.last_block_timestamp_seconds("ethereum").get();
+    assert!(block >= before.floor() as u64);
+    assert!(metrics.last_block_timestamp_seconds("base").get() >= block);
+}
+
+#[test]
+fn observe_duration_works() {
+    use std::time::{Duration, Instant};
+
+    #[prometric_derive::metrics(scope = "durations")]
+    struct DurationMetrics {
+        /// The duration of requests.
+        #[metric(labels = ["method"])]
+        request_duration: prometric::Histogram,
+        /// The duration of queries.
+        #[metric(unit = "milliseconds")]
+        query_duration: prometric::Histogram,
+        /// The duration of flushes.
+        flush_duration: prometric::Summary,
+        /// The duration of syncs.
+        syncs: prometric::Timer,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| DurationMetrics::builder().with_registry(registry).build());
+    metrics.request_duration("GET").observe_duration(Duration::from_millis(250));
+    metrics.query_duration().observe_duration(Duration::from_millis(250));
+    metrics.flush_duration().observe_duration(Duration::from_secs(2));
+    let start = Instant::now();
+    metrics.request_duration("POST").observe_since(start);
+    metrics.syncs().observe_since(start);
+
+    assert_eq!(metrics.request_duration("GET").get_sample_sum(), 0.25);
+    // Durations are converted to the declared unit.
+    assert_eq!(metrics.query_duration().get_sample_sum(), 250.0);
+    assert_eq!(metrics.flush_duration().get_sample_sum(), 2.0);
+    assert_eq!(metrics.request_duration("POST").get_sample_count(), 1);
+    assert!(metrics.request_duration("POST").get_sample_sum() < 60.0);
+    assert_eq!(metrics.syncs().count(), 1);
+}
+
+#[test]
+fn struct_labels_work() {
+    #[derive(prometric_derive::Labels)]
+    struct RequestLabels {
+        method: &'static str,
+    }
+
+    #[prometric_derive::metrics(scope = "struct_labels", labels = ["chain", "network"])]
+    struct StructLabelMetrics {
+        /// The current block height.
+        #[metric]
+        height: prometric::Gauge,
+        /// The total number of RPC requests.
+        #[metric(labels = ["method"])]
+        requests: prometric::Counter,
+        /// The total number of RPC errors.
+        #[metric(labels = RequestLabels)]
+        errors: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| StructLabelMetrics::builder().with_registry(registry).build());
+
+    metrics.height("ethereum", "mainnet").set(100);
+    metrics.requests("ethereum", "mainnet", "eth_call").inc();
+    metrics.errors("ethereum", "sepolia", RequestLabels { method: "eth_call" }).inc_by(2);
+
+    let chain = [("chain", "ethereum"), ("network", "mainnet")];
+    assert_eq!(harness.value("struct_labels_height", &chain), Some(100.0));
+    assert_eq!(
+        harness.value("struct_labels_requests", &[chain[0], chain[1], ("method", "eth_call")]),
+        Some(1.0)
+    );
+    assert_eq!(
+        harness.value(
+            "struct_labels_errors",
+            &[("chain", "ethereum"), ("network", "sepolia"), ("method", "eth_call")]
+        ),
+        Some(2.0)
+    );
+}
+
+#[test]
+fn flattened_metrics_work() {
+    #[prometric_derive::metrics(scope = "db")]
+    struct DbMetrics {
+        /// The total number of queries.
+        #[metric(labels = ["table"], alert(rate_gt = 100))]
+        queries: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "app")]
+    struct AppMetrics {
+        /// The total number of HTTP requests.
+        #[metric]
+        requests: prometric::Counter,
+        #[metric(flatten)]
+        db: DbMetrics,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| {
+        AppMetrics::builder().with_registry(registry).with_label("instance", "a").build()
+    });
+
+    metrics.requests().inc();
+    metrics.db().queries("users").inc_by(2);
+
+    assert_eq!(harness.value("app_requests", &[("instance", "a")]), Some(1.0));
+    assert_eq!(
+        harness.value("app_db_queries", &[("instance", "a"), ("table", "users")]),
+        Some(2.0)
+    );
+
+    let rules = AppMetrics::alert_rules();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].metric, "app_db_queries");
+}
+
+#[test]
+fn bulk_labels_work() {
+    #[prometric_derive::metrics(scope = "bulk")]
+    struct BulkMetrics {
+        /// The total number of requests.
+        #[metric]
+        bulk_requests: prometric::Counter,
+    }
+
+    let config = std::collections::BTreeMap::from([("host", "localhost"), ("region", "eu")]);
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| {
+        BulkMetrics::builder()
+            .with_registry(registry)
+            .with_labels(config)
+            .with_labels([("region".to_owned(), "us".to_owned())])
+            .build()
+    });
+
+    metrics.bulk_requests().inc();
+    assert_eq!(
+        harness.value("bulk_bulk_requests", &[("host", "localhost"), ("region", "us")]),
+        Some(1.0)
+    );
+}
+
+#[test]
+fn subsystems_work() {
+    #[prometric_derive::metrics(scope = "sub")]
+    struct SubsystemMetrics {
+        /// The total number of queries.
+        #[metric(rename = "queries_total")]
+        queries: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let db = harness.build(|registry| {
+        SubsystemMetrics::builder().with_registry(registry).with_subsystem("db").build()
+    });
+    let cache = harness.build(|registry| {
+        SubsystemMetrics::builder().with_registry(registry).with_subsystem("cache").build()
+    });
+
+    db.queries().inc();
+    cache.queries().inc_by(2);
+    assert_eq!(harness.value("sub_db_queries_total", &[]), Some(1.0));
+    assert_eq!(harness.value("sub_cache_queries_total", &[]), Some(2.0));
+    assert!(db.render().contains("sub_db_queries_total 1"));
+    assert!(!db.render().contains("sub_cache_queries_total"));
+}
+
+#[test]
+fn scope_override_works() {
+    #[prometric_derive::metrics(scope = "db")]
+    struct DbMetrics {
+        /// The total number of queries.
+        #[metric]
+        queries: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "app")]
+    struct ScopedMetrics {
+        /// The total number of HTTP requests.
+        #[metric(rename = "http_requests")]
+        requests: prometric::Counter,
+        #[metric(flatten)]
+        db: DbMetrics,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| {
+        ScopedMetrics::builder().with_registry(registry).with_scope("builder_a").build()
+    });
+
+    metrics.requests().inc();
+    metrics.db().queries().inc();
+
+    assert_eq!(harness.value("builder_a_http_requests", &[]), Some(1.0));
+    assert_eq!(harness.value("builder_a_db_queries", &[]), Some(1.0));
+    assert_eq!(harness.value("app_http_requests", &[]), None);
+}
+
+#[test]
+fn field_const_labels_work() {
+    #[prometric_derive::metrics(scope = "queue")]
+    struct QueueMetrics {
+        /// The number of queued jobs.
+        #[metric(labels = ["priority"], const_labels = [("queue", "ingest"), ("region", "eu")])]
+        jobs: prometric::Gauge,
+        /// The total number of processed jobs.
+        #[metric]
+        processed: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| {
+        QueueMetrics::builder().with_registry(registry).with_label("instance", "a").build()
+    });
+
+    metrics.jobs("high").set(3);
+    metrics.processed().inc();
+
+    let labels = [("instance", "a"), ("priority", "high"), ("queue", "ingest"), ("region", "eu")];
+    assert_eq!(harness.value("queue_jobs", &labels), Some(3.0));
+    assert_eq!(harness.value("queue_processed", &[("instance", "a")]), Some(1.0));
+}
+
+#[test]
+fn bucket_generators_work() {
+    #[prometric_derive::metrics(scope = "generated")]
+    struct GeneratedBuckets {
+        /// The duration of requests.
+        #[metric(buckets = exponential(0.001, 2.0, 15))]
+        duration: prometric::Histogram,
+        /// The size of requests.
+        #[metric(buckets = linear(100.0, 100.0, 5))]
+        size: prometric::Histogram,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| GeneratedBuckets::builder().with_registry(registry).build());
+
+    metrics.duration().observe(0.01);
+    metrics.size().observe(250);
+
+    let families = harness.registry().gather();
+    let buckets = |name: &str| {
+        let family = families.iter().find(|family| family.name() == name).unwrap();
+        family.get_metric()[0]
+            .get_histogram()
+            .get_bucket()
+            .iter()
+            .map(|bucket| bucket.upper_bound())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(buckets("generated_duration").len(), 15);
+    assert_eq!(buckets("generated_duration")[14], 0.001 * 2f64.powi(14));
+    assert_eq!(buckets("generated_size"), vec![100.0, 200.0, 300.0, 400.0, 500.0]);
+}
+
+#[test]
+fn cfg_fields_work() {
+    #[prometric_derive::metrics(scope = "cfg")]
+    struct CfgMetrics {
+        /// The total number of requests.
+        #[metric]
+        requests: prometric::Counter,
+        /// Not compiled, this file is only compiled without `prometric_disabled`.
+        #[cfg(prometric_disabled)]
+        #[metric(alert(rate_gt = 1))]
+        disabled: prometric::Counter,
+        /// Always compiled.
+        #[cfg(not(prometric_disabled))]
+        #[metric(alert(value_gt = 1))]
+        enabled: prometric::Gauge,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| CfgMetrics::builder().with_registry(registry).build());
+
+    metrics.requests().inc();
+    metrics.enabled().set(2);
+
+    assert_eq!(harness.value("cfg_enabled", &[]), Some(2.0));
+    assert!(!harness.gather().contains("cfg_disabled"));
+
+    let rules = CfgMetrics::alert_rules();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].metric, "cfg_enabled");
+}
+
+#[test]
+fn describe_works() {
+    use prometric::descriptor::{MetricDescriptor, MetricKind};
+
+    #[prometric_derive::metrics(scope = "nested")]
+    struct NestedMetrics {
+        /// The total number of queries.
+        #[metric]
+        queries: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "described", labels = ["chain"])]
+    struct DescribedMetrics {
+        /// The total number of requests.
+        #[metric(labels = ["method"])]
+        requests: prometric::Counter,
+        /// The duration of requests.
+        #[metric(buckets = [0.1, 1.0], unit = "seconds")]
+        duration: prometric::Histogram,
+        /// The size of responses.
+        #[metric(quantiles = [0.5, 0.99])]
+        size: prometric::Summary,
+        #[metric(flatten)]
+        nested: NestedMetrics,
+    }
+
+    // The descriptors match the gathered metrics.
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| DescribedMetrics::builder().with_registry(registry).build());
+    metrics.requests("ethereum", "GET").inc();
+    metrics.duration("ethereum").observe(0.5);
+    metrics.size("ethereum").observe(1);
+    metrics.nested().queries().inc();
+    let mut gathered = MetricDescriptor::from_registry(harness.registry());
+    gathered.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut described = DescribedMetrics::describe();
+    described.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(gathered, described);
+
+    assert_eq!(
+        DescribedMetrics::describe(),
+        vec![
+            MetricDescriptor::new(
+                "described_requests",
+                MetricKind::Counter,
+                "The total number of requests.",
+                ["chain", "method"]
+            ),
+            MetricDescriptor::new(
+                "described_duration_seconds",
+                MetricKind::Histogram,
+                "The duration of requests.",
+                ["chain"]
+            )
+            .with_unit(prometric::unit::Unit::Seconds)
+            .with_buckets([0.1, 1.0]),
+            MetricDescriptor::new(
+                "described_size",
+                MetricKind::Summary,
+                "The size of responses.",
+                ["chain"]
+            )
+            .with_quantiles([0.5, 0.99]),
+            MetricDescriptor::new(
+                "described_nested_queries",
+                MetricKind::Counter,
+                "The total number of queries.",
+                [] as [&str; 0]
+            ),
+        ]
+    );
+}
+
+#[test]
+fn render_works() {
+    #[prometric_derive::metrics(scope = "nested")]
+    struct NestedMetrics {
+        /// The total number of queries.
+        #[metric]
+        queries: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "lib")]
+    struct LibMetrics {
+        /// The total number of calls.
+        #[metric]
+        calls: prometric::Counter,
+        #[metric(flatten)]
+        nested: NestedMetrics,
+    }
+
+    #[prometric_derive::metrics(scope = "app")]
+    struct OtherMetrics {
+        /// The total number of requests.
+        #[metric]
+        requests: prometric::Counter,
+    }
+
+    let registry = prometric::prefixed_registry("myapp");
+    let lib = LibMetrics::builder()
+        .with_registry(&registry)
+        .with_registry_prefix("myapp")
+        .with_scope("plugin")
+        .build();
+    let other =
+        OtherMetrics::builder().with_registry(&registry).with_registry_prefix("myapp").build();
+    lib.calls().inc();
+    lib.nested().queries().inc();
+    other.requests().inc();
+
+    let names = lib.gather().iter().map(|family| family.name().to_owned()).collect::<Vec<_>>();
+    assert_eq!(names, ["myapp_plugin_calls", "myapp_plugin_nested_queries"]);
+    let names =
+        lib.nested().gather().iter().map(|family| family.name().to_owned()).collect::<Vec<_>>();
+    assert_eq!(names, ["myapp_plugin_nested_queries"]);
+
+    let output = lib.render();
+    assert!(output.contains("myapp_plugin_calls 1"));
+    assert!(!output.contains("myapp_app_requests"));
+    assert_eq!(other.gather().len(), 1);
+}
+
+#[test]
+fn gather_matches_exact_names() {
+    #[prometric_derive::metrics(scope = "lib")]
+    struct LibMetrics {
+        /// The total number of calls.
+        #[metric]
+        calls: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "app")]
+    struct AppMetrics {
+        /// The total number of library calls.
+        #[metric]
+        lib_calls: prometric::Counter,
+    }
+
+    // `app_lib_calls` ends with `lib_calls`, but doesn't belong to the library metrics.
+    for (registry, prefix) in [
+        (prometheus::Registry::new(), None),
+        (prometric::prefixed_registry("gather"), Some("gather")),
+    ] {
+        let lib = match prefix {
+            Some(prefix) => {
+                LibMetrics::builder().with_registry(&registry).with_registry_prefix(prefix)
+            }
+            None => LibMetrics::builder().with_registry(&registry),
+        }
+        .build();
+        let app = match prefix {
+            Some(prefix) => {
+                AppMetrics::builder().with_registry(&registry).with_registry_prefix(prefix)
+            }
+            None => AppMetrics::builder().with_registry(&registry),
+        }
+        .build();
+        let prefix = prefix.map(|prefix| format!("{prefix}_")).unwrap_or_default();
+        lib.calls().inc();
+        app.lib_calls().inc();
+
+        let names = lib.gather().iter().map(|family| family.name().to_owned()).collect::<Vec<_>>();
+        assert_eq!(names, [format!("{prefix}lib_calls")]);
+        let names = app.gather().iter().map(|family| family.name().to_owned()).collect::<Vec<_>>();
+        assert_eq!(names, [format!("{prefix}app_lib_calls")]);
+        assert!(!lib.render().contains("app_lib_calls"));
+    }
+}
+
+#[test]
+fn registry_is_exposed() {
+    #[prometric_derive::metrics(scope = "exposed")]
+    struct ExposedMetrics {
+        /// The total number of calls.
+        #[metric]
+        calls: prometric::Counter,
+    }
+
+    let registry = prometheus::Registry::new();
+    let metrics = ExposedMetrics::builder().with_registry(&registry).build();
+    metrics.calls().inc();
+
+    // The registry is a clone sharing the same metrics.
+    let output = prometric::gather_to_string(metrics.registry()).unwrap();
+    assert!(output.contains("exposed_calls 1"));
+}
+
+static CUSTOM_REGISTRY: std::sync::LazyLock<prometheus::Registry> =
+    std::sync::LazyLock::new(prometheus::Registry::new);
+
+fn custom_registry() -> &'static prometheus::Registry {
+    &CUSTOM_REGISTRY
+}
+
+#[prometric_derive::metrics(scope = "custom", static, registry = custom_registry())]
+struct CustomRegistryMetrics {
+    /// The total number of calls.
+    #[metric]
+    calls: prometric::Counter,
+}
+
+#[test]
+fn static_with_custom_registry_works() {
+    CUSTOM_REGISTRY_METRICS.calls().inc();
+
+    let output = prometric::gather_to_string(custom_registry()).unwrap();
+    assert!(output.contains("custom_calls 1"));
+    assert!(
+        !prometric::gather_to_string(prometheus::default_registry())
+            .unwrap()
+            .contains("custom_calls")
+    );
+}
+
+#[test]
+fn histogram_timers_work() {
+    #[prometric_derive::metrics(scope = "timer")]
+    struct TimerMetrics {
+        /// The duration of requests.
+        #[metric(labels = ["method"])]
+        duration: prometric::Histogram,
+        /// The duration of queries.
+        #[metric(buckets = [1.0, 1000.0], unit = "milliseconds")]
+        query: prometric::Histogram,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| TimerMetrics::builder().with_registry(registry).build());
+
+    {
+        let _timer = metrics.duration("GET").start_timer();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let elapsed = metrics.query().start_timer().observe();
+    metrics.duration("POST").start_timer().discard();
+
+    let output = harness.gather();
+    assert!(output.contains("timer_duration_count{method=\"GET\"} 1"));
+    assert!(!output.contains("method=\"POST\""));
+    assert!(output.contains("timer_query_milliseconds_count 1"));
+
+    // Observed in milliseconds.
+    let sum = output
+        .lines()
+        .find_map(|line| line.strip_prefix("timer_query_milliseconds_sum "))
+        .unwrap()
+        .parse::<f64>()
+        .unwrap();
+    assert!((sum - elapsed * 1000.0).abs() < 1e-6);
+}
+
+#[test]
+fn gauge_trackers_work() {
+    #[prometric_derive::metrics(scope = "tracked")]
+    struct TrackedMetrics {
+        /// The number of in-flight requests.
+        #[metric(labels = ["method"])]
+        in_flight: prometric::Gauge,
+    }
+
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| TrackedMetrics::builder().with_registry(registry).build());
+
+    let guard = metrics.in_flight("GET").track();
+    let _other = metrics.in_flight("GET").track();
+    assert_eq!(harness.value("tracked_in_flight", &[("method", "GET")]), Some(2.0));
+    drop(guard);
+    assert_eq!(harness.value("tracked_in_flight", &[("method", "GET")]), Some(1.0));
+
+    // The gauge is decremented when unwinding.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _guard = metrics.in_flight("POST").track();
+        panic!("Task failed");
+    }));
+    assert!(result.is_err());
+    assert_eq!(harness.value("tracked_in_flight", &[("method", "POST")]), Some(0.0));
+}
+
+#[test]
+fn duration_gauges_work() {
+    #[prometric_derive::metrics(scope = "lag")]
+    struct LagMetrics {
+        /// The replication lag.
+        #[metric(labels = ["replica"])]
+        replication_lag: prometric::Gauge<std::time::Duration>,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| LagMetrics::builder().with_registry(registry).build());
+
+    metrics.replication_lag("a").set(std::time::Duration::from_millis(1500));
+    assert_eq!(harness.value("lag_replication_lag", &[("replica", "a")]), Some(1.5));
+
+    metrics.replication_lag("a").add(std::time::Duration::from_millis(500));
+    metrics.replication_lag("a").sub(std::time::Duration::from_millis(250));
+    assert_eq!(harness.value("lag_replication_lag", &[("replica", "a")]), Some(1.75));
+}
+
+#[test]
+fn companion_trait_works() {
+    #[prometric_derive::metrics(scope = "api", trait)]
+    struct ApiMetrics {
+        /// The total number of requests.
+        #[metric(labels = ["method"])]
+        api_requests: prometric::Counter,
+        /// The number of in-flight requests.
+        #[metric]
+        api_in_flight: prometric::Gauge,
+        /// The duration of requests.
+        #[metric(unit = "seconds")]
+        api_duration: prometric::Histogram,
+    }
+
+    struct Handler<M: ApiMetricsApi> {
+        metrics: M,
+    }
+
+    impl<M: ApiMetricsApi> Handler<M> {
+        fn handle(&self, method: &str) {
+            let _guard = self.metrics.api_in_flight().track();
+            let _timer = self.metrics.api_duration().start_timer();
+            self.metrics.api_requests(method).inc();
+            self.metrics.api_requests_with(&[method]).inc();
+        }
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| ApiMetrics::builder().with_registry(registry).build());
+
+    Handler { metrics }.handle("GET");
+    assert_eq!(harness.value("api_api_requests", &[("method", "GET")]), Some(2.0));
+    assert_eq!(harness.value("api_api_in_flight", &[]), Some(0.0));
+    assert!(harness.gather().contains("api_api_duration_seconds_count 1"));
+
+    // The no-op implementation records nothing.
+    Handler { metrics: NoopApiMetrics }.handle("POST");
+    assert_eq!(harness.value("api_api_requests", &[("method", "POST")]), None);
+    assert!(harness.gather().contains("api_api_duration_seconds_count 1"));
+}
+
+#[test]
+fn runtime_disabled_metrics_are_inert() {
+    #[prometric_derive::metrics(scope = "sidecar_db")]
+    struct SidecarDbMetrics {
+        /// The total number of queries.
+        #[metric]
+        queries: prometric::Counter,
+    }
+
+    #[prometric_derive::metrics(scope = "sidecar")]
+    struct SidecarMetrics {
+        /// The total number of requests.
+        #[metric(labels = ["method"])]
+        sidecar_requests: prometric::Counter,
+        /// The duration of requests.
+        #[metric]
+        sidecar_duration: prometric::Histogram,
+        #[metric(flatten)]
+        db: SidecarDbMetrics,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness
+        .build(|registry| SidecarMetrics::builder().with_registry(registry).disabled(true).build());
+
+    metrics.sidecar_requests("GET").inc();
+    metrics.sidecar_duration().start_timer().observe();
+    metrics.db().queries().inc();
+
+    assert!(harness.registry().gather().is_empty());
+    assert!(metrics.gather().is_empty());
+
+    // Explicitly enabled metrics are registered regardless of the environment.
+    let metrics = harness.build(|registry| {
+        SidecarMetrics::builder().with_registry(registry).disabled(false).build()
+    });
+    metrics.sidecar_requests("GET").inc();
+    assert_eq!(harness.value("sidecar_sidecar_requests", &[("method", "GET")]), Some(1.0));
+}
+
+mod visibility {
+    /// Metrics with accessor visibility overrides.
+    #[prometric_derive::metrics(scope = "vis")]
+    pub(crate) struct VisMetrics {
+        /// The total number of internal retries.
+        #[metric(vis = "pub(crate)")]
+        retries: prometric::Counter,
+        /// The total number of internal cache evictions, private to this module.
+        #[metric(vis = "")]
+        evictions: prometric::Counter,
+    }
+
+    impl VisMetrics {
+        pub(crate) fn evict(&self) {
+            self.evictions().inc();
+        }
+    }
+}
+
+#[test]
+fn accessor_visibility_works() {
+    let harness = Harness::new();
+    let metrics =
+        harness.build(|registry| visibility::VisMetrics::builder().with_registry(registry).build());
+
+    metrics.retries().inc();
+    metrics.evict();
+    assert_eq!(harness.value("vis_retries", &[]), Some(1.0));
+    assert_eq!(harness.value("vis_evictions", &[]), Some(1.0));
+}
+
+#[test]
+fn multi_line_docs_are_joined() {
+    #[prometric_derive::metrics(scope = "docs")]
+    struct DocsMetrics {
+        /// The total number of requests
+        /// served by the application.
+        ///
+        /// Includes failed requests.
+        #[metric]
+        served: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| DocsMetrics::builder().with_registry(registry).build());
+    metrics.served().inc();
+
+    assert!(harness.gather().contains(
+        "# HELP docs_served The total number of requests served by the application. Includes failed requests.\n"
+    ));
+}
+
+#[test]
+fn absolute_names_work() {
+    #[prometric_derive::metrics(scope = "abs")]
+    struct AbsoluteMetrics {
+        /// Whether the service is up.
+        #[metric(absolute)]
+        up: prometric::Gauge,
+        /// The total number of requests.
+        #[metric]
+        requests: prometric::Counter,
+    }
+
+    let harness = Harness::new();
+    let metrics = harness.build(|registry| {
+        AbsoluteMetrics::builder().with_registry(registry).with_scope("other").build()
+    });
+
+    metrics.up().set(1);
+    metrics.requests().inc();
+
+    // Absolute names skip the scope, including a runtime scope.
+    assert_eq!(harness.value("up", &[]), Some(1.0));
+    assert_eq!(harness.value("other_requests", &[]), Some(1.0));
+    assert_eq!(AbsoluteMetrics::describe()[0].name, "up");
+    assert_eq!(metrics.gather().len(), 2);
+}
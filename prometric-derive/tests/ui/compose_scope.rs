@@ -0,0 +1,13 @@
+#[prometric_derive::metrics(scope = "app")]
+struct DbMetrics {
+    /// The total number of queries.
+    #[metric]
+    queries: prometric::Counter,
+}
+
+#[prometric_derive::metrics(compose, scope = "app")]
+struct AppMetrics {
+    db: DbMetrics,
+}
+
+fn main() {}
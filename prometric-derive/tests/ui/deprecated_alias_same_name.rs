@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app")]
+struct AppMetrics {
+    /// The depth of the queue.
+    #[metric(rename = "queue_depth", deprecated_alias = "queue_depth")]
+    depth: prometric::Gauge,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "queue", labels = ["chain"])]
+struct QueueMetrics {
+    /// The number of queued jobs.
+    #[metric(eval = || 0u64)]
+    depth: prometric::GaugeFn,
+}
+
+fn main() {}
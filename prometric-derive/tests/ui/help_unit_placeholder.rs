@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app")]
+struct AppMetrics {
+    /// The latency of requests, in {unit}.
+    #[metric]
+    latency: prometric::Histogram,
+}
+
+fn main() {}
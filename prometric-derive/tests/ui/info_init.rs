@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app")]
+struct AppMetrics {
+    /// The build of the node.
+    #[metric(labels = ["version"], init = 1)]
+    build_info: prometric::Info,
+}
+
+fn main() {}
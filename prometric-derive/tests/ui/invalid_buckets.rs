@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app")]
+struct AppMetrics {
+    /// The duration of requests.
+    #[metric(buckets = exponential(0.001, 1.0, 15))]
+    duration: prometric::Histogram,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app")]
+struct AppMetrics {
+    /// The total number of requests.
+    #[metric(labels = ["method", "status-code"])]
+    requests: prometric::Counter,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app")]
+struct AppMetrics {
+    /// The total number of requests.
+    #[metric(rename = "2xx_requests")]
+    requests: prometric::Counter,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app", rename_all = "kebab-case")]
+struct AppMetrics {
+    /// The total number of requests.
+    #[metric]
+    http_requests: prometric::Counter,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+const COMMON_LABELS: &[&str] = &["chain", "peer"];
+
+#[prometric_derive::metrics(scope = "app")]
+struct AppMetrics {
+    /// The total number of requests.
+    #[metric(labels = COMMON_LABELS)]
+    requests: prometric::Counter,
+}
+
+fn main() {}
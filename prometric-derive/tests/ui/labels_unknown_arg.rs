@@ -0,0 +1,7 @@
+#[derive(prometric_derive::Labels)]
+#[labels(path = "facade::prometric")]
+struct RequestLabels {
+    method: &'static str,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app")]
+struct AppMetrics {
+    /// The number of connected peers.
+    #[metric(local)]
+    peers: prometric::Gauge,
+}
+
+fn main() {}
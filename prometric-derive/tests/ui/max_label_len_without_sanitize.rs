@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app", max_label_len = 64)]
+struct AppMetrics {
+    /// The total number of messages.
+    #[metric(labels = ["peer"])]
+    messages: prometric::Counter,
+}
+
+fn main() {}
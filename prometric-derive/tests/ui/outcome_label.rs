@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app")]
+struct AppMetrics {
+    /// The total number of RPC calls.
+    #[metric(labels = ["method", "outcome"])]
+    rpc_calls: prometric::OutcomeCounter,
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+mod internal {
+    #[prometric_derive::metrics(scope = "app")]
+    pub struct AppMetrics {
+        /// The total number of requests.
+        #[metric]
+        pub requests: prometric::Counter,
+        /// The total number of internal retries.
+        #[metric(vis = "")]
+        retries: prometric::Counter,
+    }
+}
+
+fn main() {
+    let metrics = internal::AppMetrics::default();
+    metrics.requests().inc();
+    metrics.retries().inc();
+}
@@ -0,0 +1,14 @@
+#[prometric_derive::metrics(scope = "cache")]
+struct CacheMetrics {
+    /// The cache hit ratio.
+    #[metric(ratio = (hits, lookups))]
+    hit_ratio: prometric::Ratio,
+    /// The total number of cache hits.
+    #[metric]
+    hits: prometric::Counter,
+    /// The number of cached entries.
+    #[metric]
+    lookups: prometric::Gauge,
+}
+
+fn main() {}
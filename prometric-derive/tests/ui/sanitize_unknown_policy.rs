@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app", sanitize = "strip")]
+struct AppMetrics {
+    /// The total number of messages.
+    #[metric(labels = ["peer"])]
+    messages: prometric::Counter,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app", scoped, static)]
+struct AppMetrics {
+    /// The total number of requests.
+    #[metric]
+    requests: prometric::Counter,
+}
+
+fn main() {}
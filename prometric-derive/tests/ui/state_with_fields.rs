@@ -0,0 +1,8 @@
+/// The sync state of the node.
+#[prometric_derive::metrics(scope = "node")]
+enum SyncState {
+    Idle,
+    Syncing { target: u64 },
+}
+
+fn main() {}
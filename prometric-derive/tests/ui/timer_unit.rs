@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app")]
+struct AppMetrics {
+    /// The RPC calls.
+    #[metric(unit = "milliseconds")]
+    rpc_calls: prometric::Timer,
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+#[prometric_derive::metrics(scope = "db")]
+struct DbMetrics {
+    /// The total number of queries.
+    #[metric]
+    queries: prometric::Counter,
+}
+
+#[prometric_derive::metrics(scope = "app", trait)]
+struct AppMetrics {
+    /// The total number of requests.
+    #[metric]
+    requests: prometric::Counter,
+    #[metric(flatten)]
+    db: DbMetrics,
+}
+
+fn main() {}
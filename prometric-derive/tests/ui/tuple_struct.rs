@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app")]
+struct AppMetrics(
+    /// The total number of requests.
+    #[metric]
+    prometric::Counter,
+);
+
+fn main() {}
@@ -0,0 +1,8 @@
+#[prometric_derive::metrics(scope = "app")]
+struct AppMetrics {
+    /// The total number of messages received from each peer.
+    #[metric(labels = ["peer"], max_cardinality = 100, on_overflow = "sample")]
+    received: prometric::Counter,
+}
+
+fn main() {}
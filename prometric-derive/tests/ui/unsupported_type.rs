@@ -0,0 +1,10 @@
+type Requests = prometric::Counter;
+
+#[prometric_derive::metrics(scope = "app")]
+struct AppMetrics {
+    /// The total number of requests.
+    #[metric]
+    requests: Requests,
+}
+
+fn main() {}
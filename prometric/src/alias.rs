@@ -0,0 +1,144 @@
+//! Deprecated aliases of metrics, used by metrics declared with
+//! `#[metric(deprecated_alias = "...")]` in the `prometric-derive` macro.
+//!
+//! An alias exports the series of a metric under a second name, so dashboards and alerts keep
+//! working while they migrate to a new name. Both names share the same underlying metric, so
+//! every update, including through handles and thread-local buffers, is visible under both.
+//!
+//! # Example
+//! ```rust
+//! use prometric::Gauge;
+//!
+//! let registry = prometheus::Registry::new();
+//! let gauge =
+//!     Gauge::<i64>::new(&registry, "queue_depth", "Queue depth.", &[], Default::default())
+//!         .with_alias(&registry, "queued_items");
+//! gauge.set(&[], 3);
+//!
+//! let output = prometric::gather_to_string(&registry).unwrap();
+//! assert!(output.contains("queue_depth 3"));
+//! assert!(output.contains("queued_items 3"));
+//! ```
+
+use prometheus::{
+    core::{Collector, Desc},
+    proto::MetricFamily,
+};
+
+/// Exports the families of a collector under an alias.
+#[derive(Clone)]
+struct AliasCollector<C> {
+    inner: C,
+    alias: String,
+    help: String,
+    desc: Vec<Desc>,
+}
+
+impl<C: Collector> Collector for AliasCollector<C> {
+    fn desc(&self) -> Vec<&Desc> {
+        self.desc.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let mut families = self.inner.collect();
+        for family in &mut families {
+            family.set_name(self.alias.clone());
+            family.set_help(self.help.clone());
+        }
+        families
+    }
+}
+
+impl<C: Collector> AliasCollector<C> {
+    /// Create the collector of the alias of the metric collected by `inner`.
+    fn new(inner: C, alias: &str) -> Self {
+        let desc = &inner.desc()[0];
+        let name = desc.fq_name.clone();
+        let help = format!("{} Deprecated, use `{name}` instead.", desc.help);
+        let const_labels = desc
+            .const_label_pairs
+            .iter()
+            .map(|pair| (pair.name().to_owned(), pair.value().to_owned()))
+            .collect();
+        let alias_desc =
+            Desc::new(alias.to_owned(), help.clone(), desc.variable_labels.clone(), const_labels)
+                .unwrap_or_else(|e| panic!("Invalid alias {alias} of metric {name}: {e}"));
+
+        Self { inner, alias: alias.to_owned(), help, desc: vec![alias_desc] }
+    }
+}
+
+/// Register the alias of the metric collected by `inner` in the given registry, overwriting a
+/// previous registration of the same alias.
+pub(crate) fn register<C: Collector + Clone + 'static>(
+    registry: &prometheus::Registry,
+    inner: C,
+    alias: &str,
+) {
+    let boxed = Box::new(AliasCollector::new(inner, alias));
+    if let Err(e) = registry.register(boxed.clone()) {
+        // If the alias is already registered, overwrite it.
+        if matches!(e, prometheus::Error::AlreadyReg) {
+            registry
+                .unregister(boxed.clone())
+                .unwrap_or_else(|_| panic!("Failed to unregister alias {alias}"));
+            registry
+                .register(boxed)
+                .unwrap_or_else(|_| panic!("Failed to overwrite alias {alias}"));
+        } else {
+            panic!("Failed to register alias {alias}: {e}");
+        }
+    }
+}
+
+/// Unregister the alias of the metric collected by `inner` from the given registry, if
+/// registered.
+pub(crate) fn unregister<C: Collector + 'static>(
+    registry: &prometheus::Registry,
+    inner: C,
+    alias: &str,
+) {
+    let _ = registry.unregister(Box::new(AliasCollector::new(inner, alias)));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Counter;
+
+    #[test]
+    fn test_alias() {
+        let registry = prometheus::Registry::new();
+        let counter = Counter::<u64>::new(
+            &registry,
+            "test_queue_pushes",
+            "Pushes.",
+            &["queue"],
+            Default::default(),
+        )
+        .with_alias(&registry, "test_queued_items");
+        counter.inc(&["a"]);
+        counter.handle(&["a"]).inc();
+
+        // Overwriting the alias keeps a single family.
+        let counter = counter.with_alias(&registry, "test_queued_items");
+        counter.inc(&["b"]);
+
+        let output = crate::gather_to_string(&registry).unwrap();
+        assert!(output.contains(r#"test_queue_pushes{queue="a"} 2"#));
+        assert!(output.contains(r#"test_queued_items{queue="a"} 2"#));
+        assert!(output.contains(r#"test_queued_items{queue="b"} 1"#));
+        assert!(output.contains("Pushes. Deprecated, use `test_queue_pushes` instead."));
+        assert_eq!(output.matches("# TYPE test_queued_items counter").count(), 1);
+    }
+
+    #[test]
+    fn test_unregister_alias() {
+        let registry = prometheus::Registry::new();
+        let counter = Counter::<u64>::new(&registry, "test_jobs", "Jobs.", &[], Default::default())
+            .with_alias(&registry, "test_old_jobs");
+        counter.inc(&[]);
+
+        counter.unregister(&registry);
+        assert!(registry.gather().is_empty());
+    }
+}
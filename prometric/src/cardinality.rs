@@ -0,0 +1,187 @@
+//! Cardinality limits of counters, gauges and histograms, used by metrics declared with
+//! `#[metric(max_cardinality = ...)]` in the `prometric-derive` macro.
+//!
+//! A metric with a limit tracks its distinct label value combinations. Beyond the limit, new
+//! combinations are routed to a single overflow series, whose label values are all
+//! [`OVERFLOW_LABEL_VALUE`], or dropped. Each routed or dropped update increments the
+//! `<name>_cardinality_overflow` counter of the metric, registered in the same registry.
+//!
+//! # Example
+//! ```rust
+//! use prometric::{Counter, cardinality::CardinalityLimit};
+//!
+//! let registry = prometheus::Registry::new();
+//! let counter =
+//!     Counter::<u64>::new(&registry, "peer_msgs", "Messages.", &["peer"], Default::default())
+//!         .limit_cardinality(&registry, CardinalityLimit::new(1));
+//! counter.inc(&["a"]);
+//! counter.inc(&["b"]);
+//!
+//! let output = prometric::gather_to_string(&registry).unwrap();
+//! assert!(output.contains(r#"peer_msgs{peer="other"} 1"#));
+//! assert!(output.contains("peer_msgs_cardinality_overflow 1"));
+//! ```
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::RwLock,
+};
+
+use crate::Counter;
+
+/// The value of all the labels of the overflow series.
+pub const OVERFLOW_LABEL_VALUE: &str = "other";
+
+/// What happens to the updates of new label value combinations beyond the limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Route the updates to the overflow series.
+    #[default]
+    Other,
+    /// Drop the updates.
+    Drop,
+}
+
+/// The maximum number of distinct label value combinations of a metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CardinalityLimit {
+    /// The maximum number of combinations, excluding the overflow series.
+    pub max: usize,
+    /// What happens to the updates of new combinations beyond the limit.
+    pub overflow: Overflow,
+}
+
+impl CardinalityLimit {
+    /// Create a limit of `max` combinations, routing new combinations beyond it to the overflow
+    /// series.
+    pub const fn new(max: usize) -> Self {
+        Self { max, overflow: Overflow::Other }
+    }
+
+    /// Set what happens to the updates of new combinations beyond the limit.
+    pub const fn with_overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+}
+
+/// The tracked combinations of a metric with a cardinality limit.
+#[derive(Debug)]
+pub(crate) struct CardinalityLimiter {
+    limit: CardinalityLimit,
+    /// The hashes of the admitted combinations.
+    seen: RwLock<HashSet<u64>>,
+    overflows: Counter<u64>,
+}
+
+impl CardinalityLimiter {
+    /// Create the limiter of the metric with the given name, registering its overflow counter.
+    pub(crate) fn new(
+        registry: &prometheus::Registry,
+        name: &str,
+        limit: CardinalityLimit,
+    ) -> Self {
+        let overflows = Counter::new(
+            registry,
+            &format!("{name}_cardinality_overflow"),
+            &format!("The number of updates of `{name}` beyond its cardinality limit."),
+            &[],
+            HashMap::new(),
+        );
+        Self { limit, seen: RwLock::new(HashSet::new()), overflows }
+    }
+
+    fn key(labels: &[&str]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        labels.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Returns the label values to update: the given ones if admitted, the overflow series beyond
+    /// the limit, or `None` if the update is dropped.
+    pub(crate) fn admit<'a, 'b>(&self, labels: &'a [&'b str]) -> Option<Cow<'a, [&'b str]>> {
+        if labels.is_empty() || labels.iter().all(|value| *value == OVERFLOW_LABEL_VALUE) {
+            return Some(Cow::Borrowed(labels));
+        }
+
+        let key = Self::key(labels);
+        if self.seen.read().unwrap_or_else(|e| e.into_inner()).contains(&key) {
+            return Some(Cow::Borrowed(labels));
+        }
+
+        let mut seen = self.seen.write().unwrap_or_else(|e| e.into_inner());
+        if seen.len() < self.limit.max || seen.contains(&key) {
+            seen.insert(key);
+            return Some(Cow::Borrowed(labels));
+        }
+        drop(seen);
+
+        self.overflows.inc(&[]);
+        match self.limit.overflow {
+            Overflow::Other => Some(Cow::Owned(vec![OVERFLOW_LABEL_VALUE; labels.len()])),
+            Overflow::Drop => None,
+        }
+    }
+
+    /// Returns whether the given combination is admitted, without admitting it, e.g. to read its
+    /// series without taking a slot.
+    pub(crate) fn contains(&self, labels: &[&str]) -> bool {
+        labels.is_empty() ||
+            labels.iter().all(|value| *value == OVERFLOW_LABEL_VALUE) ||
+            self.seen.read().unwrap_or_else(|e| e.into_inner()).contains(&Self::key(labels))
+    }
+
+    /// Forget the given combination, e.g. when its series is removed, freeing its slot.
+    pub(crate) fn remove(&self, labels: &[&str]) {
+        self.seen.write().unwrap_or_else(|e| e.into_inner()).remove(&Self::key(labels));
+    }
+
+    /// Unregister the overflow counter from the given registry.
+    pub(crate) fn unregister(&self, registry: &prometheus::Registry) {
+        self.overflows.unregister(registry);
+    }
+
+    /// Forget all the combinations, e.g. when all the series are removed.
+    pub(crate) fn clear(&self) {
+        self.seen.write().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Gauge;
+
+    #[test]
+    fn test_cardinality_limit() {
+        let registry = prometheus::Registry::new();
+        let gauge = Gauge::<i64>::new(
+            &registry,
+            "test_connections",
+            "Connections.",
+            &["peer", "kind"],
+            Default::default(),
+        )
+        .limit_cardinality(&registry, CardinalityLimit::new(1).with_overflow(Overflow::Drop));
+
+        gauge.set(&["a", "inbound"], 1);
+        gauge.set(&["b", "inbound"], 2);
+        gauge.handle(&["c", "outbound"]).set(3);
+        gauge.set(&["a", "inbound"], 4);
+
+        let output = crate::gather_to_string(&registry).unwrap();
+        assert!(output.contains(r#"test_connections{kind="inbound",peer="a"} 4"#));
+        assert!(!output.contains(r#"peer="b""#));
+        assert!(!output.contains(r#"peer="other""#));
+        assert!(output.contains("test_connections_cardinality_overflow 2"));
+
+        // Reads beyond the limit neither create a series nor count as overflows.
+        assert_eq!(gauge.get(&["a", "inbound"]), 4);
+        assert_eq!(gauge.get(&["d", "inbound"]), 0);
+        let output = crate::gather_to_string(&registry).unwrap();
+        assert!(!output.contains(r#"peer="d""#));
+        assert!(output.contains("test_connections_cardinality_overflow 2"));
+    }
+}
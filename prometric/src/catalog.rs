@@ -0,0 +1,71 @@
+//! A machine-readable catalog of metrics, generated with `#[metrics(catalog)]` in the
+//! `prometric-derive` macro, e.g. for tooling generating dashboards from the metrics of an
+//! application.
+//!
+//! The catalog serializes to JSON as an object with a `metrics` array of descriptors:
+//! ```json
+//! {
+//!   "metrics": [
+//!     {
+//!       "name": "app_http_requests_duration",
+//!       "kind": "histogram",
+//!       "help": "The duration of HTTP requests.",
+//!       "labels": ["method"],
+//!       "unit": "seconds",
+//!       "buckets": [0.1, 1.0]
+//!     }
+//!   ]
+//! }
+//! ```
+
+use std::{io, path::Path};
+
+use crate::descriptor::MetricDescriptor;
+
+/// A serializable catalog of metric descriptors.
+///
+/// # Example
+/// ```rust
+/// use prometric::{
+///     catalog::Catalog,
+///     descriptor::{MetricDescriptor, MetricKind},
+/// };
+///
+/// let catalog = Catalog::new(vec![MetricDescriptor::new(
+///     "app_http_requests_total",
+///     MetricKind::Counter,
+///     "The total number of HTTP requests.",
+///     ["method"],
+/// )]);
+///
+/// assert!(catalog.to_json().contains(r#""kind": "counter""#));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct Catalog {
+    /// The descriptors of the metrics.
+    pub metrics: Vec<MetricDescriptor>,
+}
+
+impl Catalog {
+    /// Create a new catalog with the given metric descriptors.
+    pub fn new(metrics: Vec<MetricDescriptor>) -> Self {
+        Self { metrics }
+    }
+
+    /// Merge the metrics of another catalog into this one, e.g. to export the catalogs of several
+    /// metrics structs as one.
+    pub fn merge(mut self, other: Self) -> Self {
+        self.metrics.extend(other.metrics);
+        self
+    }
+
+    /// Serialize the catalog to pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("catalog serialization can't fail")
+    }
+
+    /// Write the JSON catalog to a file. Useful in build scripts.
+    pub fn write_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+}
@@ -1,6 +1,10 @@
 use std::collections::HashMap;
 
+use prometheus::core::Number as _;
+
 use crate::private::Sealed;
+#[cfg(feature = "recording")]
+use crate::recording::{self, MetricOp};
 
 /// The default number type for counters.
 pub type CounterDefault = u64;
@@ -25,16 +29,54 @@ impl CounterNumber for f64 {
 #[derive(Debug)]
 pub struct Counter<N: CounterNumber = CounterDefault> {
     inner: prometheus::core::GenericCounterVec<N::Atomic>,
+    /// The identifier of the thread-local buffers of the counter.
+    id: crate::local::LocalId,
+    /// The series definition recording the exemplars of the counter.
+    exemplars: std::sync::Arc<crate::exemplar::ExemplarSource>,
+    /// The creation times of the series of the counter, if tracked. See [`crate::created`].
+    created: Option<std::sync::Arc<crate::created::CreatedSource>>,
+    /// The cardinality limit of the metric, if any. See [`crate::cardinality`].
+    limiter: Option<std::sync::Arc<crate::cardinality::CardinalityLimiter>>,
+    /// The deprecated aliases of the metric. See [`crate::alias`].
+    aliases: Vec<String>,
+    #[cfg(feature = "recording")]
+    definition: std::sync::Arc<crate::recording::MetricDefinition>,
 }
 
 impl<N: CounterNumber> Clone for Counter<N> {
     fn clone(&self) -> Self {
-        Self { inner: self.inner.clone() }
+        Self {
+            inner: self.inner.clone(),
+            limiter: self.limiter.clone(),
+            aliases: self.aliases.clone(),
+            id: self.id.clone(),
+            exemplars: self.exemplars.clone(),
+            created: self.created.clone(),
+            #[cfg(feature = "recording")]
+            definition: self.definition.clone(),
+        }
+    }
+}
+
+/// Collects the series of the counter, e.g. to register it in another registry along with the
+/// metrics struct holding it. Deprecated aliases and the overflow counter of a cardinality limit
+/// are registered separately.
+impl<N: CounterNumber> prometheus::core::Collector for Counter<N> {
+    fn desc(&self) -> Vec<&prometheus::core::Desc> {
+        self.inner.desc()
+    }
+
+    fn collect(&self) -> Vec<prometheus::proto::MetricFamily> {
+        self.inner.collect()
     }
 }
 
 impl<N: CounterNumber> Counter<N> {
     /// Create a new counter metric with the given registry, name, help, labels, and const labels.
+    ///
+    /// # Panics
+    /// Panics if the name or the labels are invalid, or if the registration fails. See
+    /// [`Self::try_new`] for a fallible version.
     pub fn new(
         registry: &prometheus::Registry,
         name: &str,
@@ -42,38 +84,351 @@ impl<N: CounterNumber> Counter<N> {
         labels: &[&str],
         const_labels: HashMap<String, String>,
     ) -> Self {
-        let opts = prometheus::Opts::new(name, help).const_labels(const_labels);
-        let metric = prometheus::core::GenericCounterVec::<N::Atomic>::new(opts, labels).unwrap();
+        Self::with_opts(registry, name, help, labels, const_labels, std::convert::identity)
+    }
+
+    /// Create a new counter metric like [`Self::new`], returning an error if the name or the labels
+    /// are invalid, or if the registration fails.
+    pub fn try_new(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+    ) -> Result<Self, crate::Error> {
+        Self::try_with_opts(registry, name, help, labels, const_labels, std::convert::identity)
+    }
 
-        let boxed = Box::new(metric.clone());
-        if let Err(e) = registry.register(boxed.clone()) {
-            let id = format!("{}, Labels: {}", name, labels.join(", "),);
-            // If the metric is already registered, overwrite it.
-            if matches!(e, prometheus::Error::AlreadyReg) {
-                registry
-                    .unregister(boxed.clone())
-                    .unwrap_or_else(|_| panic!("Failed to unregister metric {id}"));
+    /// Create a new counter metric like [`Self::new`], modifying its options with `modify` before
+    /// registration, e.g. to set options that aren't modeled by `prometric`.
+    ///
+    /// # Panics
+    /// Panics like [`Self::new`], see [`Self::try_with_opts`] for a fallible version.
+    pub fn with_opts(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+        modify: impl FnOnce(prometheus::Opts) -> prometheus::Opts,
+    ) -> Self {
+        Self::try_with_opts(registry, name, help, labels, const_labels, modify)
+            .unwrap_or_else(|e| crate::registration_failed(name, labels, e))
+    }
 
-                registry
-                    .register(boxed)
-                    .unwrap_or_else(|_| panic!("Failed to overwrite metric {id}"));
-            } else {
-                panic!("Failed to register metric {id}");
-            }
-        }
+    /// Create a new counter metric like [`Self::with_opts`], returning an error if the name or the
+    /// labels are invalid, or if the registration fails.
+    pub fn try_with_opts(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+        modify: impl FnOnce(prometheus::Opts) -> prometheus::Opts,
+    ) -> Result<Self, crate::Error> {
+        let opts = modify(prometheus::Opts::new(name, help).const_labels(const_labels));
+        #[cfg(feature = "recording")]
+        let definition = crate::recording::MetricDefinition::new::<N>(
+            crate::descriptor::MetricKind::Counter,
+            &opts.fq_name(),
+            &opts.help,
+            labels,
+            &opts.const_labels,
+            None,
+        );
+
+        let name = opts.fq_name();
+        let exemplars =
+            crate::exemplar::ExemplarSource::new(name.clone(), labels, &opts.const_labels, &[]);
+        let metric = prometheus::core::GenericCounterVec::<N::Atomic>::new(opts, labels)?;
+
+        crate::register(registry, &metric, &name, labels)?;
 
-        Self { inner: metric }
+        Ok(Self {
+            inner: metric,
+            limiter: None,
+            aliases: Vec::new(),
+            id: crate::local::LocalId::new(),
+            exemplars: std::sync::Arc::new(exemplars),
+            created: None,
+            #[cfg(feature = "recording")]
+            definition,
+        })
     }
 
     pub fn inc(&self, labels: &[&str]) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
+        self.touch(labels);
         self.inner.with_label_values(labels).inc();
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Inc);
     }
 
     pub fn inc_by(&self, labels: &[&str], value: <N::Atomic as prometheus::core::Atomic>::T) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
+        self.touch(labels);
         self.inner.with_label_values(labels).inc_by(value);
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::IncBy(value.into_f64()));
+    }
+
+    /// Increment the counter in the buffer of the current thread, flushed to the counter later.
+    /// See [`crate::local`].
+    pub fn local_inc(&self, labels: &[&str]) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
+        self.touch(labels);
+        let buffered = crate::local::update(
+            &self.id,
+            labels,
+            || self.inner.with_label_values(labels).local(),
+            |local| local.inc(),
+        );
+        if !buffered {
+            self.inner.with_label_values(labels).inc();
+        }
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Inc);
+    }
+
+    /// Increment the counter by the given value in the buffer of the current thread, flushed to
+    /// the counter later. See [`crate::local`].
+    pub fn local_inc_by(&self, labels: &[&str], value: <N::Atomic as prometheus::core::Atomic>::T) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
+        self.touch(labels);
+        let buffered = crate::local::update(
+            &self.id,
+            labels,
+            || self.inner.with_label_values(labels).local(),
+            |local| local.inc_by(value),
+        );
+        if !buffered {
+            self.inner.with_label_values(labels).inc_by(value);
+        }
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::IncBy(value.into_f64()));
+    }
+
+    /// Increment the counter, recording the exemplar of the increment, e.g. the trace ID of the
+    /// request. See [`crate::exemplar`].
+    pub fn inc_with_exemplar(&self, labels: &[&str], exemplar: &[(&str, &str)]) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
+        self.inc(labels);
+        self.exemplars.record(labels, exemplar, 1.0);
+    }
+
+    /// Increment the counter by the given value, recording the exemplar of the increment. See
+    /// [`crate::exemplar`].
+    pub fn inc_by_with_exemplar(
+        &self,
+        labels: &[&str],
+        value: <N::Atomic as prometheus::core::Atomic>::T,
+        exemplar: &[(&str, &str)],
+    ) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
+        self.inc_by(labels, value);
+        self.exemplars.record(labels, exemplar, value.into_f64());
     }
 
     pub fn reset(&self, labels: &[&str]) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
         self.inner.with_label_values(labels).reset();
+        if let Some(created) = &self.created {
+            created.reset(labels);
+        }
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Reset);
+    }
+
+    /// Returns the collector of the counter, e.g. to derive other metrics from its values. See
+    /// [`crate::ratio`].
+    pub(crate) fn collector(&self) -> impl prometheus::core::Collector + Clone + 'static {
+        self.inner.clone()
+    }
+
+    /// Returns the value of the child of the counter with the given label values, creating it if
+    /// it doesn't exist. Reads don't take a slot of the cardinality limit: label values beyond it
+    /// read as `0`, without creating their child. Increments buffered by [`crate::local`] aren't
+    /// included until flushed.
+    pub fn get(&self, labels: &[&str]) -> <N::Atomic as prometheus::core::Atomic>::T {
+        if !self.admitted(labels) {
+            return prometheus::core::Number::from_i64(0);
+        }
+        self.touch(labels);
+        self.inner.with_label_values(labels).get()
+    }
+
+    /// Also export the counter under the given deprecated name, registered in the given
+    /// registry, e.g. during the migration window of a rename. See [`crate::alias`].
+    pub fn with_alias(mut self, registry: &prometheus::Registry, alias: &str) -> Self {
+        crate::alias::register(registry, self.inner.clone(), alias);
+        self.aliases.push(alias.to_owned());
+        self
+    }
+
+    /// Track the creation time of each series of the counter, exported as its `_created` sample
+    /// in the OpenMetrics text format. See [`crate::created`].
+    pub fn with_created(mut self) -> Self {
+        let desc = &prometheus::core::Collector::desc(&self.inner)[0];
+        self.created = Some(std::sync::Arc::new(crate::created::CreatedSource::from_desc(desc)));
+        self
+    }
+
+    /// Record the creation time of the series with the given label values, if tracked.
+    fn touch(&self, labels: &[&str]) {
+        if let Some(created) = &self.created {
+            created.touch(labels);
+        }
+    }
+
+    /// Limit the number of distinct label value combinations of the counter, registering its
+    /// overflow counter in the given registry. See [`crate::cardinality`].
+    pub fn limit_cardinality(
+        mut self,
+        registry: &prometheus::Registry,
+        limit: crate::cardinality::CardinalityLimit,
+    ) -> Self {
+        let name = &prometheus::core::Collector::desc(&self.inner)[0].fq_name;
+        let limiter = crate::cardinality::CardinalityLimiter::new(registry, name, limit);
+        self.limiter = Some(std::sync::Arc::new(limiter));
+        self
+    }
+
+    /// Returns whether the given label values are admitted by the cardinality limit, without
+    /// admitting them.
+    fn admitted(&self, labels: &[&str]) -> bool {
+        self.limiter.as_ref().is_none_or(|limiter| limiter.contains(labels))
+    }
+
+    /// Returns the label values admitted by the cardinality limit, or `None` if the update is
+    /// dropped.
+    fn admit<'a, 'b>(&self, labels: &'a [&'b str]) -> Option<std::borrow::Cow<'a, [&'b str]>> {
+        match &self.limiter {
+            Some(limiter) => limiter.admit(labels),
+            None => Some(std::borrow::Cow::Borrowed(labels)),
+        }
+    }
+
+    /// Remove the child of the counter with the given label values, e.g. to drop the stale series
+    /// of a disconnected peer. Returns `false` if there is no such child. Handles to the child
+    /// keep updating it, without exporting it.
+    pub fn remove(&self, labels: &[&str]) -> bool {
+        let removed = self.inner.remove_label_values(labels).is_ok();
+        if let Some(limiter) = &self.limiter {
+            limiter.remove(labels);
+        }
+        self.exemplars.remove(labels);
+        if let Some(created) = &self.created {
+            created.remove(labels);
+        }
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Remove);
+        removed
+    }
+
+    /// Unregister the counter from the given registry, along with its cardinality overflow counter
+    /// and deprecated aliases, e.g. when a short-lived component is dropped. Updates keep
+    /// applying to the counter, without exporting it.
+    pub fn unregister(&self, registry: &prometheus::Registry) {
+        let _ = registry.unregister(Box::new(self.inner.clone()));
+        if let Some(limiter) = &self.limiter {
+            limiter.unregister(registry);
+        }
+        for alias in &self.aliases {
+            crate::alias::unregister(registry, self.inner.clone(), alias);
+        }
+        self.exemplars.clear();
+        if let Some(created) = &self.created {
+            created.clear();
+        }
+    }
+
+    /// Record the operations on the counter into the given recorder, whether it is started or
+    /// not, unless the counter already records into another one. See [`crate::recording`].
+    #[cfg(feature = "recording")]
+    pub fn record_into(&self, recorder: &recording::Recorder) {
+        self.definition.record_into(recorder);
+    }
+
+    /// Remove all the children of the counter, e.g. to return to a clean state between tests.
+    /// Handles to the children keep updating them, without exporting them.
+    pub fn clear(&self) {
+        self.inner.reset();
+        if let Some(limiter) = &self.limiter {
+            limiter.clear();
+        }
+        self.exemplars.clear();
+        if let Some(created) = &self.created {
+            created.clear();
+        }
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, &[], MetricOp::Clear);
+    }
+
+    /// Resolve the child of the counter with the given label values once, returning a handle
+    /// updating it without the label lookup.
+    pub fn handle(&self, labels: &[&str]) -> CounterHandle<N> {
+        let Some(admitted) = self.admit(labels) else { return CounterHandle::noop() };
+        let labels = &*admitted;
+        self.touch(labels);
+        CounterHandle {
+            inner: Some(self.inner.with_label_values(labels)),
+            #[cfg(feature = "recording")]
+            recording: Some(recording::HandleRecording::new(&self.definition, labels)),
+        }
+    }
+}
+
+/// A handle to the child of a [`Counter`] with resolved label values, returned by
+/// [`Counter::handle`]. Updates skip the label lookup and allocations, for hot paths.
+#[derive(Debug, Clone)]
+pub struct CounterHandle<N: CounterNumber = CounterDefault> {
+    inner: Option<prometheus::core::GenericCounter<N::Atomic>>,
+    #[cfg(feature = "recording")]
+    recording: Option<recording::HandleRecording>,
+}
+
+impl<N: CounterNumber> CounterHandle<N> {
+    /// Create a handle updating nothing, e.g. when metrics are disabled.
+    pub fn noop() -> Self {
+        Self {
+            inner: None,
+            #[cfg(feature = "recording")]
+            recording: None,
+        }
+    }
+
+    pub fn inc(&self) {
+        let Some(inner) = &self.inner else { return };
+        inner.inc();
+        #[cfg(feature = "recording")]
+        self.record(MetricOp::Inc);
+    }
+
+    pub fn inc_by(&self, value: <N::Atomic as prometheus::core::Atomic>::T) {
+        let Some(inner) = &self.inner else { return };
+        inner.inc_by(value);
+        #[cfg(feature = "recording")]
+        self.record(MetricOp::IncBy(value.into_f64()));
+    }
+
+    pub fn reset(&self) {
+        let Some(inner) = &self.inner else { return };
+        inner.reset();
+        #[cfg(feature = "recording")]
+        self.record(MetricOp::Reset);
+    }
+
+    #[cfg(feature = "recording")]
+    fn record(&self, op: MetricOp) {
+        if let Some(recording) = &self.recording {
+            recording.record(op);
+        }
     }
 }
@@ -0,0 +1,188 @@
+//! Creation times of counter and summary series, exposed as the `_created` samples of the
+//! OpenMetrics text format, e.g. for scrapers detecting counter resets.
+//!
+//! Tracking is opt-in per metric, with [`crate::Counter::with_created`] and
+//! [`crate::Summary::with_created`]. The creation time of a series is recorded on its first update
+//! and renewed when the series is reset. Like exemplars, creation times are kept in a process-wide
+//! store keyed by the metric name and label pairs, and are only exposed in the OpenMetrics text
+//! format, see [`crate::exemplar::encode_to_string`].
+//!
+//! NOTE: Every update of a tracked metric locks the store, so tracking shouldn't be enabled on the
+//! counters of hot paths.
+//!
+//! # Example
+//! ```rust
+//! use prometric::{Counter, exemplar};
+//!
+//! let registry = prometheus::Registry::new();
+//! let counter =
+//!     Counter::<u64>::new(&registry, "created_requests", "Requests.", &[], Default::default())
+//!         .with_created();
+//! counter.inc(&[]);
+//!
+//! let output = exemplar::encode_to_string(&registry.gather());
+//! assert!(output.contains("created_requests_total 1\ncreated_requests_created "));
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::SystemTime,
+};
+
+use prometheus::core::Desc;
+
+use crate::exemplar::{SeriesDef, SeriesKey};
+
+/// The creation time of each tracked series.
+static CREATED: LazyLock<Mutex<HashMap<SeriesKey, SystemTime>>> = LazyLock::new(Default::default);
+
+/// Returns the creation time of the given series, if tracked.
+pub(crate) fn get(key: &SeriesKey) -> Option<SystemTime> {
+    CREATED.lock().unwrap_or_else(|e| e.into_inner()).get(key).copied()
+}
+
+/// The series definition of a counter or summary, tracking the creation times of its series.
+#[derive(Debug)]
+pub(crate) struct CreatedSource {
+    series: SeriesDef,
+}
+
+impl CreatedSource {
+    /// Create the source of the metric with the given descriptor.
+    pub(crate) fn from_desc(desc: &Desc) -> Self {
+        let label_names = desc.variable_labels.iter().map(String::as_str).collect::<Vec<_>>();
+        let const_labels = desc
+            .const_label_pairs
+            .iter()
+            .map(|pair| (pair.name().to_owned(), pair.value().to_owned()))
+            .collect::<HashMap<_, _>>();
+        Self { series: SeriesDef::new(desc.fq_name.clone(), &label_names, &const_labels) }
+    }
+
+    /// Record the creation time of the series with the given label values, unless already set.
+    pub(crate) fn touch(&self, labels: &[&str]) {
+        CREATED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(self.series.key(labels))
+            .or_insert_with(SystemTime::now);
+    }
+
+    /// Renew the creation time of the series with the given label values, e.g. when it's reset.
+    pub(crate) fn reset(&self, labels: &[&str]) {
+        CREATED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(self.series.key(labels), SystemTime::now());
+    }
+
+    /// Remove the creation time of the series with the given label values.
+    pub(crate) fn remove(&self, labels: &[&str]) {
+        CREATED.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.series.key(labels));
+    }
+
+    /// Remove the creation times of all the series of the metric.
+    pub(crate) fn clear(&self) {
+        CREATED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(name, _), _| *name != self.series.name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use crate::{Counter, exemplar::encode_to_string};
+
+    /// Returns the value of the `_created` sample with the given prefix in the output.
+    fn created(output: &str, prefix: &str) -> Option<f64> {
+        let line = output.lines().find(|line| line.starts_with(prefix))?;
+        line.rsplit(' ').next()?.parse().ok()
+    }
+
+    /// Returns the current time, in seconds since the UNIX epoch.
+    fn now() -> f64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64()
+    }
+
+    #[test]
+    fn test_created() {
+        let registry = prometheus::Registry::new();
+        let counter = Counter::<u64>::new(
+            &registry,
+            "test_created_requests_total",
+            "Requests.",
+            &["method"],
+            Default::default(),
+        )
+        .with_created();
+        let untracked = Counter::<u64>::new(
+            &registry,
+            "test_created_untracked_total",
+            "Untracked.",
+            &[],
+            Default::default(),
+        );
+
+        let now = now();
+        counter.inc(&["GET"]);
+        untracked.inc(&[]);
+
+        let output = encode_to_string(&registry.gather());
+        let counter_created =
+            created(&output, r#"test_created_requests_created{method="GET"}"#).unwrap();
+        assert!(counter_created >= now.floor());
+        assert!(output.contains(
+            "test_created_requests_total{method=\"GET\"} 1\ntest_created_requests_created"
+        ));
+        assert!(!output.contains("test_created_untracked_created"));
+
+        // Later updates keep the creation time, resets renew it.
+        counter.inc(&["GET"]);
+        let output = encode_to_string(&registry.gather());
+        assert_eq!(
+            created(&output, r#"test_created_requests_created{method="GET"}"#),
+            Some(counter_created)
+        );
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        counter.reset(&["GET"]);
+        let output = encode_to_string(&registry.gather());
+        assert!(
+            created(&output, r#"test_created_requests_created{method="GET"}"#).unwrap() >
+                counter_created
+        );
+
+        // Removed series drop their creation time.
+        counter.remove(&["GET"]);
+        let output = encode_to_string(&registry.gather());
+        assert_eq!(created(&output, "test_created_requests_created"), None);
+    }
+
+    #[cfg(feature = "summary")]
+    #[test]
+    fn test_created_summary() {
+        let registry = prometheus::Registry::new();
+        let summary = crate::Summary::new(
+            &registry,
+            "test_created_latency",
+            "Latency.",
+            &[],
+            Default::default(),
+            Some(vec![0.5]),
+        )
+        .with_created();
+
+        let now = now();
+        summary.observe(&[], 1.0);
+        let output = encode_to_string(&registry.gather());
+        assert!(created(&output, "test_created_latency_created").unwrap() >= now.floor());
+
+        // Cleared series drop their creation time.
+        summary.clear();
+        let output = encode_to_string(&registry.gather());
+        assert_eq!(created(&output, "test_created_latency_created"), None);
+    }
+}
@@ -0,0 +1,190 @@
+//! Metric descriptors, describing the metrics exposed by an application. These are the basis for
+//! generating artifacts that need to stay in sync with the code, such as dashboards.
+
+use crate::unit::Unit;
+
+/// The type of a metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+    Summary,
+}
+
+impl std::fmt::Display for MetricKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Counter => write!(f, "counter"),
+            Self::Gauge => write!(f, "gauge"),
+            Self::Histogram => write!(f, "histogram"),
+            Self::Summary => write!(f, "summary"),
+        }
+    }
+}
+
+/// The static metadata of a metric: its full name, type and label names. The `METRICS` table of a
+/// `#[metrics]` struct holds one entry per metric, usable in const contexts, e.g. to reference
+/// metric names in alert tests without hard-coding them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MetricMetadata {
+    /// The full name of the metric, with the compile-time scope.
+    pub name: &'static str,
+    /// The type of the metric.
+    pub kind: MetricKind,
+    /// The variable label names of the metric.
+    pub labels: &'static [&'static str],
+}
+
+/// Concatenate two lists of label names in a const context, for the label names of the
+/// [`MetricMetadata`] of metrics with both struct-level labels and a label set.
+///
+/// # Panics
+/// Panics if `N` isn't the sum of the lengths of the lists.
+#[doc(hidden)]
+pub const fn concat_labels<const N: usize>(
+    first: &[&'static str],
+    second: &[&'static str],
+) -> [&'static str; N] {
+    assert!(first.len() + second.len() == N, "Mismatched number of labels");
+    let mut labels = [""; N];
+    let mut i = 0;
+    while i < first.len() {
+        labels[i] = first[i];
+        i += 1;
+    }
+    while i < N {
+        labels[i] = second[i - first.len()];
+        i += 1;
+    }
+    labels
+}
+
+/// Describes a single metric.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MetricDescriptor {
+    /// The full name of the metric.
+    pub name: String,
+    /// The type of the metric.
+    pub kind: MetricKind,
+    /// The help string of the metric.
+    pub help: String,
+    /// The label names of the metric.
+    pub labels: Vec<String>,
+    /// The unit of the metric, for metadata such as the OpenMetrics `# UNIT` line. Inferred from
+    /// the suffix of the name for gathered metrics.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub unit: Option<Unit>,
+    /// The bucket upper bounds of a histogram, without `+Inf`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub buckets: Option<Vec<f64>>,
+    /// The quantiles of a summary.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub quantiles: Option<Vec<f64>>,
+}
+
+impl MetricDescriptor {
+    /// Create a new descriptor.
+    pub fn new(
+        name: impl Into<String>,
+        kind: MetricKind,
+        help: impl Into<String>,
+        labels: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            help: help.into(),
+            labels: labels.into_iter().map(Into::into).collect(),
+            unit: None,
+            buckets: None,
+            quantiles: None,
+        }
+    }
+
+    /// Set the unit of the metric.
+    pub fn with_unit(mut self, unit: Unit) -> Self {
+        self.unit = Some(unit);
+        self
+    }
+
+    /// Set the bucket upper bounds of a histogram.
+    pub fn with_buckets(mut self, buckets: impl Into<Vec<f64>>) -> Self {
+        self.buckets = Some(buckets.into());
+        self
+    }
+
+    /// Set the quantiles of a summary.
+    pub fn with_quantiles(mut self, quantiles: impl Into<Vec<f64>>) -> Self {
+        self.quantiles = Some(quantiles.into());
+        self
+    }
+
+    /// Describe the metrics currently gathered by the given registry.
+    ///
+    /// NOTE: Only metrics with at least one series are gathered by the registry, and the label
+    /// names are taken from the first series, which includes any const labels.
+    pub fn from_registry(registry: &prometheus::Registry) -> Vec<Self> {
+        registry
+            .gather()
+            .into_iter()
+            .filter_map(|family| {
+                let kind = match family.get_field_type() {
+                    prometheus::proto::MetricType::COUNTER => MetricKind::Counter,
+                    prometheus::proto::MetricType::GAUGE => MetricKind::Gauge,
+                    prometheus::proto::MetricType::HISTOGRAM => MetricKind::Histogram,
+                    prometheus::proto::MetricType::SUMMARY => MetricKind::Summary,
+                    prometheus::proto::MetricType::UNTYPED => return None,
+                };
+
+                let labels = family
+                    .metric
+                    .first()
+                    .map(|metric| {
+                        metric.label.iter().map(|label| label.name().to_owned()).collect()
+                    })
+                    .unwrap_or_default();
+
+                let first = family.metric.first();
+                let buckets = (kind == MetricKind::Histogram).then(|| {
+                    first
+                        .map(|metric| {
+                            metric
+                                .get_histogram()
+                                .get_bucket()
+                                .iter()
+                                .map(|bucket| bucket.upper_bound())
+                                .filter(|bound| bound.is_finite())
+                                .collect()
+                        })
+                        .unwrap_or_default()
+                });
+                let quantiles = (kind == MetricKind::Summary).then(|| {
+                    first
+                        .map(|metric| {
+                            metric
+                                .get_summary()
+                                .get_quantile()
+                                .iter()
+                                .map(|quantile| quantile.quantile())
+                                .collect()
+                        })
+                        .unwrap_or_default()
+                });
+
+                Some(Self {
+                    name: family.name().to_owned(),
+                    kind,
+                    help: family.help().to_owned(),
+                    labels,
+                    unit: Unit::from_metric_name(family.name()),
+                    buckets,
+                    quantiles,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use prometheus::{CounterVec, Opts, Registry};
+
+/// The sysfs directory exposing the Linux powercap framework (and RAPL zones).
+const POWERCAP_ROOT: &str = "/sys/class/powercap";
+
+/// The prefix of the powercap zones that are backed by RAPL. This is also used by AMD CPUs.
+const RAPL_ZONE_PREFIX: &str = "intel-rapl:";
+
+/// A collector for energy consumption metrics, read from the RAPL (Running Average Power Limit)
+/// counters exposed by the Linux powercap framework (Linux only).
+///
+/// Every RAPL zone (e.g. `package-0`, `core`, `uncore`, `dram`) is exported as a separate series
+/// of the `system_energy_consumed_joules_total` counter, which tracks the energy consumed since
+/// the collector was created. Hardware counter wraparounds are accounted for.
+///
+/// NOTE: On most distributions, `energy_uj` is only readable by root. Zones that can't be read are
+/// skipped.
+///
+/// # Example
+/// ```rust
+/// use prometheus::Registry;
+/// use prometric::energy::EnergyCollector;
+///
+/// let registry = Registry::new();
+/// let mut collector = EnergyCollector::new(&registry);
+///
+/// // OR run with the default registry
+/// let mut collector = EnergyCollector::default();
+///
+/// // Collect the metrics
+/// collector.collect();
+/// ```
+#[derive(Debug)]
+pub struct EnergyCollector {
+    zones: Vec<RaplZone>,
+    metrics: EnergyMetrics,
+}
+
+impl Default for EnergyCollector {
+    fn default() -> Self {
+        Self::new(prometheus::default_registry())
+    }
+}
+
+impl EnergyCollector {
+    /// Create a new `EnergyCollector` with the given registry.
+    pub fn new(registry: &Registry) -> Self {
+        Self::with_root(registry, POWERCAP_ROOT)
+    }
+
+    /// Create a new `EnergyCollector`, discovering the RAPL zones under the given powercap root.
+    fn with_root(registry: &Registry, root: impl AsRef<Path>) -> Self {
+        let zones = RaplZone::discover(root.as_ref());
+        let metrics = EnergyMetrics::new(registry);
+
+        // Initialize the series, so that all zones are exported even before the first collection.
+        for zone in &zones {
+            metrics.energy_consumed.with_label_values(&[zone.id.as_str(), zone.domain.as_str()]);
+        }
+
+        Self { zones, metrics }
+    }
+
+    /// Returns the number of RAPL zones that were discovered.
+    pub fn zones(&self) -> usize {
+        self.zones.len()
+    }
+
+    /// Collect energy metrics.
+    pub fn collect(&mut self) {
+        for zone in self.zones.iter_mut() {
+            let Some(consumed_uj) = zone.read_delta() else {
+                continue;
+            };
+
+            self.metrics
+                .energy_consumed
+                .with_label_values(&[zone.id.as_str(), zone.domain.as_str()])
+                .inc_by(consumed_uj as f64 / 1_000_000.0);
+        }
+    }
+}
+
+/// A single RAPL powercap zone, e.g. `intel-rapl:0` (package) or `intel-rapl:0:0` (core).
+#[derive(Debug)]
+struct RaplZone {
+    /// The identifier of the zone, i.e. the name of its sysfs directory.
+    id: String,
+    /// The power domain of the zone, as found in its `name` file.
+    domain: String,
+    /// The path to the `energy_uj` counter of the zone.
+    energy_path: PathBuf,
+    /// The value at which the `energy_uj` counter wraps around.
+    max_energy_uj: u64,
+    /// The last value read from the `energy_uj` counter.
+    last_energy_uj: Option<u64>,
+}
+
+impl RaplZone {
+    /// Discover all readable RAPL zones under the given powercap root.
+    fn discover(root: &Path) -> Vec<Self> {
+        let Ok(entries) = fs::read_dir(root) else {
+            return Vec::new();
+        };
+
+        let mut zones = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let id = entry.file_name().into_string().ok()?;
+                if !id.starts_with(RAPL_ZONE_PREFIX) {
+                    return None;
+                }
+
+                let path = entry.path();
+                let domain = fs::read_to_string(path.join("name")).ok()?.trim().to_owned();
+                let max_energy_uj = read_u64(&path.join("max_energy_range_uj"))?;
+                let energy_path = path.join("energy_uj");
+
+                // Take a first reading to both check that the counter is readable and to
+                // establish a baseline.
+                let last_energy_uj = Some(read_u64(&energy_path)?);
+
+                Some(Self { id, domain, energy_path, max_energy_uj, last_energy_uj })
+            })
+            .collect::<Vec<_>>();
+
+        zones.sort_by(|a, b| a.id.cmp(&b.id));
+        zones
+    }
+
+    /// Read the current counter value and return the energy consumed since the last reading, in
+    /// microjoules.
+    fn read_delta(&mut self) -> Option<u64> {
+        let current = read_u64(&self.energy_path)?;
+        let last = self.last_energy_uj.replace(current)?;
+
+        // The counter wrapped around since the last reading.
+        if current < last {
+            return Some(self.max_energy_uj.saturating_sub(last) + current);
+        }
+
+        Some(current - last)
+    }
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// A collection of energy metrics, one series per RAPL zone.
+#[derive(Debug)]
+pub struct EnergyMetrics {
+    /// The total energy consumed by each RAPL zone in joules.
+    energy_consumed: CounterVec,
+}
+
+impl EnergyMetrics {
+    pub fn new(registry: &Registry) -> Self {
+        let energy_consumed = CounterVec::new(
+            Opts::new(
+                "system_energy_consumed_joules_total",
+                "The total energy consumed per RAPL zone in joules (Linux only).",
+            ),
+            &["zone", "domain"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(energy_consumed.clone())).unwrap();
+
+        Self { energy_consumed }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_zone(root: &Path, id: &str, domain: &str, energy_uj: u64, max_energy_uj: u64) {
+        let path = root.join(id);
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("name"), format!("{domain}\n")).unwrap();
+        fs::write(path.join("energy_uj"), format!("{energy_uj}\n")).unwrap();
+        fs::write(path.join("max_energy_range_uj"), format!("{max_energy_uj}\n")).unwrap();
+    }
+
+    #[test]
+    fn test_energy_collector() {
+        let root = std::env::temp_dir().join(format!("prometric-rapl-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        write_zone(&root, "intel-rapl:0", "package-0", 1_000_000, 10_000_000);
+        write_zone(&root, "intel-rapl:0:0", "core", 9_500_000, 10_000_000);
+        // Not a RAPL zone, should be ignored.
+        write_zone(&root, "dtpm", "dtpm", 0, 10_000_000);
+
+        let registry = Registry::new();
+        let mut collector = EnergyCollector::with_root(&registry, &root);
+        assert_eq!(collector.zones(), 2);
+
+        // Package consumed 2J, core wrapped around and consumed 1J.
+        write_zone(&root, "intel-rapl:0", "package-0", 3_000_000, 10_000_000);
+        write_zone(&root, "intel-rapl:0:0", "core", 500_000, 10_000_000);
+        collector.collect();
+
+        let encoder = prometheus::TextEncoder::new();
+        let body = encoder.encode_to_string(&registry.gather()).unwrap();
+        println!("{body}");
+
+        assert!(body.contains(
+            r#"system_energy_consumed_joules_total{domain="package-0",zone="intel-rapl:0"} 2"#
+        ));
+        assert!(body.contains(
+            r#"system_energy_consumed_joules_total{domain="core",zone="intel-rapl:0:0"} 1"#
+        ));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,397 @@
+//! Exemplars of counters and histograms, linking their samples to traces, and the OpenMetrics text
+//! encoding exposing them.
+//!
+//! The `prometheus` crate doesn't model exemplars, so the latest exemplar of each counter series
+//! and histogram bucket is kept in a process-wide store, keyed by the metric name and label pairs.
+//! They are only exposed in the OpenMetrics text format, see [`encode_to_string`]. The exporter
+//! serves it to scrapers accepting `application/openmetrics-text`.
+//!
+//! NOTE: The exemplars of metrics with the same name and labels in different registries overwrite
+//! each other. The label set of an exemplar should stay within the 128 UTF-8 characters allowed by
+//! the OpenMetrics specification.
+//!
+//! # Example
+//! ```rust
+//! use prometric::{Counter, exemplar};
+//!
+//! let registry = prometheus::Registry::new();
+//! let counter =
+//!     Counter::<u64>::new(&registry, "exemplar_requests", "Requests.", &[], Default::default());
+//! counter.inc_with_exemplar(&[], &[("trace_id", "4bf92f35")]);
+//!
+//! let output = exemplar::encode_to_string(&registry.gather());
+//! assert!(output.contains(r#"exemplar_requests_total 1 # {trace_id="4bf92f35"} 1 "#));
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    sync::{LazyLock, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use prometheus::proto::{LabelPair, MetricFamily, MetricType};
+
+/// The content type of the OpenMetrics text format.
+pub const OPENMETRICS_FORMAT: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// An exemplar of a sample, e.g. the trace of a request counted by a counter or observed by a
+/// histogram.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exemplar {
+    /// The label pairs of the exemplar, e.g. `trace_id`.
+    pub labels: Vec<(String, String)>,
+    /// The value of the exemplar: the increment of a counter, or the observed value of a
+    /// histogram.
+    pub value: f64,
+    /// The time of the exemplar.
+    pub timestamp: SystemTime,
+}
+
+/// The series of a metric family: its name and sorted label pairs.
+pub(crate) type SeriesKey = (String, Vec<(String, String)>);
+
+/// The latest exemplars of each series. Counters have a single slot, histograms a slot per bucket,
+/// including the `+Inf` bucket.
+static EXEMPLARS: LazyLock<Mutex<HashMap<SeriesKey, Vec<Option<Exemplar>>>>> =
+    LazyLock::new(Default::default);
+
+/// The definition of the series of a metric, resolving the key of a series from its label
+/// values.
+#[derive(Debug)]
+pub(crate) struct SeriesDef {
+    pub(crate) name: String,
+    label_names: Vec<String>,
+    const_labels: Vec<(String, String)>,
+}
+
+impl SeriesDef {
+    pub(crate) fn new(
+        name: String,
+        label_names: &[&str],
+        const_labels: &HashMap<String, String>,
+    ) -> Self {
+        Self {
+            name,
+            label_names: label_names.iter().map(|name| (*name).to_owned()).collect(),
+            const_labels: const_labels.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+        }
+    }
+
+    pub(crate) fn key(&self, labels: &[&str]) -> SeriesKey {
+        let mut pairs = self.const_labels.clone();
+        pairs.extend(
+            self.label_names
+                .iter()
+                .zip(labels)
+                .map(|(name, value)| (name.clone(), (*value).into())),
+        );
+        pairs.sort();
+        (self.name.clone(), pairs)
+    }
+}
+
+/// The series definition of a counter or histogram, recording its exemplars.
+#[derive(Debug)]
+pub(crate) struct ExemplarSource {
+    series: SeriesDef,
+    /// The upper bounds of the buckets of a histogram, without the implicit `+Inf` bucket.
+    buckets: Vec<f64>,
+}
+
+impl ExemplarSource {
+    pub(crate) fn new(
+        name: String,
+        label_names: &[&str],
+        const_labels: &HashMap<String, String>,
+        buckets: &[f64],
+    ) -> Self {
+        let buckets = buckets.iter().copied().filter(|bound| bound.is_finite()).collect();
+        Self { series: SeriesDef::new(name, label_names, const_labels), buckets }
+    }
+
+    /// Record the exemplar of the series with the given label values, in the bucket of `value` for
+    /// histograms.
+    pub(crate) fn record(&self, labels: &[&str], exemplar: &[(&str, &str)], value: f64) {
+        let slot =
+            self.buckets.iter().position(|bound| value <= *bound).unwrap_or(self.buckets.len());
+        let exemplar = Exemplar {
+            labels: exemplar.iter().map(|(k, v)| ((*k).to_owned(), (*v).to_owned())).collect(),
+            value,
+            timestamp: SystemTime::now(),
+        };
+
+        let mut exemplars = EXEMPLARS.lock().unwrap_or_else(|e| e.into_inner());
+        let slots = exemplars.entry(self.series.key(labels)).or_default();
+        slots.resize(self.buckets.len() + 1, None);
+        slots[slot] = Some(exemplar);
+    }
+
+    /// Remove the exemplars of the series with the given label values.
+    pub(crate) fn remove(&self, labels: &[&str]) {
+        EXEMPLARS.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.series.key(labels));
+    }
+
+    /// Remove the exemplars of all the series of the metric.
+    pub(crate) fn clear(&self) {
+        EXEMPLARS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(name, _), _| *name != self.series.name);
+    }
+}
+
+/// The exemplars and creation times of gathered metric families, resolved by series before the
+/// families are modified, e.g. by the global prefix and labels of the exporter.
+#[derive(Debug, Default, Clone)]
+pub struct Exemplars {
+    /// The exemplar slots of each metric of each family, in gathering order.
+    slots: Vec<Vec<Vec<Option<Exemplar>>>>,
+    /// The creation time of each metric of each family, if tracked. See [`crate::created`].
+    created: Vec<Vec<Option<SystemTime>>>,
+}
+
+impl Exemplars {
+    /// Resolve the exemplars and creation times of the given metric families.
+    pub fn collect(families: &[MetricFamily]) -> Self {
+        let keys = families
+            .iter()
+            .map(|family| {
+                family
+                    .get_metric()
+                    .iter()
+                    .map(|metric| (family.name().to_owned(), label_pairs(metric.get_label())))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let exemplars = EXEMPLARS.lock().unwrap_or_else(|e| e.into_inner());
+        let slots = keys
+            .iter()
+            .map(|keys| {
+                keys.iter().map(|key| exemplars.get(key).cloned().unwrap_or_default()).collect()
+            })
+            .collect();
+        drop(exemplars);
+
+        let created =
+            keys.iter().map(|keys| keys.iter().map(crate::created::get).collect()).collect();
+        Self { slots, created }
+    }
+
+    fn get(&self, family: usize, metric: usize, slot: usize) -> Option<&Exemplar> {
+        self.slots.get(family)?.get(metric)?.get(slot)?.as_ref()
+    }
+
+    /// Returns the creation time of the given metric, as seconds since the Unix epoch.
+    fn created(&self, family: usize, metric: usize) -> Option<f64> {
+        let created = (*self.created.get(family)?.get(metric)?)?;
+        Some(created.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64())
+    }
+
+    /// Encode the metric families in the OpenMetrics text format, with the exemplars resolved from
+    /// the same families by [`Self::collect`].
+    pub fn encode(&self, families: &[MetricFamily]) -> String {
+        let mut output = String::new();
+        for (f, family) in families.iter().enumerate() {
+            let kind = family.get_field_type();
+            // OpenMetrics counter families are named without the `_total` suffix of the samples.
+            let name = match kind {
+                MetricType::COUNTER => {
+                    family.name().strip_suffix("_total").unwrap_or(family.name())
+                }
+                _ => family.name(),
+            };
+            let type_name = match kind {
+                MetricType::COUNTER => "counter",
+                MetricType::GAUGE => "gauge",
+                MetricType::HISTOGRAM => "histogram",
+                MetricType::SUMMARY => "summary",
+                MetricType::UNTYPED => "unknown",
+            };
+
+            if !family.help().is_empty() {
+                let _ = writeln!(output, "# HELP {name} {}", escape(family.help()));
+            }
+            let _ = writeln!(output, "# TYPE {name} {type_name}");
+
+            for (m, metric) in family.get_metric().iter().enumerate() {
+                let labels = metric.get_label();
+                let mut sample =
+                    |suffix: &str, extra: Option<(&str, &str)>, value: f64, slot: Option<usize>| {
+                        write_sample(&mut output, name, suffix, labels, extra, value);
+                        if let Some(exemplar) = slot.and_then(|slot| self.get(f, m, slot)) {
+                            write_exemplar(&mut output, exemplar);
+                        }
+                        output.push('\n');
+                    };
+
+                match kind {
+                    MetricType::COUNTER => {
+                        sample("_total", None, metric.get_counter().value(), Some(0));
+                        if let Some(created) = self.created(f, m) {
+                            sample("_created", None, created, None);
+                        }
+                    }
+                    MetricType::GAUGE => sample("", None, metric.get_gauge().value(), None),
+                    MetricType::UNTYPED => sample("", None, metric.untyped.value(), None),
+                    MetricType::HISTOGRAM => {
+                        let histogram = metric.get_histogram();
+                        let buckets = histogram
+                            .get_bucket()
+                            .iter()
+                            .filter(|bucket| bucket.upper_bound().is_finite())
+                            .collect::<Vec<_>>();
+                        for (slot, bucket) in buckets.iter().enumerate() {
+                            let bound = format_value(bucket.upper_bound());
+                            let count = bucket.cumulative_count() as f64;
+                            sample("_bucket", Some(("le", &bound)), count, Some(slot));
+                        }
+                        let count = histogram.get_sample_count() as f64;
+                        sample("_bucket", Some(("le", "+Inf")), count, Some(buckets.len()));
+                        sample("_count", None, count, None);
+                        sample("_sum", None, histogram.get_sample_sum(), None);
+                    }
+                    MetricType::SUMMARY => {
+                        let summary = metric.get_summary();
+                        for quantile in summary.get_quantile() {
+                            let q = format_value(quantile.quantile());
+                            sample("", Some(("quantile", &q)), quantile.value(), None);
+                        }
+                        sample("_count", None, summary.sample_count() as f64, None);
+                        sample("_sum", None, summary.sample_sum(), None);
+                        if let Some(created) = self.created(f, m) {
+                            sample("_created", None, created, None);
+                        }
+                    }
+                }
+            }
+        }
+        output.push_str("# EOF\n");
+        output
+    }
+}
+
+/// Encode the metric families in the OpenMetrics text format, with the exemplars of their
+/// counters and histograms, and the `_created` samples of the series tracking their creation
+/// time.
+pub fn encode_to_string(families: &[MetricFamily]) -> String {
+    Exemplars::collect(families).encode(families)
+}
+
+fn label_pairs(labels: &[LabelPair]) -> Vec<(String, String)> {
+    let mut pairs = labels
+        .iter()
+        .map(|label| (label.name().to_owned(), label.value().to_owned()))
+        .collect::<Vec<_>>();
+    pairs.sort();
+    pairs
+}
+
+fn write_labels<'a>(output: &mut String, labels: impl Iterator<Item = (&'a str, &'a str)>) {
+    output.push('{');
+    for (i, (name, value)) in labels.enumerate() {
+        if i > 0 {
+            output.push(',');
+        }
+        let _ = write!(output, "{name}=\"{}\"", escape(value));
+    }
+    output.push('}');
+}
+
+fn write_sample(
+    output: &mut String,
+    name: &str,
+    suffix: &str,
+    labels: &[LabelPair],
+    extra: Option<(&str, &str)>,
+    value: f64,
+) {
+    let _ = write!(output, "{name}{suffix}");
+    if !labels.is_empty() || extra.is_some() {
+        let labels = labels.iter().map(|label| (label.name(), label.value()));
+        write_labels(output, labels.chain(extra));
+    }
+    let _ = write!(output, " {}", format_value(value));
+}
+
+fn write_exemplar(output: &mut String, exemplar: &Exemplar) {
+    output.push_str(" # ");
+    write_labels(output, exemplar.labels.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+    let timestamp = exemplar.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let _ = write!(output, " {} {}", format_value(exemplar.value), timestamp.as_secs_f64());
+}
+
+fn format_value(value: f64) -> String {
+    if value == f64::INFINITY {
+        "+Inf".to_owned()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_owned()
+    } else if value.is_nan() {
+        "NaN".to_owned()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escape a help string or label value, as in the Prometheus text format.
+fn escape(value: &str) -> String {
+    value.replace('\\', r"\\").replace('\n', r"\n").replace('"', r#"\""#)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Counter, Gauge, Histogram};
+
+    #[test]
+    fn test_encode_exemplars() {
+        let registry = prometheus::Registry::new();
+        let counter = Counter::<u64>::new(
+            &registry,
+            "test_exemplar_errors_total",
+            "Errors.",
+            &["kind"],
+            Default::default(),
+        );
+        let histogram = Histogram::new(
+            &registry,
+            "test_exemplar_latency",
+            "Latency.\nIn seconds.",
+            &[],
+            Default::default(),
+            Some(vec![0.1, 1.0]),
+        );
+        let gauge =
+            Gauge::<f64>::new(&registry, "test_exemplar_ratio", "Ratio.", &[], Default::default());
+
+        counter.inc_with_exemplar(&["io"], &[("trace_id", "a\"b")]);
+        histogram.observe_with_exemplar(&[], 0.05, &[("trace_id", "c")]);
+        histogram.observe_with_exemplar(&[], 5.0, &[("trace_id", "d")]);
+        gauge.set(&[], f64::INFINITY);
+
+        let output = encode_to_string(&registry.gather());
+        println!("{output}");
+
+        assert!(output.contains("# TYPE test_exemplar_errors counter\n"));
+        assert!(
+            output.contains(r#"test_exemplar_errors_total{kind="io"} 1 # {trace_id="a\"b"} 1 "#)
+        );
+        assert!(output.contains("# HELP test_exemplar_latency Latency.\\nIn seconds.\n"));
+        assert!(
+            output.contains(r#"test_exemplar_latency_bucket{le="0.1"} 1 # {trace_id="c"} 0.05 "#)
+        );
+        assert!(output.contains("test_exemplar_latency_bucket{le=\"1\"} 1\n"));
+        assert!(
+            output.contains(r#"test_exemplar_latency_bucket{le="+Inf"} 2 # {trace_id="d"} 5 "#)
+        );
+        assert!(output.contains("test_exemplar_ratio +Inf\n"));
+        assert!(output.ends_with("# EOF\n"));
+
+        // Removing the series drops its exemplar.
+        counter.remove(&["io"]);
+        counter.inc(&["io"]);
+        let output = encode_to_string(&registry.gather());
+        assert!(output.contains("test_exemplar_errors_total{kind=\"io\"} 1\n"));
+    }
+}
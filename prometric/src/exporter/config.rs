@@ -0,0 +1,322 @@
+//! Hot-reloadable exporter configuration, allowing operators to mute metrics or rotate the auth
+//! token without redeploying.
+//!
+//! The configuration is read from environment variables, or from a file with one `KEY=value` pair
+//! per line (`#` starts a comment). The supported keys are:
+//! - `PROMETRIC_DISABLED_METRICS`: Comma-separated names of metrics to not export.
+//! - `PROMETRIC_EXPORT_FILTERS`: Comma-separated name prefixes. If set, only metrics matching one
+//!   of them are exported.
+//! - `PROMETRIC_AUTH_TOKEN`: If set, scrapes must provide an `Authorization: Bearer <token>`
+//!   header.
+//!
+//! Metric names are matched before the exporter namespace is applied.
+//!
+//! With the `serde` feature, [`ExporterConfig`] also implements `Deserialize`, with the settings of
+//! the exporter itself (address, path, namespace, TLS and process metrics), so that it can be
+//! driven entirely from application config files with [`super::ExporterBuilder::from_config`]. Only
+//! the settings above are hot-reloaded.
+//!
+//! ```toml
+//! [metrics]
+//! address = "0.0.0.0:9090"
+//! namespace = "myapp"
+//! disabled_metrics = ["expensive"]
+//! auth_token = "secret"
+//! process_metrics_interval_secs = 10
+//!
+//! [metrics.tls]
+//! cert_path = "/etc/myapp/cert.pem"
+//! key_path = "/etc/myapp/key.pem"
+//! ```
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+/// The key of the disabled metrics.
+pub const DISABLED_METRICS_KEY: &str = "PROMETRIC_DISABLED_METRICS";
+
+/// The key of the export filters.
+pub const EXPORT_FILTERS_KEY: &str = "PROMETRIC_EXPORT_FILTERS";
+
+/// The key of the auth token.
+pub const AUTH_TOKEN_KEY: &str = "PROMETRIC_AUTH_TOKEN";
+
+/// The interval at which the configuration file is checked for changes.
+pub const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The configuration of the exporter. The auth token is redacted from its `Debug` output.
+#[derive(Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct ExporterConfig {
+    /// The socket address to listen on. See [`super::ExporterBuilder::with_address`].
+    pub address: Option<String>,
+    /// The path to serve the metrics on. See [`super::ExporterBuilder::with_path`].
+    pub path: Option<String>,
+    /// The global namespace of the metrics. See [`super::ExporterBuilder::with_namespace`].
+    pub namespace: Option<String>,
+    /// If set, serve the metrics over HTTPS. Requires the `tls` feature, otherwise installing the
+    /// exporter fails.
+    pub tls: Option<TlsConfig>,
+    /// If set, also collect process metrics at this interval, in seconds. Requires the `process`
+    /// feature, otherwise installing the exporter fails.
+    pub process_metrics_interval_secs: Option<u64>,
+    /// The names of the metrics to not export.
+    pub disabled_metrics: Vec<String>,
+    /// If not empty, only the metrics whose name starts with one of these prefixes are exported.
+    pub export_filters: Vec<String>,
+    /// If set, scrapes must provide this token as a bearer token.
+    pub auth_token: Option<String>,
+}
+
+impl std::fmt::Debug for ExporterConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExporterConfig")
+            .field("address", &self.address)
+            .field("path", &self.path)
+            .field("namespace", &self.namespace)
+            .field("tls", &self.tls)
+            .field("process_metrics_interval_secs", &self.process_metrics_interval_secs)
+            .field("disabled_metrics", &self.disabled_metrics)
+            .field("export_filters", &self.export_filters)
+            .field("auth_token", &self.auth_token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// The TLS configuration of the exporter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct TlsConfig {
+    /// The path to the PEM-encoded certificate chain.
+    pub cert_path: PathBuf,
+    /// The path to the PEM-encoded private key.
+    pub key_path: PathBuf,
+}
+
+impl ExporterConfig {
+    /// Read the configuration from the environment.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        for key in [DISABLED_METRICS_KEY, EXPORT_FILTERS_KEY, AUTH_TOKEN_KEY] {
+            if let Ok(value) = std::env::var(key) {
+                config.set(key, &value);
+            }
+        }
+        config
+    }
+
+    /// Read the configuration from the given file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::parse(&std::fs::read_to_string(path).map_err(ConfigError::Io)?)
+    }
+
+    /// Parse the configuration from `KEY=value` lines.
+    pub fn parse(contents: &str) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(ConfigError::InvalidLine(i + 1, line.to_owned()));
+            };
+
+            let key = key.trim();
+            if !config.set(key, value.trim()) {
+                return Err(ConfigError::UnknownKey(i + 1, key.to_owned()));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Set the given key. Returns `false` if the key is unknown.
+    fn set(&mut self, key: &str, value: &str) -> bool {
+        let list = |value: &str| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_owned)
+                .collect()
+        };
+
+        match key {
+            DISABLED_METRICS_KEY => self.disabled_metrics = list(value),
+            EXPORT_FILTERS_KEY => self.export_filters = list(value),
+            AUTH_TOKEN_KEY => self.auth_token = (!value.is_empty()).then(|| value.to_owned()),
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Returns `true` if the metric with the given name should be exported.
+    pub fn is_exported(&self, name: &str) -> bool {
+        !self.disabled_metrics.iter().any(|disabled| disabled == name) &&
+            (self.export_filters.is_empty() ||
+                self.export_filters.iter().any(|prefix| name.starts_with(prefix.as_str())))
+    }
+
+    /// Returns `true` if the given `Authorization` header value is accepted.
+    pub fn is_authorized(&self, authorization: Option<&[u8]>) -> bool {
+        let Some(token) = &self.auth_token else {
+            return true;
+        };
+
+        authorization
+            .and_then(|value| value.strip_prefix(b"Bearer "))
+            .is_some_and(|provided| constant_time_eq(provided, token.as_bytes()))
+    }
+}
+
+/// Returns `true` if the byte strings are equal, in a time independent of their contents, so the
+/// auth token can't be recovered by timing scrapes. Only the length leaks.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b));
+    std::hint::black_box(diff) == 0
+}
+
+/// Where the configuration is loaded from.
+#[derive(Debug, Clone)]
+pub(super) enum ConfigSource {
+    Static(ExporterConfig),
+    Env,
+    File(PathBuf),
+}
+
+impl ConfigSource {
+    pub(super) fn load(&self) -> Result<ExporterConfig, ConfigError> {
+        match self {
+            Self::Static(config) => Ok(config.clone()),
+            Self::Env => Ok(ExporterConfig::from_env()),
+            Self::File(path) => ExporterConfig::from_file(path),
+        }
+    }
+}
+
+/// The configuration shared with the HTTP server, swapped on reload.
+pub(super) type SharedConfig = Arc<RwLock<ExporterConfig>>;
+
+/// Reload the configuration file on SIGHUP (on Unix) or when its modification time changes. If the
+/// new configuration is invalid, the previous one is kept. Never returns.
+pub(super) async fn watch(path: PathBuf, config: SharedConfig) -> Result<(), super::ExporterError> {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last_modified: Option<SystemTime> = modified(&path);
+
+    #[cfg(unix)]
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+
+    loop {
+        #[cfg(unix)]
+        let force = tokio::select! {
+            _ = hangup.recv() => true,
+            _ = tokio::time::sleep(CONFIG_POLL_INTERVAL) => false,
+        };
+        #[cfg(not(unix))]
+        let force = {
+            tokio::time::sleep(CONFIG_POLL_INTERVAL).await;
+            false
+        };
+
+        let current = modified(&path);
+        if !force && current == last_modified {
+            continue;
+        }
+        last_modified = current;
+
+        if let Ok(new) = ExporterConfig::from_file(&path) {
+            *config.write().unwrap_or_else(|e| e.into_inner()) = new;
+        }
+    }
+}
+
+/// An error that can occur when loading the exporter configuration.
+pub enum ConfigError {
+    Io(std::io::Error),
+    InvalidLine(usize, String),
+    UnknownKey(usize, String),
+}
+
+impl std::error::Error for ConfigError {}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Failed to read config: {e:?}"),
+            Self::InvalidLine(line, content) => {
+                write!(f, "Invalid config line {line}, expected KEY=value: {content}")
+            }
+            Self::UnknownKey(line, key) => write!(f, "Unknown config key on line {line}: {key}"),
+        }
+    }
+}
+
+impl std::fmt::Debug for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = ExporterConfig::parse(
+            "# Mute the expensive metric\n\
+             PROMETRIC_DISABLED_METRICS = app_expensive, app_other\n\
+             PROMETRIC_EXPORT_FILTERS=app_\n\
+             PROMETRIC_AUTH_TOKEN=secret\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.disabled_metrics, ["app_expensive", "app_other"]);
+        assert!(config.is_exported("app_requests"));
+        assert!(!config.is_exported("app_expensive"));
+        assert!(!config.is_exported("process_cpu_usage"));
+
+        assert!(config.is_authorized(Some(b"Bearer secret")));
+        assert!(!config.is_authorized(Some(b"Bearer wrong")));
+        assert!(!config.is_authorized(None));
+        assert!(!config.is_authorized(Some(b"Bearer secre")));
+        assert!(!format!("{config:?}").contains("secret"));
+
+        assert!(matches!(
+            ExporterConfig::parse("PROMETRIC_UNKNOWN=1"),
+            Err(ConfigError::UnknownKey(1, _))
+        ));
+        assert!(matches!(ExporterConfig::parse("\ninvalid"), Err(ConfigError::InvalidLine(2, _))));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_config() {
+        let config: ExporterConfig = serde_json::from_str(
+            r#"{
+                "address": "127.0.0.1:9100",
+                "namespace": "myapp",
+                "tls": { "cert_path": "cert.pem", "key_path": "key.pem" },
+                "export_filters": ["app_"]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.address.as_deref(), Some("127.0.0.1:9100"));
+        assert_eq!(config.tls.as_ref().unwrap().key_path, PathBuf::from("key.pem"));
+        assert!(!config.is_exported("process_cpu_usage"));
+        assert!(serde_json::from_str::<ExporterConfig>(r#"{ "adress": "" }"#).is_err());
+    }
+}
@@ -0,0 +1,481 @@
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    thread,
+    time::Duration,
+};
+
+use hyper::{
+    Request, Response,
+    body::Incoming,
+    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
+    server::conn::http1,
+    service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use prometheus::{Encoder, TextEncoder};
+
+pub mod config;
+use config::{ConfigError, ConfigSource, ExporterConfig, SharedConfig, TlsConfig};
+
+#[cfg(feature = "tls")]
+mod tls;
+
+/// A builder for the Prometheus HTTP exporter.
+pub struct ExporterBuilder {
+    registry: Option<prometheus::Registry>,
+    additional_registries: Vec<prometheus::Registry>,
+    address: String,
+    path: String,
+    global_prefix: Option<String>,
+    global_labels: Vec<(String, String)>,
+    process_metrics_poll_interval: Option<Duration>,
+    config: Option<ConfigSource>,
+    tls: Option<TlsConfig>,
+    #[cfg(feature = "multiprocess")]
+    multiprocess: Option<crate::multiprocess::MultiProcessCollector>,
+}
+
+impl Default for ExporterBuilder {
+    fn default() -> Self {
+        Self {
+            registry: None,
+            additional_registries: Vec::new(),
+            address: "0.0.0.0:9090".to_owned(),
+            path: "/metrics".to_owned(),
+            global_prefix: None,
+            global_labels: Vec::new(),
+            process_metrics_poll_interval: None,
+            config: None,
+            tls: None,
+            #[cfg(feature = "multiprocess")]
+            multiprocess: None,
+        }
+    }
+}
+
+impl ExporterBuilder {
+    /// Create a new exporter with the default registry and socket address.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new exporter from the given configuration, e.g. deserialized from the config file
+    /// of the application with the `serde` feature. Unset settings keep their default.
+    pub fn from_config(config: ExporterConfig) -> Self {
+        let mut builder = Self::new();
+        if let Some(address) = &config.address {
+            builder.address = address.clone();
+        }
+        if let Some(path) = &config.path {
+            builder.path = path.clone();
+        }
+        builder.global_prefix = config.namespace.clone();
+        builder.tls = config.tls.clone();
+        builder.process_metrics_poll_interval =
+            config.process_metrics_interval_secs.map(Duration::from_secs);
+
+        builder.with_config(config)
+    }
+
+    /// Set the socket address for the exporter.
+    ///
+    /// # Panics
+    /// Panics if the socket address is malformed, i.e. if [`str::parse`] into a [`SocketAddr`]
+    /// returns an error.
+    pub fn with_address(mut self, address: impl Into<String>) -> Self {
+        let address = address.into();
+        self.address = address;
+        self
+    }
+
+    /// Set the path for the exporter.
+    ///
+    /// If no path is provided, the default path is `/`.
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        let path = path.into();
+        self.path = path;
+        self
+    }
+
+    /// Set the global namespace for the metrics in the associated registry. This will be prepended
+    /// to all metric names.
+    pub fn with_namespace(mut self, global_prefix: impl Into<String>) -> Self {
+        let global_prefix = global_prefix.into();
+        self.global_prefix = Some(global_prefix);
+        self
+    }
+
+    /// Add a label to all exported series, e.g. the `region` or `instance` of the deployment, which
+    /// is usually only known at startup, after the metrics are defined.
+    ///
+    /// Series that already have a label with the same name keep their own value.
+    pub fn with_global_label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.global_labels.push((key.into(), value.into()));
+        self
+    }
+
+    /// Serve the metrics over HTTPS, with the given PEM-encoded certificate chain and private key.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        self.tls = Some(TlsConfig { cert_path: cert_path.into(), key_path: key_path.into() });
+        self
+    }
+
+    /// Set the registry for the exporter.
+    pub fn with_registry(mut self, registry: prometheus::Registry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Also serve the metrics of the given registry, along with the main one.
+    ///
+    /// This allows library metrics to be registered with their own registry, with an
+    /// application-level prefix that stacks with the library scope. See
+    /// [`crate::prefixed_registry`].
+    pub fn with_additional_registry(mut self, registry: prometheus::Registry) -> Self {
+        self.additional_registries.push(registry);
+        self
+    }
+
+    /// Also collect process metrics, polling at the given interval in the background.
+    ///
+    /// A 10 second interval is a good default for most applications.
+    #[cfg(feature = "process")]
+    pub fn with_process_metrics(mut self, poll_interval: Duration) -> Self {
+        self.process_metrics_poll_interval = Some(poll_interval);
+        self
+    }
+
+    /// Also serve the metrics written by worker processes to the given directory with
+    /// [`crate::multiprocess::MultiProcessWriter`], aggregated at scrape time.
+    #[cfg(feature = "multiprocess")]
+    pub fn with_multiprocess_dir(self, dir: impl Into<std::path::PathBuf>) -> Self {
+        self.with_multiprocess(crate::multiprocess::MultiProcessCollector::new(dir))
+    }
+
+    /// Also serve the metrics aggregated by the given
+    /// [`crate::multiprocess::MultiProcessCollector`] at scrape time.
+    #[cfg(feature = "multiprocess")]
+    pub fn with_multiprocess(
+        mut self,
+        collector: crate::multiprocess::MultiProcessCollector,
+    ) -> Self {
+        self.multiprocess = Some(collector);
+        self
+    }
+
+    /// Set a static configuration for disabled metrics, export filters and authentication. See
+    /// [`config`] for the available settings.
+    pub fn with_config(mut self, config: ExporterConfig) -> Self {
+        self.config = Some(ConfigSource::Static(config));
+        self
+    }
+
+    /// Read the configuration from the environment when installing. See [`config`] for the
+    /// available variables.
+    pub fn with_config_env(mut self) -> Self {
+        self.config = Some(ConfigSource::Env);
+        self
+    }
+
+    /// Read the configuration from the given file when installing, and reload it on SIGHUP or
+    /// when the file changes. See [`config`] for the file format.
+    ///
+    /// If a reloaded configuration is invalid, the previous one is kept.
+    pub fn with_config_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config = Some(ConfigSource::File(path.into()));
+        self
+    }
+
+    fn path(&self) -> Result<String, ExporterError> {
+        if self.path.is_empty() {
+            return Err(ExporterError::InvalidPath(self.path.clone()));
+        }
+
+        if !self.path.starts_with('/') {
+            return Err(ExporterError::InvalidPath(self.path.clone()));
+        }
+
+        // Remove trailing slash from path
+        let path = if self.path.eq("/") {
+            "/".to_owned()
+        } else {
+            self.path.trim_end_matches('/').to_owned()
+        };
+
+        Ok(path)
+    }
+
+    fn address(&self) -> Result<SocketAddr, ExporterError> {
+        self.address.parse().map_err(|e| ExporterError::InvalidAddress(self.address.clone(), e))
+    }
+
+    /// Install the HTTP exporter with the given configuration and start serving metrics.
+    /// Uses [hyper] for the HTTP server and [tokio] for the runtime.
+    ///
+    /// # Behavior
+    /// - If a Tokio runtime is available, use it to spawn the listener.
+    /// - Otherwise, spawn a new single-threaded Tokio runtime on a thread, and spawn the listener
+    ///   there.
+    pub fn install(self) -> Result<(), ExporterError> {
+        let path = self.path()?;
+        let address = self.address()?;
+        let registry = self.registry.unwrap_or_else(|| prometheus::default_registry().clone());
+        let registries =
+            std::iter::once(registry).chain(self.additional_registries).collect::<Vec<_>>();
+
+        let config = match &self.config {
+            Some(source) => source.load().map_err(ExporterError::InvalidConfig)?,
+            None => ExporterConfig::default(),
+        };
+        let config: SharedConfig = Arc::new(RwLock::new(config));
+
+        #[cfg(feature = "tls")]
+        let tls = self.tls.as_ref().map(tls::acceptor).transpose()?;
+        #[cfg(not(feature = "tls"))]
+        if self.tls.is_some() {
+            return Err(ExporterError::FeatureDisabled("tls"));
+        }
+        // Process metrics can only be set without the `process` feature from a configuration.
+        #[cfg(not(feature = "process"))]
+        if self.process_metrics_poll_interval.is_some() {
+            return Err(ExporterError::FeatureDisabled("process"));
+        }
+
+        // Build the serve, process collection and config reload futures.
+        let state = ExporterState {
+            registries,
+            path,
+            global_prefix: self.global_prefix,
+            global_labels: self.global_labels,
+            config: config.clone(),
+            #[cfg(feature = "multiprocess")]
+            multiprocess: self.multiprocess,
+        };
+        let serve = serve(
+            address,
+            state,
+            #[cfg(feature = "tls")]
+            tls,
+        );
+        let collect = collect_process_metrics(self.process_metrics_poll_interval);
+        let reload = reload_config(self.config, config);
+        let fut = async { tokio::try_join!(serve, collect, reload) };
+
+        // If a Tokio runtime is available, use it to spawn the listener. Otherwise,
+        // create a new single-threaded runtime and spawn the listener there.
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(fut);
+        } else {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+
+            thread::spawn(move || {
+                runtime.block_on(fut).unwrap_or_else(|e| panic!("server error: {e:?}"));
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The state shared by all the connections of the exporter.
+#[derive(Clone)]
+struct ExporterState {
+    registries: Vec<prometheus::Registry>,
+    path: String,
+    global_prefix: Option<String>,
+    global_labels: Vec<(String, String)>,
+    config: SharedConfig,
+    #[cfg(feature = "multiprocess")]
+    multiprocess: Option<crate::multiprocess::MultiProcessCollector>,
+}
+
+async fn serve(
+    addr: SocketAddr,
+    state: ExporterState,
+    #[cfg(feature = "tls")] tls: Option<tokio_rustls::TlsAcceptor>,
+) -> Result<(), ExporterError> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+
+        let state = state.clone();
+        let service = service_fn(move |req| serve_req(req, state.clone()));
+
+        #[cfg(feature = "tls")]
+        if let Some(tls) = tls.clone() {
+            tokio::spawn(async move {
+                // Drop connections failing the handshake.
+                let Ok(stream) = tls.accept(stream).await else {
+                    return;
+                };
+                let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+            });
+            continue;
+        }
+
+        tokio::spawn(async move {
+            let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+        });
+    }
+}
+
+async fn serve_req(
+    req: Request<Incoming>,
+    state: ExporterState,
+) -> Result<Response<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let ExporterState {
+        registries,
+        path,
+        global_prefix,
+        global_labels,
+        config,
+        #[cfg(feature = "multiprocess")]
+        multiprocess,
+    } = state;
+
+    let encoder = TextEncoder::new();
+
+    if req.uri().path() != path {
+        return Ok(Response::builder().status(404).body("Not Found".to_string())?);
+    }
+
+    let config = config.read().unwrap_or_else(|e| e.into_inner()).clone();
+    let authorization = req.headers().get(AUTHORIZATION).map(|value| value.as_bytes());
+    if !config.is_authorized(authorization) {
+        return Ok(Response::builder().status(401).body("Unauthorized".to_string())?);
+    }
+
+    // Export the updates buffered by idle threads
+    crate::local::flush_all();
+    let mut metrics = registries.iter().flat_map(|registry| registry.gather()).collect::<Vec<_>>();
+
+    // Aggregate the metrics of the worker processes with the local ones
+    #[cfg(feature = "multiprocess")]
+    if let Some(multiprocess) = multiprocess {
+        metrics = multiprocess.gather_with(metrics)?;
+    }
+
+    // Drop the metrics disabled by the configuration, before the global prefix is applied
+    metrics.retain(|metric| config.is_exported(metric.name()));
+
+    // Serve the exemplars to scrapers accepting OpenMetrics, resolved before the metrics are
+    // modified by the global labels and prefix
+    let accepts_openmetrics = req
+        .headers()
+        .get(ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/openmetrics-text"));
+    let exemplars = accepts_openmetrics.then(|| crate::exemplar::Exemplars::collect(&metrics));
+
+    // Add the global labels to all series, unless already set
+    if !global_labels.is_empty() {
+        for metric in metrics.iter_mut().flat_map(|family| family.mut_metric()) {
+            for (key, value) in &global_labels {
+                if metric.get_label().iter().any(|label| label.name() == key) {
+                    continue;
+                }
+
+                let mut label = prometheus::proto::LabelPair::default();
+                label.set_name(key.clone());
+                label.set_value(value.clone());
+                metric.label.push(label);
+            }
+            metric.label.sort_by(|a, b| a.name().cmp(b.name()));
+        }
+    }
+
+    // Set the global prefix for the metrics
+    if let Some(prefix) = global_prefix {
+        metrics.iter_mut().for_each(|metric| {
+            if let Some(name) = metric.name.as_mut() {
+                name.insert(0, '_');
+                name.insert_str(0, &prefix);
+            };
+        });
+    }
+
+    let (body, format) = match exemplars {
+        Some(exemplars) => (exemplars.encode(&metrics), crate::exemplar::OPENMETRICS_FORMAT),
+        None => (encoder.encode_to_string(&metrics)?, encoder.format_type()),
+    };
+
+    let response = Response::builder().status(200).header(CONTENT_TYPE, format).body(body)?;
+
+    Ok(response)
+}
+
+/// If the "process" feature is enabled AND the poll interval is provided, collect
+/// process metrics at the given interval. Otherwise, no-op.
+///
+/// NOTE: the return type is Result to use [`tokio::try_join!`] with [`serve`].
+async fn collect_process_metrics(_poll_interval: Option<Duration>) -> Result<(), ExporterError> {
+    #[cfg(feature = "process")]
+    if let Some(interval) = _poll_interval {
+        let mut collector = crate::process::ProcessCollector::default();
+        loop {
+            collector.collect();
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// If the configuration is read from a file, reload it on SIGHUP or when it changes. Otherwise,
+/// no-op.
+///
+/// NOTE: the return type is Result to use [`tokio::try_join!`] with [`serve`].
+async fn reload_config(
+    source: Option<ConfigSource>,
+    config: SharedConfig,
+) -> Result<(), ExporterError> {
+    if let Some(ConfigSource::File(path)) = source {
+        config::watch(path, config).await?;
+    }
+
+    Ok(())
+}
+
+/// An error that can occur when building or installing the Prometheus HTTP exporter.
+pub enum ExporterError {
+    BindError(std::io::Error),
+    ServeError(hyper::Error),
+    InvalidPath(String),
+    InvalidAddress(String, std::net::AddrParseError),
+    InvalidConfig(ConfigError),
+    InvalidTls(String),
+    FeatureDisabled(&'static str),
+}
+
+impl std::error::Error for ExporterError {}
+
+impl std::fmt::Display for ExporterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BindError(e) => write!(f, "Failed to bind to address: {e:?}"),
+            Self::ServeError(e) => write!(f, "HTTP server failed: {e:?}"),
+            Self::InvalidPath(path) => write!(f, "Invalid path: {path}"),
+            Self::InvalidAddress(address, e) => write!(f, "Invalid address: {address}: {e:?}"),
+            Self::InvalidConfig(e) => write!(f, "Invalid config: {e}"),
+            Self::InvalidTls(e) => write!(f, "Invalid TLS config: {e}"),
+            Self::FeatureDisabled(feature) => {
+                write!(f, "The `{feature}` feature is required but not enabled")
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for ExporterError {
+    fn from(e: std::io::Error) -> Self {
+        Self::BindError(e)
+    }
+}
+
+impl std::fmt::Debug for ExporterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
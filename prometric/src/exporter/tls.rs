@@ -0,0 +1,44 @@
+//! HTTPS support for the exporter, with `rustls`.
+
+use std::sync::Arc;
+
+use rustls_pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+use tokio_rustls::{TlsAcceptor, rustls::ServerConfig};
+
+use super::{ExporterError, config::TlsConfig};
+
+/// Build a TLS acceptor from the certificate chain and private key of the given configuration.
+pub(super) fn acceptor(config: &TlsConfig) -> Result<TlsAcceptor, ExporterError> {
+    let invalid = |e: &dyn std::fmt::Display| ExporterError::InvalidTls(e.to_string());
+
+    let certs = CertificateDer::pem_file_iter(&config.cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid(&e))?;
+    let key = PrivateKeyDer::from_pem_file(&config.key_path).map_err(|e| invalid(&e))?;
+
+    let server_config = ServerConfig::builder_with_provider(Arc::new(
+        tokio_rustls::rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(|e| invalid(&e))?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)
+    .map_err(|e| invalid(&e))?;
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_cert() {
+        let config = TlsConfig {
+            cert_path: "/nonexistent/cert.pem".into(),
+            key_path: "/nonexistent/key.pem".into(),
+        };
+
+        assert!(matches!(acceptor(&config), Err(ExporterError::InvalidTls(_))));
+    }
+}
@@ -1,27 +1,87 @@
 use std::collections::HashMap;
 
+#[cfg(feature = "recording")]
+use prometheus::core::Number as _;
+
 use crate::private::Sealed;
+#[cfg(feature = "recording")]
+use crate::recording::{self, MetricOp};
 
 /// The default number type for gauges.
 pub type GaugeDefault = u64;
 
 /// A marker trait for numbers that can be used as gauge values.
-/// Supported types: `i64`, `f64`, `u64`
+/// Supported types: `i64`, `f64`, `u64`, [`Duration`](std::time::Duration) (exported as seconds)
 pub trait GaugeNumber: Sized + 'static + Sealed {
     /// The atomic type associated with this number type.
     type Atomic: prometheus::core::Atomic;
+
+    /// Convert the number into the value stored in the atomic type.
+    fn into_value(self) -> <Self::Atomic as prometheus::core::Atomic>::T;
+
+    /// Convert the time since the UNIX epoch into a number of seconds, truncated for integers.
+    fn from_timestamp(since_epoch: std::time::Duration) -> Self;
 }
 
 impl GaugeNumber for i64 {
     type Atomic = prometheus::core::AtomicI64;
+
+    #[inline]
+    fn into_value(self) -> i64 {
+        self
+    }
+
+    #[inline]
+    fn from_timestamp(since_epoch: std::time::Duration) -> Self {
+        since_epoch.as_secs() as i64
+    }
 }
 
 impl GaugeNumber for f64 {
     type Atomic = prometheus::core::AtomicF64;
+
+    #[inline]
+    fn into_value(self) -> f64 {
+        self
+    }
+
+    #[inline]
+    fn from_timestamp(since_epoch: std::time::Duration) -> Self {
+        since_epoch.as_secs_f64()
+    }
 }
 
 impl GaugeNumber for u64 {
     type Atomic = prometheus::core::AtomicU64;
+
+    #[inline]
+    fn into_value(self) -> u64 {
+        self
+    }
+
+    #[inline]
+    fn from_timestamp(since_epoch: std::time::Duration) -> Self {
+        since_epoch.as_secs()
+    }
+}
+
+impl GaugeNumber for std::time::Duration {
+    type Atomic = prometheus::core::AtomicF64;
+
+    #[inline]
+    fn into_value(self) -> f64 {
+        self.as_secs_f64()
+    }
+
+    #[inline]
+    fn from_timestamp(since_epoch: std::time::Duration) -> Self {
+        since_epoch
+    }
+}
+
+/// Returns the time since the UNIX epoch, or zero if the system clock is set before it.
+fn since_epoch() -> std::time::Duration {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default()
 }
 
 /// A gauge metric with a generic number type. Default is `i64`, which provides better performance
@@ -29,16 +89,45 @@ impl GaugeNumber for u64 {
 #[derive(Debug)]
 pub struct Gauge<N: GaugeNumber = GaugeDefault> {
     inner: prometheus::core::GenericGaugeVec<N::Atomic>,
+    /// The cardinality limit of the metric, if any. See [`crate::cardinality`].
+    limiter: Option<std::sync::Arc<crate::cardinality::CardinalityLimiter>>,
+    /// The deprecated aliases of the metric. See [`crate::alias`].
+    aliases: Vec<String>,
+    #[cfg(feature = "recording")]
+    definition: std::sync::Arc<recording::MetricDefinition>,
 }
 
 impl<N: GaugeNumber> Clone for Gauge<N> {
     fn clone(&self) -> Self {
-        Self { inner: self.inner.clone() }
+        Self {
+            inner: self.inner.clone(),
+            limiter: self.limiter.clone(),
+            aliases: self.aliases.clone(),
+            #[cfg(feature = "recording")]
+            definition: self.definition.clone(),
+        }
+    }
+}
+
+/// Collects the series of the gauge, e.g. to register it in another registry along with the
+/// metrics struct holding it. Deprecated aliases and the overflow counter of a cardinality limit
+/// are registered separately.
+impl<N: GaugeNumber> prometheus::core::Collector for Gauge<N> {
+    fn desc(&self) -> Vec<&prometheus::core::Desc> {
+        self.inner.desc()
+    }
+
+    fn collect(&self) -> Vec<prometheus::proto::MetricFamily> {
+        self.inner.collect()
     }
 }
 
 impl<N: GaugeNumber> Gauge<N> {
     /// Create a new gauge metric with the given registry, name, help, labels, and const labels.
+    ///
+    /// # Panics
+    /// Panics if the name or the labels are invalid, or if the registration fails. See
+    /// [`Self::try_new`] for a fallible version.
     pub fn new(
         registry: &prometheus::Registry,
         name: &str,
@@ -46,46 +135,334 @@ impl<N: GaugeNumber> Gauge<N> {
         labels: &[&str],
         const_labels: HashMap<String, String>,
     ) -> Self {
-        let opts = prometheus::Opts::new(name, help).const_labels(const_labels);
-        let metric = prometheus::core::GenericGaugeVec::<N::Atomic>::new(opts, labels).unwrap();
-
-        let boxed = Box::new(metric.clone());
-        if let Err(e) = registry.register(boxed.clone()) {
-            let id = format!("{}, Labels: {}", name, labels.join(", "),);
-            // If the metric is already registered, overwrite it.
-            if matches!(e, prometheus::Error::AlreadyReg) {
-                registry
-                    .unregister(boxed.clone())
-                    .unwrap_or_else(|_| panic!("Failed to unregister metric {id}"));
-
-                registry
-                    .register(boxed)
-                    .unwrap_or_else(|_| panic!("Failed to overwrite metric {id}"));
-            } else {
-                panic!("Failed to register metric {id}");
-            }
-        }
+        Self::with_opts(registry, name, help, labels, const_labels, std::convert::identity)
+    }
+
+    /// Create a new gauge metric like [`Self::new`], returning an error if the name or the labels
+    /// are invalid, or if the registration fails.
+    pub fn try_new(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+    ) -> Result<Self, crate::Error> {
+        Self::try_with_opts(registry, name, help, labels, const_labels, std::convert::identity)
+    }
+
+    /// Create a new gauge metric like [`Self::new`], modifying its options with `modify` before
+    /// registration, e.g. to set options that aren't modeled by `prometric`.
+    ///
+    /// # Panics
+    /// Panics like [`Self::new`], see [`Self::try_with_opts`] for a fallible version.
+    pub fn with_opts(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+        modify: impl FnOnce(prometheus::Opts) -> prometheus::Opts,
+    ) -> Self {
+        Self::try_with_opts(registry, name, help, labels, const_labels, modify)
+            .unwrap_or_else(|e| crate::registration_failed(name, labels, e))
+    }
+
+    /// Create a new gauge metric like [`Self::with_opts`], returning an error if the name or the
+    /// labels are invalid, or if the registration fails.
+    pub fn try_with_opts(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+        modify: impl FnOnce(prometheus::Opts) -> prometheus::Opts,
+    ) -> Result<Self, crate::Error> {
+        let opts = modify(prometheus::Opts::new(name, help).const_labels(const_labels));
+        #[cfg(feature = "recording")]
+        let definition = recording::MetricDefinition::new::<N>(
+            crate::descriptor::MetricKind::Gauge,
+            &opts.fq_name(),
+            &opts.help,
+            labels,
+            &opts.const_labels,
+            None,
+        );
 
-        Self { inner: metric }
+        let name = opts.fq_name();
+        let metric = prometheus::core::GenericGaugeVec::<N::Atomic>::new(opts, labels)?;
+
+        crate::register(registry, &metric, &name, labels)?;
+
+        Ok(Self {
+            inner: metric,
+            limiter: None,
+            aliases: Vec::new(),
+            #[cfg(feature = "recording")]
+            definition,
+        })
     }
 
     pub fn inc(&self, labels: &[&str]) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
         self.inner.with_label_values(labels).inc();
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Inc);
     }
 
     pub fn dec(&self, labels: &[&str]) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
         self.inner.with_label_values(labels).dec();
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Dec);
     }
 
-    pub fn add(&self, labels: &[&str], value: <N::Atomic as prometheus::core::Atomic>::T) {
+    pub fn add(&self, labels: &[&str], value: N) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
+        let value = value.into_value();
         self.inner.with_label_values(labels).add(value);
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Add(value.into_f64()));
     }
 
-    pub fn sub(&self, labels: &[&str], value: <N::Atomic as prometheus::core::Atomic>::T) {
+    pub fn sub(&self, labels: &[&str], value: N) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
+        let value = value.into_value();
         self.inner.with_label_values(labels).sub(value);
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Sub(value.into_f64()));
     }
 
-    pub fn set(&self, labels: &[&str], value: <N::Atomic as prometheus::core::Atomic>::T) {
+    pub fn set(&self, labels: &[&str], value: N) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
+        let value = value.into_value();
         self.inner.with_label_values(labels).set(value);
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Set(value.into_f64()));
+    }
+
+    /// Set the gauge to the current UNIX timestamp, in seconds, e.g. for
+    /// `*_last_success_timestamp_seconds` metrics. Integer gauges are truncated to whole seconds.
+    pub fn set_to_current_time(&self, labels: &[&str]) {
+        self.set(labels, N::from_timestamp(since_epoch()));
+    }
+
+    /// Returns the value of the child of the gauge with the given label values, creating it if it
+    /// doesn't exist. Reads don't take a slot of the cardinality limit: label values beyond it
+    /// read as `0`, without creating their child. Durations are returned in seconds.
+    pub fn get(&self, labels: &[&str]) -> <N::Atomic as prometheus::core::Atomic>::T {
+        if !self.admitted(labels) {
+            return prometheus::core::Number::from_i64(0);
+        }
+        self.inner.with_label_values(labels).get()
+    }
+
+    /// Also export the gauge under the given deprecated name, registered in the given
+    /// registry, e.g. during the migration window of a rename. See [`crate::alias`].
+    pub fn with_alias(mut self, registry: &prometheus::Registry, alias: &str) -> Self {
+        crate::alias::register(registry, self.inner.clone(), alias);
+        self.aliases.push(alias.to_owned());
+        self
+    }
+
+    /// Limit the number of distinct label value combinations of the gauge, registering its
+    /// overflow counter in the given registry. See [`crate::cardinality`].
+    pub fn limit_cardinality(
+        mut self,
+        registry: &prometheus::Registry,
+        limit: crate::cardinality::CardinalityLimit,
+    ) -> Self {
+        let name = &prometheus::core::Collector::desc(&self.inner)[0].fq_name;
+        let limiter = crate::cardinality::CardinalityLimiter::new(registry, name, limit);
+        self.limiter = Some(std::sync::Arc::new(limiter));
+        self
+    }
+
+    /// Returns whether the given label values are admitted by the cardinality limit, without
+    /// admitting them.
+    fn admitted(&self, labels: &[&str]) -> bool {
+        self.limiter.as_ref().is_none_or(|limiter| limiter.contains(labels))
+    }
+
+    /// Returns the label values admitted by the cardinality limit, or `None` if the update is
+    /// dropped.
+    fn admit<'a, 'b>(&self, labels: &'a [&'b str]) -> Option<std::borrow::Cow<'a, [&'b str]>> {
+        match &self.limiter {
+            Some(limiter) => limiter.admit(labels),
+            None => Some(std::borrow::Cow::Borrowed(labels)),
+        }
+    }
+
+    /// Remove the child of the gauge with the given label values, e.g. to drop the stale series
+    /// of a disconnected peer. Returns `false` if there is no such child. Handles to the child
+    /// keep updating it, without exporting it.
+    pub fn remove(&self, labels: &[&str]) -> bool {
+        let removed = self.inner.remove_label_values(labels).is_ok();
+        if let Some(limiter) = &self.limiter {
+            limiter.remove(labels);
+        }
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Remove);
+        removed
+    }
+
+    /// Unregister the gauge from the given registry, along with its cardinality overflow counter
+    /// and deprecated aliases, e.g. when a short-lived component is dropped. Updates keep
+    /// applying to the gauge, without exporting it.
+    pub fn unregister(&self, registry: &prometheus::Registry) {
+        let _ = registry.unregister(Box::new(self.inner.clone()));
+        if let Some(limiter) = &self.limiter {
+            limiter.unregister(registry);
+        }
+        for alias in &self.aliases {
+            crate::alias::unregister(registry, self.inner.clone(), alias);
+        }
+    }
+
+    /// Record the operations on the gauge into the given recorder, whether it is started or
+    /// not, unless the gauge already records into another one. See [`crate::recording`].
+    #[cfg(feature = "recording")]
+    pub fn record_into(&self, recorder: &recording::Recorder) {
+        self.definition.record_into(recorder);
+    }
+
+    /// Remove all the children of the gauge, e.g. to return to a clean state between tests.
+    /// Handles to the children keep updating them, without exporting them.
+    pub fn clear(&self) {
+        self.inner.reset();
+        if let Some(limiter) = &self.limiter {
+            limiter.clear();
+        }
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, &[], MetricOp::Clear);
+    }
+
+    /// Resolve the child of the gauge with the given label values once, returning a handle
+    /// updating it without the label lookup.
+    pub fn handle(&self, labels: &[&str]) -> GaugeHandle<N> {
+        let Some(admitted) = self.admit(labels) else { return GaugeHandle::noop() };
+        let labels = &*admitted;
+        GaugeHandle {
+            inner: Some(self.inner.with_label_values(labels)),
+            #[cfg(feature = "recording")]
+            recording: Some(recording::HandleRecording::new(&self.definition, labels)),
+        }
+    }
+}
+
+/// A handle to the child of a [`Gauge`] with resolved label values, returned by [`Gauge::handle`].
+/// Updates skip the label lookup and allocations, for hot paths.
+#[derive(Debug, Clone)]
+pub struct GaugeHandle<N: GaugeNumber = GaugeDefault> {
+    inner: Option<prometheus::core::GenericGauge<N::Atomic>>,
+    #[cfg(feature = "recording")]
+    recording: Option<recording::HandleRecording>,
+}
+
+impl<N: GaugeNumber> GaugeHandle<N> {
+    /// Create a handle updating nothing, e.g. when metrics are disabled.
+    pub fn noop() -> Self {
+        Self {
+            inner: None,
+            #[cfg(feature = "recording")]
+            recording: None,
+        }
+    }
+
+    pub fn inc(&self) {
+        let Some(inner) = &self.inner else { return };
+        inner.inc();
+        #[cfg(feature = "recording")]
+        self.record(MetricOp::Inc);
+    }
+
+    pub fn dec(&self) {
+        let Some(inner) = &self.inner else { return };
+        inner.dec();
+        #[cfg(feature = "recording")]
+        self.record(MetricOp::Dec);
+    }
+
+    pub fn add(&self, value: N) {
+        let Some(inner) = &self.inner else { return };
+        let value = value.into_value();
+        inner.add(value);
+        #[cfg(feature = "recording")]
+        self.record(MetricOp::Add(value.into_f64()));
+    }
+
+    pub fn sub(&self, value: N) {
+        let Some(inner) = &self.inner else { return };
+        let value = value.into_value();
+        inner.sub(value);
+        #[cfg(feature = "recording")]
+        self.record(MetricOp::Sub(value.into_f64()));
+    }
+
+    pub fn set(&self, value: N) {
+        let Some(inner) = &self.inner else { return };
+        let value = value.into_value();
+        inner.set(value);
+        #[cfg(feature = "recording")]
+        self.record(MetricOp::Set(value.into_f64()));
+    }
+
+    /// Set the gauge to the current UNIX timestamp, in seconds. See [`Gauge::set_to_current_time`].
+    pub fn set_to_current_time(&self) {
+        self.set(N::from_timestamp(since_epoch()));
+    }
+
+    #[cfg(feature = "recording")]
+    fn record(&self, op: MetricOp) {
+        if let Some(recording) = &self.recording {
+            recording.record(op);
+        }
+    }
+}
+
+/// A guard decrementing a gauge when dropped, returned by the `track()` method of generated gauge
+/// accessors after incrementing the gauge. This covers the "in-flight requests" pattern.
+///
+/// The gauge is also decremented when the guard is dropped while unwinding from a panic, so it
+/// doesn't drift.
+///
+/// # Example
+/// ```rust
+/// use prometric::{Gauge, GaugeGuard};
+///
+/// let registry = prometheus::Registry::new();
+/// let gauge = Gauge::<i64>::new(&registry, "in_flight", "In flight.", &[], Default::default());
+///
+/// gauge.inc(&[]);
+/// let guard = GaugeGuard::new(|| gauge.dec(&[]));
+/// drop(guard);
+/// ```
+#[must_use = "The gauge is decremented when the guard is dropped"]
+pub struct GaugeGuard<F: FnOnce()> {
+    release: Option<F>,
+}
+
+impl<F: FnOnce()> GaugeGuard<F> {
+    /// Create a guard calling `release` when dropped.
+    pub fn new(release: F) -> Self {
+        Self { release: Some(release) }
+    }
+}
+
+impl<F: FnOnce()> Drop for GaugeGuard<F> {
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            release();
+        }
+    }
+}
+
+impl<F: FnOnce()> std::fmt::Debug for GaugeGuard<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GaugeGuard").finish_non_exhaustive()
     }
 }
@@ -0,0 +1,171 @@
+//! Gauges evaluated when gathered, used by `GaugeFn` fields of the `prometric-derive` macro with
+//! `#[metric(eval = || ...)]`.
+//!
+//! Instead of being set whenever the measured value changes, the value is read from a callback
+//! each time the registry is gathered, e.g. the length of a queue or the size of a cache owned by
+//! another component.
+//!
+//! # Example
+//! ```rust
+//! use std::{
+//!     collections::HashMap,
+//!     sync::{
+//!         Arc,
+//!         atomic::{AtomicUsize, Ordering},
+//!     },
+//! };
+//!
+//! use prometric::GaugeFn;
+//!
+//! let registry = prometheus::Registry::new();
+//! let queue = Arc::new(AtomicUsize::new(0));
+//! let depth = queue.clone();
+//! let _gauge =
+//!     GaugeFn::new(&registry, "queue_depth", "Queue depth.", HashMap::new(), move || {
+//!         depth.load(Ordering::Relaxed) as f64
+//!     });
+//!
+//! queue.store(3, Ordering::Relaxed);
+//! let output = prometric::gather_to_string(&registry).unwrap();
+//! assert!(output.contains("queue_depth 3"));
+//! ```
+
+use std::{collections::HashMap, sync::Arc};
+
+use prometheus::{
+    core::{Collector, Desc},
+    proto::{Gauge, Metric, MetricFamily, MetricType},
+};
+
+/// The callback of a [`GaugeFn`].
+type Eval = Arc<dyn Fn() -> f64 + Send + Sync>;
+
+/// Evaluates the callback of a gauge when collected.
+#[derive(Clone)]
+struct GaugeFnCollector {
+    eval: Eval,
+    desc: Desc,
+}
+
+impl Collector for GaugeFnCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        vec![&self.desc]
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let mut gauge = Gauge::default();
+        gauge.set_value((self.eval)());
+        let mut metric = Metric::default();
+        metric.set_label(self.desc.const_label_pairs.clone());
+        metric.set_gauge(gauge);
+
+        let mut family = MetricFamily::default();
+        family.set_name(self.desc.fq_name.clone());
+        family.set_help(self.desc.help.clone());
+        family.set_field_type(MetricType::GAUGE);
+        family.set_metric(vec![metric]);
+        vec![family]
+    }
+}
+
+/// A gauge whose value is read from a callback when gathered. See [`crate::gauge_fn`].
+#[derive(Clone)]
+pub struct GaugeFn {
+    collector: GaugeFnCollector,
+}
+
+impl std::fmt::Debug for GaugeFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GaugeFn")
+            .field("name", &self.collector.desc.fq_name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Collects the series of the gauge, evaluating its callback.
+impl Collector for GaugeFn {
+    fn desc(&self) -> Vec<&Desc> {
+        self.collector.desc()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        self.collector.collect()
+    }
+}
+
+impl GaugeFn {
+    /// Create a new gauge evaluating `eval` when gathered, registered in the given registry with
+    /// the given const labels, overwriting a previous registration of the same name.
+    pub fn new(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        const_labels: HashMap<String, String>,
+        eval: impl Fn() -> f64 + Send + Sync + 'static,
+    ) -> Self {
+        let desc = Desc::new(name.to_owned(), help.to_owned(), Vec::new(), const_labels)
+            .unwrap_or_else(|e| panic!("Invalid gauge {name}: {e}"));
+        let collector = GaugeFnCollector { eval: Arc::new(eval), desc };
+
+        let boxed = Box::new(collector.clone());
+        if let Err(e) = registry.register(boxed.clone()) {
+            // If the gauge is already registered, overwrite it.
+            if matches!(e, prometheus::Error::AlreadyReg) {
+                #[cfg(feature = "testing")]
+                crate::testing::on_duplicate(name);
+
+                registry
+                    .unregister(boxed.clone())
+                    .unwrap_or_else(|_| panic!("Failed to unregister gauge {name}"));
+                registry
+                    .register(boxed)
+                    .unwrap_or_else(|_| panic!("Failed to overwrite gauge {name}"));
+            } else {
+                panic!("Failed to register gauge {name}: {e}");
+            }
+        }
+
+        Self { collector }
+    }
+
+    /// Evaluate the callback, returning the current value of the gauge.
+    pub fn get(&self) -> f64 {
+        (self.collector.eval)()
+    }
+
+    /// Unregister the gauge from the given registry.
+    pub fn unregister(&self, registry: &prometheus::Registry) {
+        let _ = registry.unregister(Box::new(self.collector.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_gauge_fn() {
+        let registry = prometheus::Registry::new();
+        let value = Arc::new(AtomicU64::new(1));
+        let eval = value.clone();
+        let gauge = GaugeFn::new(
+            &registry,
+            "test_queue_depth",
+            "Queue depth.",
+            HashMap::from([("queue".to_owned(), "blocks".to_owned())]),
+            move || eval.load(Ordering::Relaxed) as f64,
+        );
+        assert_eq!(gauge.get(), 1.0);
+
+        // The callback is evaluated on every gather.
+        value.store(5, Ordering::Relaxed);
+        let output = crate::gather_to_string(&registry).unwrap();
+        assert!(output.contains("# TYPE test_queue_depth gauge"));
+        assert!(output.contains(r#"test_queue_depth{queue="blocks"} 5"#));
+
+        gauge.unregister(&registry);
+        assert!(!crate::gather_to_string(&registry).unwrap().contains("test_queue_depth"));
+    }
+}
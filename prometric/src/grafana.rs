@@ -0,0 +1,203 @@
+//! Grafana dashboard generation from [`MetricDescriptor`]s, keeping dashboards mechanically in
+//! sync with the code.
+//!
+//! One panel is generated per metric:
+//! - Counters: the per-second rate.
+//! - Gauges: the current value.
+//! - Histograms: a heatmap of the bucket rates.
+//! - Summaries: the exported quantiles.
+
+use std::{io, path::Path};
+
+use serde_json::{Value, json};
+
+use crate::descriptor::{MetricDescriptor, MetricKind};
+
+/// The width of a panel, in grid units (a row is 24 units wide).
+const PANEL_WIDTH: u64 = 12;
+
+/// The height of a panel, in grid units.
+const PANEL_HEIGHT: u64 = 8;
+
+/// The number of panels per row.
+const PANELS_PER_ROW: u64 = 24 / PANEL_WIDTH;
+
+/// A builder for Grafana dashboards.
+///
+/// # Example
+/// ```rust
+/// use prometric::{
+///     descriptor::{MetricDescriptor, MetricKind},
+///     grafana::DashboardBuilder,
+/// };
+///
+/// let descriptors = vec![MetricDescriptor::new(
+///     "app_http_requests_total",
+///     MetricKind::Counter,
+///     "The total number of HTTP requests.",
+///     ["method", "path"],
+/// )];
+///
+/// let dashboard = DashboardBuilder::new("App").with_uid("app").render(&descriptors);
+/// assert!(dashboard.contains("rate(app_http_requests_total[$__rate_interval])"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DashboardBuilder {
+    title: String,
+    uid: Option<String>,
+    namespace: Option<String>,
+}
+
+impl DashboardBuilder {
+    /// Create a new builder for a dashboard with the given title.
+    pub fn new(title: impl Into<String>) -> Self {
+        Self { title: title.into(), uid: None, namespace: None }
+    }
+
+    /// Set the UID of the dashboard. If not set, Grafana generates one on import.
+    pub fn with_uid(mut self, uid: impl Into<String>) -> Self {
+        self.uid = Some(uid.into());
+        self
+    }
+
+    /// Set the global namespace of the metrics, as configured with
+    /// `ExporterBuilder::with_namespace`. This will be prepended to all metric names.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// Build the dashboard JSON model for the given metrics.
+    pub fn build(&self, descriptors: &[MetricDescriptor]) -> Value {
+        let panels = descriptors
+            .iter()
+            .enumerate()
+            .map(|(i, descriptor)| self.panel(i as u64, descriptor))
+            .collect::<Vec<_>>();
+
+        json!({
+            "title": self.title,
+            "uid": self.uid,
+            "schemaVersion": 39,
+            "editable": true,
+            "time": { "from": "now-1h", "to": "now" },
+            "templating": {
+                "list": [{
+                    "name": "datasource",
+                    "label": "Data source",
+                    "type": "datasource",
+                    "query": "prometheus",
+                }],
+            },
+            "panels": panels,
+        })
+    }
+
+    /// Render the dashboard JSON for the given metrics.
+    pub fn render(&self, descriptors: &[MetricDescriptor]) -> String {
+        serde_json::to_string_pretty(&self.build(descriptors))
+            .expect("dashboard serialization can't fail")
+    }
+
+    /// Write the dashboard JSON for the given metrics to a file. Useful in build scripts.
+    pub fn write_to(
+        &self,
+        path: impl AsRef<Path>,
+        descriptors: &[MetricDescriptor],
+    ) -> io::Result<()> {
+        std::fs::write(path, self.render(descriptors))
+    }
+
+    fn panel(&self, i: u64, descriptor: &MetricDescriptor) -> Value {
+        let name = match &self.namespace {
+            Some(namespace) => format!("{namespace}_{}", descriptor.name),
+            None => descriptor.name.clone(),
+        };
+
+        let legend = if descriptor.labels.is_empty() {
+            name.clone()
+        } else {
+            descriptor
+                .labels
+                .iter()
+                .map(|label| format!("{{{{{label}}}}}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        let (ty, target) = match descriptor.kind {
+            MetricKind::Counter => (
+                "timeseries",
+                json!({ "expr": format!("rate({name}[$__rate_interval])"), "legendFormat": legend }),
+            ),
+            MetricKind::Gauge => ("timeseries", json!({ "expr": name, "legendFormat": legend })),
+            MetricKind::Histogram => (
+                "heatmap",
+                json!({
+                    "expr": format!("sum by (le) (rate({name}_bucket[$__rate_interval]))"),
+                    "format": "heatmap",
+                    "legendFormat": "{{le}}",
+                }),
+            ),
+            MetricKind::Summary => (
+                "timeseries",
+                json!({ "expr": name, "legendFormat": format!("{legend} {{{{quantile}}}}") }),
+            ),
+        };
+
+        let mut target = target;
+        target["refId"] = json!("A");
+        target["datasource"] = datasource();
+
+        json!({
+            "id": i + 1,
+            "type": ty,
+            "title": name,
+            "description": descriptor.help,
+            "datasource": datasource(),
+            "gridPos": {
+                "h": PANEL_HEIGHT,
+                "w": PANEL_WIDTH,
+                "x": (i % PANELS_PER_ROW) * PANEL_WIDTH,
+                "y": (i / PANELS_PER_ROW) * PANEL_HEIGHT,
+            },
+            "targets": [target],
+        })
+    }
+}
+
+/// The datasource of the panels, referencing the `datasource` template variable.
+fn datasource() -> Value {
+    json!({ "type": "prometheus", "uid": "${datasource}" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dashboard() {
+        let descriptors = vec![
+            MetricDescriptor::new("requests", MetricKind::Counter, "Requests.", ["method"]),
+            MetricDescriptor::new("in_flight", MetricKind::Gauge, "In flight.", [] as [&str; 0]),
+            MetricDescriptor::new("latency", MetricKind::Histogram, "Latency.", ["method"]),
+            MetricDescriptor::new("sizes", MetricKind::Summary, "Sizes.", ["method"]),
+        ];
+
+        let dashboard = DashboardBuilder::new("Test").with_namespace("app").build(&descriptors);
+        let panels = dashboard["panels"].as_array().unwrap();
+        assert_eq!(panels.len(), 4);
+
+        assert_eq!(panels[0]["targets"][0]["expr"], "rate(app_requests[$__rate_interval])");
+        assert_eq!(panels[0]["targets"][0]["legendFormat"], "{{method}}");
+        assert_eq!(panels[1]["targets"][0]["expr"], "app_in_flight");
+        assert_eq!(panels[1]["gridPos"]["x"], 12);
+        assert_eq!(panels[2]["type"], "heatmap");
+        assert_eq!(panels[2]["gridPos"]["y"], 8);
+        assert_eq!(
+            panels[2]["targets"][0]["expr"],
+            "sum by (le) (rate(app_latency_bucket[$__rate_interval]))"
+        );
+        assert_eq!(panels[3]["targets"][0]["legendFormat"], "{{method}} {{quantile}}");
+    }
+}
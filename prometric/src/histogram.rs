@@ -1,18 +1,58 @@
 use std::collections::HashMap;
 
+#[cfg(feature = "recording")]
+use crate::recording::{self, MetricOp};
+
 /// A histogram metric.
 #[derive(Debug)]
 pub struct Histogram {
     inner: prometheus::HistogramVec,
+    /// The identifier of the thread-local buffers of the histogram.
+    id: crate::local::LocalId,
+    /// The series definition recording the exemplars of the histogram buckets.
+    exemplars: std::sync::Arc<crate::exemplar::ExemplarSource>,
+    /// The cardinality limit of the metric, if any. See [`crate::cardinality`].
+    limiter: Option<std::sync::Arc<crate::cardinality::CardinalityLimiter>>,
+    /// The deprecated aliases of the metric. See [`crate::alias`].
+    aliases: Vec<String>,
+    #[cfg(feature = "recording")]
+    definition: std::sync::Arc<recording::MetricDefinition>,
 }
 
 impl Clone for Histogram {
     fn clone(&self) -> Self {
-        Self { inner: self.inner.clone() }
+        Self {
+            inner: self.inner.clone(),
+            limiter: self.limiter.clone(),
+            aliases: self.aliases.clone(),
+            id: self.id.clone(),
+            exemplars: self.exemplars.clone(),
+            #[cfg(feature = "recording")]
+            definition: self.definition.clone(),
+        }
+    }
+}
+
+/// Collects the series of the histogram, e.g. to register it in another registry along with the
+/// metrics struct holding it. Deprecated aliases and the overflow counter of a cardinality limit
+/// are registered separately.
+impl prometheus::core::Collector for Histogram {
+    fn desc(&self) -> Vec<&prometheus::core::Desc> {
+        self.inner.desc()
+    }
+
+    fn collect(&self) -> Vec<prometheus::proto::MetricFamily> {
+        self.inner.collect()
     }
 }
 
 impl Histogram {
+    /// Create a new histogram metric with the given registry, name, help, labels, const labels,
+    /// and buckets, [`prometheus::DEFAULT_BUCKETS`] by default.
+    ///
+    /// # Panics
+    /// Panics if the name or the labels are invalid, or if the registration fails. See
+    /// [`Self::try_new`] for a fallible version.
     pub fn new(
         registry: &prometheus::Registry,
         name: &str,
@@ -21,32 +61,391 @@ impl Histogram {
         const_labels: HashMap<String, String>,
         buckets: Option<Vec<f64>>,
     ) -> Self {
+        Self::with_opts(registry, name, help, labels, const_labels, buckets, std::convert::identity)
+    }
+
+    /// Create a new histogram metric like [`Self::new`], returning an error if the name or the
+    /// labels are invalid, or if the registration fails.
+    pub fn try_new(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+        buckets: Option<Vec<f64>>,
+    ) -> Result<Self, crate::Error> {
+        Self::try_with_opts(
+            registry,
+            name,
+            help,
+            labels,
+            const_labels,
+            buckets,
+            std::convert::identity,
+        )
+    }
+
+    /// Create a new histogram metric like [`Self::new`], modifying its options with `modify`
+    /// before registration, e.g. to set options that aren't modeled by `prometric`.
+    ///
+    /// # Panics
+    /// Panics like [`Self::new`], see [`Self::try_with_opts`] for a fallible version.
+    pub fn with_opts(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+        buckets: Option<Vec<f64>>,
+        modify: impl FnOnce(prometheus::HistogramOpts) -> prometheus::HistogramOpts,
+    ) -> Self {
+        Self::try_with_opts(registry, name, help, labels, const_labels, buckets, modify)
+            .unwrap_or_else(|e| crate::registration_failed(name, labels, e))
+    }
+
+    /// Create a new histogram metric like [`Self::with_opts`], returning an error if the name or
+    /// the labels are invalid, or if the registration fails.
+    pub fn try_with_opts(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+        buckets: Option<Vec<f64>>,
+        modify: impl FnOnce(prometheus::HistogramOpts) -> prometheus::HistogramOpts,
+    ) -> Result<Self, crate::Error> {
         let buckets = buckets.unwrap_or(prometheus::DEFAULT_BUCKETS.to_vec());
-        let opts =
-            prometheus::HistogramOpts::new(name, help).const_labels(const_labels).buckets(buckets);
-        let metric = prometheus::HistogramVec::new(opts, labels).unwrap();
+        let opts = modify(
+            prometheus::HistogramOpts::new(name, help).const_labels(const_labels).buckets(buckets),
+        );
+        #[cfg(feature = "recording")]
+        let definition = recording::MetricDefinition::new::<f64>(
+            crate::descriptor::MetricKind::Histogram,
+            &opts.common_opts.fq_name(),
+            &opts.common_opts.help,
+            labels,
+            &opts.common_opts.const_labels,
+            Some(&opts.buckets),
+        );
 
-        let boxed = Box::new(metric.clone());
-        if let Err(e) = registry.register(boxed.clone()) {
-            let id = format!("{}, Labels: {}", name, labels.join(", "),);
-            // If the metric is already registered, overwrite it.
-            if matches!(e, prometheus::Error::AlreadyReg) {
-                registry
-                    .unregister(boxed.clone())
-                    .unwrap_or_else(|_| panic!("Failed to unregister metric {id}"));
+        let name = opts.common_opts.fq_name();
+        let exemplars = crate::exemplar::ExemplarSource::new(
+            name.clone(),
+            labels,
+            &opts.common_opts.const_labels,
+            &opts.buckets,
+        );
+        let metric = prometheus::HistogramVec::new(opts, labels)?;
 
-                registry
-                    .register(boxed)
-                    .unwrap_or_else(|_| panic!("Failed to overwrite metric {id}"));
-            } else {
-                panic!("Failed to register metric {id}");
-            }
-        }
+        crate::register(registry, &metric, &name, labels)?;
 
-        Self { inner: metric }
+        Ok(Self {
+            inner: metric,
+            limiter: None,
+            aliases: Vec::new(),
+            id: crate::local::LocalId::new(),
+            exemplars: std::sync::Arc::new(exemplars),
+            #[cfg(feature = "recording")]
+            definition,
+        })
     }
 
     pub fn observe(&self, labels: &[&str], value: f64) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
         self.inner.with_label_values(labels).observe(value);
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Observe(value));
+    }
+
+    /// Observe the duration, in seconds, the unit of [`prometheus::DEFAULT_BUCKETS`].
+    pub fn observe_duration(&self, labels: &[&str], duration: std::time::Duration) {
+        self.observe(labels, duration.as_secs_f64());
+    }
+
+    /// Observe the time elapsed since the given instant, in seconds.
+    pub fn observe_since(&self, labels: &[&str], start: std::time::Instant) {
+        self.observe_duration(labels, start.elapsed());
+    }
+
+    /// Start a timer observing the elapsed seconds into the child with the given label values when
+    /// dropped. See [`HistogramTimer`].
+    pub fn start_timer<'a>(
+        &'a self,
+        labels: &'a [&'a str],
+    ) -> HistogramTimer<impl FnOnce(f64) + 'a> {
+        HistogramTimer::new(move |seconds| self.observe(labels, seconds))
+    }
+
+    /// Observe the value, recording the exemplar of its bucket, e.g. the trace ID of the request.
+    /// See [`crate::exemplar`].
+    pub fn observe_with_exemplar(&self, labels: &[&str], value: f64, exemplar: &[(&str, &str)]) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
+        self.observe(labels, value);
+        self.exemplars.record(labels, exemplar, value);
+    }
+
+    /// Observe the value in the buffer of the current thread, flushed to the histogram later. See
+    /// [`crate::local`].
+    pub fn local_observe(&self, labels: &[&str], value: f64) {
+        let Some(admitted) = self.admit(labels) else { return };
+        let labels = &*admitted;
+        let buffered = crate::local::update(
+            &self.id,
+            labels,
+            || self.inner.with_label_values(labels).local(),
+            |local| local.observe(value),
+        );
+        if !buffered {
+            self.inner.with_label_values(labels).observe(value);
+        }
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Observe(value));
+    }
+
+    /// Returns the number of observations of the child of the histogram with the given label
+    /// values, creating it if it doesn't exist. Label values beyond the cardinality limit read as
+    /// `0`, without creating their child. Observations buffered by [`crate::local`] aren't
+    /// included until flushed.
+    pub fn get_sample_count(&self, labels: &[&str]) -> u64 {
+        if !self.admitted(labels) {
+            return 0;
+        }
+        self.inner.with_label_values(labels).get_sample_count()
+    }
+
+    /// Returns the sum of the observations of the child of the histogram with the given label
+    /// values, creating it if it doesn't exist. Label values beyond the cardinality limit read as
+    /// `0`, without creating their child. Observations buffered by [`crate::local`] aren't
+    /// included until flushed.
+    pub fn get_sample_sum(&self, labels: &[&str]) -> f64 {
+        if !self.admitted(labels) {
+            return 0.0;
+        }
+        self.inner.with_label_values(labels).get_sample_sum()
+    }
+
+    /// Also export the histogram under the given deprecated name, registered in the given
+    /// registry, e.g. during the migration window of a rename. See [`crate::alias`].
+    pub fn with_alias(mut self, registry: &prometheus::Registry, alias: &str) -> Self {
+        crate::alias::register(registry, self.inner.clone(), alias);
+        self.aliases.push(alias.to_owned());
+        self
+    }
+
+    /// Limit the number of distinct label value combinations of the histogram, registering its
+    /// overflow counter in the given registry. See [`crate::cardinality`].
+    pub fn limit_cardinality(
+        mut self,
+        registry: &prometheus::Registry,
+        limit: crate::cardinality::CardinalityLimit,
+    ) -> Self {
+        let name = &prometheus::core::Collector::desc(&self.inner)[0].fq_name;
+        let limiter = crate::cardinality::CardinalityLimiter::new(registry, name, limit);
+        self.limiter = Some(std::sync::Arc::new(limiter));
+        self
+    }
+
+    /// Returns whether the given label values are admitted by the cardinality limit, without
+    /// admitting them.
+    fn admitted(&self, labels: &[&str]) -> bool {
+        self.limiter.as_ref().is_none_or(|limiter| limiter.contains(labels))
+    }
+
+    /// Returns the label values admitted by the cardinality limit, or `None` if the update is
+    /// dropped.
+    fn admit<'a, 'b>(&self, labels: &'a [&'b str]) -> Option<std::borrow::Cow<'a, [&'b str]>> {
+        match &self.limiter {
+            Some(limiter) => limiter.admit(labels),
+            None => Some(std::borrow::Cow::Borrowed(labels)),
+        }
+    }
+
+    /// Remove the child of the histogram with the given label values, e.g. to drop the stale series
+    /// of a disconnected peer. Returns `false` if there is no such child. Handles to the child
+    /// keep updating it, without exporting it.
+    pub fn remove(&self, labels: &[&str]) -> bool {
+        let removed = self.inner.remove_label_values(labels).is_ok();
+        if let Some(limiter) = &self.limiter {
+            limiter.remove(labels);
+        }
+        self.exemplars.remove(labels);
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Remove);
+        removed
+    }
+
+    /// Unregister the histogram from the given registry, along with its cardinality overflow
+    /// counter and deprecated aliases, e.g. when a short-lived component is dropped. Updates
+    /// keep applying to the histogram, without exporting it.
+    pub fn unregister(&self, registry: &prometheus::Registry) {
+        let _ = registry.unregister(Box::new(self.inner.clone()));
+        if let Some(limiter) = &self.limiter {
+            limiter.unregister(registry);
+        }
+        for alias in &self.aliases {
+            crate::alias::unregister(registry, self.inner.clone(), alias);
+        }
+        self.exemplars.clear();
+    }
+
+    /// Record the operations on the histogram into the given recorder, whether it is started or
+    /// not, unless the histogram already records into another one. See [`crate::recording`].
+    #[cfg(feature = "recording")]
+    pub fn record_into(&self, recorder: &recording::Recorder) {
+        self.definition.record_into(recorder);
+    }
+
+    /// Remove all the children of the histogram, e.g. to return to a clean state between tests.
+    /// Handles to the children keep updating them, without exporting them.
+    pub fn clear(&self) {
+        self.inner.reset();
+        if let Some(limiter) = &self.limiter {
+            limiter.clear();
+        }
+        self.exemplars.clear();
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, &[], MetricOp::Clear);
+    }
+
+    /// Resolve the child of the histogram with the given label values once, returning a handle
+    /// updating it without the label lookup.
+    pub fn handle(&self, labels: &[&str]) -> HistogramHandle {
+        let Some(admitted) = self.admit(labels) else { return HistogramHandle::noop() };
+        let labels = &*admitted;
+        HistogramHandle {
+            inner: Some(self.inner.with_label_values(labels)),
+            #[cfg(feature = "recording")]
+            recording: Some(recording::HandleRecording::new(&self.definition, labels)),
+        }
+    }
+}
+
+/// A handle to the child of a [`Histogram`] with resolved label values, returned by
+/// [`Histogram::handle`]. Observations skip the label lookup and allocations, for hot paths.
+#[derive(Debug, Clone)]
+pub struct HistogramHandle {
+    inner: Option<prometheus::Histogram>,
+    #[cfg(feature = "recording")]
+    recording: Option<recording::HandleRecording>,
+}
+
+impl HistogramHandle {
+    /// Create a handle observing nothing, e.g. when metrics are disabled.
+    pub fn noop() -> Self {
+        Self {
+            inner: None,
+            #[cfg(feature = "recording")]
+            recording: None,
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        let Some(inner) = &self.inner else { return };
+        inner.observe(value);
+        #[cfg(feature = "recording")]
+        if let Some(recording) = &self.recording {
+            recording.record(MetricOp::Observe(value));
+        }
+    }
+
+    /// Start a timer observing the elapsed seconds when dropped. See [`HistogramTimer`].
+    pub fn start_timer(&self) -> HistogramTimer<impl FnOnce(f64) + '_> {
+        HistogramTimer::new(move |seconds| self.observe(seconds))
+    }
+}
+
+/// A timer observing the elapsed seconds when dropped, returned by [`Histogram::start_timer`],
+/// [`HistogramHandle::start_timer`] and the `start_timer()` method of generated histogram
+/// accessors. Stop it early with [`Self::observe`] or [`Self::observe_at`], or cancel it with
+/// [`Self::discard`].
+///
+/// # Example
+/// ```rust
+/// use prometric::{Histogram, HistogramTimer};
+///
+/// let mut observed = None;
+/// {
+///     let _timer = HistogramTimer::new(|seconds| observed = Some(seconds));
+///     // The timed section.
+/// }
+/// assert!(observed.is_some());
+///
+/// let registry = prometheus::Registry::new();
+/// let latency = Histogram::new(
+///     &registry,
+///     "rpc_latency",
+///     "RPC latency.",
+///     &["method"],
+///     Default::default(),
+///     None,
+/// );
+/// {
+///     let _timer = latency.start_timer(&["eth_call"]);
+///     // The timed section.
+/// }
+/// let elapsed = latency.start_timer(&["eth_call"]).stop_and_record();
+/// assert!(elapsed >= 0.0);
+/// latency.start_timer(&["eth_call"]).stop_and_discard();
+/// assert_eq!(latency.get_sample_count(&["eth_call"]), 2);
+/// ```
+#[must_use = "The timer observes the elapsed time when dropped"]
+pub struct HistogramTimer<F: FnOnce(f64)> {
+    start: std::time::Instant,
+    observe: Option<F>,
+}
+
+impl<F: FnOnce(f64)> HistogramTimer<F> {
+    /// Start a timer passing the elapsed seconds to `observe` when stopped.
+    pub fn new(observe: F) -> Self {
+        Self { start: std::time::Instant::now(), observe: Some(observe) }
+    }
+
+    /// Stop the timer and observe the elapsed seconds. Returns the elapsed seconds.
+    pub fn observe(self) -> f64 {
+        self.observe_at(std::time::Instant::now())
+    }
+
+    /// Stop the timer and observe the seconds elapsed until `end`. Returns the elapsed seconds.
+    pub fn observe_at(mut self, end: std::time::Instant) -> f64 {
+        let elapsed = end.saturating_duration_since(self.start).as_secs_f64();
+        if let Some(observe) = self.observe.take() {
+            observe(elapsed);
+        }
+        elapsed
+    }
+
+    /// Stop the timer without observing anything.
+    pub fn discard(mut self) {
+        self.observe = None;
+    }
+
+    /// Stop the timer and observe the elapsed seconds, like [`Self::observe`]. Named after the
+    /// timers of the `prometheus` crate.
+    pub fn stop_and_record(self) -> f64 {
+        self.observe()
+    }
+
+    /// Stop the timer without observing anything, like [`Self::discard`]. Returns the elapsed
+    /// seconds.
+    pub fn stop_and_discard(self) -> f64 {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        self.discard();
+        elapsed
+    }
+}
+
+impl<F: FnOnce(f64)> Drop for HistogramTimer<F> {
+    fn drop(&mut self) {
+        if let Some(observe) = self.observe.take() {
+            observe(self.start.elapsed().as_secs_f64());
+        }
+    }
+}
+
+impl<F: FnOnce(f64)> std::fmt::Debug for HistogramTimer<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HistogramTimer").field("start", &self.start).finish_non_exhaustive()
     }
 }
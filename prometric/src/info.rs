@@ -0,0 +1,143 @@
+//! Info metrics, exporting a constant `1`-valued gauge whose labels carry metadata such as the
+//! version or git SHA of a build, e.g. `app_build_info{version="1.2.0",git_sha="abc123"} 1`. Used
+//! by `Info` fields of the `prometric-derive` macro.
+//!
+//! An info metric has a single series: setting it replaces the previously set label values.
+//!
+//! # Example
+//! ```rust
+//! use prometric::Info;
+//!
+//! let registry = prometheus::Registry::new();
+//! let info = Info::new(&registry, "build_info", "Build info.", &["version"], Default::default());
+//! info.set(&["1.0.0"]);
+//! info.set(&["1.1.0"]);
+//!
+//! let output = prometric::gather_to_string(&registry).unwrap();
+//! assert!(output.contains(r#"build_info{version="1.1.0"} 1"#));
+//! assert!(!output.contains(r#"version="1.0.0""#));
+//! ```
+
+use std::collections::HashMap;
+
+#[cfg(feature = "recording")]
+use crate::recording;
+use crate::{Gauge, GaugeHandle};
+
+/// A constant `1`-valued gauge whose labels carry metadata. See [`crate::info`].
+#[derive(Debug, Clone)]
+pub struct Info {
+    inner: Gauge<u64>,
+}
+
+/// Collects the series of the info metric.
+impl prometheus::core::Collector for Info {
+    fn desc(&self) -> Vec<&prometheus::core::Desc> {
+        self.inner.desc()
+    }
+
+    fn collect(&self) -> Vec<prometheus::proto::MetricFamily> {
+        self.inner.collect()
+    }
+}
+
+impl Info {
+    /// Create a new info metric with the given registry, name, help, labels, and const labels.
+    pub fn new(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+    ) -> Self {
+        Self { inner: Gauge::new(registry, name, help, labels, const_labels) }
+    }
+
+    /// Set the label values of the info metric, replacing the previously set ones.
+    pub fn set(&self, labels: &[&str]) {
+        self.inner.clear();
+        self.inner.set(labels, 1);
+    }
+
+    /// Remove the series with the given label values. Returns `false` if there is no such series.
+    pub fn remove(&self, labels: &[&str]) -> bool {
+        self.inner.remove(labels)
+    }
+
+    /// Unregister the info metric from the given registry.
+    pub fn unregister(&self, registry: &prometheus::Registry) {
+        self.inner.unregister(registry);
+    }
+
+    /// Record the operations on the info metric into the given recorder. See
+    /// [`crate::recording`].
+    #[cfg(feature = "recording")]
+    pub fn record_into(&self, recorder: &recording::Recorder) {
+        self.inner.record_into(recorder);
+    }
+
+    /// Remove the series of the info metric, until it's set again.
+    pub fn clear(&self) {
+        self.inner.clear();
+    }
+
+    /// Resolve the series with the given label values once, returning a handle setting it without
+    /// the label lookup.
+    pub fn handle(&self, labels: &[&str]) -> InfoHandle {
+        InfoHandle { inner: self.inner.handle(labels) }
+    }
+}
+
+/// A handle to the series of an [`Info`] metric with resolved label values, returned by
+/// [`Info::handle`].
+#[derive(Debug)]
+pub struct InfoHandle {
+    inner: GaugeHandle<u64>,
+}
+
+impl InfoHandle {
+    /// Create a handle setting nothing, e.g. when metrics are disabled.
+    pub fn noop() -> Self {
+        Self { inner: GaugeHandle::noop() }
+    }
+
+    /// Set the series to `1`. Unlike [`Info::set`], series with other label values are kept.
+    pub fn set(&self) {
+        self.inner.set(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_info() {
+        let registry = prometheus::Registry::new();
+        let info = Info::new(
+            &registry,
+            "test_build_info",
+            "Build info.",
+            &["version", "git_sha"],
+            Default::default(),
+        );
+        info.set(&["1.0.0", "abc"]);
+        info.set(&["1.1.0", "def"]);
+
+        let output = crate::gather_to_string(&registry).unwrap();
+        assert!(output.contains("# TYPE test_build_info gauge"));
+        assert!(output.contains(r#"test_build_info{git_sha="def",version="1.1.0"} 1"#));
+        assert!(!output.contains(r#"version="1.0.0""#));
+
+        assert!(info.remove(&["1.1.0", "def"]));
+        info.handle(&["1.2.0", "ghi"]).set();
+        assert!(
+            crate::gather_to_string(&registry)
+                .unwrap()
+                .contains(r#"test_build_info{git_sha="ghi",version="1.2.0"} 1"#)
+        );
+
+        info.unregister(&registry);
+        assert!(registry.gather().is_empty());
+    }
+}
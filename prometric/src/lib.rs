@@ -6,6 +6,11 @@
 //! - [`gauge::Gauge`]: A gauge metric.
 //! - [`histogram::Histogram`]: A histogram metric.
 //! - [`summary::Summary`]: A summary metric. Requires the `summary` feature to be enabled.
+//!
+//! # WASM
+//! With `default-features = false`, the core types (and the `prometric-derive` macro) can be used
+//! on `wasm32` targets, as none of `tokio`, `hyper` or `sysinfo` are pulled in. Use
+//! [`gather_to_string`] to report the metrics to the host, which can then merge them with its own.
 
 #[cfg(feature = "exporter")]
 pub mod exporter;
@@ -13,6 +18,51 @@ pub mod exporter;
 #[cfg(feature = "process")]
 pub mod process;
 
+#[cfg(feature = "multiprocess")]
+pub mod multiprocess;
+
+#[cfg(feature = "tracing")]
+pub mod tracing;
+
+#[cfg(feature = "thread-pool")]
+pub mod thread_pool;
+
+#[cfg(feature = "energy")]
+pub mod energy;
+
+pub mod descriptor;
+
+#[cfg(feature = "catalog")]
+pub mod catalog;
+
+#[cfg(feature = "grafana")]
+pub mod grafana;
+
+pub mod rules;
+
+pub mod unit;
+
+pub mod local;
+
+pub mod exemplar;
+
+pub mod created;
+
+pub mod cardinality;
+
+pub mod sanitize;
+
+pub mod alias;
+
+#[cfg(feature = "recording")]
+pub mod recording;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "scrape")]
+pub mod scrape;
+
 pub mod counter;
 pub use counter::*;
 
@@ -22,6 +72,24 @@ pub use gauge::*;
 pub mod histogram;
 pub use histogram::*;
 
+pub mod timer;
+pub use timer::*;
+
+pub mod info;
+pub use info::*;
+
+pub mod outcome;
+pub use outcome::*;
+
+pub mod state_set;
+pub use state_set::{State, StateSet};
+
+pub mod ratio;
+pub use ratio::Ratio;
+
+pub mod gauge_fn;
+pub use gauge_fn::GaugeFn;
+
 #[cfg(feature = "summary")]
 pub mod summary;
 #[cfg(feature = "summary")]
@@ -30,6 +98,503 @@ pub use summary::*;
 #[doc(hidden)]
 pub use prometheus;
 
+/// The error of the fallible constructors of the metrics, e.g. [`Counter::try_new`]: an
+/// invalid name or label, or a registration error.
+///
+/// # Example
+/// ```rust
+/// use prometric::{Counter, Histogram};
+///
+/// let registry = prometheus::Registry::new();
+/// let counter = Counter::<u64>::try_new(&registry, "bad-name", "Typo.", &[], Default::default());
+/// assert!(counter.is_err());
+/// let histogram = Histogram::try_new(
+///     &registry,
+///     "latency",
+///     "Latency.",
+///     &["bad label"],
+///     Default::default(),
+///     None,
+/// );
+/// assert!(histogram.is_err());
+///
+/// let counter =
+///     Counter::<u64>::try_new(&registry, "requests", "Requests.", &[], Default::default());
+/// assert!(counter.is_ok());
+/// ```
+pub use prometheus::Error;
+
+/// Register the metric with the registry, overwriting an already registered metric with the same
+/// descriptor.
+fn register<C>(
+    registry: &prometheus::Registry,
+    metric: &C,
+    name: &str,
+    labels: &[&str],
+) -> Result<(), Error>
+where
+    C: prometheus::core::Collector + Clone + 'static,
+{
+    match registry.register(Box::new(metric.clone())) {
+        Err(Error::AlreadyReg) => {
+            #[cfg(feature = "testing")]
+            testing::on_duplicate(&format!("{name}, Labels: {}", labels.join(", ")));
+            #[cfg(not(feature = "testing"))]
+            let _ = (name, labels);
+
+            registry.unregister(Box::new(metric.clone()))?;
+            registry.register(Box::new(metric.clone()))
+        }
+        result => result,
+    }
+}
+
+/// Panic on the failed creation of the metric with the given name and labels, in the infallible
+/// constructors.
+#[track_caller]
+fn registration_failed(name: &str, labels: &[&str], error: Error) -> ! {
+    panic!("Failed to register metric {name}, Labels: {}: {error}", labels.join(", "))
+}
+
+/// Gather all metrics from the given registry and encode them in the Prometheus text format.
+///
+/// This is useful when the metrics can't be served directly, e.g. to report the metrics of a WASM
+/// plugin to its host for merging.
+///
+/// # Example
+/// ```rust
+/// use prometric::{Counter, gather_to_string};
+///
+/// let registry = prometheus::Registry::new();
+/// let counter =
+///     Counter::<u64>::new(&registry, "plugin_calls", "Plugin calls.", &[], Default::default());
+/// counter.inc(&[]);
+///
+/// let output = gather_to_string(&registry).unwrap();
+/// assert!(output.contains("plugin_calls 1"));
+/// ```
+pub fn gather_to_string(registry: &prometheus::Registry) -> prometheus::Result<String> {
+    local::flush_all();
+    prometheus::TextEncoder::new().encode_to_string(&registry.gather())
+}
+
+/// Create a new registry whose metrics are all prefixed with the given prefix (and an underscore)
+/// when gathered.
+///
+/// The prefix stacks with the `scope` of the `prometric-derive` macro: library crates can define
+/// metrics with their own scope, and the application adds its own prefix by building them against
+/// a prefixed registry. Serve it along with the main registry with
+/// `ExporterBuilder::with_additional_registry`. Pass the prefix to the builders of the metrics
+/// structs with `with_registry_prefix` as well, for their `gather()` and `render()` to match the
+/// prefixed names.
+///
+/// # Panics
+/// Panics if the prefix is empty.
+///
+/// # Example
+/// ```rust
+/// use prometric::{Counter, gather_to_string, prefixed_registry};
+///
+/// let registry = prefixed_registry("myapp");
+///
+/// // E.g. a library metric with the `libp2p` scope
+/// let counter = Counter::<u64>::new(&registry, "libp2p_dials", "Dials.", &[], Default::default());
+/// counter.inc(&[]);
+///
+/// let output = gather_to_string(&registry).unwrap();
+/// assert!(output.contains("myapp_libp2p_dials 1"));
+/// ```
+pub fn prefixed_registry(prefix: impl Into<String>) -> prometheus::Registry {
+    prometheus::Registry::new_custom(Some(prefix.into()), None)
+        .expect("Failed to create prefixed registry")
+}
+
+/// The label names and environment variables of [`kubernetes_labels`], following the usual
+/// Downward API variable names of the pod spec.
+pub const KUBERNETES_ENV_LABELS: &[(&str, &str)] =
+    &[("pod", "POD_NAME"), ("namespace", "POD_NAMESPACE"), ("node", "NODE_NAME")];
+
+/// Returns a label for each of the given environment variables that is set and non-empty, named
+/// after the lowercase variable name, e.g. `hostname` for `HOSTNAME`.
+///
+/// Used by the `with_labels_from_env` method of the builders of `#[metrics]` structs, to add
+/// deployment metadata as static labels.
+///
+/// # Example
+/// ```rust
+/// // SAFETY: Nothing else accesses the environment in this example.
+/// unsafe { std::env::set_var("REGION", "eu-west-1") };
+///
+/// let labels = prometric::labels_from_env(&["REGION", "PROMETRIC_UNSET_VARIABLE"]);
+/// assert_eq!(labels, [("region".to_owned(), "eu-west-1".to_owned())]);
+/// ```
+pub fn labels_from_env(vars: &[&str]) -> Vec<(String, String)> {
+    vars.iter().filter_map(|var| label_from_env(&var.to_lowercase(), var)).collect()
+}
+
+/// Returns the `pod`, `namespace` and `node` labels of a Kubernetes pod from the environment
+/// variables of [`KUBERNETES_ENV_LABELS`], skipping the unset ones.
+///
+/// The variables must be exposed to the container with the Downward API, e.g. `POD_NAME` from
+/// `metadata.name`.
+pub fn kubernetes_labels() -> Vec<(String, String)> {
+    KUBERNETES_ENV_LABELS.iter().filter_map(|(key, var)| label_from_env(key, var)).collect()
+}
+
+fn label_from_env(key: &str, var: &str) -> Option<(String, String)> {
+    std::env::var(var).ok().filter(|value| !value.is_empty()).map(|value| (key.to_owned(), value))
+}
+
+/// The environment variable disabling the metrics of `#[metrics]` structs at runtime.
+pub const DISABLED_KEY: &str = "PROMETRIC_DISABLED";
+
+/// Returns `true` if the [`DISABLED_KEY`] environment variable is set to `1` or `true`, in which
+/// case `#[metrics]` structs are built with inert metrics, unless overridden with the `disabled`
+/// method of their builder. Nothing is registered, and the accessors do nothing.
+///
+/// This turns metrics off without code changes, e.g. in memory-constrained deployments.
+pub fn disabled_by_env() -> bool {
+    std::env::var(DISABLED_KEY)
+        .is_ok_and(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+}
+
+/// Join a prefix, e.g. the scope of a `#[metrics]` struct, and a metric name with an underscore.
+/// Empty segments, e.g. with `scope = ""`, are skipped.
+#[doc(hidden)]
+pub fn join_name(prefix: &str, name: &str) -> String {
+    match (prefix, name) {
+        ("", name) => name.to_owned(),
+        (prefix, "") => prefix.to_owned(),
+        (prefix, name) => format!("{prefix}_{name}"),
+    }
+}
+
+/// The registry and the metric names of a `#[metrics]` struct, to gather only the metrics of that
+/// struct. This lets library crates expose their metrics without owning the exporter.
+///
+/// Generated metrics structs hold a handle, used by their `gather()` and `render()` methods.
+///
+/// # Example
+/// ```rust
+/// use prometric::{Counter, MetricsHandle};
+///
+/// let registry = prometheus::Registry::new();
+/// let counter = Counter::<u64>::new(&registry, "lib_calls", "Calls.", &[], Default::default());
+/// let other = Counter::<u64>::new(&registry, "app_lib_calls", "Calls.", &[], Default::default());
+/// counter.inc(&[]);
+/// other.inc(&[]);
+///
+/// let handle = MetricsHandle::new(registry, None, vec!["lib_calls".to_owned()]);
+/// assert_eq!(handle.render(), "# HELP lib_calls Calls.\n# TYPE lib_calls counter\nlib_calls 1\n");
+/// ```
+#[derive(Clone)]
+pub struct MetricsHandle {
+    registry: prometheus::Registry,
+    /// The prefix of the registry, if it was created with [`prefixed_registry`].
+    prefix: Option<String>,
+    names: Vec<String>,
+}
+
+impl MetricsHandle {
+    /// Create a new handle to the metrics with the given full names in the registry, with the
+    /// prefix of the registry if it was created with [`prefixed_registry`].
+    pub fn new(registry: prometheus::Registry, prefix: Option<String>, names: Vec<String>) -> Self {
+        Self { registry, prefix, names }
+    }
+
+    /// Returns the registry of the metrics.
+    pub fn registry(&self) -> &prometheus::Registry {
+        &self.registry
+    }
+
+    /// Returns the full names of the metrics.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Returns the prefix of the registry, if it was created with [`prefixed_registry`].
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    /// Gather the metric families of the handle from the registry. Families are matched by their
+    /// exact name, prefixed with the prefix of the registry if any.
+    pub fn gather(&self) -> Vec<prometheus::proto::MetricFamily> {
+        let mut families = self.registry.gather();
+        families.retain(|family| {
+            let name = match &self.prefix {
+                Some(prefix) => {
+                    let Some(name) = family.name().strip_prefix(prefix.as_str()) else {
+                        return false;
+                    };
+                    let Some(name) = name.strip_prefix('_') else { return false };
+                    name
+                }
+                None => family.name(),
+            };
+            self.names.iter().any(|own| own == name)
+        });
+        families
+    }
+
+    /// Gather the metric families of the handle and encode them in the text format.
+    pub fn render(&self) -> String {
+        prometheus::TextEncoder::new()
+            .encode_to_string(&self.gather())
+            .expect("Failed to encode metrics")
+    }
+
+    /// Gather the current value of each series of the handle, keyed by name and labels, e.g.
+    /// `app_requests{method="GET"}`. Histograms and summaries have `_count` and `_sum` series.
+    pub fn values(&self) -> Vec<(String, f64)> {
+        let mut values = Vec::new();
+        for family in self.gather() {
+            for metric in family.get_metric() {
+                let labels = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| format!("{}=\"{}\"", label.name(), label.value()))
+                    .collect::<Vec<_>>();
+                let labels = if labels.is_empty() {
+                    String::new()
+                } else {
+                    format!("{{{}}}", labels.join(","))
+                };
+                let name = family.name();
+
+                match family.get_field_type() {
+                    prometheus::proto::MetricType::COUNTER => {
+                        values.push((format!("{name}{labels}"), metric.get_counter().value()));
+                    }
+                    prometheus::proto::MetricType::GAUGE => {
+                        values.push((format!("{name}{labels}"), metric.get_gauge().value()));
+                    }
+                    prometheus::proto::MetricType::HISTOGRAM => {
+                        let histogram = metric.get_histogram();
+                        values.push((
+                            format!("{name}_count{labels}"),
+                            histogram.get_sample_count() as f64,
+                        ));
+                        values.push((format!("{name}_sum{labels}"), histogram.get_sample_sum()));
+                    }
+                    prometheus::proto::MetricType::SUMMARY => {
+                        let summary = metric.get_summary();
+                        values
+                            .push((format!("{name}_count{labels}"), summary.sample_count() as f64));
+                        values.push((format!("{name}_sum{labels}"), summary.sample_sum()));
+                    }
+                    prometheus::proto::MetricType::UNTYPED => {
+                        values.push((format!("{name}{labels}"), metric.untyped.value()));
+                    }
+                }
+            }
+        }
+        values
+    }
+}
+
+impl std::fmt::Debug for MetricsHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MetricsHandle")
+            .field("prefix", &self.prefix)
+            .field("names", &self.names)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A type that can be bound to a label of a generated accessor with `labels = [name: Type]`,
+/// instead of free-form strings. This prevents typos and unbounded cardinality, as label values
+/// come from a closed set, usually the variants of an enum.
+///
+/// # Example
+/// ```rust
+/// use prometric::LabelValue;
+///
+/// enum HttpMethod {
+///     Get,
+///     Post,
+/// }
+///
+/// impl LabelValue for HttpMethod {
+///     fn label_value(&self) -> &'static str {
+///         match self {
+///             Self::Get => "GET",
+///             Self::Post => "POST",
+///         }
+///     }
+/// }
+/// ```
+pub trait LabelValue {
+    /// Returns the value of the label.
+    fn label_value(&self) -> &'static str;
+}
+
+impl LabelValue for bool {
+    fn label_value(&self) -> &'static str {
+        if *self { "true" } else { "false" }
+    }
+}
+
+/// The value of an untyped label passed to a generated accessor. Implemented for all types
+/// implementing [`std::fmt::Display`], so strings, integers, enums or `SocketAddr`s can be passed
+/// without `format!()`.
+///
+/// # Example
+/// ```rust
+/// use prometric::ToLabelValue;
+///
+/// let addr: std::net::SocketAddr = "127.0.0.1:8080".parse().unwrap();
+/// assert_eq!(addr.to_label_value(), "127.0.0.1:8080");
+/// assert_eq!(42u16.to_label_value(), "42");
+/// ```
+pub trait ToLabelValue {
+    /// Converts the value into the value of the label.
+    fn to_label_value(self) -> String;
+}
+
+impl<T: std::fmt::Display> ToLabelValue for T {
+    fn to_label_value(self) -> String {
+        self.to_string()
+    }
+}
+
+/// A set of labels shared by several metrics, used as `#[metric(labels = MyLabels)]` instead of
+/// positional labels. The generated accessor then takes the label set. Usually derived with
+/// `#[derive(prometric_derive::Labels)]`.
+///
+/// # Example
+/// ```rust
+/// use prometric::LabelSet;
+///
+/// struct RequestLabels {
+///     method: String,
+///     path: String,
+/// }
+///
+/// impl LabelSet for RequestLabels {
+///     const NAMES: &'static [&'static str] = &["method", "path"];
+///
+///     fn values(&self) -> impl AsRef<[&str]> {
+///         [self.method.as_str(), self.path.as_str()]
+///     }
+/// }
+/// ```
+pub trait LabelSet {
+    /// The names of the labels.
+    const NAMES: &'static [&'static str];
+
+    /// Returns the values of the labels, in the order of [`Self::NAMES`].
+    fn values(&self) -> impl AsRef<[&str]>;
+}
+
+/// A field of a [`LabelSet`] derived with `#[derive(prometric_derive::Labels)]`: a string, or a
+/// type implementing [`LabelValue`].
+pub trait AsLabelValue {
+    /// Returns the value of the label.
+    fn as_label_value(&self) -> &str;
+}
+
+impl AsLabelValue for String {
+    fn as_label_value(&self) -> &str {
+        self
+    }
+}
+
+impl AsLabelValue for &str {
+    fn as_label_value(&self) -> &str {
+        self
+    }
+}
+
+impl AsLabelValue for std::borrow::Cow<'_, str> {
+    fn as_label_value(&self) -> &str {
+        self
+    }
+}
+
+impl<T: LabelValue> AsLabelValue for T {
+    fn as_label_value(&self) -> &str {
+        self.label_value()
+    }
+}
+
+/// A metric type of a `#[metrics]` struct field declared with an explicit
+/// `#[metric(kind = "...")]`: one of the core metric types, or a newtype wrapping one. Type
+/// aliases of the core metric types work as is.
+///
+/// # Example
+/// ```rust
+/// use prometric::{AsMetric, Counter};
+///
+/// struct RequestCounter(Counter);
+///
+/// impl AsMetric for RequestCounter {
+///     type Metric = Counter;
+///
+///     fn from_metric(metric: Counter) -> Self {
+///         Self(metric)
+///     }
+///
+///     fn as_metric(&self) -> &Counter {
+///         &self.0
+///     }
+/// }
+/// ```
+pub trait AsMetric {
+    /// The wrapped core metric type.
+    type Metric;
+
+    /// Wrap the core metric.
+    fn from_metric(metric: Self::Metric) -> Self;
+
+    /// Returns the wrapped core metric.
+    fn as_metric(&self) -> &Self::Metric;
+}
+
+macro_rules! impl_as_metric {
+    ($(impl<$($generic:ident: $bound:path),*> for $ty:ty;)*) => {
+        $(
+            impl<$($generic: $bound),*> AsMetric for $ty {
+                type Metric = Self;
+
+                fn from_metric(metric: Self) -> Self {
+                    metric
+                }
+
+                fn as_metric(&self) -> &Self {
+                    self
+                }
+            }
+        )*
+    };
+}
+
+impl_as_metric! {
+    impl<N: CounterNumber> for Counter<N>;
+    impl<N: GaugeNumber> for Gauge<N>;
+    impl<> for Histogram;
+}
+
+#[cfg(feature = "summary")]
+impl_as_metric! {
+    impl<S: summary::traits::SummaryMetric> for Summary<S>;
+}
+
+/// The number type of a counter or gauge, used by the `prometric-derive` macro to resolve it from
+/// an [`AsMetric`] type.
+#[doc(hidden)]
+pub trait MetricNumber {
+    type Number;
+}
+
+impl<N: CounterNumber> MetricNumber for Counter<N> {
+    type Number = N;
+}
+
+impl<N: GaugeNumber> MetricNumber for Gauge<N> {
+    type Number = N;
+}
+
 /// Sealed trait to prevent outside code from implementing the metric types.
 mod private {
     pub trait Sealed {}
@@ -41,6 +606,7 @@ mod private {
     impl Sealed for u32 {}
     impl Sealed for usize {}
     impl Sealed for f32 {}
+    impl Sealed for std::time::Duration {}
 }
 
 /// Internal conversion trait to allow ergonomic value passing (e.g., `u32`, `usize`).
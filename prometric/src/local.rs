@@ -0,0 +1,229 @@
+//! Thread-local buffering of counter and histogram updates, used by metrics declared with
+//! `#[metric(local)]` in the `prometric-derive` macro.
+//!
+//! Updates to the shared atomics of a metric contend when many threads update it at once, e.g.
+//! per-event instrumentation on a large thread pool. Local updates are buffered per thread instead,
+//! and flushed to the shared metric:
+//! - Every [`FLUSH_INTERVAL`], by a background thread flushing the buffers of all threads, started
+//!   on first use (where threads are supported).
+//! - On the first update after [`FLUSH_INTERVAL`] has elapsed since the last flush of the thread.
+//! - On every scrape of the [`crate::exporter`], and by [`crate::gather_to_string`].
+//! - Explicitly, with [`flush`] or [`flush_all`].
+//! - When the thread exits.
+//!
+//! The buffers of a metric are evicted on the first flush after all of its clones are dropped.
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        Arc, Mutex, Once, TryLockError, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use prometheus::local::LocalMetric;
+
+/// The interval after which the buffered updates of all threads are flushed.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The source of the unique identifiers of the metrics, keying their buffers.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The identity of a metric in the thread-local buffers, shared by its clones. The buffers of the
+/// metric are evicted once it is dropped.
+#[derive(Debug, Clone)]
+pub(crate) struct LocalId(Arc<u64>);
+
+impl LocalId {
+    /// Returns a new unique metric identity.
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(NEXT_ID.fetch_add(1, Ordering::Relaxed)))
+    }
+}
+
+/// A buffered metric, erased to be stored along buffers of other types.
+trait Buffer: Send {
+    fn flush(&self);
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<L: LocalMetric + Send + 'static> Buffer for L {
+    fn flush(&self) {
+        LocalMetric::flush(self);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+struct Entry {
+    /// The identity of the metric, dead once the metric is dropped.
+    owner: Weak<u64>,
+    labels: Vec<String>,
+    buffer: Box<dyn Buffer>,
+}
+
+/// The buffers of a thread, keyed by metric identifier and hash of the label values.
+struct Buffers {
+    entries: HashMap<(u64, u64), Entry>,
+    last_flush: Instant,
+}
+
+impl Buffers {
+    fn flush(&mut self) {
+        for entry in self.entries.values() {
+            entry.buffer.flush();
+        }
+        self.entries.retain(|_, entry| entry.owner.strong_count() > 0);
+        self.last_flush = Instant::now();
+    }
+}
+
+impl Drop for Buffers {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// The buffers of all live threads, drained by [`flush_all`].
+static THREADS: Mutex<Vec<Weak<Mutex<Buffers>>>> = Mutex::new(Vec::new());
+
+/// Guards the start of the background flusher thread.
+static FLUSHER: Once = Once::new();
+
+/// Create the buffers of the current thread, registering them to be drained by [`flush_all`].
+fn register() -> Arc<Mutex<Buffers>> {
+    let buffers =
+        Arc::new(Mutex::new(Buffers { entries: HashMap::new(), last_flush: Instant::now() }));
+    THREADS.lock().unwrap_or_else(|e| e.into_inner()).push(Arc::downgrade(&buffers));
+
+    // Fails on targets without threads, where flushes happen on updates and scrapes only.
+    FLUSHER.call_once(|| {
+        let _ = std::thread::Builder::new().name("prometric-local-flush".to_owned()).spawn(|| {
+            loop {
+                std::thread::sleep(FLUSH_INTERVAL);
+                flush_all();
+            }
+        });
+    });
+
+    buffers
+}
+
+thread_local! {
+    static BUFFERS: Arc<Mutex<Buffers>> = register();
+}
+
+/// Apply `update` to the buffer of the metric with the given identity and label values on the
+/// current thread, creating it with `create` on first use.
+///
+/// Returns `false` if the update couldn't be buffered, e.g. during thread teardown, while the
+/// buffers are being flushed, or on a hash collision of the label values, in which case the caller
+/// updates the shared metric directly.
+pub(crate) fn update<L: LocalMetric + Send + 'static>(
+    id: &LocalId,
+    labels: &[&str],
+    create: impl FnOnce() -> L,
+    update: impl FnOnce(&L),
+) -> bool {
+    let mut hasher = DefaultHasher::new();
+    labels.hash(&mut hasher);
+    let key = (*id.0, hasher.finish());
+
+    BUFFERS
+        .try_with(|buffers| {
+            let mut buffers = match buffers.try_lock() {
+                Ok(buffers) => buffers,
+                Err(TryLockError::Poisoned(e)) => e.into_inner(),
+                Err(TryLockError::WouldBlock) => return false,
+            };
+            let entry = buffers.entries.entry(key).or_insert_with(|| Entry {
+                owner: Arc::downgrade(&id.0),
+                labels: labels.iter().map(|label| (*label).to_owned()).collect(),
+                buffer: Box::new(create()),
+            });
+            if entry.labels.iter().ne(labels.iter().copied()) {
+                return false;
+            }
+            let Some(buffer) = entry.buffer.as_any().downcast_ref::<L>() else { return false };
+            update(buffer);
+
+            if buffers.last_flush.elapsed() >= FLUSH_INTERVAL {
+                buffers.flush();
+            }
+            true
+        })
+        .unwrap_or(false)
+}
+
+/// Flush the buffered updates of the current thread to the shared metrics, e.g. at the end of a
+/// batch of work.
+pub fn flush() {
+    let _ = BUFFERS.try_with(|buffers| buffers.lock().unwrap_or_else(|e| e.into_inner()).flush());
+}
+
+/// Flush the buffered updates of all threads to the shared metrics, e.g. before gathering the
+/// metrics in tests.
+pub fn flush_all() {
+    let threads = {
+        let mut threads = THREADS.lock().unwrap_or_else(|e| e.into_inner());
+        threads.retain(|buffers| buffers.strong_count() > 0);
+        threads.iter().filter_map(Weak::upgrade).collect::<Vec<_>>()
+    };
+
+    for buffers in threads {
+        buffers.lock().unwrap_or_else(|e| e.into_inner()).flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Counter;
+
+    #[test]
+    fn test_flush_all_drains_idle_threads() {
+        let registry = prometheus::Registry::new();
+        let counter = Counter::<u64>::new(&registry, "events", "Events.", &[], Default::default());
+
+        // The thread stays alive and idle until its buffered update is exported.
+        let (done, wait) = std::sync::mpsc::channel::<()>();
+        let worker = {
+            let counter = counter.clone();
+            let (buffered, on_buffered) = std::sync::mpsc::channel();
+            let worker = std::thread::spawn(move || {
+                counter.local_inc(&[]);
+                buffered.send(()).unwrap();
+                wait.recv().unwrap();
+            });
+            on_buffered.recv().unwrap();
+            worker
+        };
+
+        assert!(crate::gather_to_string(&registry).unwrap().contains("events 1"));
+        done.send(()).unwrap();
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn test_flush_evicts_dropped_metrics() {
+        let counter = prometheus::IntCounter::new("evicted", "Evicted.").unwrap();
+        let id = super::LocalId::new();
+        let key = *id.0;
+        assert!(super::update(&id, &[], || counter.local(), |local| local.inc()));
+
+        let buffered = |key| {
+            super::BUFFERS
+                .with(|buffers| buffers.lock().unwrap().entries.keys().any(|(id, _)| *id == key))
+        };
+        assert!(buffered(key));
+        drop(id);
+        super::flush();
+        assert!(!buffered(key));
+        assert_eq!(counter.get(), 1);
+    }
+}
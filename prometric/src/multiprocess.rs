@@ -0,0 +1,428 @@
+//! Multi-process mode, for pre-forked or worker-pool architectures.
+//!
+//! Each worker process periodically writes the metrics of its registry to a file in a shared
+//! directory with a [`MultiProcessWriter`]. A single exporter then aggregates all the files at
+//! scrape time with a [`MultiProcessCollector`] (see
+//! [`crate::exporter::ExporterBuilder::with_multiprocess_dir`]).
+//!
+//! # Aggregation
+//! Series with the same name and labels are merged across processes:
+//! - Counters are summed.
+//! - Gauges (and untyped metrics) are aggregated according to the configured [`GaugeAggregation`].
+//!   By default, a `pid` label is added to keep a series per process.
+//! - Histograms are merged by summing the bucket counts, sample count and sample sum. Histograms
+//!   with different buckets are not merged, and only the first one is kept.
+//! - Summaries are merged by summing the sample count and sample sum. Quantiles can't be merged,
+//!   and are dropped.
+//!
+//! # Dead Processes
+//! The file of an exited worker is kept, so its counters keep contributing to the sums. Call
+//! [`mark_process_dead`] when a worker exits, e.g. from the hook of the process manager, to drop
+//! its gauges, which would otherwise be exported forever.
+//!
+//! # Durability
+//! Unlike the memory-mapped files of the Python client, workers write snapshots of their registry
+//! at an interval. Exported values lag by up to the interval, and the updates since the last write
+//! are lost when a worker crashes. Call [`MultiProcessWriter::write`] on graceful shutdown to
+//! flush the last updates.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use prometheus::{
+    Encoder as _, ProtobufEncoder, Registry,
+    proto::{LabelPair, Metric, MetricFamily, MetricType},
+};
+
+/// The extension of the files written by the [`MultiProcessWriter`].
+const FILE_EXTENSION: &str = "pb";
+
+/// The label added to gauges with [`GaugeAggregation::PerProcess`].
+const PID_LABEL: &str = "pid";
+
+/// Writes the metrics of a worker process to a file in the shared multi-process directory.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use prometric::multiprocess::MultiProcessWriter;
+///
+/// let dir = std::env::temp_dir().join("prometric-multiprocess-example");
+/// std::fs::create_dir_all(&dir).unwrap();
+///
+/// // In each worker process, write the metrics of the default registry every second.
+/// MultiProcessWriter::new(&dir, prometheus::default_registry().clone())
+///     .install(Duration::from_secs(1));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MultiProcessWriter {
+    path: PathBuf,
+    registry: Registry,
+}
+
+impl MultiProcessWriter {
+    /// Create a new writer for the current process, writing the metrics of the given registry to
+    /// a file in `dir`.
+    pub fn new(dir: impl AsRef<Path>, registry: Registry) -> Self {
+        let path = dir.as_ref().join(format!("{}.{FILE_EXTENSION}", std::process::id()));
+        Self { path, registry }
+    }
+
+    /// Returns the path of the file written by this writer.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write the current metrics to the file. The file is replaced atomically, so the collector
+    /// never reads a partially written file.
+    pub fn write(&self) -> io::Result<()> {
+        let mut buf = Vec::new();
+        ProtobufEncoder::new()
+            .encode(&self.registry.gather(), &mut buf)
+            .map_err(io::Error::other)?;
+
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, buf)?;
+        fs::rename(tmp, &self.path)
+    }
+
+    /// Write the metrics at the given interval on a background thread.
+    pub fn install(self, interval: Duration) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            loop {
+                // Writing is best-effort, the next interval will try again.
+                let _ = self.write();
+                thread::sleep(interval);
+            }
+        })
+    }
+}
+
+/// How gauges (and untyped metrics) are aggregated across processes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GaugeAggregation {
+    /// Keep a series per process, adding a `pid` label.
+    #[default]
+    PerProcess,
+    /// Sum the values of all processes.
+    Sum,
+    /// Keep the maximum value of all processes.
+    Max,
+    /// Keep the minimum value of all processes.
+    Min,
+}
+
+/// Aggregates the metrics written by [`MultiProcessWriter`]s at scrape time.
+#[derive(Debug, Clone)]
+pub struct MultiProcessCollector {
+    dir: PathBuf,
+    gauge_aggregation: GaugeAggregation,
+}
+
+impl MultiProcessCollector {
+    /// Create a new collector reading the files in the given directory.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), gauge_aggregation: GaugeAggregation::default() }
+    }
+
+    /// Set how gauges are aggregated across processes.
+    pub fn with_gauge_aggregation(mut self, gauge_aggregation: GaugeAggregation) -> Self {
+        self.gauge_aggregation = gauge_aggregation;
+        self
+    }
+
+    /// Read and aggregate the metrics of all processes.
+    pub fn gather(&self) -> io::Result<Vec<MetricFamily>> {
+        self.gather_with(Vec::new())
+    }
+
+    /// Read and aggregate the metrics of all processes, together with the given metrics of the
+    /// current process.
+    pub fn gather_with(&self, local: Vec<MetricFamily>) -> io::Result<Vec<MetricFamily>> {
+        let mut aggregator = Aggregator::new(self.gauge_aggregation);
+
+        for entry in fs::read_dir(&self.dir)? {
+            // Files can disappear between listing and reading them, e.g. when a worker exits.
+            let Ok(entry) = entry else { continue };
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != FILE_EXTENSION) {
+                continue;
+            }
+
+            let Some(pid) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+
+            // The file of the current process is superseded by the local metrics.
+            if pid == std::process::id().to_string() {
+                continue;
+            }
+
+            // Skip files that can't be read or decoded instead of failing the whole scrape.
+            let Ok(buf) = fs::read(&path) else { continue };
+            let Ok(families) = decode(&buf) else { continue };
+
+            aggregator.add(pid, families);
+        }
+
+        aggregator.add(&std::process::id().to_string(), local);
+
+        Ok(aggregator.finish())
+    }
+}
+
+/// Drop the gauges (and untyped metrics) of the exited worker process with the given pid from the
+/// multi-process directory, keeping its counters, histograms and summaries so the aggregated sums
+/// don't decrease. Does nothing if the process didn't write any metrics.
+///
+/// # Example
+/// ```rust
+/// use prometric::multiprocess::mark_process_dead;
+///
+/// let dir = std::env::temp_dir().join("prometric-multiprocess-dead-example");
+/// std::fs::create_dir_all(&dir).unwrap();
+///
+/// // E.g. when the process manager reaps a worker.
+/// mark_process_dead(&dir, 4242).unwrap();
+/// ```
+pub fn mark_process_dead(dir: impl AsRef<Path>, pid: u32) -> io::Result<()> {
+    let path = dir.as_ref().join(format!("{pid}.{FILE_EXTENSION}"));
+    let buf = match fs::read(&path) {
+        Ok(buf) => buf,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    // An undecodable file is skipped by the collector anyway.
+    let mut families = decode(&buf).unwrap_or_default();
+    families.retain(|family| {
+        !matches!(family.get_field_type(), MetricType::GAUGE | MetricType::UNTYPED)
+    });
+    if families.is_empty() {
+        return fs::remove_file(&path);
+    }
+
+    let mut buf = Vec::new();
+    ProtobufEncoder::new().encode(&families, &mut buf).map_err(io::Error::other)?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, buf)?;
+    fs::rename(tmp, path)
+}
+
+/// Decode length-delimited metric families, as written by the [`ProtobufEncoder`].
+fn decode(buf: &[u8]) -> protobuf::Result<Vec<MetricFamily>> {
+    let mut input = protobuf::CodedInputStream::from_bytes(buf);
+    let mut families = Vec::new();
+    while !input.eof()? {
+        families.push(input.read_message()?);
+    }
+
+    Ok(families)
+}
+
+/// The index of the metrics of a family, by label pairs.
+type MetricIndex = HashMap<Vec<(String, String)>, usize>;
+
+/// Merges metric families of multiple processes.
+struct Aggregator {
+    gauge_aggregation: GaugeAggregation,
+    families: BTreeMap<String, (MetricFamily, MetricIndex)>,
+}
+
+impl Aggregator {
+    fn new(gauge_aggregation: GaugeAggregation) -> Self {
+        Self { gauge_aggregation, families: BTreeMap::new() }
+    }
+
+    fn add(&mut self, pid: &str, families: Vec<MetricFamily>) {
+        for mut family in families {
+            let metrics = family.take_metric();
+            let (merged, index) = self
+                .families
+                .entry(family.name().to_owned())
+                .or_insert_with(|| (family.clone(), HashMap::new()));
+
+            // Metrics with the same name but a different type can't be merged.
+            if merged.get_field_type() != family.get_field_type() {
+                continue;
+            }
+
+            let ty = family.get_field_type();
+            let per_process = matches!(ty, MetricType::GAUGE | MetricType::UNTYPED) &&
+                self.gauge_aggregation == GaugeAggregation::PerProcess;
+
+            for mut metric in metrics {
+                if per_process {
+                    let mut label = LabelPair::default();
+                    label.set_name(PID_LABEL.to_owned());
+                    label.set_value(pid.to_owned());
+                    metric.label.push(label);
+                    metric.label.sort();
+                }
+
+                let key = metric
+                    .label
+                    .iter()
+                    .map(|label| (label.name().to_owned(), label.value().to_owned()))
+                    .collect::<Vec<_>>();
+
+                match index.get(&key) {
+                    Some(&i) => {
+                        merge(ty, self.gauge_aggregation, &mut merged.mut_metric()[i], metric)
+                    }
+                    None => {
+                        if ty == MetricType::SUMMARY {
+                            metric.summary.mut_or_insert_default().quantile.clear();
+                        }
+
+                        index.insert(key, merged.metric.len());
+                        merged.mut_metric().push(metric);
+                    }
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> Vec<MetricFamily> {
+        self.families
+            .into_values()
+            .map(|(family, _)| family)
+            .filter(|family| !family.metric.is_empty())
+            .collect()
+    }
+}
+
+/// Merge `other` into `into`. Both metrics are of the given type, and have the same labels.
+fn merge(ty: MetricType, gauge_aggregation: GaugeAggregation, into: &mut Metric, other: Metric) {
+    match ty {
+        MetricType::COUNTER => {
+            let counter = into.counter.mut_or_insert_default();
+            counter.set_value(counter.value() + other.get_counter().value());
+        }
+        MetricType::GAUGE => {
+            let gauge = into.gauge.mut_or_insert_default();
+            gauge.set_value(aggregate(gauge_aggregation, gauge.value(), other.get_gauge().value()));
+        }
+        MetricType::UNTYPED => {
+            let untyped = into.untyped.mut_or_insert_default();
+            untyped.set_value(aggregate(gauge_aggregation, untyped.value(), other.untyped.value()));
+        }
+        MetricType::HISTOGRAM => {
+            let histogram = into.histogram.mut_or_insert_default();
+            let other = other.get_histogram();
+
+            let same_buckets = histogram.bucket.len() == other.bucket.len() &&
+                histogram
+                    .bucket
+                    .iter()
+                    .zip(&other.bucket)
+                    .all(|(a, b)| a.upper_bound() == b.upper_bound());
+            if !same_buckets {
+                return;
+            }
+
+            for (bucket, other) in histogram.bucket.iter_mut().zip(&other.bucket) {
+                bucket.set_cumulative_count(bucket.cumulative_count() + other.cumulative_count());
+            }
+            histogram.set_sample_count(histogram.sample_count() + other.sample_count());
+            histogram.set_sample_sum(histogram.sample_sum() + other.sample_sum());
+        }
+        MetricType::SUMMARY => {
+            let summary = into.summary.mut_or_insert_default();
+            let other = other.get_summary();
+
+            summary.set_sample_count(summary.sample_count() + other.sample_count());
+            summary.set_sample_sum(summary.sample_sum() + other.sample_sum());
+        }
+    }
+}
+
+fn aggregate(gauge_aggregation: GaugeAggregation, a: f64, b: f64) -> f64 {
+    match gauge_aggregation {
+        // With `PerProcess`, series only collide within the same process.
+        GaugeAggregation::PerProcess | GaugeAggregation::Sum => a + b,
+        GaugeAggregation::Max => a.max(b),
+        GaugeAggregation::Min => a.min(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::{CounterVec, Gauge, Histogram, HistogramOpts, Opts};
+
+    use super::*;
+
+    /// Write the metrics of a fake worker process with the given pid to `dir`.
+    fn write_worker(dir: &Path, pid: u32, requests: u64, in_flight: f64, latency: f64) {
+        let registry = Registry::new();
+
+        let counter =
+            CounterVec::new(Opts::new("requests_total", "Requests."), &["method"]).unwrap();
+        let gauge = Gauge::new("in_flight", "In-flight requests.").unwrap();
+        let histogram =
+            Histogram::with_opts(HistogramOpts::new("latency", "Latency.").buckets(vec![1.0]))
+                .unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        registry.register(Box::new(gauge.clone())).unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+
+        counter.with_label_values(&["GET"]).inc_by(requests as f64);
+        gauge.set(in_flight);
+        histogram.observe(latency);
+
+        let writer = MultiProcessWriter { path: dir.join(format!("{pid}.pb")), registry };
+        writer.write().unwrap();
+    }
+
+    fn render(families: &[MetricFamily]) -> String {
+        prometheus::TextEncoder::new().encode_to_string(families).unwrap()
+    }
+
+    #[test]
+    fn test_multiprocess_aggregation() {
+        let dir = std::env::temp_dir().join(format!("prometric-mp-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        write_worker(&dir, 1, 2, 3.0, 0.5);
+        write_worker(&dir, 2, 5, 1.0, 2.0);
+        // Garbage and unreadable files are skipped.
+        fs::write(dir.join("3.pb"), b"garbage").unwrap();
+        fs::write(dir.join("README"), b"not a metrics file").unwrap();
+        fs::create_dir(dir.join("4.pb")).unwrap();
+
+        let collector = MultiProcessCollector::new(&dir);
+        let body = render(&collector.gather().unwrap());
+        println!("{body}");
+
+        assert!(body.contains(r#"requests_total{method="GET"} 7"#));
+        assert!(body.contains(r#"in_flight{pid="1"} 3"#));
+        assert!(body.contains(r#"in_flight{pid="2"} 1"#));
+        assert!(body.contains(r#"latency_bucket{le="1"} 1"#));
+        assert!(body.contains(r#"latency_bucket{le="+Inf"} 2"#));
+        assert!(body.contains("latency_sum 2.5"));
+        assert!(body.contains("latency_count 2"));
+
+        let collector = collector.with_gauge_aggregation(GaugeAggregation::Max);
+        let body = render(&collector.gather().unwrap());
+        assert!(body.contains("in_flight 3"));
+
+        // The gauges of dead processes are dropped, their counters are kept.
+        mark_process_dead(&dir, 1).unwrap();
+        mark_process_dead(&dir, 5).unwrap();
+        let collector = collector.with_gauge_aggregation(GaugeAggregation::PerProcess);
+        let body = render(&collector.gather().unwrap());
+        assert!(!body.contains(r#"in_flight{pid="1"}"#));
+        assert!(body.contains(r#"in_flight{pid="2"} 1"#));
+        assert!(body.contains(r#"requests_total{method="GET"} 7"#));
+        assert!(body.contains("latency_count 2"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
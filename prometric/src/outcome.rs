@@ -0,0 +1,219 @@
+//! Outcome counters, counting operations by an implicit `outcome` label, `success` or `failure`.
+//! Used by `OutcomeCounter` fields of the `prometric-derive` macro.
+//!
+//! An outcome counter named `rpc_calls` with the `method` label registers the `rpc_calls` counter
+//! with the `method` and `outcome` labels, the `outcome` label being last.
+//!
+//! # Example
+//! ```rust
+//! use prometric::{Outcome, OutcomeCounter};
+//!
+//! let registry = prometheus::Registry::new();
+//! let calls =
+//!     OutcomeCounter::new(&registry, "rpc_calls", "RPC calls.", &["method"], Default::default());
+//! calls.success(&["eth_call"]);
+//! calls.record(&["eth_call"], &Err::<(), _>("timeout"));
+//! assert_eq!(calls.get(&["eth_call"], Outcome::Success), 1);
+//!
+//! let output = prometric::gather_to_string(&registry).unwrap();
+//! assert!(output.contains(r#"rpc_calls{method="eth_call",outcome="success"} 1"#));
+//! assert!(output.contains(r#"rpc_calls{method="eth_call",outcome="failure"} 1"#));
+//! ```
+
+use std::collections::HashMap;
+
+#[cfg(feature = "recording")]
+use crate::recording;
+use crate::{Counter, CounterHandle};
+
+/// The name of the label of the outcome.
+pub const OUTCOME_LABEL: &str = "outcome";
+
+/// The outcome of an operation, the value of the `outcome` label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    /// The operation succeeded.
+    Success,
+    /// The operation failed.
+    Failure,
+}
+
+impl Outcome {
+    /// Returns the outcome of the given result.
+    pub fn of<T, E>(result: &Result<T, E>) -> Self {
+        if result.is_ok() { Self::Success } else { Self::Failure }
+    }
+
+    /// Returns the value of the `outcome` label.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+        }
+    }
+}
+
+/// A counter with an implicit `outcome` label. See [`crate::outcome`].
+#[derive(Debug, Clone)]
+pub struct OutcomeCounter {
+    inner: Counter<u64>,
+}
+
+/// Collects the series of the counter.
+impl prometheus::core::Collector for OutcomeCounter {
+    fn desc(&self) -> Vec<&prometheus::core::Desc> {
+        self.inner.desc()
+    }
+
+    fn collect(&self) -> Vec<prometheus::proto::MetricFamily> {
+        self.inner.collect()
+    }
+}
+
+impl OutcomeCounter {
+    /// Create a new outcome counter with the given registry, name, help, labels, and const labels.
+    /// The `outcome` label is appended to the labels.
+    pub fn new(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+    ) -> Self {
+        let labels = [labels, &[OUTCOME_LABEL]].concat();
+        Self { inner: Counter::new(registry, name, help, &labels, const_labels) }
+    }
+
+    /// Count an operation with the given outcome.
+    pub fn inc(&self, labels: &[&str], outcome: Outcome) {
+        self.inner.inc(&with_outcome(labels, outcome));
+    }
+
+    /// Count a successful operation.
+    pub fn success(&self, labels: &[&str]) {
+        self.inc(labels, Outcome::Success);
+    }
+
+    /// Count a failed operation.
+    pub fn failure(&self, labels: &[&str]) {
+        self.inc(labels, Outcome::Failure);
+    }
+
+    /// Count an operation with the outcome of the given result.
+    pub fn record<T, E>(&self, labels: &[&str], result: &Result<T, E>) {
+        self.inc(labels, Outcome::of(result));
+    }
+
+    /// Returns the number of operations with the given label values and outcome.
+    pub fn get(&self, labels: &[&str], outcome: Outcome) -> u64 {
+        self.inner.get(&with_outcome(labels, outcome))
+    }
+
+    /// Returns the inner counter, with the `outcome` label last.
+    pub fn counter(&self) -> &Counter<u64> {
+        &self.inner
+    }
+
+    /// Remove the series of both outcomes with the given label values. Returns `false` if there is
+    /// no such series.
+    pub fn remove(&self, labels: &[&str]) -> bool {
+        let success = self.inner.remove(&with_outcome(labels, Outcome::Success));
+        let failure = self.inner.remove(&with_outcome(labels, Outcome::Failure));
+        success || failure
+    }
+
+    /// Unregister the counter from the given registry.
+    pub fn unregister(&self, registry: &prometheus::Registry) {
+        self.inner.unregister(registry);
+    }
+
+    /// Record the operations on the counter into the given recorder. See [`crate::recording`].
+    #[cfg(feature = "recording")]
+    pub fn record_into(&self, recorder: &recording::Recorder) {
+        self.inner.record_into(recorder);
+    }
+
+    /// Remove all the series of the counter.
+    pub fn clear(&self) {
+        self.inner.clear();
+    }
+
+    /// Resolve the series of both outcomes with the given label values once, returning a handle
+    /// updating them without the label lookup.
+    pub fn handle(&self, labels: &[&str]) -> OutcomeCounterHandle {
+        OutcomeCounterHandle {
+            success: self.inner.handle(&with_outcome(labels, Outcome::Success)),
+            failure: self.inner.handle(&with_outcome(labels, Outcome::Failure)),
+        }
+    }
+}
+
+/// Returns the label values with the `outcome` label value appended.
+fn with_outcome<'a>(labels: &[&'a str], outcome: Outcome) -> Vec<&'a str> {
+    [labels, &[outcome.as_str()]].concat()
+}
+
+/// A handle to the series of an [`OutcomeCounter`] with resolved label values, returned by
+/// [`OutcomeCounter::handle`].
+#[derive(Debug)]
+pub struct OutcomeCounterHandle {
+    success: CounterHandle<u64>,
+    failure: CounterHandle<u64>,
+}
+
+impl OutcomeCounterHandle {
+    /// Create a handle updating nothing, e.g. when metrics are disabled.
+    pub fn noop() -> Self {
+        Self { success: CounterHandle::noop(), failure: CounterHandle::noop() }
+    }
+
+    /// Count an operation with the given outcome.
+    pub fn inc(&self, outcome: Outcome) {
+        match outcome {
+            Outcome::Success => self.success.inc(),
+            Outcome::Failure => self.failure.inc(),
+        }
+    }
+
+    /// Count a successful operation.
+    pub fn success(&self) {
+        self.success.inc();
+    }
+
+    /// Count a failed operation.
+    pub fn failure(&self) {
+        self.failure.inc();
+    }
+
+    /// Count an operation with the outcome of the given result.
+    pub fn record<T, E>(&self, result: &Result<T, E>) {
+        self.inc(Outcome::of(result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outcome_counter() {
+        let registry = prometheus::Registry::new();
+        let calls =
+            OutcomeCounter::new(&registry, "test_calls", "Calls.", &["method"], Default::default());
+        calls.success(&["a"]);
+        calls.record(&["a"], &Ok::<_, ()>(1));
+        calls.failure(&["b"]);
+        calls.handle(&["b"]).record(&Err::<(), _>("timeout"));
+
+        assert_eq!(calls.get(&["a"], Outcome::Success), 2);
+        assert_eq!(calls.get(&["a"], Outcome::Failure), 0);
+        assert_eq!(calls.get(&["b"], Outcome::Failure), 2);
+        let output = crate::gather_to_string(&registry).unwrap();
+        assert!(output.contains(r#"test_calls{method="b",outcome="failure"} 2"#));
+
+        assert!(calls.remove(&["a"]));
+        assert!(!calls.remove(&["c"]));
+        calls.unregister(&registry);
+        assert!(registry.gather().is_empty());
+    }
+}
@@ -1,11 +1,16 @@
+use std::collections::HashMap;
+
 use prometheus::{
     Gauge, GaugeVec, Opts, Registry,
-    core::{AtomicU64, GenericGauge},
+    core::{AtomicU64, GenericGauge, GenericGaugeVec},
 };
+use regex::Regex;
 use sysinfo::{CpuRefreshKind, MemoryRefreshKind, Pid, ProcessRefreshKind, RefreshKind, System};
 
 type UintGauge = GenericGauge<AtomicU64>;
 
+type UintGaugeVec = GenericGaugeVec<AtomicU64>;
+
 type UintCounter = GenericGauge<AtomicU64>;
 
 /// A collector for process (and some system) metrics.
@@ -31,6 +36,7 @@ pub struct ProcessCollector {
     specifics: RefreshKind,
     sys: System,
     cores: u64,
+    thread_grouping: Option<ThreadGrouping>,
 
     metrics: ProcessMetrics,
 }
@@ -64,7 +70,30 @@ impl ProcessCollector {
         let cores = sys.cpus().len() as u64;
         let metrics = ProcessMetrics::new(registry);
 
-        Self { specifics, sys, cores, metrics }
+        // Host information doesn't change over the lifetime of the process, so it's only
+        // collected once.
+        let unknown = || "unknown".to_owned();
+        let hostname = System::host_name().unwrap_or_else(unknown);
+        let os_name = System::name().unwrap_or_else(unknown);
+        let os_version = System::os_version().unwrap_or_else(unknown);
+        let kernel_version = System::kernel_version().unwrap_or_else(unknown);
+        let arch = System::cpu_arch();
+        metrics
+            .system_info
+            .with_label_values(&[&hostname, &os_name, &os_version, &kernel_version, &arch])
+            .set(1);
+
+        Self { specifics, sys, cores, thread_grouping: None, metrics }
+    }
+
+    /// Aggregate the per-thread CPU usage by thread group instead of exporting a series per
+    /// thread, to keep the cardinality under control for thread pools.
+    ///
+    /// When enabled, the `process_thread_group_usage` metric is exported instead of
+    /// `process_thread_usage`. See [`ThreadGrouping`] for how threads are grouped.
+    pub fn with_thread_grouping(mut self, grouping: ThreadGrouping) -> Self {
+        self.thread_grouping = Some(grouping);
+        self
     }
 
     /// Get the PID of the current process.
@@ -93,7 +122,10 @@ impl ProcessCollector {
         // Collect thread stats and reset the vector each scrape so exited threads do not
         // leave stale PID-labelled series behind.
         self.metrics.thread_usage.reset();
+        self.metrics.thread_group_usage.reset();
         if let Some(tasks) = process.tasks() {
+            let mut group_usage = HashMap::<String, f64>::new();
+
             tasks.iter().for_each(|pid| {
                 let Some(thread) = self.sys.process(*pid) else {
                     return;
@@ -102,11 +134,21 @@ impl ProcessCollector {
                 let pid = pid.to_string();
                 let name = thread.name().to_str().unwrap_or(pid.as_str());
 
+                if let Some(grouping) = &self.thread_grouping {
+                    *group_usage.entry(grouping.group(name)).or_default() +=
+                        thread.cpu_usage() as f64;
+                    return;
+                }
+
                 self.metrics
                     .thread_usage
                     .with_label_values(&[pid.as_str(), name])
                     .set(thread.cpu_usage() as f64);
             });
+
+            for (group, usage) in group_usage {
+                self.metrics.thread_group_usage.with_label_values(&[group.as_str()]).set(usage);
+            }
         }
 
         let threads = process.tasks().map(|tasks| tasks.len()).unwrap_or(0);
@@ -136,9 +178,71 @@ impl ProcessCollector {
     }
 }
 
+/// Rules used to aggregate threads into groups, by thread name.
+///
+/// Each thread name is matched against the configured rules in order, and the first match
+/// determines the group. The group name may reference capture groups of the pattern (e.g. `$1`).
+/// Threads that don't match any rule are grouped by their normalized name, i.e. with any trailing
+/// digits and separators removed (`rayon-worker-12` becomes `rayon-worker`).
+///
+/// # Example
+/// ```rust
+/// use prometric::process::{ProcessCollector, ThreadGrouping};
+///
+/// let grouping = ThreadGrouping::new()
+///     // Linux truncates thread names to 15 characters
+///     .with_group(r"^tokio-runtime-w", "tokio-runtime-worker")
+///     .unwrap()
+///     .with_group(r"^(db|io)-pool-", "$1-pool")
+///     .unwrap();
+///
+/// let mut collector = ProcessCollector::default().with_thread_grouping(grouping);
+/// collector.collect();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ThreadGrouping {
+    rules: Vec<(Regex, String)>,
+}
+
+impl ThreadGrouping {
+    /// Create a new `ThreadGrouping` without any rules, which groups threads by normalized name.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule grouping all threads whose name matches `pattern` under `group`.
+    ///
+    /// Returns an error if the pattern is not a valid regular expression.
+    pub fn with_group(
+        mut self,
+        pattern: &str,
+        group: impl Into<String>,
+    ) -> Result<Self, regex::Error> {
+        self.rules.push((Regex::new(pattern)?, group.into()));
+        Ok(self)
+    }
+
+    /// Returns the group of the thread with the given name.
+    pub fn group(&self, name: &str) -> String {
+        for (pattern, group) in &self.rules {
+            if let Some(captures) = pattern.captures(name) {
+                let mut expanded = String::new();
+                captures.expand(group, &mut expanded);
+                return expanded;
+            }
+        }
+
+        let normalized = name
+            .trim_end_matches(|c: char| c.is_ascii_digit() || matches!(c, '-' | '_' | '#' | ' '));
+        if normalized.is_empty() { name.to_owned() } else { normalized.to_owned() }
+    }
+}
+
 /// A collection of metrics for a process, with some useful system metrics.
 pub struct ProcessMetrics {
     // System metrics
+    /// Information about the host, exposed as labels with a constant value of 1.
+    system_info: UintGaugeVec,
     /// The number of logical CPU cores available in the system.
     system_cores: UintGauge,
     /// The maximum CPU frequency of all cores in MHz.
@@ -169,6 +273,8 @@ pub struct ProcessMetrics {
     disk_written_bytes: UintCounter,
     /// The statistics of the threads used by the process (Linux only).
     thread_usage: GaugeVec,
+    /// The statistics of the threads used by the process, aggregated by thread group (Linux only).
+    thread_group_usage: GaugeVec,
 
     /// The duration of the associated collection routine in seconds.
     collection_duration: Gauge,
@@ -176,6 +282,14 @@ pub struct ProcessMetrics {
 
 impl ProcessMetrics {
     pub fn new(registry: &prometheus::Registry) -> Self {
+        let system_info = UintGaugeVec::new(
+            Opts::new(
+                "system_info",
+                "Information about the host, exposed as labels with a constant value of 1.",
+            ),
+            &["hostname", "os_name", "os_version", "kernel_version", "arch"],
+        )
+        .unwrap();
         let system_cores = UintGauge::new(
             "system_cpu_cores",
             "The number of logical CPU cores available in the system.",
@@ -242,6 +356,14 @@ impl ProcessMetrics {
             &["pid", "name"],
         )
         .unwrap();
+        let thread_group_usage: GaugeVec = GaugeVec::new(
+            Opts::new(
+                "process_thread_group_usage",
+                "Per-thread-group CPU usage as a percentage of the process's CPU usage (Linux only).",
+            ),
+            &["group"],
+        )
+        .unwrap();
 
         let collection_duration = Gauge::new(
             "process_collection_duration_seconds",
@@ -250,6 +372,7 @@ impl ProcessMetrics {
         .unwrap();
 
         // Register all metrics with the registry
+        registry.register(Box::new(system_info.clone())).unwrap();
         registry.register(Box::new(system_cores.clone())).unwrap();
         registry.register(Box::new(system_max_cpu_freq.clone())).unwrap();
         registry.register(Box::new(system_min_cpu_freq.clone())).unwrap();
@@ -265,10 +388,12 @@ impl ProcessMetrics {
         registry.register(Box::new(max_fds.clone())).unwrap();
         registry.register(Box::new(disk_written_bytes.clone())).unwrap();
         registry.register(Box::new(thread_usage.clone())).unwrap();
+        registry.register(Box::new(thread_group_usage.clone())).unwrap();
 
         registry.register(Box::new(collection_duration.clone())).unwrap();
 
         Self {
+            system_info,
             system_cores,
             system_max_cpu_freq,
             system_min_cpu_freq,
@@ -283,6 +408,7 @@ impl ProcessMetrics {
             max_fds,
             disk_written_bytes,
             thread_usage,
+            thread_group_usage,
             collection_duration,
         }
     }
@@ -345,8 +471,35 @@ mod tests {
         let encoder = prometheus::TextEncoder::new();
         let body = encoder.encode_to_string(&metrics).unwrap();
         println!("{}", body);
+        assert!(body.contains("system_info{"));
 
         handle.join().unwrap();
         handle2.join().unwrap();
     }
+
+    #[test]
+    fn test_thread_grouping() {
+        let grouping = ThreadGrouping::new()
+            .with_group(r"^tokio-runtime-w", "tokio-runtime-worker")
+            .unwrap()
+            .with_group(r"^(db|io)-pool-", "$1-pool")
+            .unwrap();
+
+        assert_eq!(grouping.group("tokio-runtime-w"), "tokio-runtime-worker");
+        assert_eq!(grouping.group("db-pool-3"), "db-pool");
+        assert_eq!(grouping.group("io-pool-12"), "io-pool");
+        assert_eq!(grouping.group("rayon-worker-12"), "rayon-worker");
+        assert_eq!(grouping.group("main"), "main");
+        assert_eq!(grouping.group("1234"), "1234");
+
+        let registry = Registry::new();
+        let mut collector = ProcessCollector::new(&registry).with_thread_grouping(grouping);
+        collector.collect();
+
+        let metrics = registry.gather();
+        let encoder = prometheus::TextEncoder::new();
+        let body = encoder.encode_to_string(&metrics).unwrap();
+
+        assert!(!body.contains("process_thread_usage{"));
+    }
 }
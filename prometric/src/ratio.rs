@@ -0,0 +1,234 @@
+//! Ratios of two counters, computed when gathered, used by `Ratio` fields of the
+//! `prometric-derive` macro with `#[metric(ratio = (numerator, denominator))]`.
+//!
+//! A ratio is exported as a gauge with one series per label set of the numerator, divided by the
+//! series of the denominator with the same labels, e.g. a cache hit ratio per cache. Series whose
+//! denominator is missing or zero are skipped.
+//!
+//! # Example
+//! ```rust
+//! use prometric::{Counter, Ratio};
+//!
+//! let registry = prometheus::Registry::new();
+//! let hits =
+//!     Counter::<u64>::new(&registry, "cache_hits", "Hits.", &["cache"], Default::default());
+//! let lookups =
+//!     Counter::<u64>::new(&registry, "cache_lookups", "Lookups.", &["cache"], Default::default());
+//! let _ratio = Ratio::new(&registry, "cache_hit_ratio", "Hit ratio.", &hits, &lookups);
+//!
+//! hits.inc(&["blocks"]);
+//! lookups.inc_by(&["blocks"], 4);
+//!
+//! let output = prometric::gather_to_string(&registry).unwrap();
+//! assert!(output.contains(r#"cache_hit_ratio{cache="blocks"} 0.25"#));
+//! ```
+
+use std::{collections::HashMap, sync::Arc};
+
+use prometheus::{
+    core::{Collector, Desc},
+    proto::{Gauge, LabelPair, Metric, MetricFamily, MetricType},
+};
+
+use crate::{Counter, CounterNumber};
+
+/// Computes the ratio of two counters when collected.
+#[derive(Clone)]
+struct RatioCollector {
+    numerator: Arc<dyn Collector>,
+    denominator: Arc<dyn Collector>,
+    name: String,
+    help: String,
+    desc: Vec<Desc>,
+}
+
+impl Collector for RatioCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.desc.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let denominators = self
+            .denominator
+            .collect()
+            .iter()
+            .flat_map(|family| family.get_metric())
+            .map(|metric| (label_pairs(metric.get_label()), metric.get_counter().value()))
+            .collect::<HashMap<_, _>>();
+
+        let metrics = self
+            .numerator
+            .collect()
+            .iter()
+            .flat_map(|family| family.get_metric())
+            .filter_map(|metric| {
+                let denominator = *denominators.get(&label_pairs(metric.get_label()))?;
+                if denominator == 0.0 {
+                    return None;
+                }
+                let mut gauge = Gauge::default();
+                gauge.set_value(metric.get_counter().value() / denominator);
+                let mut ratio = Metric::default();
+                ratio.set_label(metric.get_label().to_vec());
+                ratio.set_gauge(gauge);
+                Some(ratio)
+            })
+            .collect::<Vec<_>>();
+        if metrics.is_empty() {
+            return Vec::new();
+        }
+
+        let mut family = MetricFamily::default();
+        family.set_name(self.name.clone());
+        family.set_help(self.help.clone());
+        family.set_field_type(MetricType::GAUGE);
+        family.metric = metrics;
+        vec![family]
+    }
+}
+
+/// Returns the sorted name and value pairs of the labels, identifying a series.
+fn label_pairs(labels: &[LabelPair]) -> Vec<(String, String)> {
+    let mut pairs = labels
+        .iter()
+        .map(|label| (label.name().to_owned(), label.value().to_owned()))
+        .collect::<Vec<_>>();
+    pairs.sort();
+    pairs
+}
+
+/// A gauge computed as the ratio of two counters when gathered. See [`crate::ratio`].
+#[derive(Clone)]
+pub struct Ratio {
+    collector: RatioCollector,
+}
+
+impl std::fmt::Debug for Ratio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ratio").field("name", &self.collector.name).finish_non_exhaustive()
+    }
+}
+
+/// Collects the series of the ratio, reading its counters.
+impl Collector for Ratio {
+    fn desc(&self) -> Vec<&Desc> {
+        self.collector.desc()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        self.collector.collect()
+    }
+}
+
+impl Ratio {
+    /// Create a new ratio of the given counters, registered in the given registry. It has the
+    /// labels of the numerator, overwriting a previous registration of the same name.
+    pub fn new<N: CounterNumber>(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        numerator: &Counter<N>,
+        denominator: &Counter<N>,
+    ) -> Self {
+        let numerator = numerator.collector();
+        let numerator_desc = &numerator.desc()[0];
+        let const_labels = numerator_desc
+            .const_label_pairs
+            .iter()
+            .map(|pair| (pair.name().to_owned(), pair.value().to_owned()))
+            .collect();
+        let desc = Desc::new(
+            name.to_owned(),
+            help.to_owned(),
+            numerator_desc.variable_labels.clone(),
+            const_labels,
+        )
+        .unwrap_or_else(|e| panic!("Invalid ratio {name}: {e}"));
+
+        let collector = RatioCollector {
+            numerator: Arc::new(numerator),
+            denominator: Arc::new(denominator.collector()),
+            name: name.to_owned(),
+            help: help.to_owned(),
+            desc: vec![desc],
+        };
+
+        let boxed = Box::new(collector.clone());
+        if let Err(e) = registry.register(boxed.clone()) {
+            // If the ratio is already registered, overwrite it.
+            if matches!(e, prometheus::Error::AlreadyReg) {
+                registry
+                    .unregister(boxed.clone())
+                    .unwrap_or_else(|_| panic!("Failed to unregister ratio {name}"));
+                registry
+                    .register(boxed)
+                    .unwrap_or_else(|_| panic!("Failed to overwrite ratio {name}"));
+            } else {
+                panic!("Failed to register ratio {name}: {e}");
+            }
+        }
+
+        Self { collector }
+    }
+
+    /// Returns the current ratio of the series with the given label pairs, or `None` if the
+    /// denominator is missing or zero.
+    pub fn value(&self, labels: &[(&str, &str)]) -> Option<f64> {
+        let mut expected = labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        expected.sort();
+        self.collector
+            .collect()
+            .iter()
+            .flat_map(|family| family.get_metric())
+            .find(|metric| {
+                let mut pairs = label_pairs(metric.get_label());
+                pairs.retain(|pair| expected.iter().any(|(name, _)| *name == pair.0));
+                pairs == expected
+            })
+            .map(|metric| metric.get_gauge().value())
+    }
+
+    /// Unregister the ratio from the given registry. The counters stay registered.
+    pub fn unregister(&self, registry: &prometheus::Registry) {
+        let _ = registry.unregister(Box::new(self.collector.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ratio() {
+        let registry = prometheus::Registry::new();
+        let hits =
+            Counter::<u64>::new(&registry, "test_hits", "Hits.", &["cache"], Default::default());
+        let lookups = Counter::<u64>::new(
+            &registry,
+            "test_lookups",
+            "Lookups.",
+            &["cache"],
+            Default::default(),
+        );
+        let ratio = Ratio::new(&registry, "test_hit_ratio", "Hit ratio.", &hits, &lookups);
+
+        hits.inc_by(&["a"], 3);
+        lookups.inc_by(&["a"], 4);
+        hits.inc(&["b"]);
+        lookups.inc(&["c"]);
+        assert_eq!(ratio.value(&[("cache", "a")]), Some(0.75));
+        // Series without a denominator are skipped.
+        assert_eq!(ratio.value(&[("cache", "b")]), None);
+
+        let output = crate::gather_to_string(&registry).unwrap();
+        assert!(output.contains("# TYPE test_hit_ratio gauge"));
+        assert!(output.contains(r#"test_hit_ratio{cache="a"} 0.75"#));
+        assert!(!output.contains(r#"test_hit_ratio{cache="c"}"#));
+
+        ratio.unregister(&registry);
+        assert!(!crate::gather_to_string(&registry).unwrap().contains("test_hit_ratio"));
+    }
+}
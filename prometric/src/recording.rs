@@ -0,0 +1,561 @@
+//! Record-and-replay of metric operations, for deterministic golden tests of instrumentation.
+//!
+//! While a [`Recorder`] is started, every operation on a [`Counter`], [`Gauge`], [`Histogram`] or
+//! [`Summary`](crate::Summary) (and thus every call on a generated accessor) is captured into an
+//! inspectable log, along with the definition of the metric. The log can then be replayed against
+//! a fresh registry, re-creating the metrics and applying the same operations.
+//!
+//! NOTE: The recorder is process-wide. Operations from all threads (and all registries) are
+//! captured while it is started. Values are recorded as `f64`, so integers above 2^53 lose
+//! precision.
+//!
+//! Metrics can also record into a recorder of their own with `record_into`, whether it is started
+//! or not, e.g. the metrics of a struct built with the generated `mock()` constructor of
+//! `#[metrics(test_support)]`.
+
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    fmt,
+    sync::{
+        Arc, Mutex, OnceLock, RwLock, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use prometheus::Registry;
+
+use crate::{Counter, Gauge, Histogram, descriptor::MetricKind};
+
+/// Whether a recorder is started. Checked before taking the lock, to keep operations cheap when
+/// not recording.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The currently started recorder, if any.
+static ACTIVE: RwLock<Option<Recorder>> = RwLock::new(None);
+
+/// The number type of a counter or gauge, used to re-create the same metric on replay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueType {
+    U64,
+    I64,
+    F64,
+}
+
+impl ValueType {
+    fn of<N: 'static>() -> Self {
+        if TypeId::of::<N>() == TypeId::of::<u64>() {
+            Self::U64
+        } else if TypeId::of::<N>() == TypeId::of::<i64>() {
+            Self::I64
+        } else {
+            Self::F64
+        }
+    }
+}
+
+/// The definition of a recorded metric, as passed to its constructor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricDefinition {
+    /// The full name of the metric.
+    pub name: String,
+    /// The type of the metric.
+    pub kind: MetricKind,
+    /// The help string of the metric.
+    pub help: String,
+    /// The label names of the metric.
+    pub labels: Vec<String>,
+    /// The const labels of the metric.
+    pub const_labels: HashMap<String, String>,
+    /// The buckets of a histogram, or the quantiles of a summary.
+    pub partitions: Option<Vec<f64>>,
+    value_type: ValueType,
+    sink: Sink,
+}
+
+/// The recorder a metric records into besides the started one, set with `record_into`. Weak, as
+/// the recorded operations hold the definition of the metric. Not part of the definition itself.
+#[derive(Clone, Default)]
+struct Sink(OnceLock<Weak<Mutex<Vec<Operation>>>>);
+
+impl PartialEq for Sink {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl fmt::Debug for Sink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Sink")
+    }
+}
+
+impl MetricDefinition {
+    pub(crate) fn new<N: 'static>(
+        kind: MetricKind,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: &HashMap<String, String>,
+        partitions: Option<&[f64]>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            name: name.to_owned(),
+            kind,
+            help: help.to_owned(),
+            labels: labels.iter().map(|label| (*label).to_owned()).collect(),
+            const_labels: const_labels.clone(),
+            partitions: partitions.map(<[f64]>::to_vec),
+            value_type: ValueType::of::<N>(),
+            sink: Sink::default(),
+        })
+    }
+
+    /// Record the operations on the metric into the given recorder, unless it already records into
+    /// another one.
+    pub(crate) fn record_into(&self, recorder: &Recorder) {
+        let _ = self.sink.0.set(Arc::downgrade(&recorder.operations));
+    }
+}
+
+/// An operation on a metric.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricOp {
+    Inc,
+    IncBy(f64),
+    Dec,
+    Add(f64),
+    Sub(f64),
+    Set(f64),
+    Reset,
+    Observe(f64),
+    Remove,
+    /// Remove all the children of the metric, recorded without label values.
+    Clear,
+}
+
+impl fmt::Display for MetricOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Inc => write!(f, "inc"),
+            Self::IncBy(value) => write!(f, "inc_by {value}"),
+            Self::Dec => write!(f, "dec"),
+            Self::Add(value) => write!(f, "add {value}"),
+            Self::Sub(value) => write!(f, "sub {value}"),
+            Self::Set(value) => write!(f, "set {value}"),
+            Self::Reset => write!(f, "reset"),
+            Self::Observe(value) => write!(f, "observe {value}"),
+            Self::Remove => write!(f, "remove"),
+            Self::Clear => write!(f, "clear"),
+        }
+    }
+}
+
+/// A single recorded operation.
+///
+/// The [`Display`](fmt::Display) implementation renders a stable, human-readable line, e.g.
+/// `app_requests{method="GET"} inc_by 2`, suitable for golden files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation {
+    /// The definition of the metric the operation was applied to.
+    pub metric: Arc<MetricDefinition>,
+    /// The label values of the operation, in the order of [`MetricDefinition::labels`].
+    pub labels: Vec<String>,
+    /// The operation.
+    pub op: MetricOp,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.metric.name)?;
+        if !self.labels.is_empty() {
+            let labels = self
+                .metric
+                .labels
+                .iter()
+                .zip(&self.labels)
+                .map(|(name, value)| format!("{name}={value:?}"))
+                .collect::<Vec<_>>();
+            write!(f, "{{{}}}", labels.join(","))?;
+        }
+        write!(f, " {}", self.op)
+    }
+}
+
+/// Records metric operations while started. Cheap to clone.
+///
+/// # Example
+/// ```rust
+/// use prometheus::Registry;
+/// use prometric::{Counter, recording::Recorder};
+///
+/// let recorder = Recorder::new();
+/// let registry = Registry::new();
+/// let counter =
+///     Counter::<u64>::new(&registry, "requests", "Requests.", &["method"], Default::default());
+///
+/// let guard = recorder.start();
+/// counter.inc_by(&["GET"], 2);
+/// drop(guard);
+///
+/// let log = recorder.operations().iter().map(ToString::to_string).collect::<Vec<_>>();
+/// assert_eq!(log, [r#"requests{method="GET"} inc_by 2"#]);
+///
+/// // Replay the operations against a fresh registry
+/// let fresh = Registry::new();
+/// recorder.replay(&fresh);
+/// assert_eq!(
+///     prometric::gather_to_string(&fresh).unwrap(),
+///     prometric::gather_to_string(&registry).unwrap()
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    operations: Arc<Mutex<Vec<Operation>>>,
+}
+
+impl Recorder {
+    /// Create a new, empty recorder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start recording operations. Recording stops when the returned guard is dropped, restoring
+    /// the previously started recorder, if any.
+    #[must_use = "Recording stops when the guard is dropped"]
+    pub fn start(&self) -> RecordingGuard {
+        let previous = ACTIVE.write().unwrap().replace(self.clone());
+        ENABLED.store(true, Ordering::Release);
+        RecordingGuard { previous }
+    }
+
+    /// Returns the operations recorded so far.
+    pub fn operations(&self) -> Vec<Operation> {
+        self.operations.lock().unwrap().clone()
+    }
+
+    /// Returns the operations recorded so far, rendered as lines, e.g.
+    /// `app_errors{kind="timeout"} inc`.
+    pub fn log(&self) -> Vec<String> {
+        self.operations.lock().unwrap().iter().map(ToString::to_string).collect()
+    }
+
+    /// Clear the recorded operations.
+    pub fn clear(&self) {
+        self.operations.lock().unwrap().clear();
+    }
+
+    /// Replay the recorded operations against the given registry, re-creating the metrics from
+    /// their definitions.
+    ///
+    /// NOTE: Summary operations are skipped if the `summary` feature is disabled.
+    pub fn replay(&self, registry: &Registry) {
+        let mut metrics = HashMap::<String, Option<ReplayMetric>>::new();
+
+        for operation in self.operations().iter() {
+            let definition = &operation.metric;
+            let Some(metric) = metrics
+                .entry(definition.name.clone())
+                .or_insert_with(|| ReplayMetric::new(registry, definition))
+            else {
+                continue;
+            };
+
+            let labels = operation.labels.iter().map(String::as_str).collect::<Vec<_>>();
+            metric.apply(&labels, operation.op);
+        }
+    }
+
+    fn push(&self, operation: Operation) {
+        self.operations.lock().unwrap().push(operation);
+    }
+}
+
+/// Stops recording when dropped. Returned by [`Recorder::start`].
+#[derive(Debug)]
+pub struct RecordingGuard {
+    previous: Option<Recorder>,
+}
+
+impl Drop for RecordingGuard {
+    fn drop(&mut self) {
+        let mut active = ACTIVE.write().unwrap();
+        *active = self.previous.take();
+        ENABLED.store(active.is_some(), Ordering::Release);
+    }
+}
+
+/// Record an operation with the recorder of the metric and the started recorder, if any.
+pub(crate) fn record(metric: &Arc<MetricDefinition>, labels: &[&str], op: MetricOp) {
+    let operation = || Operation {
+        metric: metric.clone(),
+        labels: labels.iter().map(|label| (*label).to_owned()).collect(),
+        op,
+    };
+
+    let sink = metric.sink.0.get().and_then(Weak::upgrade);
+    if let Some(sink) = &sink {
+        sink.lock().unwrap().push(operation());
+    }
+
+    if !ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+
+    if let Some(recorder) = ACTIVE.read().unwrap().as_ref() {
+        // Don't record the operation twice into the same recorder.
+        if sink.is_none_or(|sink| !Arc::ptr_eq(&sink, &recorder.operations)) {
+            recorder.push(operation());
+        }
+    }
+}
+
+/// The definition and label values of a metric handle, to record its operations.
+#[derive(Debug, Clone)]
+pub(crate) struct HandleRecording {
+    definition: Arc<MetricDefinition>,
+    labels: Vec<String>,
+}
+
+impl HandleRecording {
+    pub(crate) fn new(definition: &Arc<MetricDefinition>, labels: &[&str]) -> Self {
+        Self {
+            definition: definition.clone(),
+            labels: labels.iter().map(|label| (*label).to_owned()).collect(),
+        }
+    }
+
+    /// Record an operation on the handle with the started recorder, if any.
+    pub(crate) fn record(&self, op: MetricOp) {
+        if !ENABLED.load(Ordering::Acquire) && self.definition.sink.0.get().is_none() {
+            return;
+        }
+
+        let labels = self.labels.iter().map(String::as_str).collect::<Vec<_>>();
+        record(&self.definition, &labels, op);
+    }
+}
+
+/// A metric re-created from its definition during replay.
+enum ReplayMetric {
+    CounterU64(Counter<u64>),
+    CounterF64(Counter<f64>),
+    GaugeU64(Gauge<u64>),
+    GaugeI64(Gauge<i64>),
+    GaugeF64(Gauge<f64>),
+    Histogram(Histogram),
+    #[cfg(feature = "summary")]
+    Summary(crate::Summary),
+}
+
+impl ReplayMetric {
+    /// Re-create the metric from its definition. Returns `None` if the metric type isn't
+    /// available.
+    fn new(registry: &Registry, definition: &MetricDefinition) -> Option<Self> {
+        let MetricDefinition { name, help, const_labels, partitions, .. } = definition;
+        let labels = definition.labels.iter().map(String::as_str).collect::<Vec<_>>();
+        let const_labels = const_labels.clone();
+
+        let metric = match (definition.kind, definition.value_type) {
+            (MetricKind::Counter, ValueType::F64) => {
+                Self::CounterF64(Counter::new(registry, name, help, &labels, const_labels))
+            }
+            (MetricKind::Counter, _) => {
+                Self::CounterU64(Counter::new(registry, name, help, &labels, const_labels))
+            }
+            (MetricKind::Gauge, ValueType::U64) => {
+                Self::GaugeU64(Gauge::new(registry, name, help, &labels, const_labels))
+            }
+            (MetricKind::Gauge, ValueType::I64) => {
+                Self::GaugeI64(Gauge::new(registry, name, help, &labels, const_labels))
+            }
+            (MetricKind::Gauge, ValueType::F64) => {
+                Self::GaugeF64(Gauge::new(registry, name, help, &labels, const_labels))
+            }
+            (MetricKind::Histogram, _) => Self::Histogram(Histogram::new(
+                registry,
+                name,
+                help,
+                &labels,
+                const_labels,
+                partitions.clone(),
+            )),
+            #[cfg(feature = "summary")]
+            (MetricKind::Summary, _) => Self::Summary(crate::Summary::new(
+                registry,
+                name,
+                help,
+                &labels,
+                const_labels,
+                partitions.clone(),
+            )),
+            #[cfg(not(feature = "summary"))]
+            (MetricKind::Summary, _) => return None,
+        };
+
+        Some(metric)
+    }
+
+    fn apply(&self, labels: &[&str], op: MetricOp) {
+        match (self, op) {
+            (Self::CounterU64(counter), MetricOp::Inc) => counter.inc(labels),
+            (Self::CounterU64(counter), MetricOp::IncBy(value)) => {
+                counter.inc_by(labels, value as u64)
+            }
+            (Self::CounterU64(counter), MetricOp::Reset) => counter.reset(labels),
+            (Self::CounterU64(counter), MetricOp::Remove) => {
+                counter.remove(labels);
+            }
+            (Self::CounterU64(counter), MetricOp::Clear) => counter.clear(),
+            (Self::CounterF64(counter), MetricOp::Inc) => counter.inc(labels),
+            (Self::CounterF64(counter), MetricOp::IncBy(value)) => counter.inc_by(labels, value),
+            (Self::CounterF64(counter), MetricOp::Reset) => counter.reset(labels),
+            (Self::CounterF64(counter), MetricOp::Remove) => {
+                counter.remove(labels);
+            }
+            (Self::CounterF64(counter), MetricOp::Clear) => counter.clear(),
+            (Self::GaugeU64(gauge), op) => apply_gauge(gauge, labels, op, |value| value as u64),
+            (Self::GaugeI64(gauge), op) => apply_gauge(gauge, labels, op, |value| value as i64),
+            (Self::GaugeF64(gauge), op) => apply_gauge(gauge, labels, op, |value| value),
+            (Self::Histogram(histogram), MetricOp::Observe(value)) => {
+                histogram.observe(labels, value)
+            }
+            (Self::Histogram(histogram), MetricOp::Remove) => {
+                histogram.remove(labels);
+            }
+            (Self::Histogram(histogram), MetricOp::Clear) => histogram.clear(),
+            #[cfg(feature = "summary")]
+            (Self::Summary(summary), MetricOp::Observe(value)) => summary.observe(labels, value),
+            #[cfg(feature = "summary")]
+            (Self::Summary(summary), MetricOp::Remove) => {
+                summary.remove(labels);
+            }
+            #[cfg(feature = "summary")]
+            (Self::Summary(summary), MetricOp::Clear) => summary.clear(),
+            _ => {}
+        }
+    }
+}
+
+fn apply_gauge<N: crate::GaugeNumber>(
+    gauge: &Gauge<N>,
+    labels: &[&str],
+    op: MetricOp,
+    convert: impl Fn(f64) -> N,
+) {
+    match op {
+        MetricOp::Inc => gauge.inc(labels),
+        MetricOp::Dec => gauge.dec(labels),
+        MetricOp::Add(value) => gauge.add(labels, convert(value)),
+        MetricOp::Sub(value) => gauge.sub(labels, convert(value)),
+        MetricOp::Set(value) => gauge.set(labels, convert(value)),
+        MetricOp::Remove => {
+            gauge.remove(labels);
+        }
+        MetricOp::Clear => gauge.clear(),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_replay() {
+        let recorder = Recorder::new();
+        let registry = Registry::new();
+
+        let counter = Counter::<u64>::new(
+            &registry,
+            "recording_requests",
+            "Requests.",
+            &["method"],
+            Default::default(),
+        );
+        let gauge =
+            Gauge::<i64>::new(&registry, "recording_balance", "Balance.", &[], Default::default());
+        let histogram = Histogram::new(
+            &registry,
+            "recording_latency",
+            "Latency.",
+            &[],
+            Default::default(),
+            Some(vec![1.0, 2.0]),
+        );
+
+        // Not recorded
+        counter.inc(&["HEAD"]);
+
+        let guard = recorder.start();
+        counter.inc(&["GET"]);
+        counter.inc_by(&["POST"], 3);
+        gauge.set(&[], -5);
+        gauge.add(&[], 2);
+        histogram.observe(&[], 1.5);
+        drop(guard);
+
+        // Not recorded
+        counter.inc(&["HEAD"]);
+
+        // Other tests might run concurrently, only keep the operations of this test.
+        let log = recorder
+            .operations()
+            .iter()
+            .filter(|operation| operation.metric.name.starts_with("recording_"))
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            log,
+            [
+                r#"recording_requests{method="GET"} inc"#,
+                r#"recording_requests{method="POST"} inc_by 3"#,
+                "recording_balance set -5",
+                "recording_balance add 2",
+                "recording_latency observe 1.5",
+            ]
+        );
+
+        let fresh = Registry::new();
+        recorder.replay(&fresh);
+
+        let output = crate::gather_to_string(&fresh).unwrap();
+        println!("{output}");
+        assert!(output.contains(r#"recording_requests{method="GET"} 1"#));
+        assert!(output.contains(r#"recording_requests{method="POST"} 3"#));
+        assert!(!output.contains("HEAD"));
+        assert!(output.contains("recording_balance -3"));
+        assert!(output.contains(r#"recording_latency_bucket{le="2"} 1"#));
+    }
+
+    #[test]
+    fn test_record_into() {
+        let recorder = Recorder::new();
+        let registry = Registry::new();
+        let counter = Counter::<u64>::new(
+            &registry,
+            "record_into_errors",
+            "Errors.",
+            &["kind"],
+            Default::default(),
+        );
+
+        counter.record_into(&recorder);
+        counter.inc(&["timeout"]);
+        counter.handle(&["io"]).inc_by(2);
+
+        // Recorded once while also started.
+        let guard = recorder.start();
+        counter.inc(&["timeout"]);
+        drop(guard);
+
+        assert_eq!(
+            recorder.log(),
+            [
+                r#"record_into_errors{kind="timeout"} inc"#,
+                r#"record_into_errors{kind="io"} inc_by 2"#,
+                r#"record_into_errors{kind="timeout"} inc"#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! Prometheus alerting and recording rule scaffolding, generated from metric definitions so that
+//! renamed metrics can never silently orphan their alerts.
+//!
+//! Alert rules are usually generated by the `#[metric(alert(...))]` attribute of the
+//! `prometric-derive` macro, through the `alert_rules()` function on the metrics struct. Recording
+//! rules are generated from [`MetricDescriptor`]s.
+
+use std::fmt::Write as _;
+
+use crate::descriptor::{MetricDescriptor, MetricKind};
+
+/// The default range of `rate` expressions.
+pub const DEFAULT_RATE_RANGE: &str = "5m";
+
+/// The condition of an alert.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertCondition {
+    /// Fires when the per-second rate of the metric is greater than the threshold.
+    RateGt(f64),
+    /// Fires when the per-second rate of the metric is less than the threshold.
+    RateLt(f64),
+    /// Fires when the value of the metric is greater than the threshold.
+    ValueGt(f64),
+    /// Fires when the value of the metric is less than the threshold.
+    ValueLt(f64),
+}
+
+impl AlertCondition {
+    /// Returns the suffix of the alert name for this condition.
+    fn suffix(&self) -> &'static str {
+        match self {
+            Self::RateGt(_) => "RateHigh",
+            Self::RateLt(_) => "RateLow",
+            Self::ValueGt(_) => "High",
+            Self::ValueLt(_) => "Low",
+        }
+    }
+}
+
+/// An alert on a single metric.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    /// The full name of the metric.
+    pub metric: String,
+    /// The type of the metric.
+    pub kind: MetricKind,
+    /// The condition of the alert.
+    pub condition: AlertCondition,
+    /// How long the condition must hold before the alert fires, e.g. `5m`.
+    pub for_duration: Option<String>,
+}
+
+impl AlertRule {
+    /// Create a new alert rule.
+    pub fn new(metric: impl Into<String>, kind: MetricKind, condition: AlertCondition) -> Self {
+        Self { metric: metric.into(), kind, condition, for_duration: None }
+    }
+
+    /// Set how long the condition must hold before the alert fires.
+    pub fn with_for(mut self, duration: impl Into<String>) -> Self {
+        self.for_duration = Some(duration.into());
+        self
+    }
+
+    /// Returns the name of the alert, the PascalCase metric name followed by the condition.
+    pub fn alert_name(&self, namespace: Option<&str>) -> String {
+        let name = self.metric_name(namespace);
+        let mut alert = name
+            .split('_')
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let mut chars = part.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            })
+            .collect::<String>();
+        alert.push_str(self.condition.suffix());
+        alert
+    }
+
+    /// Returns the PromQL expression of the alert.
+    pub fn expr(&self, namespace: Option<&str>) -> String {
+        let name = self.metric_name(namespace);
+        // The rate of histograms and summaries is the rate of observations.
+        let series = match self.kind {
+            MetricKind::Histogram | MetricKind::Summary => format!("{name}_count"),
+            MetricKind::Counter | MetricKind::Gauge => name,
+        };
+
+        match self.condition {
+            AlertCondition::RateGt(threshold) => {
+                format!("rate({series}[{DEFAULT_RATE_RANGE}]) > {threshold}")
+            }
+            AlertCondition::RateLt(threshold) => {
+                format!("rate({series}[{DEFAULT_RATE_RANGE}]) < {threshold}")
+            }
+            AlertCondition::ValueGt(threshold) => format!("{series} > {threshold}"),
+            AlertCondition::ValueLt(threshold) => format!("{series} < {threshold}"),
+        }
+    }
+
+    fn metric_name(&self, namespace: Option<&str>) -> String {
+        match namespace {
+            Some(namespace) => format!("{namespace}_{}", self.metric),
+            None => self.metric.clone(),
+        }
+    }
+}
+
+/// A builder for a Prometheus rule file, containing a single group of alerting and recording
+/// rules.
+///
+/// The generated rules are stubs: summaries, severities and thresholds are meant to be reviewed
+/// before deploying.
+///
+/// # Example
+/// ```rust
+/// use prometric::{
+///     descriptor::MetricKind,
+///     rules::{AlertCondition, AlertRule, RuleFileBuilder},
+/// };
+///
+/// let rules = RuleFileBuilder::new("app")
+///     .with_alert(AlertRule::new("app_errors", MetricKind::Counter, AlertCondition::RateGt(5.0)))
+///     .render();
+///
+/// assert!(rules.contains("alert: AppErrorsRateHigh"));
+/// assert!(rules.contains("expr: rate(app_errors[5m]) > 5"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RuleFileBuilder {
+    group: String,
+    namespace: Option<String>,
+    alerts: Vec<AlertRule>,
+    recordings: Vec<MetricDescriptor>,
+}
+
+impl RuleFileBuilder {
+    /// Create a new builder for a rule file with a single group of the given name.
+    pub fn new(group: impl Into<String>) -> Self {
+        Self { group: group.into(), namespace: None, alerts: Vec::new(), recordings: Vec::new() }
+    }
+
+    /// Set the global namespace of the metrics, as configured with
+    /// `ExporterBuilder::with_namespace`. This will be prepended to all metric names.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// Add an alerting rule.
+    pub fn with_alert(mut self, alert: AlertRule) -> Self {
+        self.alerts.push(alert);
+        self
+    }
+
+    /// Add multiple alerting rules, e.g. those returned by the generated `alert_rules()`.
+    pub fn with_alerts(mut self, alerts: impl IntoIterator<Item = AlertRule>) -> Self {
+        self.alerts.extend(alerts);
+        self
+    }
+
+    /// Add recording rules for the given metrics: the rate of counters, and the 99th percentile
+    /// of histograms. Gauges and summaries are skipped.
+    pub fn with_recordings(
+        mut self,
+        descriptors: impl IntoIterator<Item = MetricDescriptor>,
+    ) -> Self {
+        self.recordings.extend(descriptors);
+        self
+    }
+
+    /// Render the rule file as YAML.
+    pub fn render(&self) -> String {
+        let namespace = self.namespace.as_deref();
+        let mut yaml = String::new();
+
+        writeln!(yaml, "groups:").unwrap();
+        writeln!(yaml, "  - name: {}", quote(&self.group)).unwrap();
+        writeln!(yaml, "    rules:").unwrap();
+
+        for descriptor in &self.recordings {
+            let name = match namespace {
+                Some(namespace) => format!("{namespace}_{}", descriptor.name),
+                None => descriptor.name.clone(),
+            };
+
+            let (record, expr) = match descriptor.kind {
+                MetricKind::Counter => (
+                    format!("{name}:rate{DEFAULT_RATE_RANGE}"),
+                    format!("rate({name}[{DEFAULT_RATE_RANGE}])"),
+                ),
+                MetricKind::Histogram => (
+                    format!("{name}:p99"),
+                    format!(
+                        "histogram_quantile(0.99, sum by (le) (rate({name}_bucket[{DEFAULT_RATE_RANGE}])))"
+                    ),
+                ),
+                MetricKind::Gauge | MetricKind::Summary => continue,
+            };
+
+            writeln!(yaml, "      - record: {record}").unwrap();
+            writeln!(yaml, "        expr: {expr}").unwrap();
+        }
+
+        for alert in &self.alerts {
+            writeln!(yaml, "      - alert: {}", alert.alert_name(namespace)).unwrap();
+            writeln!(yaml, "        expr: {}", alert.expr(namespace)).unwrap();
+            if let Some(duration) = &alert.for_duration {
+                writeln!(yaml, "        for: {duration}").unwrap();
+            }
+            writeln!(yaml, "        labels:").unwrap();
+            writeln!(yaml, "          severity: warning").unwrap();
+            writeln!(yaml, "        annotations:").unwrap();
+            let summary = format!("{} is firing", alert.alert_name(namespace));
+            writeln!(yaml, "          summary: {}", quote(&summary)).unwrap();
+        }
+
+        yaml
+    }
+
+    /// Write the rule file to the given path. Useful in build scripts.
+    pub fn write_to(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.render())
+    }
+}
+
+/// Quote a string as a YAML double-quoted scalar.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_file() {
+        let rules = RuleFileBuilder::new("app")
+            .with_namespace("exporter")
+            .with_alert(
+                AlertRule::new("app_errors", MetricKind::Counter, AlertCondition::RateGt(5.0))
+                    .with_for("10m"),
+            )
+            .with_alert(AlertRule::new(
+                "app_latency",
+                MetricKind::Histogram,
+                AlertCondition::RateLt(0.5),
+            ))
+            .with_alert(AlertRule::new(
+                "app_users",
+                MetricKind::Gauge,
+                AlertCondition::ValueGt(100.0),
+            ))
+            .with_recordings([
+                MetricDescriptor::new("app_errors", MetricKind::Counter, "Errors.", ["kind"]),
+                MetricDescriptor::new("app_users", MetricKind::Gauge, "Users.", [] as [&str; 0]),
+            ])
+            .render();
+        println!("{rules}");
+
+        assert!(rules.contains("- record: exporter_app_errors:rate5m\n"));
+        assert!(rules.contains("expr: rate(exporter_app_errors[5m])\n"));
+        assert!(!rules.contains("record: exporter_app_users"));
+
+        assert!(rules.contains("- alert: ExporterAppErrorsRateHigh\n"));
+        assert!(rules.contains("expr: rate(exporter_app_errors[5m]) > 5\n        for: 10m\n"));
+        assert!(rules.contains("expr: rate(exporter_app_latency_count[5m]) < 0.5\n"));
+        assert!(rules.contains("- alert: ExporterAppUsersHigh\n"));
+        assert!(rules.contains("expr: exporter_app_users > 100\n"));
+    }
+}
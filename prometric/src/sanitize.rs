@@ -0,0 +1,113 @@
+//! Sanitization of untrusted label values, e.g. peer-supplied strings, used by the accessors of
+//! structs declared with `#[metrics(sanitize = ...)]` in the `prometric-derive` macro.
+//!
+//! A label value is invalid if it contains control characters such as newlines, or the U+FFFD
+//! replacement character left by the lossy decoding of invalid UTF-8 or unpaired surrogates, or if
+//! it's longer than the maximum length, in bytes. Invalid values are escaped, replaced or rejected,
+//! depending on the [`SanitizePolicy`]. Valid values are kept as is, without allocating.
+//!
+//! # Example
+//! ```rust
+//! use prometric::sanitize::{LabelSanitizer, SanitizePolicy};
+//!
+//! let sanitizer = LabelSanitizer::new(SanitizePolicy::Escape).with_max_len(8);
+//! assert_eq!(sanitizer.sanitize("peer\n1".to_owned()).as_deref(), Some(r"peer\n1"));
+//! assert_eq!(sanitizer.sanitize("peer-123456".to_owned()).as_deref(), Some("peer-123"));
+//!
+//! let sanitizer = LabelSanitizer::new(SanitizePolicy::Reject);
+//! assert_eq!(sanitizer.sanitize("peer\n1".to_owned()), None);
+//! ```
+
+/// What happens to invalid label values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SanitizePolicy {
+    /// Escape the invalid characters, e.g. a newline as `\n`, and truncate the value to the
+    /// maximum length.
+    Escape,
+    /// Replace the invalid characters with [`REPLACEMENT_CHAR`], and truncate the value to the
+    /// maximum length.
+    Replace,
+    /// Reject the value, dropping the update.
+    Reject,
+}
+
+/// The character replacing invalid characters with [`SanitizePolicy::Replace`].
+pub const REPLACEMENT_CHAR: char = '_';
+
+/// The sanitization of label values: a policy, and an optional maximum length in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LabelSanitizer {
+    /// What happens to invalid label values.
+    pub policy: SanitizePolicy,
+    /// The maximum length of label values, in bytes, if any.
+    pub max_len: Option<usize>,
+}
+
+impl LabelSanitizer {
+    /// Create a sanitizer with the given policy, without a maximum length.
+    pub const fn new(policy: SanitizePolicy) -> Self {
+        Self { policy, max_len: None }
+    }
+
+    /// Set the maximum length of label values, in bytes.
+    pub const fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Sanitize the label value, returning `None` if it's rejected.
+    pub fn sanitize(&self, value: String) -> Option<String> {
+        let too_long = self.max_len.is_some_and(|max_len| value.len() > max_len);
+        if !too_long && !value.chars().any(is_invalid) {
+            return Some(value);
+        }
+
+        let mut sanitized = match self.policy {
+            SanitizePolicy::Reject => return None,
+            SanitizePolicy::Escape => value
+                .chars()
+                .map(|c| if is_invalid(c) { c.escape_default().to_string() } else { c.to_string() })
+                .collect::<String>(),
+            SanitizePolicy::Replace => {
+                value.chars().map(|c| if is_invalid(c) { REPLACEMENT_CHAR } else { c }).collect()
+            }
+        };
+        if let Some(max_len) = self.max_len.filter(|max_len| sanitized.len() > *max_len) {
+            // Truncate at the last character boundary within the limit.
+            let end = (0..=max_len).rev().find(|i| sanitized.is_char_boundary(*i)).unwrap_or(0);
+            sanitized.truncate(end);
+        }
+        Some(sanitized)
+    }
+}
+
+/// Returns `true` if the character is invalid in a label value.
+fn is_invalid(c: char) -> bool {
+    c.is_control() || c == char::REPLACEMENT_CHARACTER
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize() {
+        let escape = LabelSanitizer::new(SanitizePolicy::Escape);
+        assert_eq!(escape.sanitize("peer".to_owned()).as_deref(), Some("peer"));
+        assert_eq!(escape.sanitize("a\nb\tc".to_owned()).as_deref(), Some(r"a\nb\tc"));
+        let lossy = String::from_utf8_lossy(b"a\xffb").into_owned();
+        assert_eq!(escape.sanitize(lossy.clone()).as_deref(), Some(r"a\u{fffd}b"));
+
+        let replace = LabelSanitizer::new(SanitizePolicy::Replace).with_max_len(4);
+        assert_eq!(replace.sanitize("a\r\nb".to_owned()).as_deref(), Some("a__b"));
+        assert_eq!(replace.sanitize(lossy).as_deref(), Some("a_b"));
+        assert_eq!(replace.sanitize("abcdef".to_owned()).as_deref(), Some("abcd"));
+        // Multi-byte characters aren't split.
+        assert_eq!(replace.sanitize("abcé".to_owned()).as_deref(), Some("abc"));
+
+        let reject = LabelSanitizer::new(SanitizePolicy::Reject).with_max_len(4);
+        assert_eq!(reject.sanitize("peer".to_owned()).as_deref(), Some("peer"));
+        assert_eq!(reject.sanitize("a\nb".to_owned()), None);
+        assert_eq!(reject.sanitize("peers".to_owned()), None);
+    }
+}
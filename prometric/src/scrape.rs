@@ -0,0 +1,540 @@
+//! A scrape client for remote `/metrics` endpoints, to build aggregators or sidecars merging the
+//! metrics of co-located processes into their own registry.
+//!
+//! The client fetches the Prometheus text exposition of another process and [`parse`]s it into
+//! typed metric families. A [`ScrapedCollector`] registered in the local registry serves the
+//! families of the latest scrape.
+//!
+//! # Example
+//! ```rust,no_run
+//! use std::time::Duration;
+//!
+//! use prometric::scrape::{ScrapeClient, ScrapedCollector};
+//!
+//! # async fn example() {
+//! let registry = prometheus::Registry::new();
+//!
+//! let client = ScrapeClient::new("http://127.0.0.1:9100/metrics").unwrap();
+//! let collector = ScrapedCollector::new(client.uri());
+//! registry.register(Box::new(collector.clone())).unwrap();
+//!
+//! // Scrape the remote endpoint every 10 seconds in the background.
+//! tokio::spawn(client.run(collector, Duration::from_secs(10)));
+//! # }
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use http_body_util::{BodyExt, Empty};
+use hyper::{Uri, body::Bytes};
+use hyper_util::{
+    client::legacy::{Client, connect::HttpConnector},
+    rt::TokioExecutor,
+};
+use prometheus::{
+    core::{Collector, Desc},
+    proto::{Bucket, LabelPair, Metric, MetricFamily, MetricType, Quantile},
+};
+
+/// The default timeout of a scrape.
+pub const DEFAULT_SCRAPE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fetches and parses the metrics of a remote `/metrics` endpoint.
+#[derive(Debug, Clone)]
+pub struct ScrapeClient {
+    uri: Uri,
+    timeout: Duration,
+    client: Client<HttpConnector, Empty<Bytes>>,
+}
+
+impl ScrapeClient {
+    /// Create a new client scraping the given URI. Only `http` is supported.
+    pub fn new(uri: impl AsRef<str>) -> Result<Self, ScrapeError> {
+        let uri = uri
+            .as_ref()
+            .parse::<Uri>()
+            .map_err(|_| ScrapeError::InvalidUri(uri.as_ref().to_owned()))?;
+
+        Ok(Self {
+            uri,
+            timeout: DEFAULT_SCRAPE_TIMEOUT,
+            client: Client::builder(TokioExecutor::new()).build_http(),
+        })
+    }
+
+    /// Set the timeout of a scrape. Defaults to [`DEFAULT_SCRAPE_TIMEOUT`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the scraped URI.
+    pub fn uri(&self) -> String {
+        self.uri.to_string()
+    }
+
+    /// Scrape the remote endpoint, returning its metric families.
+    pub async fn scrape(&self) -> Result<Vec<MetricFamily>, ScrapeError> {
+        let fetch = async {
+            let response = self.client.get(self.uri.clone()).await?;
+            if !response.status().is_success() {
+                return Err(ScrapeError::Status(response.status().as_u16()));
+            }
+
+            let body = response.into_body().collect().await?.to_bytes();
+            String::from_utf8(body.to_vec()).map_err(|_| ScrapeError::InvalidBody)
+        };
+
+        let body =
+            tokio::time::timeout(self.timeout, fetch).await.map_err(|_| ScrapeError::Timeout)??;
+        Ok(parse(&body)?)
+    }
+
+    /// Scrape the remote endpoint at the given interval, updating the collector with the result.
+    /// If a scrape fails, the collector is cleared so that stale metrics aren't served. Never
+    /// returns.
+    pub async fn run(self, collector: ScrapedCollector, interval: Duration) {
+        loop {
+            collector.update(self.scrape().await.unwrap_or_default());
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+/// A collector serving the metric families of the latest scrape of a remote endpoint.
+///
+/// Cloned collectors share the same families, so one can be registered while another is updated.
+#[derive(Debug, Clone)]
+pub struct ScrapedCollector {
+    /// Identifies the collector in the registry. The scraped families aren't known in advance, so
+    /// they can't be described.
+    desc: Desc,
+    families: Arc<RwLock<Vec<MetricFamily>>>,
+}
+
+impl ScrapedCollector {
+    /// Create a new, empty collector for the given target.
+    pub fn new(target: impl Into<String>) -> Self {
+        let desc = Desc::new(
+            "prometric_scrape_target".to_owned(),
+            "A remote scrape target.".to_owned(),
+            Vec::new(),
+            HashMap::from([("target".to_owned(), target.into())]),
+        )
+        .expect("Failed to create scrape target descriptor");
+
+        Self { desc, families: Default::default() }
+    }
+
+    /// Replace the served families.
+    pub fn update(&self, families: Vec<MetricFamily>) {
+        *self.families.write().unwrap() = families;
+    }
+
+    /// Returns the served families.
+    pub fn families(&self) -> Vec<MetricFamily> {
+        self.families.read().unwrap().clone()
+    }
+}
+
+impl Collector for ScrapedCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        vec![&self.desc]
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        self.families()
+    }
+}
+
+/// Parse the Prometheus text exposition format into metric families.
+///
+/// Samples without a `# TYPE` line are parsed as untyped families. The `+Inf` bucket of histograms
+/// is dropped, as it's implied by the sample count.
+pub fn parse(text: &str) -> Result<Vec<MetricFamily>, ParseError> {
+    let mut parser = Parser::default();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let result = match line.strip_prefix('#') {
+            Some(comment) => parser.comment(comment),
+            None => parser.sample(line),
+        };
+        result.map_err(|message| ParseError { line: i + 1, message })?;
+    }
+
+    Ok(parser.finish())
+}
+
+/// The label pairs of a sample.
+type Labels = Vec<(String, String)>;
+
+/// Accumulates the families of an exposition, in order of appearance.
+#[derive(Default)]
+struct Parser {
+    families: Vec<MetricFamily>,
+    /// The index of each family by name.
+    index: HashMap<String, usize>,
+    /// The index of the metrics of each family by labels, excluding `le` and `quantile`.
+    metrics: Vec<HashMap<Labels, usize>>,
+}
+
+impl Parser {
+    fn family(&mut self, name: &str) -> usize {
+        if let Some(&i) = self.index.get(name) {
+            return i;
+        }
+
+        let mut family = MetricFamily::default();
+        family.set_name(name.to_owned());
+        family.set_field_type(MetricType::UNTYPED);
+
+        self.index.insert(name.to_owned(), self.families.len());
+        self.families.push(family);
+        self.metrics.push(HashMap::new());
+        self.families.len() - 1
+    }
+
+    fn comment(&mut self, comment: &str) -> Result<(), String> {
+        let mut parts = comment.trim_start().splitn(3, ' ');
+        let (Some(keyword @ ("HELP" | "TYPE")), Some(name)) = (parts.next(), parts.next()) else {
+            // Other comments are ignored.
+            return Ok(());
+        };
+        let rest = parts.next().unwrap_or_default().trim();
+
+        let i = self.family(name);
+        if keyword == "HELP" {
+            self.families[i].set_help(unescape(rest));
+            return Ok(());
+        }
+
+        let ty = match rest {
+            "counter" => MetricType::COUNTER,
+            "gauge" => MetricType::GAUGE,
+            "histogram" => MetricType::HISTOGRAM,
+            "summary" => MetricType::SUMMARY,
+            "untyped" => MetricType::UNTYPED,
+            _ => return Err(format!("unknown metric type: {rest}")),
+        };
+        self.families[i].set_field_type(ty);
+
+        Ok(())
+    }
+
+    fn sample(&mut self, line: &str) -> Result<(), String> {
+        let (name, mut labels, rest) = split_sample(line)?;
+
+        let mut values = rest.split_whitespace();
+        let value = values.next().ok_or("missing sample value")?;
+        let value = value.parse::<f64>().map_err(|_| format!("invalid sample value: {value}"))?;
+        let timestamp = values
+            .next()
+            .map(|ts| ts.parse::<i64>().map_err(|_| format!("invalid timestamp: {ts}")))
+            .transpose()?;
+
+        // Resolve the family of histogram and summary samples from their suffix.
+        let (family_name, suffix) = ["_bucket", "_sum", "_count"]
+            .into_iter()
+            .find_map(|suffix| {
+                let base = name.strip_suffix(suffix)?;
+                let i = *self.index.get(base)?;
+                matches!(
+                    self.families[i].get_field_type(),
+                    MetricType::HISTOGRAM | MetricType::SUMMARY
+                )
+                .then_some((base, suffix))
+            })
+            .unwrap_or((name, ""));
+
+        let i = self.family(family_name);
+        let ty = self.families[i].get_field_type();
+
+        let bound = match (ty, suffix) {
+            (MetricType::HISTOGRAM, "_bucket") => Some(take_label(&mut labels, "le")?),
+            (MetricType::SUMMARY, "") => Some(take_label(&mut labels, "quantile")?),
+            (MetricType::HISTOGRAM, "") => return Err("histogram sample without suffix".into()),
+            _ => None,
+        };
+
+        let j = *self.metrics[i].entry(labels.clone()).or_insert_with(|| {
+            let mut metric = Metric::default();
+            metric.set_label(
+                labels
+                    .iter()
+                    .map(|(name, value)| {
+                        let mut label = LabelPair::default();
+                        label.set_name(name.clone());
+                        label.set_value(value.clone());
+                        label
+                    })
+                    .collect(),
+            );
+            self.families[i].metric.push(metric);
+            self.families[i].metric.len() - 1
+        });
+
+        let metric = &mut self.families[i].metric[j];
+        if let Some(timestamp) = timestamp {
+            metric.set_timestamp_ms(timestamp);
+        }
+
+        match ty {
+            MetricType::COUNTER => metric.counter.mut_or_insert_default().set_value(value),
+            MetricType::GAUGE => metric.gauge.mut_or_insert_default().set_value(value),
+            MetricType::UNTYPED => metric.untyped.mut_or_insert_default().set_value(value),
+            MetricType::HISTOGRAM => {
+                let histogram = metric.histogram.mut_or_insert_default();
+                match (suffix, bound) {
+                    ("_sum", _) => histogram.set_sample_sum(value),
+                    ("_count", _) => histogram.set_sample_count(value as u64),
+                    (_, Some(bound)) if bound.is_finite() => {
+                        let mut bucket = Bucket::default();
+                        bucket.set_upper_bound(bound);
+                        bucket.set_cumulative_count(value as u64);
+                        histogram.bucket.push(bucket);
+                    }
+                    _ => {}
+                }
+            }
+            MetricType::SUMMARY => {
+                let summary = metric.summary.mut_or_insert_default();
+                match (suffix, bound) {
+                    ("_sum", _) => summary.set_sample_sum(value),
+                    ("_count", _) => summary.set_sample_count(value as u64),
+                    (_, Some(bound)) => {
+                        let mut quantile = Quantile::default();
+                        quantile.set_quantile(bound);
+                        quantile.set_value(value);
+                        summary.quantile.push(quantile);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Vec<MetricFamily> {
+        self.families.into_iter().filter(|family| !family.metric.is_empty()).collect()
+    }
+}
+
+/// Split a sample line into its name, labels and the remaining value and timestamp.
+fn split_sample(line: &str) -> Result<(&str, Labels, &str), String> {
+    let end = line.find(|c: char| c == '{' || c.is_whitespace()).unwrap_or(line.len());
+    let (name, mut rest) = line.split_at(end);
+    if name.is_empty() {
+        return Err("missing metric name".into());
+    }
+
+    let mut labels = Vec::new();
+    if let Some(mut inner) = rest.strip_prefix('{') {
+        loop {
+            inner = inner.trim_start_matches([' ', ',']);
+            if let Some(after) = inner.strip_prefix('}') {
+                rest = after;
+                break;
+            }
+
+            let (label, after) = inner.split_once('=').ok_or("invalid label")?;
+            let after = after.trim_start().strip_prefix('"').ok_or("unquoted label value")?;
+
+            // Find the closing quote, skipping escaped characters.
+            let mut escaped = false;
+            let close = after
+                .char_indices()
+                .find(|&(_, c)| {
+                    let close = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    close
+                })
+                .map(|(i, _)| i)
+                .ok_or("unterminated label value")?;
+
+            labels.push((label.trim().to_owned(), unescape(&after[..close])));
+            inner = &after[close + 1..];
+        }
+    }
+
+    Ok((name, labels, rest))
+}
+
+/// Remove the label with the given name, parsing its value as a float.
+fn take_label(labels: &mut Labels, name: &str) -> Result<f64, String> {
+    let i = labels.iter().position(|(label, _)| label == name).ok_or(format!("missing {name}"))?;
+    let (_, value) = labels.remove(i);
+    value.parse().map_err(|_| format!("invalid {name}: {value}"))
+}
+
+/// Unescape `\\`, `\"` and `\n` sequences.
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// An error that can occur when parsing the text exposition format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The line of the error, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::error::Error for ParseError {}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid exposition on line {}: {}", self.line, self.message)
+    }
+}
+
+/// An error that can occur when scraping a remote endpoint.
+pub enum ScrapeError {
+    InvalidUri(String),
+    RequestError(hyper_util::client::legacy::Error),
+    BodyError(hyper::Error),
+    Status(u16),
+    InvalidBody,
+    Timeout,
+    ParseError(ParseError),
+}
+
+impl std::error::Error for ScrapeError {}
+
+impl std::fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidUri(uri) => write!(f, "Invalid URI: {uri}"),
+            Self::RequestError(e) => write!(f, "Request failed: {e:?}"),
+            Self::BodyError(e) => write!(f, "Failed to read body: {e:?}"),
+            Self::Status(status) => write!(f, "Unexpected status: {status}"),
+            Self::InvalidBody => write!(f, "Body is not valid UTF-8"),
+            Self::Timeout => write!(f, "Scrape timed out"),
+            Self::ParseError(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::fmt::Debug for ScrapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+impl From<hyper_util::client::legacy::Error> for ScrapeError {
+    fn from(e: hyper_util::client::legacy::Error) -> Self {
+        Self::RequestError(e)
+    }
+}
+
+impl From<hyper::Error> for ScrapeError {
+    fn from(e: hyper::Error) -> Self {
+        Self::BodyError(e)
+    }
+}
+
+impl From<ParseError> for ScrapeError {
+    fn from(e: ParseError) -> Self {
+        Self::ParseError(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::{HistogramOpts, Opts, Registry};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_roundtrip() {
+        let registry = Registry::new();
+
+        let counter = prometheus::CounterVec::new(
+            Opts::new("requests", "Requests with \"quotes\".\nAnd a newline."),
+            &["path"],
+        )
+        .unwrap();
+        let gauge = prometheus::Gauge::new("balance", "Balance.").unwrap();
+        let histogram = prometheus::HistogramVec::new(
+            HistogramOpts::new("latency", "Latency.").buckets(vec![0.1, 1.0]),
+            &["method"],
+        )
+        .unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        registry.register(Box::new(gauge.clone())).unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+
+        counter.with_label_values(&["/a\"b\\c"]).inc_by(3.0);
+        gauge.set(-1.5);
+        histogram.with_label_values(&["GET"]).observe(0.5);
+        histogram.with_label_values(&["GET"]).observe(5.0);
+
+        let text = crate::gather_to_string(&registry).unwrap();
+        let families = parse(&text).unwrap();
+
+        assert_eq!(families, registry.gather());
+    }
+
+    #[test]
+    fn test_parse_summary_and_untyped() {
+        let text = "\
+            # TYPE rpc summary\n\
+            rpc{quantile=\"0.5\"} 1\n\
+            rpc{quantile=\"0.99\"} 3\n\
+            rpc_sum 10\n\
+            rpc_count 4\n\
+            up 1 1700000000000\n";
+
+        let families = parse(text).unwrap();
+        assert_eq!(families.len(), 2);
+
+        let summary = families[0].get_metric()[0].get_summary();
+        assert_eq!(summary.sample_count(), 4);
+        assert_eq!(summary.sample_sum(), 10.0);
+        assert_eq!(summary.quantile.len(), 2);
+
+        assert_eq!(families[1].get_field_type(), MetricType::UNTYPED);
+        assert_eq!(families[1].get_metric()[0].timestamp_ms(), 1_700_000_000_000);
+
+        let err = parse("# TYPE rpc summary\nrpc 1").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn test_scraped_collector() {
+        let registry = Registry::new();
+        let collector = ScrapedCollector::new("http://127.0.0.1:9100/metrics");
+        registry.register(Box::new(collector.clone())).unwrap();
+
+        // Different targets can be registered in the same registry.
+        registry
+            .register(Box::new(ScrapedCollector::new("http://127.0.0.1:9101/metrics")))
+            .unwrap();
+
+        collector.update(parse("# TYPE up gauge\nup 1").unwrap());
+        assert!(crate::gather_to_string(&registry).unwrap().contains("up 1"));
+    }
+}
@@ -0,0 +1,298 @@
+//! State sets, exporting one boolean series per variant of an enum, where the current state is `1`
+//! and the others `0` (the OpenMetrics `StateSet` pattern), e.g. the sync status of a node or its
+//! leader/follower role. Used by the state set metrics generated by the `prometric-derive` macro
+//! on fieldless enums.
+//!
+//! A state set named `sync_status` with the `chain` label exports a gauge with the `chain` and
+//! `sync_status` labels, the state label being last and named after the metric. The series are
+//! built from the current state when collected, so switching states is atomic: a scrape never sees
+//! two current states, or none.
+//!
+//! # Example
+//! ```rust
+//! use prometric::{LabelValue, State, StateSet};
+//!
+//! #[derive(Debug, PartialEq)]
+//! enum SyncStatus {
+//!     Syncing,
+//!     Synced,
+//! }
+//!
+//! impl LabelValue for SyncStatus {
+//!     fn label_value(&self) -> &'static str {
+//!         match self {
+//!             Self::Syncing => "syncing",
+//!             Self::Synced => "synced",
+//!         }
+//!     }
+//! }
+//!
+//! impl State for SyncStatus {
+//!     const STATES: &'static [Self] = &[Self::Syncing, Self::Synced];
+//! }
+//!
+//! let registry = prometheus::Registry::new();
+//! let status = StateSet::<SyncStatus>::new(
+//!     &registry,
+//!     "sync_status",
+//!     "Sync status.",
+//!     &["chain"],
+//!     Default::default(),
+//! );
+//! status.set(&["mainnet"], SyncStatus::Synced);
+//! assert_eq!(status.get(&["mainnet"]), Some(&SyncStatus::Synced));
+//!
+//! let output = prometric::gather_to_string(&registry).unwrap();
+//! assert!(output.contains(r#"sync_status{chain="mainnet",sync_status="synced"} 1"#));
+//! assert!(output.contains(r#"sync_status{chain="mainnet",sync_status="syncing"} 0"#));
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+
+use prometheus::{
+    core::{Collector, Desc},
+    proto::{Gauge, LabelPair, Metric, MetricFamily, MetricType},
+};
+
+use crate::LabelValue;
+
+/// The states of a [`StateSet`], usually a fieldless enum, told apart by their label values.
+/// Implemented by the enums annotated with the `prometric-derive` macro.
+pub trait State: LabelValue + Sized + Sync + 'static {
+    /// All the states, in the order of their series.
+    const STATES: &'static [Self];
+}
+
+impl State for bool {
+    const STATES: &'static [Self] = &[false, true];
+}
+
+/// The label value of the current state of each series of a state set, `None` if no state is
+/// current. Keyed by the label values, sorted for a stable output.
+type Current = BTreeMap<Vec<String>, Option<&'static str>>;
+
+/// Builds the series of a state set from its current states when collected.
+struct StateSetCollector<E> {
+    desc: Desc,
+    current: Arc<Mutex<Current>>,
+    _states: PhantomData<fn() -> E>,
+}
+
+impl<E> Clone for StateSetCollector<E> {
+    fn clone(&self) -> Self {
+        Self { desc: self.desc.clone(), current: self.current.clone(), _states: PhantomData }
+    }
+}
+
+impl<E: State> Collector for StateSetCollector<E> {
+    fn desc(&self) -> Vec<&Desc> {
+        vec![&self.desc]
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        let (state_label, labels) =
+            self.desc.variable_labels.split_last().expect("the state label is always set");
+
+        let mut metrics = Vec::with_capacity(current.len() * E::STATES.len());
+        for (values, current) in current.iter() {
+            for state in E::STATES {
+                let mut pairs = self.desc.const_label_pairs.clone();
+                pairs
+                    .extend(labels.iter().zip(values).map(|(name, value)| label_pair(name, value)));
+                pairs.push(label_pair(state_label, state.label_value()));
+                pairs.sort_by(|a, b| a.name().cmp(b.name()));
+
+                let mut gauge = Gauge::default();
+                gauge.set_value(if *current == Some(state.label_value()) { 1.0 } else { 0.0 });
+                let mut metric = Metric::default();
+                metric.set_label(pairs);
+                metric.set_gauge(gauge);
+                metrics.push(metric);
+            }
+        }
+
+        let mut family = MetricFamily::default();
+        family.set_name(self.desc.fq_name.clone());
+        family.set_help(self.desc.help.clone());
+        family.set_field_type(MetricType::GAUGE);
+        family.set_metric(metrics);
+        vec![family]
+    }
+}
+
+/// Returns the label pair with the given name and value.
+fn label_pair(name: &str, value: &str) -> LabelPair {
+    let mut pair = LabelPair::default();
+    pair.set_name(name.to_owned());
+    pair.set_value(value.to_owned());
+    pair
+}
+
+/// A gauge with one series per state, where the current state is `1` and the others `0`. See
+/// [`crate::state_set`].
+pub struct StateSet<E> {
+    collector: StateSetCollector<E>,
+}
+
+impl<E> Clone for StateSet<E> {
+    fn clone(&self) -> Self {
+        Self { collector: self.collector.clone() }
+    }
+}
+
+impl<E> std::fmt::Debug for StateSet<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateSet")
+            .field("name", &self.collector.desc.fq_name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Collects the series of the state set.
+impl<E: State> Collector for StateSet<E> {
+    fn desc(&self) -> Vec<&Desc> {
+        self.collector.desc()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        self.collector.collect()
+    }
+}
+
+impl<E: State> StateSet<E> {
+    /// Create a new state set with the given registry, name, help, labels, and const labels. The
+    /// state label, named after the metric, is appended to the labels.
+    ///
+    /// # Panics
+    /// Panics if the name or labels are invalid, or if the registration fails.
+    #[track_caller]
+    pub fn new(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+    ) -> Self {
+        Self::try_new(registry, name, help, labels, const_labels)
+            .unwrap_or_else(|e| crate::registration_failed(name, labels, e))
+    }
+
+    /// Create a new state set like [`Self::new`], returning an error if the name or labels are
+    /// invalid, or if the registration fails.
+    pub fn try_new(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+    ) -> Result<Self, crate::Error> {
+        let variable_labels = labels.iter().chain([&name]).map(|label| label.to_string()).collect();
+        let desc = Desc::new(name.to_owned(), help.to_owned(), variable_labels, const_labels)?;
+        let collector =
+            StateSetCollector { desc, current: Default::default(), _states: PhantomData };
+        crate::register(registry, &collector, name, labels)?;
+        Ok(Self { collector })
+    }
+
+    /// Set the current state of the series with the given label values, and the other states to
+    /// `0`, atomically.
+    pub fn set(&self, labels: &[&str], state: E) {
+        self.update(labels, Some(state.label_value()));
+    }
+
+    /// Set every state of the series with the given label values to `0`, e.g. before the first
+    /// state is known.
+    pub fn reset(&self, labels: &[&str]) {
+        self.update(labels, None);
+    }
+
+    /// Returns the current state of the series with the given label values, if any.
+    pub fn get(&self, labels: &[&str]) -> Option<&'static E> {
+        let current = self.collector.current.lock().unwrap_or_else(|e| e.into_inner());
+        let value = current.get(&key(labels)).copied().flatten()?;
+        E::STATES.iter().find(|state| state.label_value() == value)
+    }
+
+    /// Remove the series of all the states with the given label values. Returns `false` if there
+    /// is no such series.
+    pub fn remove(&self, labels: &[&str]) -> bool {
+        self.collector
+            .current
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&key(labels))
+            .is_some()
+    }
+
+    /// Unregister the state set from the given registry.
+    pub fn unregister(&self, registry: &prometheus::Registry) {
+        let _ = registry.unregister(Box::new(self.collector.clone()));
+    }
+
+    /// Remove all the series of the state set.
+    pub fn clear(&self) {
+        self.collector.current.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// Update the current state of the series with the given label values.
+    fn update(&self, labels: &[&str], state: Option<&'static str>) {
+        let desc = &self.collector.desc;
+        assert_eq!(
+            labels.len(),
+            desc.variable_labels.len() - 1,
+            "Inconsistent label cardinality of state set {}",
+            desc.fq_name
+        );
+        self.collector.current.lock().unwrap_or_else(|e| e.into_inner()).insert(key(labels), state);
+    }
+}
+
+/// Returns the key of the series with the given label values.
+fn key(labels: &[&str]) -> Vec<String> {
+    labels.iter().map(|value| value.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_set() {
+        let registry = prometheus::Registry::new();
+        let leader = StateSet::<bool>::new(
+            &registry,
+            "test_leader",
+            "Leadership.",
+            &["shard"],
+            HashMap::from([("cluster".to_owned(), "a".to_owned())]),
+        );
+        leader.reset(&["0"]);
+        leader.set(&["1"], true);
+        assert_eq!(leader.get(&["0"]), None);
+        assert_eq!(leader.get(&["1"]), Some(&true));
+
+        let output = crate::gather_to_string(&registry).unwrap();
+        assert!(output.contains("# TYPE test_leader gauge"));
+        assert!(output.contains(r#"test_leader{cluster="a",shard="0",test_leader="false"} 0"#));
+        assert!(output.contains(r#"test_leader{cluster="a",shard="0",test_leader="true"} 0"#));
+        assert!(output.contains(r#"test_leader{cluster="a",shard="1",test_leader="false"} 0"#));
+        assert!(output.contains(r#"test_leader{cluster="a",shard="1",test_leader="true"} 1"#));
+
+        // Switching states replaces the current state.
+        leader.set(&["1"], false);
+        let output = crate::gather_to_string(&registry).unwrap();
+        assert!(output.contains(r#"test_leader{cluster="a",shard="1",test_leader="false"} 1"#));
+        assert!(output.contains(r#"test_leader{cluster="a",shard="1",test_leader="true"} 0"#));
+
+        assert!(leader.remove(&["0"]));
+        assert!(!leader.remove(&["2"]));
+        assert!(!crate::gather_to_string(&registry).unwrap().contains(r#"shard="0""#));
+        leader.unregister(&registry);
+        assert!(registry.gather().is_empty());
+    }
+}
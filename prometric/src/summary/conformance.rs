@@ -0,0 +1,264 @@
+//! A reusable conformance test suite for summary providers, built on [`proptest`].
+//!
+//! Downstream implementers of [`SummaryProvider`] (or [`NonConcurrentSummaryProvider`]) can run
+//! it against custom providers to check the invariants the [`Summary`](crate::Summary) metric
+//! relies on:
+//! - Count and sum consistency: the snapshot count and sum match the observations.
+//! - Quantile monotonicity: quantiles are non-decreasing, and within the observed range.
+//! - Merge correctness: observations from concurrent threads all end up in the snapshot.
+//!
+//! # Example
+//! ```rust
+//! use prometric::summary::{
+//!     conformance::Conformance,
+//!     simple::{SimpleSummary, SimpleSummaryOpts},
+//! };
+//!
+//! Conformance::<SimpleSummary>::new(SimpleSummaryOpts::default()).with_cases(32).run();
+//! ```
+
+use std::{marker::PhantomData, sync::Arc};
+
+use proptest::{
+    prelude::*,
+    test_runner::{Config, TestCaseError, TestRunner},
+};
+
+use crate::summary::traits::{NonConcurrentSummaryProvider, Summary, SummaryProvider};
+
+/// The default relative error tolerated for quantiles, with respect to the observed range.
+pub const DEFAULT_RELATIVE_ERROR: f64 = 0.01;
+
+/// The default number of cases to run for each property.
+pub const DEFAULT_CASES: u32 = 256;
+
+/// A strategy generating non-empty observations, as positive finite values spanning several orders
+/// of magnitude.
+pub fn observations() -> impl Strategy<Value = Vec<f64>> {
+    prop::collection::vec(1e-3..1e6f64, 1..512)
+}
+
+/// A strategy generating sorted quantiles in `[0, 1]`.
+pub fn quantiles() -> impl Strategy<Value = Vec<f64>> {
+    prop::collection::vec(0.0..=1.0f64, 1..16).prop_map(|mut quantiles| {
+        quantiles.sort_by(f64::total_cmp);
+        quantiles
+    })
+}
+
+/// A strategy generating observations partitioned into chunks, one per concurrent observer.
+pub fn partitioned_observations() -> impl Strategy<Value = Vec<Vec<f64>>> {
+    prop::collection::vec(observations(), 1..8)
+}
+
+/// Check that the count and sum of the snapshot match the observations.
+pub fn check_count_sum<P: NonConcurrentSummaryProvider>(
+    opts: &P::Opts,
+    observations: &[f64],
+) -> Result<(), TestCaseError> {
+    let mut provider = P::new_provider(opts);
+    for value in observations {
+        provider.observe(*value);
+    }
+
+    let snapshot = provider.snapshot();
+    prop_assert_eq!(snapshot.sample_count(), observations.len() as u64, "count mismatch");
+    check_sum(snapshot.sample_sum(), observations.iter().sum())
+}
+
+/// Check that the quantiles of the snapshot are non-decreasing, and within the observed range
+/// (with the given relative error). Quantiles the provider can't compute are skipped.
+pub fn check_quantiles<P: NonConcurrentSummaryProvider>(
+    opts: &P::Opts,
+    observations: &[f64],
+    quantiles: &[f64],
+    relative_error: f64,
+) -> Result<(), TestCaseError> {
+    let mut provider = P::new_provider(opts);
+    for value in observations {
+        provider.observe(*value);
+    }
+
+    let min = observations.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = observations.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let (low, high) = (min * (1.0 - relative_error), max * (1.0 + relative_error));
+
+    let snapshot = provider.snapshot();
+    let mut previous: Option<(f64, f64)> = None;
+    for quantile in quantiles {
+        let Some(value) = snapshot.quantile(*quantile) else {
+            continue;
+        };
+
+        prop_assert!(
+            (low..=high).contains(&value),
+            "quantile {} = {} outside of the observed range [{}, {}]",
+            quantile,
+            value,
+            min,
+            max
+        );
+
+        if let Some((previous_quantile, previous_value)) = previous {
+            prop_assert!(
+                value >= previous_value,
+                "quantile {} = {} is less than quantile {} = {}",
+                quantile,
+                value,
+                previous_quantile,
+                previous_value
+            );
+        }
+        previous = Some((*quantile, value));
+    }
+
+    Ok(())
+}
+
+/// Check that observations made concurrently from one thread per partition are all merged into
+/// the snapshot.
+pub fn check_merge<P>(opts: &P::Opts, partitions: &[Vec<f64>]) -> Result<(), TestCaseError>
+where
+    P: SummaryProvider + Send + Sync + 'static,
+{
+    let provider = Arc::new(P::new_provider(opts));
+
+    let handles = partitions
+        .iter()
+        .cloned()
+        .map(|partition| {
+            let provider = provider.clone();
+            std::thread::spawn(move || {
+                for value in partition {
+                    provider.observe(value);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().expect("Observer thread panicked");
+    }
+
+    let snapshot = provider.snapshot();
+    let count = partitions.iter().map(Vec::len).sum::<usize>();
+    prop_assert_eq!(snapshot.sample_count(), count as u64, "count mismatch after merge");
+    check_sum(snapshot.sample_sum(), partitions.iter().flatten().sum())
+}
+
+/// Check that the sums match, tolerating floating point errors due to a different summation order.
+fn check_sum(actual: f64, expected: f64) -> Result<(), TestCaseError> {
+    prop_assert!(
+        (actual - expected).abs() <= expected.abs() * 1e-9,
+        "sum mismatch: {} != {}",
+        actual,
+        expected
+    );
+    Ok(())
+}
+
+/// Runs the conformance suite against a summary provider.
+///
+/// [`Conformance::run`] checks the count/sum and quantile invariants. For concurrent
+/// providers, [`Conformance::run_concurrent`] additionally checks merge correctness.
+pub struct Conformance<P: NonConcurrentSummaryProvider> {
+    opts: P::Opts,
+    relative_error: f64,
+    cases: u32,
+    _provider: PhantomData<fn() -> P>,
+}
+
+impl<P: NonConcurrentSummaryProvider> Conformance<P> {
+    /// Create a new conformance suite for providers created with the given options.
+    pub fn new(opts: P::Opts) -> Self {
+        Self {
+            opts,
+            relative_error: DEFAULT_RELATIVE_ERROR,
+            cases: DEFAULT_CASES,
+            _provider: PhantomData,
+        }
+    }
+
+    /// Set the relative error tolerated for quantiles. Defaults to [`DEFAULT_RELATIVE_ERROR`].
+    pub fn with_relative_error(mut self, relative_error: f64) -> Self {
+        self.relative_error = relative_error;
+        self
+    }
+
+    /// Set the number of cases to run for each property. Defaults to [`DEFAULT_CASES`].
+    pub fn with_cases(mut self, cases: u32) -> Self {
+        self.cases = cases;
+        self
+    }
+
+    /// Run the count/sum and quantile checks, panicking on the first failure with the minimal
+    /// failing input.
+    pub fn run(&self) {
+        self.runner()
+            .run(&observations(), |observations| check_count_sum::<P>(&self.opts, &observations))
+            .unwrap_or_else(|err| panic!("Count/sum consistency failed: {err}"));
+
+        self.runner()
+            .run(&(observations(), quantiles()), |(observations, quantiles)| {
+                check_quantiles::<P>(&self.opts, &observations, &quantiles, self.relative_error)
+            })
+            .unwrap_or_else(|err| panic!("Quantile monotonicity failed: {err}"));
+    }
+
+    fn runner(&self) -> TestRunner {
+        TestRunner::new(Config {
+            cases: self.cases,
+            failure_persistence: None,
+            ..Config::default()
+        })
+    }
+}
+
+impl<P> Conformance<P>
+where
+    P: SummaryProvider + Send + Sync + 'static,
+{
+    /// Run all checks, including merge correctness for concurrent observations.
+    pub fn run_concurrent(&self) {
+        self.run();
+
+        self.runner()
+            .run(&partitioned_observations(), |partitions| {
+                check_merge::<P>(&self.opts, &partitions)
+            })
+            .unwrap_or_else(|err| panic!("Merge correctness failed: {err}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summary::{
+        DefaultSummaryProvider,
+        batching::{BatchOpts, BatchedSummary},
+        rolling::{RollingSummary, RollingSummaryOpts},
+        simple::{SimpleSummary, SimpleSummaryOpts},
+    };
+
+    #[test]
+    fn simple_summary_conformance() {
+        Conformance::<SimpleSummary>::new(SimpleSummaryOpts::default()).with_cases(64).run();
+    }
+
+    #[test]
+    fn rolling_summary_conformance() {
+        Conformance::<RollingSummary>::new(RollingSummaryOpts::default()).with_cases(64).run();
+    }
+
+    #[test]
+    fn batched_summary_conformance() {
+        let opts = BatchOpts::from_inner(SimpleSummaryOpts::default()).with_batch_size(16);
+        Conformance::<BatchedSummary<SimpleSummary>>::new(opts).with_cases(64).run_concurrent();
+    }
+
+    #[test]
+    fn default_summary_conformance() {
+        let opts = BatchOpts::from_inner(RollingSummaryOpts::default());
+        Conformance::<DefaultSummaryProvider>::new(opts).with_cases(64).run_concurrent();
+    }
+}
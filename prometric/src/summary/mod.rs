@@ -2,6 +2,9 @@ use std::collections::HashMap;
 
 use prometheus::core::MetricVec;
 
+#[cfg(feature = "recording")]
+use crate::recording::{self, MetricOp};
+
 pub mod traits;
 use traits::{NonConcurrentSummaryProvider, SummaryMetric, SummaryProvider};
 
@@ -17,6 +20,9 @@ use rolling::{RollingSummary, RollingSummaryOpts};
 pub mod batching;
 use batching::{BatchOpts, BatchedSummary};
 
+#[cfg(feature = "summary-conformance")]
+pub mod conformance;
+
 pub type DefaultSummaryProvider = BatchedSummary<RollingSummary>;
 
 type SummaryVec<S = DefaultSummaryProvider> = MetricVec<SummaryVecBuilder<S>>;
@@ -25,6 +31,24 @@ type SummaryVec<S = DefaultSummaryProvider> = MetricVec<SummaryVecBuilder<S>>;
 #[derive(Clone, Debug)]
 pub struct Summary<S: SummaryMetric = DefaultSummaryProvider> {
     inner: SummaryVec<S>,
+    /// The deprecated aliases of the metric. See [`crate::alias`].
+    aliases: Vec<String>,
+    /// The creation times of the series of the summary, if tracked. See [`crate::created`].
+    created: Option<std::sync::Arc<crate::created::CreatedSource>>,
+    #[cfg(feature = "recording")]
+    definition: std::sync::Arc<recording::MetricDefinition>,
+}
+
+/// Collects the series of the summary, e.g. to register it in another registry along with the
+/// metrics struct holding it. Deprecated aliases are registered separately.
+impl<S: SummaryMetric> prometheus::core::Collector for Summary<S> {
+    fn desc(&self) -> Vec<&prometheus::core::Desc> {
+        self.inner.desc()
+    }
+
+    fn collect(&self) -> Vec<prometheus::proto::MetricFamily> {
+        self.inner.collect()
+    }
 }
 
 impl<S: SummaryMetric> Summary<S> {
@@ -47,6 +71,12 @@ impl<S: SummaryMetric> Summary<S> {
 }
 
 impl Summary<DefaultSummaryProvider> {
+    /// Create a new summary metric with the given registry, name, help, labels, const labels, and
+    /// quantiles, [`generic::DEFAULT_QUANTILES`] by default.
+    ///
+    /// # Panics
+    /// Panics if the name or the labels are invalid, or if the registration fails. See
+    /// [`Self::try_new`] for a fallible version.
     pub fn new(
         registry: &prometheus::Registry,
         name: &str,
@@ -55,33 +85,48 @@ impl Summary<DefaultSummaryProvider> {
         const_labels: HashMap<String, String>,
         quantiles: Option<Vec<f64>>,
     ) -> Self {
+        Self::try_new(registry, name, help, labels, const_labels, quantiles)
+            .unwrap_or_else(|e| crate::registration_failed(name, labels, e))
+    }
+
+    /// Create a new summary metric like [`Self::new`], returning an error if the name or the labels
+    /// are invalid, or if the registration fails.
+    pub fn try_new(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+        quantiles: Option<Vec<f64>>,
+    ) -> Result<Self, crate::Error> {
         let quantiles = quantiles.unwrap_or(generic::DEFAULT_QUANTILES.to_vec());
 
+        #[cfg(feature = "recording")]
+        let definition = recording::MetricDefinition::new::<f64>(
+            crate::descriptor::MetricKind::Summary,
+            name,
+            help,
+            labels,
+            &const_labels,
+            Some(&quantiles),
+        );
+
         let opts = RollingSummaryOpts::default().with_quantiles(&quantiles);
         let opts = BatchOpts::from_inner(opts);
         let opts =
             SummaryOpts::new(name, help, opts).const_labels(const_labels).quantiles(quantiles);
 
-        let metric = Self::new_summary_vec(opts, labels).unwrap();
-
-        let boxed = Box::new(metric.clone());
-        if let Err(e) = registry.register(boxed.clone()) {
-            let id = format!("{}, Labels: {}", name, labels.join(", "),);
-            // If the metric is already registered, overwrite it.
-            if matches!(e, prometheus::Error::AlreadyReg) {
-                registry
-                    .unregister(boxed.clone())
-                    .unwrap_or_else(|_| panic!("Failed to unregister metric {id}"));
-
-                registry
-                    .register(boxed)
-                    .unwrap_or_else(|_| panic!("Failed to overwrite metric {id}"));
-            } else {
-                panic!("Failed to register metric {id}");
-            }
-        }
+        let metric = Self::new_summary_vec(opts, labels)?;
 
-        Self { inner: metric }
+        crate::register(registry, &metric, name, labels)?;
+
+        Ok(Self {
+            inner: metric,
+            aliases: Vec::new(),
+            created: None,
+            #[cfg(feature = "recording")]
+            definition,
+        })
     }
 }
 
@@ -90,12 +135,160 @@ where
     S: SummaryProvider<Summary = <S as NonConcurrentSummaryProvider>::Summary> + SummaryMetric,
 {
     pub fn observe(&self, labels: &[&str], value: f64) {
+        self.touch(labels);
         self.inner.with_label_values(labels).observe(value);
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Observe(value));
+    }
+
+    /// Observe the duration, in seconds.
+    pub fn observe_duration(&self, labels: &[&str], duration: std::time::Duration) {
+        self.observe(labels, duration.as_secs_f64());
+    }
+
+    /// Observe the time elapsed since the given instant, in seconds.
+    pub fn observe_since(&self, labels: &[&str], start: std::time::Instant) {
+        self.observe_duration(labels, start.elapsed());
     }
 
     pub fn snapshot(&self, labels: &[&str]) -> <S as NonConcurrentSummaryProvider>::Summary {
+        self.touch(labels);
         NonConcurrentSummaryProvider::snapshot(&**self.inner.with_label_values(labels))
     }
+
+    /// Returns the number of observations of the child of the summary with the given label
+    /// values, creating it if it doesn't exist.
+    pub fn get_sample_count(&self, labels: &[&str]) -> u64 {
+        traits::Summary::sample_count(&self.snapshot(labels))
+    }
+
+    /// Returns the sum of the observations of the child of the summary with the given label
+    /// values, creating it if it doesn't exist.
+    pub fn get_sample_sum(&self, labels: &[&str]) -> f64 {
+        traits::Summary::sample_sum(&self.snapshot(labels))
+    }
+
+    /// Remove the child of the summary with the given label values, e.g. to drop the stale series
+    /// of a disconnected peer. Returns `false` if there is no such child. Handles to the child
+    /// keep updating it, without exporting it.
+    pub fn remove(&self, labels: &[&str]) -> bool {
+        let removed = self.inner.remove_label_values(labels).is_ok();
+        if let Some(created) = &self.created {
+            created.remove(labels);
+        }
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, labels, MetricOp::Remove);
+        removed
+    }
+
+    /// Unregister the summary from the given registry, along with its deprecated aliases, e.g.
+    /// when a short-lived component is dropped. Updates keep applying to the summary, without
+    /// exporting it.
+    pub fn unregister(&self, registry: &prometheus::Registry)
+    where
+        S: 'static,
+    {
+        let _ = registry.unregister(Box::new(self.inner.clone()));
+        for alias in &self.aliases {
+            crate::alias::unregister(registry, self.inner.clone(), alias);
+        }
+        if let Some(created) = &self.created {
+            created.clear();
+        }
+    }
+
+    /// Record the operations on the summary into the given recorder, whether it is started or
+    /// not, unless the summary already records into another one. See [`crate::recording`].
+    #[cfg(feature = "recording")]
+    pub fn record_into(&self, recorder: &recording::Recorder) {
+        self.definition.record_into(recorder);
+    }
+
+    /// Remove all the children of the summary, e.g. to return to a clean state between tests.
+    /// Handles to the children keep updating them, without exporting them.
+    pub fn clear(&self) {
+        self.inner.reset();
+        if let Some(created) = &self.created {
+            created.clear();
+        }
+        #[cfg(feature = "recording")]
+        recording::record(&self.definition, &[], MetricOp::Clear);
+    }
+
+    /// Also export the summary under the given deprecated name, registered in the given
+    /// registry, e.g. during the migration window of a rename. See [`crate::alias`].
+    pub fn with_alias(mut self, registry: &prometheus::Registry, alias: &str) -> Self
+    where
+        S: 'static,
+    {
+        crate::alias::register(registry, self.inner.clone(), alias);
+        self.aliases.push(alias.to_owned());
+        self
+    }
+
+    /// Track the creation time of each series of the summary, exported as its `_created` sample
+    /// in the OpenMetrics text format. See [`crate::created`].
+    pub fn with_created(mut self) -> Self {
+        let desc = &prometheus::core::Collector::desc(&self.inner)[0];
+        self.created = Some(std::sync::Arc::new(crate::created::CreatedSource::from_desc(desc)));
+        self
+    }
+
+    /// Record the creation time of the series with the given label values, if tracked.
+    fn touch(&self, labels: &[&str]) {
+        if let Some(created) = &self.created {
+            created.touch(labels);
+        }
+    }
+
+    /// Resolve the child of the summary with the given label values once, returning a handle
+    /// updating it without the label lookup.
+    pub fn handle(&self, labels: &[&str]) -> SummaryHandle<S> {
+        self.touch(labels);
+        SummaryHandle {
+            inner: Some(self.inner.with_label_values(labels)),
+            #[cfg(feature = "recording")]
+            recording: Some(recording::HandleRecording::new(&self.definition, labels)),
+        }
+    }
+}
+
+/// A handle to the child of a [`Summary`] with resolved label values, returned by
+/// [`Summary::handle`]. Observations skip the label lookup and allocations, for hot paths.
+#[derive(Clone)]
+pub struct SummaryHandle<S: SummaryMetric = DefaultSummaryProvider> {
+    inner: Option<generic::GenericSummaryMetric<S>>,
+    #[cfg(feature = "recording")]
+    recording: Option<recording::HandleRecording>,
+}
+
+impl<S> SummaryHandle<S>
+where
+    S: SummaryProvider<Summary = <S as NonConcurrentSummaryProvider>::Summary> + SummaryMetric,
+{
+    /// Create a handle observing nothing, e.g. when metrics are disabled.
+    pub fn noop() -> Self {
+        Self {
+            inner: None,
+            #[cfg(feature = "recording")]
+            recording: None,
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        let Some(inner) = &self.inner else { return };
+        inner.observe(value);
+        #[cfg(feature = "recording")]
+        if let Some(recording) = &self.recording {
+            recording.record(MetricOp::Observe(value));
+        }
+    }
+}
+
+impl<S: SummaryMetric> std::fmt::Debug for SummaryHandle<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SummaryHandle").finish_non_exhaustive()
+    }
 }
 
 #[cfg(test)]
@@ -44,6 +44,7 @@ impl NonConcurrentSummaryProvider for SimpleSummary {
 
     fn observe(&mut self, val: f64) {
         self.inner.add(val);
+        self.sum += val;
     }
 
     fn snapshot(&self) -> Self::Summary {
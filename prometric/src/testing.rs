@@ -0,0 +1,185 @@
+//! Test utilities, giving each test an isolated registry to avoid crosstalk through the default
+//! registry.
+
+use std::{cell::RefCell, marker::PhantomData};
+
+use prometheus::Registry;
+
+thread_local! {
+    /// The duplicate registrations observed on this thread while a [`Harness`] is alive.
+    static DUPLICATES: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Record a duplicate registration of the given metric, if a [`Harness`] is alive on this thread.
+pub(crate) fn on_duplicate(id: &str) {
+    DUPLICATES.with(|duplicates| {
+        if let Some(duplicates) = duplicates.borrow_mut().as_mut() {
+            duplicates.push(id.to_owned());
+        }
+    });
+}
+
+/// A test harness with a fresh, isolated registry.
+///
+/// While the harness is alive, duplicate registrations made on the current thread (which are
+/// otherwise silently overwritten) are tracked. The harness asserts that there were none when
+/// dropped, unless the test is already panicking.
+///
+/// # Example
+/// ```rust
+/// use prometric::{Counter, testing::Harness};
+///
+/// let harness = Harness::new();
+/// let counter = harness.build(|registry| {
+///     Counter::<u64>::new(registry, "requests", "Requests.", &["method"], Default::default())
+/// });
+///
+/// counter.inc(&["GET"]);
+/// assert_eq!(harness.value("requests", &[("method", "GET")]), Some(1.0));
+/// ```
+///
+/// With the `prometric-derive` macro, build the metrics struct against the harness registry:
+/// ```rust,ignore
+/// let metrics = harness.build(|registry| AppMetrics::builder().with_registry(registry).build());
+/// ```
+#[derive(Debug)]
+pub struct Harness {
+    registry: Registry,
+    /// The duplicates tracked by the harness this one replaced, if nested.
+    previous: Option<Vec<String>>,
+    /// The harness tracks duplicates of the current thread, so it must not be sent to another.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Harness {
+    /// Create a new harness with a fresh registry.
+    pub fn new() -> Self {
+        let previous = DUPLICATES.with(|duplicates| duplicates.replace(Some(Vec::new())));
+        Self { registry: Registry::new(), previous, _not_send: PhantomData }
+    }
+
+    /// Returns the registry of the harness.
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Build metrics against the registry of the harness.
+    pub fn build<T>(&self, f: impl FnOnce(&Registry) -> T) -> T {
+        f(&self.registry)
+    }
+
+    /// Gather the metrics of the registry, encoded in the Prometheus text format.
+    pub fn gather(&self) -> String {
+        crate::gather_to_string(&self.registry).expect("Failed to encode metrics")
+    }
+
+    /// Returns the value of the counter or gauge series with the given name and labels, or the
+    /// sample count of a histogram or summary. Const labels must be included. Like a scrape, the
+    /// buffered updates of [`crate::local`] metrics are flushed first.
+    pub fn value(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        crate::local::flush_all();
+        let families = self.registry.gather();
+        let family = families.iter().find(|family| family.name() == name)?;
+
+        let metric = family.get_metric().iter().find(|metric| {
+            metric.get_label().len() == labels.len() &&
+                labels.iter().all(|(name, value)| {
+                    metric
+                        .get_label()
+                        .iter()
+                        .any(|label| label.name() == *name && label.value() == *value)
+                })
+        })?;
+
+        match family.get_field_type() {
+            prometheus::proto::MetricType::COUNTER => Some(metric.get_counter().value()),
+            prometheus::proto::MetricType::GAUGE => Some(metric.get_gauge().value()),
+            prometheus::proto::MetricType::HISTOGRAM => {
+                Some(metric.get_histogram().get_sample_count() as f64)
+            }
+            prometheus::proto::MetricType::SUMMARY => {
+                Some(metric.get_summary().sample_count() as f64)
+            }
+            prometheus::proto::MetricType::UNTYPED => None,
+        }
+    }
+
+    /// Returns the duplicate registrations made on this thread since the harness was created.
+    pub fn duplicates(&self) -> Vec<String> {
+        DUPLICATES.with(|duplicates| duplicates.borrow().clone().unwrap_or_default())
+    }
+
+    /// Assert that no duplicate registrations were made on this thread since the harness was
+    /// created.
+    #[track_caller]
+    pub fn assert_no_duplicates(&self) {
+        let duplicates = self.duplicates();
+        assert!(duplicates.is_empty(), "Unexpected duplicate metric registrations: {duplicates:?}");
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let duplicates = DUPLICATES
+            .with(|duplicates| duplicates.replace(self.previous.take()))
+            .unwrap_or_default();
+
+        if !std::thread::panicking() {
+            assert!(
+                duplicates.is_empty(),
+                "Unexpected duplicate metric registrations: {duplicates:?}"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Counter, Gauge};
+
+    #[test]
+    fn test_harness() {
+        let harness = Harness::new();
+        let (counter, gauge) = harness.build(|registry| {
+            (
+                Counter::<u64>::new(
+                    registry,
+                    "requests",
+                    "Requests.",
+                    &["method"],
+                    Default::default(),
+                ),
+                Gauge::<i64>::new(registry, "balance", "Balance.", &[], Default::default()),
+            )
+        });
+
+        counter.inc_by(&["GET"], 2);
+        gauge.set(&[], -3);
+
+        assert_eq!(harness.value("requests", &[("method", "GET")]), Some(2.0));
+        assert_eq!(harness.value("requests", &[("method", "POST")]), None);
+        assert_eq!(harness.value("balance", &[]), Some(-3.0));
+        harness.assert_no_duplicates();
+    }
+
+    #[test]
+    fn test_harness_duplicates() {
+        let harness = Harness::new();
+        harness.build(|registry| {
+            Counter::<u64>::new(registry, "requests", "Requests.", &[], Default::default());
+            Counter::<u64>::new(registry, "requests", "Requests.", &[], Default::default());
+        });
+
+        assert_eq!(harness.duplicates().len(), 1);
+
+        // Don't fail the test on drop.
+        DUPLICATES.with(|duplicates| duplicates.replace(Some(Vec::new())));
+    }
+}
@@ -0,0 +1,314 @@
+use prometheus::{IntGauge, IntGaugeVec, Opts, Registry};
+
+/// A collector for thread pool metrics, exporting the pool size, queued jobs and busy workers of
+/// each instrumented pool.
+///
+/// Thread pools are instrumented through a [`ThreadPoolHandle`], obtained with
+/// [`ThreadPoolCollector::pool`]. The handle provides generic hooks that can be wired into any
+/// thread pool implementation. When the `rayon` feature is enabled, it can also instrument a
+/// [`rayon::ThreadPoolBuilder`] directly.
+///
+/// # Example
+/// ```rust
+/// use prometheus::Registry;
+/// use prometric::thread_pool::ThreadPoolCollector;
+///
+/// let registry = Registry::new();
+/// let collector = ThreadPoolCollector::new(&registry);
+///
+/// // OR run with the default registry
+/// let collector = ThreadPoolCollector::default();
+///
+/// let pool = collector.pool("io");
+/// pool.set_size(4);
+///
+/// // Wrap a job before submitting it to the pool
+/// let job = pool.wrap(|| println!("Hello from the pool!"));
+/// std::thread::spawn(job).join().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ThreadPoolCollector {
+    metrics: ThreadPoolMetrics,
+}
+
+impl Default for ThreadPoolCollector {
+    fn default() -> Self {
+        Self::new(prometheus::default_registry())
+    }
+}
+
+impl ThreadPoolCollector {
+    /// Create a new `ThreadPoolCollector` with the given registry, overwriting the thread pool
+    /// metrics already registered in it.
+    ///
+    /// # Panics
+    /// Panics if the registration fails.
+    #[track_caller]
+    pub fn new(registry: &Registry) -> Self {
+        Self { metrics: ThreadPoolMetrics::new(registry) }
+    }
+
+    /// Create a new `ThreadPoolCollector` like [`Self::new`], returning an error if the
+    /// registration fails.
+    pub fn try_new(registry: &Registry) -> Result<Self, crate::Error> {
+        Ok(Self { metrics: ThreadPoolMetrics::try_new(registry)? })
+    }
+
+    /// Returns a handle to instrument the thread pool with the given name.
+    pub fn pool(&self, name: &str) -> ThreadPoolHandle {
+        ThreadPoolHandle {
+            size: self.metrics.size.with_label_values(&[name]),
+            queued_jobs: self.metrics.queued_jobs.with_label_values(&[name]),
+            busy_workers: self.metrics.busy_workers.with_label_values(&[name]),
+        }
+    }
+}
+
+/// A handle to instrument a single thread pool. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct ThreadPoolHandle {
+    size: IntGauge,
+    queued_jobs: IntGauge,
+    busy_workers: IntGauge,
+}
+
+impl ThreadPoolHandle {
+    /// Set the number of workers in the pool.
+    pub fn set_size(&self, size: usize) {
+        self.size.set(size as i64);
+    }
+
+    /// Record that a worker was started.
+    pub fn worker_started(&self) {
+        self.size.inc();
+    }
+
+    /// Record that a worker was stopped.
+    pub fn worker_stopped(&self) {
+        self.size.dec();
+    }
+
+    /// Record that a job was queued.
+    pub fn job_queued(&self) {
+        self.queued_jobs.inc();
+    }
+
+    /// Record that a previously queued job was picked up by a worker. The worker is considered
+    /// busy until the returned guard is dropped.
+    #[must_use = "The worker is only considered busy while the guard is alive"]
+    pub fn job_started(&self) -> BusyGuard {
+        self.queued_jobs.dec();
+        self.busy()
+    }
+
+    /// Mark a worker as busy until the returned guard is dropped.
+    fn busy(&self) -> BusyGuard {
+        self.busy_workers.inc();
+        BusyGuard { busy_workers: self.busy_workers.clone() }
+    }
+
+    /// Wrap a job so that it is accounted as queued until it runs, and as busy while it runs. If
+    /// the wrapped job is dropped without running, e.g. when the pool is shut down or rejects it,
+    /// it is no longer accounted as queued.
+    pub fn wrap<F, R>(&self, job: F) -> impl FnOnce() -> R + Send + 'static
+    where
+        F: FnOnce() -> R + Send + 'static,
+    {
+        self.job_queued();
+        let queued = QueuedGuard { queued_jobs: self.queued_jobs.clone() };
+        let handle = self.clone();
+        move || {
+            drop(queued);
+            let _guard = handle.busy();
+            job()
+        }
+    }
+
+    /// Instrument a [`rayon::ThreadPoolBuilder`], tracking the pool size through its start and
+    /// exit handlers.
+    ///
+    /// NOTE: This overrides any start or exit handler previously set on the builder. Jobs should
+    /// still be wrapped with [`ThreadPoolHandle::wrap`] (or spawned with
+    /// [`ThreadPoolHandle::spawn`]) to track queued jobs and busy workers.
+    #[cfg(feature = "rayon")]
+    pub fn instrument<S>(
+        &self,
+        builder: rayon::ThreadPoolBuilder<S>,
+    ) -> rayon::ThreadPoolBuilder<S> {
+        let start = self.clone();
+        let exit = self.clone();
+        builder
+            .start_handler(move |_| start.worker_started())
+            .exit_handler(move |_| exit.worker_stopped())
+    }
+
+    /// Spawn a job on the given [`rayon::ThreadPool`], tracking it as queued and busy.
+    #[cfg(feature = "rayon")]
+    pub fn spawn<F>(&self, pool: &rayon::ThreadPool, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        pool.spawn(self.wrap(job));
+    }
+}
+
+/// A guard that marks a worker as busy until dropped. Returned by
+/// [`ThreadPoolHandle::job_started`].
+#[derive(Debug)]
+pub struct BusyGuard {
+    busy_workers: IntGauge,
+}
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) {
+        self.busy_workers.dec();
+    }
+}
+
+/// A guard that accounts a wrapped job as queued until it runs or is dropped. Owned by the
+/// closures returned by [`ThreadPoolHandle::wrap`].
+#[derive(Debug)]
+struct QueuedGuard {
+    queued_jobs: IntGauge,
+}
+
+impl Drop for QueuedGuard {
+    fn drop(&mut self) {
+        self.queued_jobs.dec();
+    }
+}
+
+/// A collection of metrics for thread pools, labeled by pool name.
+#[derive(Debug, Clone)]
+pub struct ThreadPoolMetrics {
+    /// The number of workers in the pool.
+    size: IntGaugeVec,
+    /// The number of jobs waiting to be picked up by a worker.
+    queued_jobs: IntGaugeVec,
+    /// The number of workers currently running a job.
+    busy_workers: IntGaugeVec,
+}
+
+impl ThreadPoolMetrics {
+    /// Create and register the thread pool metrics with the given registry, overwriting the ones
+    /// already registered in it.
+    ///
+    /// # Panics
+    /// Panics if the registration fails.
+    #[track_caller]
+    pub fn new(registry: &Registry) -> Self {
+        Self::try_new(registry)
+            .unwrap_or_else(|e| crate::registration_failed("thread_pool", &["pool"], e))
+    }
+
+    /// Create and register the thread pool metrics like [`Self::new`], returning an error if the
+    /// registration fails.
+    pub fn try_new(registry: &Registry) -> Result<Self, crate::Error> {
+        let size = IntGaugeVec::new(
+            Opts::new("thread_pool_size", "The number of workers in the thread pool."),
+            &["pool"],
+        )?;
+        let queued_jobs = IntGaugeVec::new(
+            Opts::new(
+                "thread_pool_queued_jobs",
+                "The number of jobs waiting to be picked up by a worker of the thread pool.",
+            ),
+            &["pool"],
+        )?;
+        let busy_workers = IntGaugeVec::new(
+            Opts::new(
+                "thread_pool_busy_workers",
+                "The number of workers of the thread pool currently running a job.",
+            ),
+            &["pool"],
+        )?;
+
+        // Register all metrics with the registry
+        crate::register(registry, &size, "thread_pool_size", &["pool"])?;
+        crate::register(registry, &queued_jobs, "thread_pool_queued_jobs", &["pool"])?;
+        crate::register(registry, &busy_workers, "thread_pool_busy_workers", &["pool"])?;
+
+        Ok(Self { size, queued_jobs, busy_workers })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn test_thread_pool_handle() {
+        let registry = Registry::new();
+        let collector = ThreadPoolCollector::new(&registry);
+        let pool = collector.pool("test");
+
+        pool.set_size(2);
+        pool.worker_started();
+        assert_eq!(pool.size.get(), 3);
+        pool.worker_stopped();
+        assert_eq!(pool.size.get(), 2);
+
+        let (started_tx, started_rx) = mpsc::channel();
+        let (finish_tx, finish_rx) = mpsc::channel::<()>();
+        let job = pool.wrap(move || {
+            started_tx.send(()).unwrap();
+            finish_rx.recv().unwrap();
+        });
+        assert_eq!(pool.queued_jobs.get(), 1);
+        assert_eq!(pool.busy_workers.get(), 0);
+
+        let worker = std::thread::spawn(job);
+        started_rx.recv().unwrap();
+        assert_eq!(pool.queued_jobs.get(), 0);
+        assert_eq!(pool.busy_workers.get(), 1);
+
+        finish_tx.send(()).unwrap();
+        worker.join().unwrap();
+        assert_eq!(pool.busy_workers.get(), 0);
+
+        // Jobs dropped without running, e.g. rejected by the pool, are no longer queued.
+        let job = pool.wrap(|| ());
+        assert_eq!(pool.queued_jobs.get(), 1);
+        drop(job);
+        assert_eq!(pool.queued_jobs.get(), 0);
+        assert_eq!(pool.busy_workers.get(), 0);
+
+        // Building the collector again on the same registry overwrites the metrics.
+        let collector = ThreadPoolCollector::new(&registry);
+        collector.pool("test").set_size(1);
+        let output = crate::gather_to_string(&registry).unwrap();
+        assert!(output.contains(r#"thread_pool_size{pool="test"} 1"#));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_rayon_thread_pool() {
+        let registry = Registry::new();
+        let collector = ThreadPoolCollector::new(&registry);
+        let handle = collector.pool("rayon");
+
+        let pool =
+            handle.instrument(rayon::ThreadPoolBuilder::new().num_threads(2)).build().unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..4 {
+            let tx = tx.clone();
+            handle.spawn(&pool, move || tx.send(()).unwrap());
+        }
+        for _ in 0..4 {
+            rx.recv().unwrap();
+        }
+        assert_eq!(handle.queued_jobs.get(), 0);
+
+        // Workers might still be starting up.
+        for _ in 0..100 {
+            if handle.size.get() == 2 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(handle.size.get(), 2);
+    }
+}
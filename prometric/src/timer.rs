@@ -0,0 +1,196 @@
+//! Timers pairing a duration histogram with a call counter sharing its labels, the rate and
+//! duration of the RED method. Used by `Timer` fields of the `prometric-derive` macro.
+//!
+//! A timer named `rpc_calls` registers the `rpc_calls_duration_seconds` histogram and the
+//! `rpc_calls_total` counter. Each observation updates both.
+//!
+//! # Example
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use prometric::Timer;
+//!
+//! let registry = prometheus::Registry::new();
+//! let timer =
+//!     Timer::new(&registry, "rpc_calls", "RPC calls.", &["method"], Default::default(), None);
+//! timer.observe(&["eth_call"], Duration::from_millis(20));
+//! {
+//!     let _guard = timer.start(&["eth_call"]);
+//!     // The timed call.
+//! }
+//! assert_eq!(timer.count(&["eth_call"]), 2);
+//!
+//! let output = prometric::gather_to_string(&registry).unwrap();
+//! assert!(output.contains(r#"rpc_calls_total{method="eth_call"} 2"#));
+//! assert!(output.contains(r#"rpc_calls_duration_seconds_count{method="eth_call"} 2"#));
+//! ```
+
+use std::{collections::HashMap, time::Duration};
+
+#[cfg(feature = "recording")]
+use crate::recording;
+use crate::{Counter, CounterHandle, Histogram, HistogramHandle, HistogramTimer};
+
+/// A duration histogram and a call counter with the same labels. See [`crate::timer`].
+#[derive(Debug, Clone)]
+pub struct Timer {
+    duration: Histogram,
+    calls: Counter<u64>,
+}
+
+/// Collects the series of the duration histogram and the call counter.
+impl prometheus::core::Collector for Timer {
+    fn desc(&self) -> Vec<&prometheus::core::Desc> {
+        let mut desc = self.duration.desc();
+        desc.extend(self.calls.desc());
+        desc
+    }
+
+    fn collect(&self) -> Vec<prometheus::proto::MetricFamily> {
+        let mut families = self.duration.collect();
+        families.extend(self.calls.collect());
+        families
+    }
+}
+
+impl Timer {
+    /// Create a new timer, registering the `{name}_duration_seconds` histogram with the given
+    /// buckets, and the `{name}_total` counter.
+    pub fn new(
+        registry: &prometheus::Registry,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        const_labels: HashMap<String, String>,
+        buckets: Option<Vec<f64>>,
+    ) -> Self {
+        let duration = Histogram::new(
+            registry,
+            &format!("{name}_duration_seconds"),
+            &format!("{help} The duration of the calls, in seconds."),
+            labels,
+            const_labels.clone(),
+            buckets,
+        );
+        let calls = Counter::new(
+            registry,
+            &format!("{name}_total"),
+            &format!("{help} The total number of calls."),
+            labels,
+            const_labels,
+        );
+        Self { duration, calls }
+    }
+
+    /// Observe the duration of a call, and count it.
+    pub fn observe(&self, labels: &[&str], duration: Duration) {
+        self.duration.observe(labels, duration.as_secs_f64());
+        self.calls.inc(labels);
+    }
+
+    /// Start timing a call, observed and counted when the returned timer is dropped.
+    pub fn start<'a>(&'a self, labels: &'a [&'a str]) -> HistogramTimer<impl FnOnce(f64) + 'a> {
+        HistogramTimer::new(move |seconds| self.observe(labels, Duration::from_secs_f64(seconds)))
+    }
+
+    /// Returns the number of calls with the given label values.
+    pub fn count(&self, labels: &[&str]) -> u64 {
+        self.calls.get(labels)
+    }
+
+    /// Returns the duration histogram of the timer.
+    pub fn duration(&self) -> &Histogram {
+        &self.duration
+    }
+
+    /// Returns the call counter of the timer.
+    pub fn calls(&self) -> &Counter<u64> {
+        &self.calls
+    }
+
+    /// Remove the children of the histogram and the counter with the given label values. Returns
+    /// `false` if there is no such child.
+    pub fn remove(&self, labels: &[&str]) -> bool {
+        let removed = self.duration.remove(labels);
+        self.calls.remove(labels) && removed
+    }
+
+    /// Unregister the histogram and the counter from the given registry.
+    pub fn unregister(&self, registry: &prometheus::Registry) {
+        self.duration.unregister(registry);
+        self.calls.unregister(registry);
+    }
+
+    /// Record the operations on the histogram and the counter into the given recorder. See
+    /// [`crate::recording`].
+    #[cfg(feature = "recording")]
+    pub fn record_into(&self, recorder: &recording::Recorder) {
+        self.duration.record_into(recorder);
+        self.calls.record_into(recorder);
+    }
+
+    /// Remove all the children of the histogram and the counter.
+    pub fn clear(&self) {
+        self.duration.clear();
+        self.calls.clear();
+    }
+
+    /// Resolve the children of the histogram and the counter with the given label values once,
+    /// returning a handle updating them without the label lookup.
+    pub fn handle(&self, labels: &[&str]) -> TimerHandle {
+        TimerHandle { duration: self.duration.handle(labels), calls: self.calls.handle(labels) }
+    }
+}
+
+/// A handle to the children of a [`Timer`] with resolved label values, returned by
+/// [`Timer::handle`].
+#[derive(Debug)]
+pub struct TimerHandle {
+    duration: HistogramHandle,
+    calls: CounterHandle<u64>,
+}
+
+impl TimerHandle {
+    /// Create a handle updating nothing, e.g. when metrics are disabled.
+    pub fn noop() -> Self {
+        Self { duration: HistogramHandle::noop(), calls: CounterHandle::noop() }
+    }
+
+    /// Observe the duration of a call, and count it.
+    pub fn observe(&self, duration: Duration) {
+        self.duration.observe(duration.as_secs_f64());
+        self.calls.inc();
+    }
+
+    /// Start timing a call, observed and counted when the returned timer is dropped.
+    pub fn start(&self) -> HistogramTimer<impl FnOnce(f64) + '_> {
+        HistogramTimer::new(move |seconds| self.observe(Duration::from_secs_f64(seconds)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_timer() {
+        let registry = prometheus::Registry::new();
+        let timer =
+            Timer::new(&registry, "test_rpc", "RPC calls.", &["method"], Default::default(), None);
+        timer.observe(&["a"], Duration::from_millis(500));
+        timer.handle(&["a"]).observe(Duration::from_millis(500));
+        drop(timer.start(&["b"]));
+
+        assert_eq!(timer.count(&["a"]), 2);
+        assert_eq!(timer.count(&["b"]), 1);
+        let output = crate::gather_to_string(&registry).unwrap();
+        assert!(output.contains(r#"test_rpc_duration_seconds_sum{method="a"} 1"#));
+        assert!(output.contains(r#"test_rpc_total{method="b"} 1"#));
+
+        assert!(timer.remove(&["a"]));
+        timer.unregister(&registry);
+        assert!(registry.gather().is_empty());
+    }
+}
@@ -0,0 +1,358 @@
+//! Integrations with the [`tracing`](::tracing) ecosystem, turning existing instrumentation into
+//! Prometheus metrics.
+//!
+//! - [`SpanDurationLayer`]: Records the duration of closed spans into a histogram.
+//! - [`EventCounterLayer`]: Counts events by level and target.
+
+use std::{fmt, sync::Arc, time::Instant};
+
+use ::tracing::{
+    Event, Level, Metadata, Subscriber,
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+};
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
+use tracing_subscriber::{Layer, layer::Context, registry::LookupSpan};
+
+/// The default name of the span duration histogram.
+pub const DEFAULT_SPAN_DURATION_NAME: &str = "span_duration_seconds";
+
+/// The name of the event counter.
+pub const EVENT_COUNTER_NAME: &str = "log_events_total";
+
+type MetadataFilter = Arc<dyn Fn(&Metadata<'_>) -> bool + Send + Sync>;
+
+/// A builder for the [`SpanDurationLayer`].
+pub struct SpanDurationLayerBuilder {
+    registry: Option<Registry>,
+    name: String,
+    buckets: Vec<f64>,
+    field_labels: Vec<String>,
+    filter: Option<MetadataFilter>,
+}
+
+impl Default for SpanDurationLayerBuilder {
+    fn default() -> Self {
+        Self {
+            registry: None,
+            name: DEFAULT_SPAN_DURATION_NAME.to_owned(),
+            buckets: prometheus::DEFAULT_BUCKETS.to_vec(),
+            field_labels: Vec::new(),
+            filter: None,
+        }
+    }
+}
+
+impl fmt::Debug for SpanDurationLayerBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpanDurationLayerBuilder")
+            .field("name", &self.name)
+            .field("buckets", &self.buckets)
+            .field("field_labels", &self.field_labels)
+            .field("filter", &self.filter.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl SpanDurationLayerBuilder {
+    /// Set the registry to register the histogram with. Defaults to the default registry.
+    pub fn with_registry(mut self, registry: Registry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Set the name of the histogram. Defaults to [`DEFAULT_SPAN_DURATION_NAME`].
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Set the buckets of the histogram. Defaults to [`prometheus::DEFAULT_BUCKETS`].
+    pub fn with_buckets(mut self, buckets: impl Into<Vec<f64>>) -> Self {
+        self.buckets = buckets.into();
+        self
+    }
+
+    /// Add a span field to use as a label of the histogram. Spans that don't record the field
+    /// will have an empty value for the label.
+    pub fn with_field_label(mut self, field: impl Into<String>) -> Self {
+        self.field_labels.push(field.into());
+        self
+    }
+
+    /// Only record the spans for which the given filter returns `true`. By default, all spans are
+    /// recorded.
+    pub fn with_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&Metadata<'_>) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Build the layer, registering the histogram with the registry.
+    pub fn build(self) -> prometheus::Result<SpanDurationLayer> {
+        let registry = self.registry.unwrap_or_else(|| prometheus::default_registry().clone());
+
+        let labels = ["target", "name"]
+            .into_iter()
+            .chain(self.field_labels.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+        let opts = HistogramOpts::new(&self.name, "The duration of closed spans in seconds.")
+            .buckets(self.buckets);
+        let histogram = HistogramVec::new(opts, &labels)?;
+        registry.register(Box::new(histogram.clone()))?;
+
+        Ok(SpanDurationLayer {
+            histogram,
+            field_labels: self.field_labels.into(),
+            filter: self.filter,
+        })
+    }
+}
+
+/// A [`Layer`] recording the duration of spans into a histogram, from creation until close.
+///
+/// Observations are labeled by the `target` and `name` of the span, and by any span field
+/// configured with [`SpanDurationLayerBuilder::with_field_label`].
+///
+/// # Example
+/// ```rust
+/// use prometric::tracing::SpanDurationLayer;
+/// use tracing_subscriber::layer::SubscriberExt as _;
+///
+/// let layer = SpanDurationLayer::builder()
+///     .with_field_label("method")
+///     .with_filter(|metadata| metadata.target().starts_with("my_app"))
+///     .build()
+///     .unwrap();
+///
+/// let subscriber = tracing_subscriber::registry().with(layer);
+/// tracing::subscriber::with_default(subscriber, || {
+///     let _span = tracing::info_span!(target: "my_app", "request", method = "GET").entered();
+/// });
+/// ```
+#[derive(Clone)]
+pub struct SpanDurationLayer {
+    histogram: HistogramVec,
+    field_labels: Arc<[String]>,
+    filter: Option<MetadataFilter>,
+}
+
+impl fmt::Debug for SpanDurationLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpanDurationLayer")
+            .field("field_labels", &self.field_labels)
+            .field("filter", &self.filter.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl SpanDurationLayer {
+    /// Create a new builder for the layer.
+    pub fn builder() -> SpanDurationLayerBuilder {
+        SpanDurationLayerBuilder::default()
+    }
+}
+
+/// The timing state of a span, stored in its extensions.
+struct SpanTiming {
+    start: Instant,
+    field_values: Vec<String>,
+}
+
+/// Records the values of the configured label fields.
+struct FieldLabelVisitor<'a> {
+    field_labels: &'a [String],
+    field_values: &'a mut [String],
+}
+
+impl FieldLabelVisitor<'_> {
+    fn set(&mut self, field: &Field, value: impl FnOnce() -> String) {
+        if let Some(i) = self.field_labels.iter().position(|label| label == field.name()) {
+            self.field_values[i] = value();
+        }
+    }
+}
+
+impl Visit for FieldLabelVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, || value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.set(field, || format!("{value:?}"));
+    }
+}
+
+impl<S> Layer<S> for SpanDurationLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if self.filter.as_ref().is_some_and(|filter| !filter(attrs.metadata())) {
+            return;
+        }
+
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut field_values = vec![String::new(); self.field_labels.len()];
+        attrs.record(&mut FieldLabelVisitor {
+            field_labels: &self.field_labels,
+            field_values: &mut field_values,
+        });
+
+        span.extensions_mut().insert(SpanTiming { start: Instant::now(), field_values });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut extensions = span.extensions_mut();
+        if let Some(timing) = extensions.get_mut::<SpanTiming>() {
+            values.record(&mut FieldLabelVisitor {
+                field_labels: &self.field_labels,
+                field_values: &mut timing.field_values,
+            });
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+
+        let extensions = span.extensions();
+        let Some(timing) = extensions.get::<SpanTiming>() else {
+            return;
+        };
+
+        let metadata = span.metadata();
+        let labels = [metadata.target(), metadata.name()]
+            .into_iter()
+            .chain(timing.field_values.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+
+        self.histogram.with_label_values(&labels).observe(timing.start.elapsed().as_secs_f64());
+    }
+}
+
+/// A [`Layer`] counting events by level and target into the `log_events_total` counter.
+///
+/// This makes error and warning rates alertable, even where no explicit counters were added. Use
+/// [`Layer::with_filter`] to restrict the events that are counted.
+///
+/// # Example
+/// ```rust
+/// use prometheus::Registry;
+/// use prometric::tracing::EventCounterLayer;
+/// use tracing_subscriber::layer::SubscriberExt as _;
+///
+/// let registry = Registry::new();
+/// let layer = EventCounterLayer::new(&registry).unwrap();
+///
+/// let subscriber = tracing_subscriber::registry().with(layer);
+/// tracing::subscriber::with_default(subscriber, || {
+///     tracing::warn!("Something happened");
+/// });
+/// ```
+#[derive(Debug, Clone)]
+pub struct EventCounterLayer {
+    events: IntCounterVec,
+}
+
+impl EventCounterLayer {
+    /// Create a new `EventCounterLayer`, registering the counter with the given registry.
+    pub fn new(registry: &Registry) -> prometheus::Result<Self> {
+        let events = IntCounterVec::new(
+            Opts::new(EVENT_COUNTER_NAME, "The total number of events, by level and target."),
+            &["level", "target"],
+        )?;
+        registry.register(Box::new(events.clone()))?;
+
+        Ok(Self { events })
+    }
+}
+
+impl<S: Subscriber> Layer<S> for EventCounterLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let level = match *metadata.level() {
+            Level::ERROR => "error",
+            Level::WARN => "warn",
+            Level::INFO => "info",
+            Level::DEBUG => "debug",
+            Level::TRACE => "trace",
+        };
+
+        self.events.with_label_values(&[level, metadata.target()]).inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    use super::*;
+
+    #[test]
+    fn test_span_duration_layer() {
+        let registry = Registry::new();
+        let layer = SpanDurationLayer::builder()
+            .with_registry(registry.clone())
+            .with_field_label("method")
+            .with_filter(|metadata| metadata.target() == "test")
+            .build()
+            .unwrap();
+
+        let subscriber = tracing_subscriber::registry().with(layer);
+        ::tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..2 {
+                let span = ::tracing::info_span!(target: "test", "request", method = "GET");
+                let _guard = span.enter();
+            }
+
+            let span =
+                ::tracing::info_span!(target: "test", "request", method = ::tracing::field::Empty);
+            span.record("method", "POST");
+            drop(span);
+
+            // Filtered out
+            let _span = ::tracing::info_span!(target: "other", "request").entered();
+        });
+
+        let body = prometheus::TextEncoder::new().encode_to_string(&registry.gather()).unwrap();
+        println!("{body}");
+
+        assert!(body.contains(
+            r#"span_duration_seconds_count{method="GET",name="request",target="test"} 2"#
+        ));
+        assert!(body.contains(
+            r#"span_duration_seconds_count{method="POST",name="request",target="test"} 1"#
+        ));
+        assert!(!body.contains(r#"target="other""#));
+    }
+
+    #[test]
+    fn test_event_counter_layer() {
+        let registry = Registry::new();
+        let layer = EventCounterLayer::new(&registry).unwrap();
+
+        let subscriber = tracing_subscriber::registry().with(layer);
+        ::tracing::subscriber::with_default(subscriber, || {
+            ::tracing::error!(target: "test", "first");
+            ::tracing::error!(target: "test", "second");
+            ::tracing::warn!(target: "other", "third");
+        });
+
+        let body = prometheus::TextEncoder::new().encode_to_string(&registry.gather()).unwrap();
+        println!("{body}");
+
+        assert!(body.contains(r#"log_events_total{level="error",target="test"} 2"#));
+        assert!(body.contains(r#"log_events_total{level="warn",target="other"} 1"#));
+    }
+}
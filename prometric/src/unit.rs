@@ -0,0 +1,154 @@
+//! Units of metrics, declared with `#[metric(unit = "...")]` in the `prometric-derive` macro.
+//!
+//! Declaring the unit of a metric generates helpers on its accessor converting from another unit
+//! of the same dimension, e.g. `observe_millis(u64)` on a histogram in seconds. This prevents the
+//! recurring bugs of observing milliseconds into a histogram in seconds.
+
+use crate::private::Sealed;
+
+/// The unit of a metric. Time units are based on seconds, and data units on bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Unit {
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    Nanoseconds,
+    Bytes,
+    Kilobytes,
+    Megabytes,
+    Gigabytes,
+}
+
+impl Unit {
+    /// All supported units.
+    pub const ALL: [Self; 8] = [
+        Self::Seconds,
+        Self::Milliseconds,
+        Self::Microseconds,
+        Self::Nanoseconds,
+        Self::Bytes,
+        Self::Kilobytes,
+        Self::Megabytes,
+        Self::Gigabytes,
+    ];
+
+    /// Returns the name of the unit, as used in metric names (e.g. `seconds`).
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Seconds => "seconds",
+            Self::Milliseconds => "milliseconds",
+            Self::Microseconds => "microseconds",
+            Self::Nanoseconds => "nanoseconds",
+            Self::Bytes => "bytes",
+            Self::Kilobytes => "kilobytes",
+            Self::Megabytes => "megabytes",
+            Self::Gigabytes => "gigabytes",
+        }
+    }
+
+    /// Returns the unit with the given name, if supported.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|unit| unit.as_str() == name)
+    }
+
+    /// Returns the unit of a metric from the suffix of its name, e.g. `seconds` for
+    /// `app_cpu_seconds_total`. Metrics declaring a unit get this suffix appended by the
+    /// `prometric-derive` macro.
+    pub fn from_metric_name(name: &str) -> Option<Self> {
+        let name = name.strip_suffix("_total").unwrap_or(name);
+        Self::ALL
+            .into_iter()
+            .find(|unit| name.strip_suffix(unit.as_str()).is_some_and(|base| base.ends_with('_')))
+    }
+
+    /// Returns `true` if this is a time unit.
+    pub const fn is_time(&self) -> bool {
+        matches!(self, Self::Seconds | Self::Milliseconds | Self::Microseconds | Self::Nanoseconds)
+    }
+
+    /// Returns the power of ten of the unit in base units (seconds or bytes), e.g. `-3` for
+    /// milliseconds.
+    pub const fn exponent(&self) -> i32 {
+        match self {
+            Self::Seconds | Self::Bytes => 0,
+            Self::Milliseconds => -3,
+            Self::Microseconds => -6,
+            Self::Nanoseconds => -9,
+            Self::Kilobytes => 3,
+            Self::Megabytes => 6,
+            Self::Gigabytes => 9,
+        }
+    }
+
+    /// Convert a value in this unit to the given unit.
+    ///
+    /// # Panics
+    /// Panics if the units have a different dimension (e.g. seconds to bytes).
+    pub fn convert(self, value: f64, to: Self) -> f64 {
+        assert_eq!(self.is_time(), to.is_time(), "Can't convert {self} to {to}");
+
+        // Divide by exact powers of ten rather than multiplying by inexact fractions.
+        let exponent = self.exponent() - to.exponent();
+        if exponent >= 0 { value * 10f64.powi(exponent) } else { value / 10f64.powi(-exponent) }
+    }
+}
+
+impl std::fmt::Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A number type a unit-converted value can be stored as. Integers are rounded to the nearest
+/// value.
+pub trait FromUnitValue: Sealed {
+    fn from_unit_value(value: f64) -> Self;
+}
+
+impl FromUnitValue for u64 {
+    #[inline]
+    fn from_unit_value(value: f64) -> Self {
+        value.round() as u64
+    }
+}
+
+impl FromUnitValue for i64 {
+    #[inline]
+    fn from_unit_value(value: f64) -> Self {
+        value.round() as i64
+    }
+}
+
+impl FromUnitValue for f64 {
+    #[inline]
+    fn from_unit_value(value: f64) -> Self {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(Unit::Milliseconds.convert(1500.0, Unit::Seconds), 1.5);
+        assert_eq!(Unit::Seconds.convert(2.0, Unit::Microseconds), 2_000_000.0);
+        assert_eq!(Unit::Bytes.convert(2_500.0, Unit::Kilobytes), 2.5);
+        assert_eq!(Unit::from_name("nanoseconds"), Some(Unit::Nanoseconds));
+        assert_eq!(Unit::from_name("hours"), None);
+        assert_eq!(Unit::from_metric_name("app_cpu_seconds_total"), Some(Unit::Seconds));
+        assert_eq!(Unit::from_metric_name("app_received_bytes"), Some(Unit::Bytes));
+        assert_eq!(Unit::from_metric_name("app_nanoseconds"), Some(Unit::Nanoseconds));
+        assert_eq!(Unit::from_metric_name("app_requests_total"), None);
+        assert_eq!(u64::from_unit_value(2.5), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't convert seconds to bytes")]
+    fn test_convert_dimension_mismatch() {
+        Unit::Seconds.convert(1.0, Unit::Bytes);
+    }
+}